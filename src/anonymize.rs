@@ -0,0 +1,126 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{config, error::RunError, report, store::Sample};
+
+/// `--anonymize`: stable pseudonyms for targets in shared exports and
+/// summaries. The mapping file stays local, so the same target keeps its
+/// name across runs and the owner can still tell which host was meant.
+#[derive(Debug)]
+pub struct Pseudonyms {
+    path: PathBuf,
+    /// Target → pseudonym.
+    names: BTreeMap<String, String>,
+    changed: bool,
+}
+
+impl Pseudonyms {
+    /// The mapping in `map`, or in `anonymize.json` next to the binary.
+    pub fn open(map: Option<&Path>) -> Result<Pseudonyms, RunError> {
+        let path = map.map_or_else(|| config::exe_dir().join("anonymize.json"), Path::to_path_buf);
+        Pseudonyms::load(&path)
+    }
+
+    /// Reads the mapping; a missing file starts an empty one.
+    pub fn load(path: &Path) -> Result<Pseudonyms, RunError> {
+        let context = || format!("Failed to read mapping file {}", path.display());
+        let names = match fs::read_to_string(path) {
+            Ok(text) => serde_json::from_str(&text).map_err(|err| RunError::Invalid {
+                context: context(),
+                message: err.to_string(),
+            })?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(err) => return Err(RunError::io(context())(err)),
+        };
+        Ok(Pseudonyms {
+            path: path.to_path_buf(),
+            names,
+            changed: false,
+        })
+    }
+
+    /// The pseudonym of `target`, assigning the next free `host-N`.
+    pub fn name(&mut self, target: &str) -> String {
+        if let Some(name) = self.names.get(target) {
+            return name.clone();
+        }
+        let name = (self.names.len() + 1..)
+            .map(|n| format!("host-{n}"))
+            .find(|name| !self.names.values().any(|v| v == name))
+            .expect("the range is unbounded");
+        self.names.insert(target.to_string(), name.clone());
+        self.changed = true;
+        name
+    }
+
+    /// Writes the mapping back if new targets were named.
+    pub fn save(&self) -> Result<(), RunError> {
+        if !self.changed {
+            return Ok(());
+        }
+        let json = serde_json::to_string_pretty(&self.names).unwrap_or_default();
+        fs::write(&self.path, json + "\n").map_err(RunError::io(format!(
+            "Failed to write mapping file {}",
+            self.path.display()
+        )))
+    }
+
+    pub fn samples(&mut self, samples: &mut [Sample]) {
+        for sample in samples {
+            sample.target = self.name(&sample.target);
+        }
+    }
+
+    pub fn summary(&mut self, summary: &mut report::Summary) {
+        for target in &mut summary.targets {
+            target.target = self.name(&target.target);
+        }
+        let outages = summary
+            .outages
+            .iter_mut()
+            .chain(summary.incidents.iter_mut().flat_map(|i| i.outages.iter_mut()));
+        for outage in outages {
+            outage.target = self.name(&outage.target);
+        }
+        for incident in &mut summary.incidents {
+            for target in &mut incident.targets {
+                *target = self.name(target);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_names_stable_across_runs() {
+        let path = std::env::temp_dir().join(format!("anonymize_{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut first = Pseudonyms::load(&path).unwrap();
+        assert_eq!(first.name("10.0.0.1"), "host-1");
+        assert_eq!(first.name("router.lan"), "host-2");
+        assert_eq!(first.name("10.0.0.1"), "host-1");
+        first.save().unwrap();
+
+        let mut second = Pseudonyms::load(&path).unwrap();
+        let mut samples = [Sample {
+            ts_ms: 0,
+            target: "router.lan".to_string(),
+            success: true,
+            latency_ms: Some(1.0),
+        }];
+        second.samples(&mut samples);
+        assert_eq!(samples[0].target, "host-2");
+        assert_eq!(second.name("8.8.8.8"), "host-3");
+        let _ = fs::remove_file(&path);
+
+        fs::write(&path, "not json").unwrap();
+        assert!(matches!(Pseudonyms::load(&path), Err(RunError::Invalid { .. })));
+        let _ = fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,1800 @@
+use std::{
+    collections::HashMap,
+    fs,
+    net::SocketAddr,
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{self, AtomicBool},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use crossterm::event::KeyCode;
+use tracing::{error, info, warn};
+
+use crate::{
+    DEFAULT_INTERVAL, Engine, PingMonitor, Prober, Session, Stats, Target, anonymize, budget,
+    clock::{self, Clock},
+    config, control, debuglog, detail, disk,
+    error::RunError,
+    events::{self, Kind},
+    graph, heartbeat, icmp, jitter, locale,
+    lock::Lock,
+    log::{LogFormat, LogSink},
+    metrics,
+    output::CsvWriter,
+    overhead::{self, Phase},
+    parse::{Loss, ProbeOutcome},
+    preset, quality, resolver, schedule, schema,
+    session::{self, Outputs},
+    sim, sinks, store, targets, template,
+    ui::{self, KeyAction, PromptAction, PromptKind, SortColumn, View},
+    wan,
+};
+
+/// The variables of `--final-template`.
+pub const FINAL_VARS: &[&str] = &["time", "rounds"];
+
+/// The variables of `--final-row-template`.
+pub const FINAL_ROW_VARS: &[&str] = &[
+    "target",
+    "success",
+    "total",
+    "loss_pct",
+    "min_ms",
+    "avg_ms",
+    "max_ms",
+    "p50_ms",
+    "p95_ms",
+    "p99_ms",
+    "jitter_ms",
+    "stddev_ms",
+    "missed",
+    "no_rtt",
+    "timeouts",
+    "unreachable",
+    "ttl_exceeded",
+    "prohibited",
+    "errors",
+];
+
+/// `--timeout` default, for intervals long enough to leave room for it.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1900);
+
+/// How real probes are sent: `--engine`, `--timeout`, `-4`/`-6` and
+/// `--dump-unparsed`.
+#[derive(Debug, Clone)]
+pub struct Probing {
+    pub kind: icmp::EngineKind,
+    pub timeout: Duration,
+    pub family: Option<targets::Family>,
+    pub dump_unparsed: Option<PathBuf>,
+}
+
+impl Probing {
+    /// The engine for real probes, over `native` if given.
+    pub fn engine(&self, native: Option<icmp::Socket>, routes: wan::Routes) -> Engine {
+        Engine::System {
+            dump_dir: self.dump_unparsed.clone(),
+            routes,
+            timeout: self.timeout,
+            native,
+            family: self.family,
+        }
+    }
+
+    /// The engine `kind` asks for, falling back to the system ping.
+    pub fn chosen(&self) -> Engine {
+        let native = match self.kind {
+            icmp::EngineKind::System => None,
+            icmp::EngineKind::Native => native_socket().ok(),
+        };
+        self.engine(native, wan::Routes::default())
+    }
+}
+
+/// A monitoring run as the flags describe it.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub settings: session::Settings,
+    pub interval: Duration,
+    /// `--duration`; `None` runs until quit.
+    pub run_for: Option<Duration>,
+    pub schedule: Option<schedule::Schedule>,
+    pub probing: Probing,
+    /// `--simulate`: the profile's virtual targets instead of the network.
+    pub simulate: Option<PathBuf>,
+    pub preset: Option<preset::Choice>,
+    /// `--ips` (`-` for stdin); without it the config file's targets, else
+    /// `ips.txt` next to the binary.
+    pub ip_file: Option<PathBuf>,
+    pub config_targets: Option<config::ConfigTargets>,
+    pub skip_invalid: bool,
+    pub max_targets: usize,
+    /// `--refresh-targets`; zero never re-reads the list.
+    pub refresh_targets: Duration,
+    pub dual_stack: bool,
+    pub resolve_every: Duration,
+    pub bindings: Vec<wan::Binding>,
+    /// `--log`; `result.txt` next to the binary without it, and no log at
+    /// all for `once`.
+    pub log_file: Option<PathBuf>,
+    pub log_format: LogFormat,
+    pub csv: Option<PathBuf>,
+    pub store_dir: Option<PathBuf>,
+    pub retention: Option<Duration>,
+    /// `--min-free-space` in MB.
+    pub min_free_space: u64,
+    pub force: bool,
+    pub summary: Option<PathBuf>,
+    pub anonymize: bool,
+    pub anonymize_map: Option<PathBuf>,
+    pub socket: Option<PathBuf>,
+    pub metrics_listen: Option<SocketAddr>,
+    pub heartbeat_url: Option<String>,
+    pub heartbeat_every: Duration,
+    pub probe_stats: bool,
+    pub jitter_buffers: Vec<u32>,
+    pub locale_numbers: bool,
+    pub snapshot_format: ui::SnapshotFormat,
+    pub final_template: Option<template::Template>,
+    pub final_row_template: Option<template::Template>,
+    pub profile_overhead: bool,
+}
+
+impl Default for Config {
+    /// The `ping-plotter` defaults.
+    fn default() -> Config {
+        Config {
+            settings: session::Settings::default(),
+            interval: DEFAULT_INTERVAL,
+            run_for: None,
+            schedule: None,
+            probing: Probing {
+                kind: icmp::EngineKind::System,
+                timeout: DEFAULT_TIMEOUT,
+                family: None,
+                dump_unparsed: None,
+            },
+            simulate: None,
+            preset: None,
+            ip_file: None,
+            config_targets: None,
+            skip_invalid: false,
+            max_targets: targets::DEFAULT_MAX_TARGETS,
+            refresh_targets: Duration::from_secs(10),
+            dual_stack: false,
+            resolve_every: Duration::from_secs(300),
+            bindings: Vec::new(),
+            log_file: None,
+            log_format: LogFormat::default(),
+            csv: None,
+            store_dir: None,
+            retention: None,
+            min_free_space: 100,
+            force: false,
+            summary: None,
+            anonymize: false,
+            anonymize_map: None,
+            socket: None,
+            metrics_listen: None,
+            heartbeat_url: None,
+            heartbeat_every: Duration::from_secs(300),
+            probe_stats: false,
+            jitter_buffers: Vec::new(),
+            locale_numbers: false,
+            snapshot_format: ui::SnapshotFormat::Html,
+            final_template: None,
+            final_row_template: None,
+            profile_overhead: false,
+        }
+    }
+}
+
+/// The ICMP socket for the native engine; when it isn't permitted, warns
+/// what would allow it and returns why.
+fn native_socket() -> Result<icmp::Socket, String> {
+    icmp::detect().map_err(|err| {
+        warn!(
+            "native ICMP engine not permitted ({err}); using the system ping. To use it, {}",
+            icmp::permission_hint()
+        );
+        err.to_string()
+    })
+}
+
+fn timestamp() -> String {
+    schema::log_timestamp(Local::now())
+}
+
+fn open_store(dir: &Path, health: &mut sinks::Health) -> Option<store::Store> {
+    match store::Store::open(dir) {
+        Ok(store) => Some(store),
+        Err(err) => {
+            error!("Failed to open sample store {}: {err}", dir.display());
+            health.failed(err);
+            None
+        }
+    }
+}
+
+/// The outputs the run was started with, for the footer and `status`.
+fn configured_outputs<'a>(
+    config: &Config,
+    log: &'a LogSink,
+    csv: &'a CsvWriter,
+    store_health: &'a sinks::Health,
+    metrics_health: Option<&'a sinks::Health>,
+    heartbeat: Option<&'a heartbeat::Heartbeat>,
+) -> Vec<(sinks::Kind, &'a sinks::Health)> {
+    let mut outputs = vec![(sinks::Kind::Log, log.health())];
+    if config.csv.is_some() {
+        outputs.push((sinks::Kind::Csv, csv.health()));
+    }
+    if config.store_dir.is_some() {
+        outputs.push((sinks::Kind::Store, store_health));
+    }
+    outputs.extend(metrics_health.map(|h| (sinks::Kind::Metrics, h)));
+    outputs.extend(heartbeat.map(|h| (sinks::Kind::Heartbeat, h.health())));
+    outputs
+}
+
+/// Adds the `Timeline` entries among `events` to the events view.
+fn show(events: &[session::Event], timeline: &mut events::Timeline) {
+    for event in events {
+        if let session::Event::Timeline {
+            ts_ms,
+            kind,
+            target,
+            text,
+        } = event
+        {
+            timeline.push(*ts_ms, *kind, target.as_deref(), text.clone());
+        }
+    }
+}
+
+/// Logs `events` and shows them in the events view.
+fn emit(events: &[session::Event], outputs: &mut Outputs, timeline: &mut events::Timeline) {
+    outputs.events(events);
+    show(events, timeline);
+}
+
+/// The source's groups, with `--dual-stack` also for the pinned rows of its
+/// host names.
+fn groups_of(
+    source: &dyn targets::TargetSource,
+    dual_stack: bool,
+) -> HashMap<String, targets::Group> {
+    let groups = source.groups();
+    if !dual_stack {
+        return groups;
+    }
+    targets::dual_stack_keys(groups, |group, _| group.clone())
+}
+
+/// The rows for a list read from the source: with `--dual-stack` its host
+/// names pinned to both families.
+fn rows(targets: Vec<Target>, dual_stack: bool) -> Vec<Target> {
+    if dual_stack {
+        targets::dual_stack(targets)
+    } else {
+        targets
+    }
+}
+
+fn final_row(tpl: &template::Template, target: &str, stat: &Stats) -> String {
+    let fmt = |v: Option<f64>| v.map(|n| format!("{n:.2}")).unwrap_or_default();
+    let loss = stat
+        .loss_pct()
+        .map(|l| format!("{l:.1}"))
+        .unwrap_or_default();
+    tpl.render(&[
+        ("target", target.to_string()),
+        ("success", stat.success.to_string()),
+        ("total", stat.total.to_string()),
+        ("loss_pct", loss),
+        ("min_ms", fmt(stat.min_ms)),
+        ("avg_ms", fmt(stat.avg_ms())),
+        ("max_ms", fmt(stat.max_ms)),
+        ("p50_ms", fmt(stat.p50_ms())),
+        ("p95_ms", fmt(stat.p95_ms())),
+        ("p99_ms", fmt(stat.p99_ms())),
+        ("jitter_ms", fmt(stat.jitter_ms())),
+        ("stddev_ms", fmt(stat.stddev_ms())),
+        ("missed", stat.missed.to_string()),
+        ("no_rtt", stat.no_rtt().to_string()),
+        ("timeouts", stat.losses.timeout.to_string()),
+        ("unreachable", stat.losses.unreachable.to_string()),
+        ("ttl_exceeded", stat.losses.ttl_exceeded.to_string()),
+        ("prohibited", stat.losses.prohibited.to_string()),
+        ("errors", stat.losses.error.to_string()),
+    ])
+}
+
+/// What a run starts from: the engine, the target source and its first
+/// list.
+struct Start {
+    engine: Engine,
+    source: Box<dyn targets::TargetSource>,
+    /// Why `--engine native` fell back to the system ping.
+    native_error: Option<String>,
+    check: Option<preset::Check>,
+    routes: wan::Routes,
+    /// Last list read from the source.
+    loaded: Vec<Target>,
+    groups: HashMap<String, targets::Group>,
+    ips: Vec<Target>,
+}
+
+/// Picks the engine and the target source and loads the targets; probes
+/// are answered by `prober` if given.
+fn start(config: &Config, prober: Option<Prober>) -> Result<Start, RunError> {
+    let routes = wan::Routes::default();
+    let check = config
+        .preset
+        .as_ref()
+        .map(preset::Choice::check)
+        .transpose()?;
+    let mut native_error = None;
+    let (engine, mut source): (Engine, Box<dyn targets::TargetSource>) = match &config.simulate {
+        Some(path) => {
+            let profile = sim::Profile::load(path).map_err(RunError::Profile)?;
+            let source = targets::StaticSource {
+                origin: path.display().to_string(),
+                targets: profile
+                    .targets
+                    .iter()
+                    .map(|t| Target::new(&t.name))
+                    .collect(),
+            };
+            let engine = Engine::Simulated(Arc::new(sim::Simulator::new(&profile)));
+            (engine, Box::new(source))
+        }
+        None => {
+            let native = match config.probing.kind {
+                icmp::EngineKind::System => None,
+                icmp::EngineKind::Native => match native_socket() {
+                    Ok(socket) => Some(socket),
+                    Err(err) => {
+                        native_error = Some(err);
+                        None
+                    }
+                },
+            };
+            let engine = config.probing.engine(native, routes.clone());
+            let refresh = (!config.refresh_targets.is_zero()).then_some(config.refresh_targets);
+            let ip_file =
+                (config.ip_file.clone()).unwrap_or_else(|| config::exe_dir().join("ips.txt"));
+            let source: Box<dyn targets::TargetSource> = if let Some(check) = &check {
+                Box::new(preset::PresetSource {
+                    check: check.clone(),
+                })
+            } else if config.ip_file.is_none()
+                && let Some(targets) = &config.config_targets
+            {
+                Box::new(targets.clone().refreshing(refresh))
+            } else if ip_file == Path::new("-") {
+                Box::new(targets::StdinSource::new(
+                    config.skip_invalid,
+                    config.max_targets,
+                ))
+            } else {
+                if !ip_file.exists() {
+                    return Err(RunError::Targets(format!(
+                        "IP list file not found: {} (default is ips.txt next to executable)",
+                        ip_file.display()
+                    )));
+                }
+                Box::new(targets::FileSource::new(
+                    ip_file,
+                    refresh,
+                    config.skip_invalid,
+                    config.max_targets,
+                ))
+            };
+            (engine, source)
+        }
+    };
+    let engine = prober.map_or(engine, Engine::Custom);
+    let loaded = targets::load(source.as_mut())?;
+    let groups = groups_of(source.as_ref(), config.dual_stack);
+    let ips = rows(loaded.clone(), config.dual_stack);
+    if let Some(family) = config.probing.family {
+        for ip in ips.iter().map(|t| &t.name) {
+            if targets::family_of(ip, None).is_some_and(|f| f != family) {
+                warn!("{ip} is an IP address of the other family; -4/-6 only apply to host names");
+            }
+        }
+    }
+    Ok(Start {
+        engine,
+        source,
+        native_error,
+        check,
+        routes,
+        loaded,
+        groups,
+        ips,
+    })
+}
+
+/// `ping-plotter once`: probes every target once, prints the results and
+/// returns `RunError::Unreachable` for the ones that failed. Only logs
+/// when `--log` is given; scripts calling `once` in a loop would otherwise
+/// fill `result.txt` next to the binary.
+pub fn once(config: &Config) -> Result<(), RunError> {
+    let Start { engine, ips, .. } = start(config, None)?;
+    let log_path = config.log_file.as_deref();
+    let _log_lock = log_path
+        .map(|path| Lock::file(path, config.force))
+        .transpose()?;
+    let _store_lock = (config.store_dir.as_deref())
+        .map(|dir| Lock::dir(dir, config.force))
+        .transpose()?;
+
+    let ts_ms = store::now_ms();
+    let results: Vec<ProbeOutcome> = thread::scope(|scope| {
+        let handles: Vec<_> = ips
+            .iter()
+            .map(|ip| scope.spawn(|| engine.probe(ip)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().unwrap_or(ProbeOutcome::Lost(Loss::Error)))
+            .collect()
+    });
+
+    let mut store_health = sinks::Health::default();
+    let store_dir = config.store_dir.as_deref();
+    let mut sample_store = store_dir.and_then(|dir| open_store(dir, &mut store_health));
+    let mut unreachable = Vec::new();
+    for (target, &outcome) in ips.iter().zip(&results) {
+        let ip = &target.name;
+        let (success, latency_ms) = (outcome.success(), outcome.rtt_ms());
+        let rtt = match outcome {
+            ProbeOutcome::Reply(ms) => format!("{ms:.2} ms"),
+            // Answered, but the RTT could not be read from the output.
+            ProbeOutcome::Unparsed => "?".to_string(),
+            ProbeOutcome::Lost(loss) => loss.label().to_string(),
+        };
+        let state = if success { "ok" } else { "unreachable" };
+        println!("{ip:<20} {state:<12} {rtt:>10}");
+        if !success {
+            unreachable.push(ip.clone());
+        }
+        if let Some(s) = sample_store.as_mut() {
+            let sample = store::Sample {
+                ts_ms,
+                target: ip.clone(),
+                success,
+                latency_ms,
+            };
+            if s.append(&sample).is_err() {
+                error!("Failed to write to sample store; disabling persistence");
+                sample_store = None;
+            }
+        }
+    }
+    if let Some(s) = sample_store.as_mut()
+        && let Err(err) = s.flush()
+    {
+        error!("Failed to flush sample store: {err}");
+        store_health.failed(err);
+    }
+
+    if let Some(path) = log_path
+        && !unreachable.is_empty()
+    {
+        let mut log = LogSink::open(path, config.log_format);
+        let template = config.settings.unreachable_template.as_ref();
+        log.line(&session::unreachable_line(template, 0, &unreachable));
+    }
+
+    if unreachable.is_empty() {
+        Ok(())
+    } else {
+        Err(RunError::Unreachable(unreachable))
+    }
+}
+
+/// Why the monitoring loop ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Exit {
+    Deadline,
+    Quit,
+    Signal,
+    Panic,
+}
+
+/// What the shutdown path takes over from the monitoring loop.
+struct Finish<'a> {
+    config: &'a Config,
+    exit: Exit,
+    monitor: PingMonitor,
+    session: Session,
+    pseudonyms: Option<anonymize::Pseudonyms>,
+    last_display: Vec<String>,
+    outputs: Outputs,
+    intervals: u32,
+}
+
+/// The one shutdown path for a monitoring run, whether it ended at the
+/// deadline, by `q`, a signal or a panic: stops the workers and waits for
+/// their last results, records the rounds still open, then writes the final
+/// state, flushes the store and writes the summary.
+fn finalize(run: Finish) {
+    let Finish {
+        config,
+        exit,
+        monitor,
+        mut session,
+        pseudonyms,
+        last_display,
+        mut outputs,
+        intervals,
+    } = run;
+    info!(?exit, "stopping");
+    let run_ms = monitor.first_tick().elapsed().as_millis() as i64;
+
+    // Outages ending in the last rounds belong in the summary too.
+    for round in session.finish(monitor.stop()) {
+        outputs.record(&round);
+    }
+
+    let log = &mut outputs.log;
+    match exit {
+        Exit::Signal => {
+            log.event("interrupted by signal");
+        }
+        Exit::Panic => log.event("aborted by an internal error; final state may be stale"),
+        Exit::Deadline | Exit::Quit => {}
+    }
+    let header = match &config.final_template {
+        Some(t) => t.render(&[
+            ("time", timestamp()),
+            ("rounds", session.completed().to_string()),
+        ]),
+        None => format!("[{}] Final state:", timestamp()),
+    };
+    log.line(&header);
+    match &config.final_row_template {
+        Some(t) => {
+            for target in &session.targets {
+                let stat = session.stats.get(&target.name).copied().unwrap_or_default();
+                log.line(&final_row(t, &target.name, &stat));
+            }
+        }
+        None => {
+            for line in &last_display {
+                log.line(line);
+            }
+        }
+    }
+    let now_ms = store::now_ms();
+    for line in session.report(now_ms) {
+        log.line(&line);
+    }
+    let highlights = session.highlights(run_ms, now_ms);
+    outputs.flush();
+
+    if let Some(path) = &config.summary {
+        let weights = (config.config_targets.as_ref())
+            .map(|t| t.weights())
+            .unwrap_or_default();
+        let mut summary = session.summary(&weights, store::now_ms());
+        if let Some(mut pseudonyms) = pseudonyms {
+            pseudonyms.summary(&mut summary);
+            if let Err(err) = pseudonyms.save() {
+                error!("{err}");
+            }
+        }
+        if let Err(err) = fs::write(path, summary.to_json()) {
+            error!("failed to write summary to {}: {err}", path.display());
+        }
+    }
+
+    if config.profile_overhead {
+        let totals = overhead::totals();
+        let zero = [Duration::ZERO; 4];
+        println!("Overhead gesamt: {}", overhead::breakdown(&totals, &zero));
+        if intervals > 0 {
+            let avg = totals.map(|d| d / intervals);
+            println!(
+                "Overhead je Intervall ({intervals} Intervalle): {}",
+                overhead::breakdown(&avg, &zero)
+            );
+        }
+    }
+    eprintln!("{highlights}");
+}
+
+/// Like `Terminal::next_input`, but returns early once a control request
+/// arrives, leaving it in `queued`.
+fn next_input_or_request(
+    term: &ui::Terminal,
+    timeout: Duration,
+    requests: &mpsc::Receiver<control::Request>,
+    queued: &mut Vec<control::Request>,
+) -> Option<ui::Input> {
+    let until = Instant::now() + timeout;
+    loop {
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return None;
+        }
+        if let Some(input) = term.next_input(left.min(Duration::from_millis(100))) {
+            return Some(input);
+        }
+        queued.extend(requests.try_iter());
+        if !queued.is_empty() {
+            return None;
+        }
+    }
+}
+
+/// Monitors until the duration ends, the user quits or `stop` is set (the
+/// binary's SIGINT/SIGTERM handler), with probes answered by `prober`
+/// instead of `ping` or the simulation if given; the targets still come
+/// from the list, config or profile. The live view is drawn on `terminal()`,
+/// opened once the run is set up.
+pub fn run(
+    config: Config,
+    prober: Option<Prober>,
+    terminal: impl FnOnce() -> ui::Terminal,
+    stop: &AtomicBool,
+) -> Result<(), RunError> {
+    let log_path =
+        (config.log_file.clone()).unwrap_or_else(|| config::exe_dir().join("result.txt"));
+    let pseudonyms = (config.anonymize)
+        .then(|| anonymize::Pseudonyms::open(config.anonymize_map.as_deref()))
+        .transpose()?;
+    let broadcast = Arc::new(control::Broadcast::default());
+    let (control_tx, control_rx) = mpsc::channel::<control::Request>();
+    // Dropped when `run` returns, which removes the socket file.
+    let _server = match &config.socket {
+        Some(path) => Some(control::serve(path, Arc::clone(&broadcast), control_tx)?),
+        None => None,
+    };
+    let metrics = match config.metrics_listen {
+        Some(addr) => {
+            let metrics = Arc::new(metrics::Metrics::default());
+            let bound = metrics::serve(addr, Arc::clone(&metrics))?;
+            info!("serving metrics on http://{bound}/metrics");
+            Some(metrics)
+        }
+        None => None,
+    };
+    let mut heartbeat = (config.heartbeat_url.clone())
+        .map(|url| heartbeat::Heartbeat::new(url, config.heartbeat_every));
+
+    let Start {
+        engine,
+        mut source,
+        native_error,
+        check,
+        routes,
+        mut loaded,
+        mut groups,
+        mut ips,
+    } = start(&config, prober)?;
+
+    // Held until the run returns.
+    let _log_lock = Lock::file(&log_path, config.force)?;
+    let _store_lock = (config.store_dir.as_deref())
+        .map(|dir| Lock::dir(dir, config.force))
+        .transpose()?;
+
+    info!(
+        "monitoring {} targets from {}, logging to {}",
+        ips.len(),
+        source.describe(),
+        log_path.display()
+    );
+
+    if config.profile_overhead {
+        overhead::enable();
+    }
+    // Host names are looked up before the first probe; simulated and
+    // custom probes never see an address.
+    let (mut resolver, resolved_at_start) = match &engine {
+        Engine::System { .. } => {
+            let (every, family) = (config.resolve_every, config.probing.family);
+            let (resolver, changes) = resolver::Resolver::start(&ips, every, family);
+            (Some(resolver), changes)
+        }
+        _ => (None, Vec::new()),
+    };
+    for change in &resolved_at_start {
+        change.apply(&mut ips);
+    }
+    let clock: Arc<dyn Clock> = Arc::new(clock::SystemClock);
+    let schedule = config.schedule.clone().map(Arc::new);
+    let mut monitor = PingMonitor::start(
+        &ips,
+        crate::Options {
+            interval: config.interval,
+            run_for: config.run_for,
+            schedule: schedule.clone(),
+            engine,
+            clock: Arc::clone(&clock),
+        },
+    );
+    let (first_tick, deadline) = (monitor.first_tick(), monitor.deadline());
+    let disabled = Arc::clone(monitor.disabled());
+    let held = Arc::clone(monitor.held());
+    let mut next_refresh = source.refresh_interval().map(|d| Instant::now() + d);
+    // A new target list to switch to (from the source or edited in the TUI).
+    let mut pending: Option<Vec<Target>> = None;
+    let missing = routes.rebind(&config.bindings, &groups);
+    if !missing.is_empty() {
+        return Err(RunError::Targets(format!(
+            "--group names groups not found in {}: {} (a comment at the start of a group \
+             names it)",
+            source.describe(),
+            missing.join(", ")
+        )));
+    }
+
+    let mut session = Session::new(ips, config.settings.clone(), Arc::clone(&disabled));
+    let columns = ui::Columns {
+        smooth: config.settings.smooth.is_some(),
+        loss_windows: config.settings.loss_windows,
+        window: config.settings.window,
+    };
+    let mut paused = false;
+    let mut diagnosed = preset::Diagnosis::Ok;
+    let mut selected: usize = 0;
+    // Text typed after `a` or `n`; `notice` reports the outcome of TUI edits.
+    let mut prompt: Option<(PromptKind, String)> = None;
+    let mut notice = String::new();
+    let mut queued: Vec<control::Request> = Vec::new();
+    let mut view = View::Table;
+    // Detail panel for the selected row instead of the table; `x` expands
+    // its failure breakdown.
+    let mut show_detail = false;
+    let mut detail_expanded = false;
+    // `f` stops redrawing so text can be selected; probing and logging go on.
+    // The frozen screen is drawn once with a hint in the footer.
+    let mut frozen = false;
+    let mut frozen_drawn = false;
+    // Set by clicking a table header: column and whether descending.
+    let mut sort: Option<(SortColumn, bool)> = None;
+    let numbers = if config.locale_numbers {
+        locale::Numbers::from_env()
+    } else {
+        locale::Numbers::default()
+    };
+    let mut timeline = events::Timeline::default();
+    // The events view follows new events until scrolled up.
+    let mut event_cursor: usize = 0;
+    let mut follow = true;
+    let mut last_display: Vec<String> = Vec::new();
+    let mut log = LogSink::open(&log_path, config.log_format);
+    let mut csv = (config.csv.as_deref()).map_or_else(CsvWriter::disabled, CsvWriter::open);
+    if let Some(retention) = config.retention {
+        log.set_retention(retention);
+        csv.set_retention(retention);
+    }
+    let mut disk_dirs = vec![disk::dir_of(&log_path)];
+    disk_dirs.extend(config.csv.as_deref().map(disk::dir_of));
+    disk_dirs.extend(config.store_dir.clone());
+    let mut disk_guard = disk::Guard::new(config.min_free_space * 1_000_000, disk_dirs);
+    let mut store_health = sinks::Health::default();
+    let store = (config.store_dir.as_deref()).and_then(|dir| open_store(dir, &mut store_health));
+    if let (Some(s), Some(retention)) = (store.as_ref(), config.retention) {
+        s.spawn_pruner(retention);
+    }
+    let mut outputs = Outputs {
+        log,
+        csv,
+        store,
+        store_health,
+        raw: true,
+    };
+    for change in &resolved_at_start {
+        emit(&session::dns_events(change), &mut outputs, &mut timeline);
+    }
+    // Exports and reports of the store show the time since the last run
+    // as a gap.
+    match outputs.store.as_mut().map(store::Store::start_run) {
+        Some(Ok(Some(end_ms))) if store::now_ms() - end_ms >= store::GAP_MIN_MS => {
+            outputs.log.event(&format!(
+                "monitor was not running for {}s (since {})",
+                (store::now_ms() - end_ms) / 1000,
+                locale::iso_ms(end_ms)
+            ));
+        }
+        Some(Err(err)) => error!("Failed to record the run in the sample store: {err}"),
+        _ => {}
+    }
+
+    let mut overhead_prev = overhead::totals();
+    let mut overhead_line = String::new();
+    let mut intervals: u32 = 0;
+    let term = terminal();
+    let mut next_render = first_tick;
+    // A panic in the loop still ends in `finalize`, like every other exit.
+    let exit = panic::catch_unwind(AssertUnwindSafe(|| {
+        loop {
+            if stop.load(atomic::Ordering::SeqCst) {
+                break Exit::Signal;
+            }
+            if let Some(due) = next_refresh
+                && Instant::now() >= due
+            {
+                next_refresh = source.refresh_interval().map(|d| Instant::now() + d);
+                match source.load() {
+                    // Only changes of the source count, so keys pressed in the
+                    // TUI survive refreshes until the file itself is edited.
+                    Ok(new) if !new.is_empty() => {
+                        let changed = if new != loaded {
+                            "changed"
+                        } else {
+                            "unchanged"
+                        };
+                        debuglog::record(format!("reloaded {}: {changed}", source.describe()));
+                        if new != loaded {
+                            loaded = new.clone();
+                            pending = Some(new);
+                        }
+                    }
+                    Ok(_) => {
+                        debuglog::record(format!("reloaded {}: no targets", source.describe()));
+                        warn!(
+                            "{} lists no targets; keeping the current ones",
+                            source.describe()
+                        )
+                    }
+                    Err(err) => {
+                        debuglog::record(format!("reload failed: {err}"));
+                        warn!("{err}; keeping the current targets")
+                    }
+                }
+            }
+            if let Some(new) = pending.take() {
+                groups = groups_of(source.as_ref(), config.dual_stack);
+                let events = session.retarget(&mut monitor, rows(new, config.dual_stack));
+                emit(&events, &mut outputs, &mut timeline);
+                if let Some(resolver) = resolver.as_mut() {
+                    resolver.refresh_soon();
+                }
+                for group in routes.rebind(&config.bindings, &groups) {
+                    warn!(
+                        "group {group} from --group is no longer in {}",
+                        source.describe()
+                    );
+                }
+            }
+            let changes = resolver
+                .as_mut()
+                .map(|r| r.poll(Instant::now(), &session.targets));
+            for change in changes.unwrap_or_default() {
+                info!("{}", change.text());
+                let events = session.resolved(&monitor, &change);
+                emit(&events, &mut outputs, &mut timeline);
+            }
+            if let Some(heartbeat) = heartbeat.as_mut() {
+                if let Some(text) = heartbeat.poll() {
+                    info!("{text}");
+                    outputs.log.event(&format!("HEARTBEAT: {text}"));
+                }
+                if heartbeat.due(Instant::now()) {
+                    let health = &session.health;
+                    let down = (session.targets.iter())
+                        .map(|t| t.name.as_str())
+                        .filter(|ip| health.get(*ip).is_some_and(|h| h.failing_since().is_some()))
+                        .collect();
+                    let body =
+                        heartbeat::Body::new(session.targets.len(), down, session.completed());
+                    heartbeat.send(Instant::now(), &body);
+                }
+            }
+            if let Some(change) = disk_guard.check(clock.now()) {
+                let text = change.text();
+                warn!("{text}");
+                outputs.log.event(&format!("DISK: {text}"));
+                timeline.push(store::now_ms(), Kind::Disk, None, text);
+            }
+            outputs.raw = !disk_guard.is_low();
+            for round in session.poll(&monitor) {
+                outputs.record(&round);
+                show(&round.events, &mut timeline);
+            }
+
+            if let Some(check) = &check {
+                let health = &session.health;
+                let up = |t: &str| health.get(t).is_none_or(|h| h.failing_since().is_none());
+                let diagnosis = check.diagnose(up);
+                if diagnosis != diagnosed {
+                    let legs: Vec<String> = check
+                        .legs()
+                        .iter()
+                        .map(|(t, _)| format!("{t} {}", if up(t) { "up" } else { "down" }))
+                        .collect();
+                    let text = format!(
+                        "{}: {} ({})",
+                        check.name(),
+                        diagnosis.label(),
+                        legs.join(", ")
+                    );
+                    outputs.log.event(&text);
+                    timeline.push(store::now_ms(), Kind::Connectivity, None, text);
+                    diagnosed = diagnosis;
+                }
+            }
+
+            if let Some(sched) = schedule.as_deref() {
+                let now_paused = !sched.is_active(&Local::now());
+                if now_paused != paused {
+                    let state = if now_paused {
+                        "paused (outside schedule)"
+                    } else {
+                        "resumed (schedule active)"
+                    };
+                    outputs.log.event(state);
+                    debuglog::record(state);
+                    timeline.push(store::now_ms(), Kind::Schedule, None, state.to_string());
+                    paused = now_paused;
+                }
+            }
+
+            // Severity is judged before drawing, so every view shows it current.
+            let now_ms = store::now_ms();
+            let events = session.severity_changes(now_ms);
+            emit(&events, &mut outputs, &mut timeline);
+            let ips = &session.targets;
+            let (rules, warmup) = (session.settings.rules, session.settings.warmup);
+            let shown = session.shown();
+            let mut status = format!("Runde {}", session.completed());
+            if let Some(since_ms) = session.local.since() {
+                let secs = (store::now_ms() - since_ms) / 1000;
+                status.push_str(&format!(
+                    " – LOCAL-DOWN seit {secs}s: kein Netzwerk auf diesem Rechner, nicht gezählt"
+                ));
+            }
+            if held.load(atomic::Ordering::SeqCst) {
+                status.push_str(" – pausiert (über den Steuer-Socket)");
+            } else if paused {
+                status.push_str(" – pausiert (außerhalb des Zeitplans)");
+            }
+            if session.in_warmup() {
+                let probed = session.probed_rounds;
+                status.push_str(&format!(
+                    " (Warm-up: {probed} von {warmup} Runden, nicht in der Statistik)"
+                ));
+            }
+            if let Some(check) = &check {
+                status.push_str(&format!(" – {}: {}", check.title(), diagnosed.text()));
+            }
+            if let Some((column, descending)) = sort {
+                let direction = if descending {
+                    "absteigend"
+                } else {
+                    "aufsteigend"
+                };
+                status.push_str(&format!(" – sortiert nach {} {direction}", column.title()));
+            }
+
+            let disabled_now = disabled.lock().map(|d| d.clone()).unwrap_or_default();
+            let table = ui::Table::new(
+                &session,
+                &groups,
+                &disabled_now,
+                (sort, columns),
+                numbers,
+                now_ms,
+            );
+            let order = table.order;
+            selected = selected.min(order.len() - 1);
+            let mut screen = vec![ui::Line::plain(status)];
+            screen.extend(table.lines);
+            // Screen lines of the header and of each row, for mouse clicks.
+            let mut header_line = 1;
+            let mut row_lines: Vec<(usize, usize)> = table
+                .rows
+                .iter()
+                .enumerate()
+                .map(|(row, line)| (line + 1, row))
+                .collect();
+            if term.interactive() {
+                screen[row_lines[selected].0].selected = true;
+            }
+
+            if config.probe_stats {
+                screen.push(ui::Line::plain(String::new()));
+                let lines = budget::lines(ips, &session.budgets);
+                screen.extend(lines.into_iter().map(ui::Line::plain));
+            }
+            if !config.jitter_buffers.is_empty() {
+                screen.push(ui::Line::plain(String::new()));
+                let lines = jitter::lines(ips, &session.playouts, &config.jitter_buffers, numbers);
+                screen.extend(lines.into_iter().map(ui::Line::plain));
+            }
+            if let Some(limits) = &session.settings.limits {
+                screen.push(ui::Line::plain(String::new()));
+                let lines = quality::lines(ips, &session.qualities, limits, numbers);
+                screen.extend(lines.into_iter().map(ui::Line::plain));
+            }
+            if !config.bindings.is_empty() {
+                screen.push(ui::Line::plain(String::new()));
+                let lines = routes.comparison(&config.bindings, shown, numbers);
+                screen.extend(lines.into_iter().map(ui::Line::plain));
+            }
+
+            last_display.clear();
+            last_display.extend(screen.iter().map(|l| l.text.clone()));
+            // The sparklines are for the screen only; the final state in the log
+            // keeps the plain table.
+            screen[1].text.push_str(" Verlauf");
+            for (line, sparkline) in table.sparklines {
+                screen[line + 1].text.push(' ');
+                screen[line + 1].text.push_str(&sparkline);
+            }
+            if config.socket.is_some() {
+                broadcast.publish(&screen);
+            }
+            if let Some(metrics) = &metrics {
+                metrics.publish(ips.iter().map(|t| &t.name).map(|ip| {
+                    let stat = shown.get(ip).copied().unwrap_or_default();
+                    let last = session.histories.get(ip).and_then(|h| h.recent(1).next());
+                    (ip.as_str(), stat, last.map(|l| l.1))
+                }));
+            }
+
+            // The table is still built above for the other views: the final
+            // state and the control socket show it either way.
+            if view != View::Table || show_detail {
+                screen.truncate(1);
+                row_lines.clear();
+            }
+            match view {
+                View::Table if show_detail => {
+                    let ip = &order[selected].name;
+                    let panel = detail::TargetDetail {
+                        target: ip,
+                        address: order[selected].address,
+                        stats: shown.get(ip).copied().unwrap_or_default(),
+                        severity: session.severities.get(ip).copied().unwrap_or_default(),
+                        health: session.health.get(ip),
+                        rules: &rules,
+                        auto_disable_after: session.settings.auto_disable_after,
+                        acked: session.acked.contains(ip),
+                        disabled: disabled_now.contains(ip),
+                        outages: &session.outages,
+                        history: session.histories.get(ip),
+                        timeline: &timeline,
+                        numbers,
+                        now_ms,
+                        expanded: detail_expanded,
+                    };
+                    screen.extend(panel.lines());
+                }
+                View::Table => {}
+                View::Graphs => {
+                    screen.push(ui::Line::plain(format!(
+                        "Latenz der letzten {} Proben (× verloren, ? ohne RTT):",
+                        graph::WIDTH
+                    )));
+                    for ip in ips.iter().map(|t| &t.name) {
+                        let history = session.histories.get(ip).cloned().unwrap_or_default();
+                        let range = history.range().map_or_else(
+                            || "-".to_string(),
+                            |(lo, hi)| {
+                                format!("{}–{} ms", numbers.fixed(lo, 1), numbers.fixed(hi, 1))
+                            },
+                        );
+                        let text = format!(
+                            "{ip:<20} {:<width$} {range}",
+                            history.sparkline(),
+                            width = graph::WIDTH
+                        );
+                        let severity = session.severities.get(ip).copied().unwrap_or_default();
+                        screen.push(ui::Line {
+                            text,
+                            style: ui::Style::of(severity),
+                            selected: false,
+                        });
+                    }
+                }
+                View::Events => {
+                    if follow {
+                        event_cursor = timeline.len().saturating_sub(1);
+                    }
+                    screen.push(ui::Line::plain(format!("Ereignisse ({}):", timeline.len())));
+                    screen.extend(timeline.lines(
+                        event_cursor,
+                        ui::EVENTS_PAGE,
+                        term.interactive(),
+                    ));
+                }
+                View::Config => {
+                    let health_of = |configured: bool, open: bool| match (configured, open) {
+                        (false, _) => "aus",
+                        (true, true) => "ok",
+                        (true, false) => "Fehler, deaktiviert",
+                    };
+                    let refresh = source
+                        .refresh_interval()
+                        .map_or_else(|| "nie".to_string(), |d| format!("alle {}s", d.as_secs()));
+                    let engine = match (&config.simulate, monitor.engine()) {
+                        (Some(path), _) => format!("Simulation ({})", path.display()),
+                        (
+                            None,
+                            Engine::System {
+                                native: Some(socket),
+                                ..
+                            },
+                        ) => format!(
+                            "nativ ({}, IPv6 und --group über System-ping)",
+                            socket.describe()
+                        ),
+                        (None, _) => match &native_error {
+                            Some(err) => format!("System-ping (nativ nicht möglich: {err})"),
+                            None => "System-ping".to_string(),
+                        },
+                    };
+                    let (mut intended, mut sent, mut delay_sum, mut max_delay) =
+                        (0, 0, 0.0, 0.0f64);
+                    for b in session.budgets.values() {
+                        intended += b.intended();
+                        sent += b.sent;
+                        delay_sum += b.avg_delay_ms().unwrap_or(0.0) * b.sent as f64;
+                        max_delay = max_delay.max(b.max_delay_ms);
+                    }
+                    let avg_delay = if sent > 0 {
+                        delay_sum / sent as f64
+                    } else {
+                        0.0
+                    };
+                    let schedule_state = match (&schedule, paused) {
+                        (None, _) => "immer aktiv",
+                        (Some(_), false) => "aktiv",
+                        (Some(_), true) => "pausiert",
+                    };
+                    let store_state =
+                        health_of(config.store_dir.is_some(), outputs.store.is_some());
+                    for line in [
+                        format!(
+                            "Ziele:        {} aktiv, {} deaktiviert, {} quittiert",
+                            ips.len() - disabled_now.len(),
+                            disabled_now.len(),
+                            session.acked.len()
+                        ),
+                        format!(
+                            "Zielquelle:   {} (neu einlesen {refresh})",
+                            source.describe()
+                        ),
+                        format!("Messung:      {engine}"),
+                        format!(
+                            "Log:          {} – {}",
+                            log_path.display(),
+                            health_of(true, outputs.log.is_open())
+                        ),
+                        format!(
+                            "Speicher:     {}{store_state}",
+                            (config.store_dir.as_ref())
+                                .map(|d| format!("{} – ", d.display()))
+                                .unwrap_or_default()
+                        ),
+                        format!(
+                            "Summary:      {}",
+                            config.summary.as_ref().map_or_else(
+                                || "aus".to_string(),
+                                |p| format!("{} (beim Beenden)", p.display())
+                            )
+                        ),
+                        format!("Zeitplan:     {schedule_state}"),
+                        format!(
+                            "Takt:         {:?} (Timeout {:?}), {} Runden, Warm-up {}/{warmup}",
+                            config.interval,
+                            config.probing.timeout,
+                            session.completed(),
+                            session.probed_rounds.min(warmup)
+                        ),
+                        format!(
+                            "Proben:       {sent} von {intended} gesendet, Verzögerung \
+                         avg {avg_delay:.1} ms, max {max_delay:.1} ms"
+                        ),
+                        format!(
+                            "Schwellen:    Warnung ab {:.0}% Verlust in {}s, kritisch nach {}s",
+                            rules.warning_loss_pct,
+                            rules.window.as_secs(),
+                            rules.critical_after.as_secs()
+                        ),
+                    ] {
+                        screen.push(ui::Line::plain(line));
+                    }
+                    // The full log is `status --debug`; the pane shows the tail.
+                    screen.push(ui::Line::plain(String::new()));
+                    screen.push(ui::Line::plain("Interne Abläufe:".to_string()));
+                    for line in debuglog::recent(10) {
+                        screen.push(ui::Line::plain(format!("  {line}")));
+                    }
+                }
+            }
+            if term.interactive() {
+                screen.insert(0, ui::Line::plain(ui::tab_bar(view)));
+                header_line += 1;
+                for (line, _) in &mut row_lines {
+                    *line += 1;
+                }
+            }
+
+            if config.profile_overhead {
+                screen.push(ui::Line::plain(String::new()));
+                screen.push(ui::Line::plain(format!(
+                    "Overhead im letzten Intervall: {overhead_line}"
+                )));
+            }
+
+            // Snapshots show the table without the footers.
+            let snapshot_len = screen.len();
+            screen.push(ui::Line::plain(String::new()));
+            let metrics_health = metrics.as_ref().map(|m| m.health());
+            let configured = configured_outputs(
+                &config,
+                &outputs.log,
+                &outputs.csv,
+                &outputs.store_health,
+                metrics_health.as_ref(),
+                heartbeat.as_ref(),
+            );
+            screen.push(sinks::footer(&configured, store::now_ms()));
+            if term.interactive() {
+                if !notice.is_empty() {
+                    screen.push(ui::Line::plain(notice.clone()));
+                }
+                screen.push(ui::Line::plain(ui::key_hint(prompt.as_ref(), view, frozen)));
+            }
+            if !(frozen && frozen_drawn) {
+                let now = clock.now();
+                let remaining = deadline.map(|end| end.saturating_duration_since(now));
+                let elapsed = now.saturating_duration_since(first_tick);
+                term.set_status(ui::status_bar(elapsed, remaining));
+                overhead::measure(Phase::Render, || term.draw(&screen));
+            }
+            frozen_drawn = frozen;
+
+            let requests: Vec<control::Request> =
+                queued.drain(..).chain(control_rx.try_iter()).collect();
+            for request in requests {
+                let base = pending.clone().unwrap_or_else(|| ips.clone());
+                let result = match &request.command {
+                    control::Command::Status(only) => {
+                        let listed: Vec<&Target> = ips
+                            .iter()
+                            .filter(|t| only.as_ref().is_none_or(|name| *name == t.name))
+                            .collect();
+                        for target in &listed {
+                            let ip = &target.name;
+                            let stat = shown.get(ip).copied().unwrap_or_default();
+                            let last = session.histories.get(ip).and_then(|h| h.recent(1).next());
+                            let sev = session.severities.get(ip).copied().unwrap_or_default();
+                            let state = session
+                                .states
+                                .get(ip)
+                                .map(|s| s.state())
+                                .unwrap_or_default();
+                            let mut line = control::status_line(
+                                ip,
+                                &stat,
+                                last.map(|l| l.1),
+                                sev.label(),
+                                state.label(),
+                            );
+                            if let Some(addr) = target.address {
+                                line.push_str(&format!(" addr={addr}"));
+                            }
+                            request.line(line);
+                        }
+                        if only.is_none() {
+                            let metrics_health = metrics.as_ref().map(|m| m.health());
+                            let now_ms = store::now_ms();
+                            let metrics_health = metrics_health.as_ref();
+                            let configured = configured_outputs(
+                                &config,
+                                &outputs.log,
+                                &outputs.csv,
+                                &outputs.store_health,
+                                metrics_health,
+                                heartbeat.as_ref(),
+                            );
+                            for (kind, health) in configured {
+                                request.line(sinks::status_line(kind, health, now_ms));
+                            }
+                        }
+                        match only {
+                            Some(target) if listed.is_empty() => {
+                                Err(format!("{target} is not monitored"))
+                            }
+                            _ => Ok(()),
+                        }
+                    }
+                    control::Command::Add(input) => match targets::Entry::parse(input) {
+                        Ok(target) if base.iter().any(|t| t.name == target.name()) => {
+                            Err(format!("{} is already monitored", target.name()))
+                        }
+                        Ok(target) => {
+                            let mut new = base;
+                            new.push(Target::new(target.name()));
+                            pending = Some(new);
+                            Ok(())
+                        }
+                        Err(err) => Err(format!("invalid target '{input}': {err}")),
+                    },
+                    control::Command::Remove(target) if !base.iter().any(|t| t.name == *target) => {
+                        Err(format!("{target} is not monitored"))
+                    }
+                    // The loop needs at least one target.
+                    control::Command::Remove(_) if base.len() == 1 => {
+                        Err("the last target can't be removed".to_string())
+                    }
+                    control::Command::Remove(target) => {
+                        pending = Some(base.into_iter().filter(|t| t.name != *target).collect());
+                        Ok(())
+                    }
+                    control::Command::Pause | control::Command::Resume => {
+                        let pause = request.command == control::Command::Pause;
+                        if held.swap(pause, atomic::Ordering::SeqCst) != pause {
+                            let state = if pause {
+                                "paused (control socket)"
+                            } else {
+                                "resumed (control socket)"
+                            };
+                            outputs.log.event(state);
+                            debuglog::record(state);
+                            timeline.push(store::now_ms(), Kind::Schedule, None, state.to_string());
+                        }
+                        Ok(())
+                    }
+                    control::Command::Snapshot => {
+                        match config.snapshot_format.write(&screen[..snapshot_len]) {
+                            Ok(path) => {
+                                request.line(path.display().to_string());
+                                Ok(())
+                            }
+                            Err(err) => Err(format!("snapshot failed: {err}")),
+                        }
+                    }
+                    control::Command::Reload => {
+                        debuglog::record("reload requested (control socket)");
+                        next_refresh = Some(Instant::now());
+                        Ok(())
+                    }
+                    control::Command::Debug => {
+                        for line in debuglog::recent(usize::MAX) {
+                            request.line(line);
+                        }
+                        Ok(())
+                    }
+                };
+                request.done(result);
+            }
+
+            let now = clock.now();
+            if let Some(end) = deadline
+                && now >= end
+            {
+                break Exit::Deadline;
+            }
+            if now < next_render {
+                let mut wait = next_render - now;
+                let mut last_wait = false;
+                if let Some(end) = deadline
+                    && now + wait >= end
+                {
+                    wait = end - now;
+                    last_wait = true;
+                }
+                let input = if config.socket.is_some() {
+                    next_input_or_request(&term, wait, &control_rx, &mut queued)
+                } else {
+                    term.next_input(wait)
+                };
+                // Answer socket clients right away instead of at the next tick.
+                if input.is_none() && !queued.is_empty() {
+                    continue;
+                }
+                if let Some(ui::Input::Click { column, row }) = input {
+                    let line = term.line_at(row);
+                    // Only the table reacts to clicks.
+                    let table = prompt.is_none() && view == View::Table && !show_detail;
+                    if table && line == header_line {
+                        if let Some(c) = columns.at(usize::from(column)) {
+                            sort = ui::next_sort(sort, c);
+                        }
+                    } else if table && let Some((_, r)) = row_lines.iter().find(|(l, _)| *l == line)
+                    {
+                        selected = *r;
+                        show_detail = true;
+                    }
+                    continue;
+                }
+                if let Some(ui::Input::Key(key)) = input {
+                    if let Some((kind, input)) = prompt.as_mut() {
+                        match ui::edit_prompt(key, input) {
+                            PromptAction::Edit => {}
+                            PromptAction::Cancel => prompt = None,
+                            PromptAction::Submit if *kind == PromptKind::Note => {
+                                let text = input.trim().to_string();
+                                prompt = None;
+                                if !text.is_empty() {
+                                    outputs.log.event(&format!("NOTE: {text}"));
+                                    timeline.push(store::now_ms(), Kind::Note, None, text);
+                                }
+                            }
+                            PromptAction::Submit => {
+                                let input = prompt.take().map(|(_, i)| i).unwrap_or_default();
+                                match targets::Entry::parse(input.trim()) {
+                                    Ok(target) if ips.iter().any(|t| t.name == target.name()) => {
+                                        notice =
+                                            format!("{} wird bereits überwacht", target.name());
+                                    }
+                                    Ok(target) => {
+                                        let mut new = ips.clone();
+                                        new.push(Target::new(target.name()));
+                                        pending = Some(new);
+                                        notice.clear();
+                                    }
+                                    Err(err) => {
+                                        notice = format!("Ungültiges Ziel '{input}': {err}")
+                                    }
+                                }
+                            }
+                        }
+                        continue;
+                    }
+                    // Only `f` and quitting work while frozen; anything else would
+                    // change a screen that is not shown.
+                    if frozen {
+                        match ui::handle_key(key, &mut 0, 0) {
+                            KeyAction::Quit => break Exit::Quit,
+                            KeyAction::Freeze => {
+                                frozen = false;
+                                term.capture_mouse(true);
+                            }
+                            _ => {}
+                        }
+                        continue;
+                    }
+                    if show_detail && matches!(key.code, KeyCode::Esc | KeyCode::Enter) {
+                        show_detail = false;
+                        continue;
+                    }
+                    if show_detail && key.code == KeyCode::Char('x') {
+                        detail_expanded = !detail_expanded;
+                        continue;
+                    }
+                    let action = match view {
+                        View::Events => {
+                            let action = ui::handle_key(key, &mut event_cursor, timeline.len());
+                            follow = event_cursor + 1 >= timeline.len();
+                            action
+                        }
+                        _ => ui::handle_key(key, &mut selected, ips.len()),
+                    };
+                    match action {
+                        KeyAction::Quit => break Exit::Quit,
+                        KeyAction::Freeze => {
+                            frozen = true;
+                            term.capture_mouse(false);
+                        }
+                        // Row actions only apply to the table.
+                        KeyAction::ToggleAck | KeyAction::Enable | KeyAction::Delete
+                            if view != View::Table => {}
+                        KeyAction::ToggleAck if !disabled_now.contains(&order[selected].name) => {
+                            let ip = order[selected].name.clone();
+                            let events = session.toggle_ack(&ip, store::now_ms());
+                            emit(&events, &mut outputs, &mut timeline);
+                        }
+                        KeyAction::Enable => {
+                            let ip = order[selected].name.clone();
+                            let events = session.enable(&ip, store::now_ms());
+                            emit(&events, &mut outputs, &mut timeline);
+                        }
+                        KeyAction::Add => {
+                            prompt = Some((PromptKind::Target, String::new()));
+                            notice.clear();
+                        }
+                        KeyAction::Note => {
+                            prompt = Some((PromptKind::Note, String::new()));
+                            notice.clear();
+                        }
+                        KeyAction::NextView => view = view.next(),
+                        KeyAction::PageUp => term.scroll_page(false),
+                        KeyAction::PageDown => term.scroll_page(true),
+                        KeyAction::ShowView(v) => view = v,
+                        KeyAction::Detail if view == View::Table => show_detail = true,
+                        // The loop needs at least one target.
+                        KeyAction::Delete if ips.len() > 1 => {
+                            let ip = &order[selected].name;
+                            pending = Some(ips.iter().filter(|t| t.name != *ip).cloned().collect());
+                            notice.clear();
+                        }
+                        KeyAction::Save => {
+                            notice = match source.save(ips) {
+                                Ok(()) => {
+                                    loaded = ips.clone();
+                                    groups = groups_of(source.as_ref(), config.dual_stack);
+                                    routes.rebind(&config.bindings, &groups);
+                                    format!(
+                                        "{} Ziele in {} gespeichert",
+                                        ips.len(),
+                                        source.describe()
+                                    )
+                                }
+                                Err(err) => format!("Speichern fehlgeschlagen: {err}"),
+                            };
+                        }
+                        KeyAction::Snapshot => {
+                            notice = match config.snapshot_format.write(&screen[..snapshot_len]) {
+                                Ok(path) => {
+                                    format!("Schnappschuss gespeichert: {}", path.display())
+                                }
+                                Err(err) => format!("Schnappschuss fehlgeschlagen: {err}"),
+                            };
+                        }
+                        KeyAction::ToggleAck
+                        | KeyAction::Delete
+                        | KeyAction::Detail
+                        | KeyAction::Redraw => {}
+                    }
+                    // Redraw right away; the render tick itself is unchanged.
+                    continue;
+                }
+                if last_wait {
+                    break Exit::Deadline;
+                }
+            }
+            next_render += config.interval;
+            let totals = overhead::totals();
+            overhead_line = overhead::breakdown(&totals, &overhead_prev);
+            overhead_prev = totals;
+            intervals += 1;
+        }
+    }))
+    .unwrap_or(Exit::Panic);
+    drop(term);
+    finalize(Finish {
+        config: &config,
+        exit,
+        monitor,
+        session,
+        pseudonyms,
+        last_display,
+        outputs,
+        intervals,
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{report, updown};
+    use std::sync::Mutex;
+
+    /// Prober playing a per-target script of `up`/`down` words; the last
+    /// word repeats once the script is used up.
+    fn scripted(script: &[(&str, &str)]) -> Prober {
+        let steps: HashMap<String, Mutex<Vec<&'static str>>> = script
+            .iter()
+            .map(|(target, words)| {
+                let mut words: Vec<&'static str> = words
+                    .split_whitespace()
+                    .map(|w| if w == "up" { "up" } else { "down" })
+                    .collect();
+                words.reverse();
+                (target.to_string(), Mutex::new(words))
+            })
+            .collect();
+        Arc::new(move |target| {
+            let mut words = steps[target].lock().unwrap();
+            let word = if words.len() > 1 {
+                words.pop()
+            } else {
+                words.last().copied()
+            };
+            match word {
+                Some("up") => ProbeOutcome::Reply(1.0),
+                _ => ProbeOutcome::Lost(Loss::Timeout),
+            }
+        })
+    }
+
+    /// Runs `run` for `seconds` on profile targets answered by
+    /// `scripted(script)` and returns the log and the summary.
+    fn run_scripted(script: &[(&str, &str)], seconds: u64) -> (String, report::Summary) {
+        run_scripted_with(script, seconds, session::Settings::default())
+    }
+
+    /// `run_scripted` with `settings`.
+    fn run_scripted_with(
+        script: &[(&str, &str)],
+        seconds: u64,
+        settings: session::Settings,
+    ) -> (String, report::Summary) {
+        let dir = std::env::temp_dir();
+        let unique = format!(
+            "{}_{}_{}",
+            std::process::id(),
+            store::now_ms(),
+            script.len()
+        );
+        let profile = dir.join(format!("scripted_{unique}.toml"));
+        let log = dir.join(format!("scripted_{unique}.log"));
+        let summary = dir.join(format!("scripted_{unique}.json"));
+        let targets: String = script
+            .iter()
+            .map(|(name, _)| format!("[[target]]\nname = \"{name}\"\nlatency_ms = 1.0\n"))
+            .collect();
+        fs::write(&profile, targets).unwrap();
+        let config = Config {
+            settings,
+            run_for: Some(Duration::from_secs(seconds)),
+            simulate: Some(profile.clone()),
+            log_file: Some(log.clone()),
+            summary: Some(summary.clone()),
+            ..Config::default()
+        };
+        let terminal = || ui::Terminal::offscreen(200, 50);
+        run(config, Some(scripted(script)), terminal, &AtomicBool::new(false)).unwrap();
+        let logged = fs::read_to_string(&log).unwrap();
+        let written = fs::read_to_string(&summary).unwrap();
+        for path in [&profile, &log, &summary] {
+            let _ = fs::remove_file(path);
+        }
+        (logged, serde_json::from_str(&written).unwrap())
+    }
+
+    #[test]
+    fn scripted_probes_drive_log_and_summary() {
+        let (logged, summary) = run_scripted(&[("a", "up down up"), ("b", "up")], 5);
+        let unreachable: Vec<&str> = logged
+            .lines()
+            .filter(|l| l.contains("] unreachable:"))
+            .collect();
+        assert_eq!(unreachable.len(), 1, "{logged}");
+        assert!(unreachable[0].ends_with("unreachable: a"), "{logged}");
+        assert!(!logged.contains("connectivity"), "{logged}");
+        assert_eq!(summary.rounds, 3);
+        let a = summary.targets.iter().find(|t| t.target == "a").unwrap();
+        assert_eq!((a.stats.success, a.stats.total), (2, 3));
+        // One lost probe is below `--down-after`: no outage, no downtime.
+        assert!(summary.outages.is_empty(), "{logged}");
+        assert_eq!((a.availability_pct, a.downtime_ms), (Some(100.0), 0));
+        assert!(!logged.contains("] DOWN "), "{logged}");
+    }
+
+    #[test]
+    fn warmup_losses_are_left_out_of_outages_and_availability() {
+        let script = [("a", "down down up"), ("b", "up")];
+        let settings = session::Settings {
+            warmup: 2,
+            ..session::Settings::default()
+        };
+        let (logged, summary) = run_scripted_with(&script, 7, settings);
+        assert_eq!(summary.rounds, 4);
+        assert!(summary.outages.is_empty(), "{logged}");
+        let a = summary.targets.iter().find(|t| t.target == "a").unwrap();
+        assert_eq!((a.stats.success, a.stats.total), (2, 2));
+        assert_eq!((a.outage_count, a.downtime_ms), (0, 0));
+        assert_eq!(a.availability_pct, Some(100.0));
+        assert!(!logged.contains("] DOWN "), "{logged}");
+    }
+
+    #[test]
+    fn simultaneous_failures_raise_one_connectivity_event() {
+        let script = [
+            ("a", "up down up"),
+            ("b", "up down up"),
+            ("c", "up down up"),
+        ];
+        let settings = session::Settings {
+            thresholds: updown::Thresholds {
+                down_after: 1,
+                up_after: 1,
+            },
+            ..session::Settings::default()
+        };
+        let (logged, summary) = run_scripted_with(&script, 5, settings);
+        assert!(!logged.contains("] unreachable:"), "{logged}");
+        let lost: Vec<&str> = logged
+            .lines()
+            .filter(|l| l.contains("] connectivity"))
+            .collect();
+        assert_eq!(lost.len(), 2, "{logged}");
+        assert!(
+            lost[0].ends_with("connectivity lost: 3/3 targets down"),
+            "{logged}"
+        );
+        assert!(
+            lost[1].ends_with("connectivity restored after 2s"),
+            "{logged}"
+        );
+        assert_eq!(summary.connectivity_lost.len(), 1);
+        assert!(summary.connectivity_lost[0].end_ms.is_some());
+        // Per-target outages are still recorded, and grouped as one incident.
+        assert_eq!(summary.outages.len(), 3);
+        assert_eq!(summary.incidents.len(), 1);
+        let mut targets = summary.incidents[0].targets.clone();
+        targets.sort();
+        assert_eq!(targets, ["a", "b", "c"]);
+        let (_, report) = logged.split_once("Correlated outages:\n").unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 4, "{logged}");
+        assert!(
+            lines[1..].iter().all(|l| l.contains("  +0s to +")),
+            "{logged}"
+        );
+    }
+
+    #[test]
+    fn finalize_waits_for_in_flight_results_and_writes_final_state() {
+        // A probe that only finishes after the loop has already ended.
+        let (started_tx, started) = mpsc::channel();
+        let started_tx = Mutex::new(started_tx);
+        let prober: Prober = Arc::new(move |_: &str| {
+            let _ = started_tx.lock().unwrap().send(());
+            thread::sleep(Duration::from_millis(100));
+            ProbeOutcome::Lost(Loss::Timeout)
+        });
+        let monitor = PingMonitor::start(
+            &[Target::new("10.0.0.1")],
+            crate::Options {
+                interval: DEFAULT_INTERVAL,
+                run_for: None,
+                schedule: None,
+                engine: Engine::Custom(prober),
+                clock: Arc::new(clock::MockClock::new(1_700_000_000_000)),
+            },
+        );
+        started.recv().unwrap();
+        let log_path = std::env::temp_dir().join(format!(
+            "finalize_{}_{}.log",
+            std::process::id(),
+            store::now_ms()
+        ));
+        let config = Config::default();
+        let targets = vec![Target::new("10.0.0.1")];
+        let settings = config.settings.clone();
+        let session = Session::new(targets, settings, Arc::clone(monitor.disabled()));
+        finalize(Finish {
+            config: &config,
+            exit: Exit::Signal,
+            monitor,
+            session,
+            pseudonyms: None,
+            last_display: vec!["10.0.0.1   0/1".to_string()],
+            outputs: Outputs {
+                log: LogSink::open(&log_path, LogFormat::Text),
+                ..Outputs::disabled()
+            },
+            intervals: 0,
+        });
+        let log = fs::read_to_string(&log_path).unwrap();
+        let _ = fs::remove_file(&log_path);
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines.len(), 9, "{log}");
+        assert!(lines[1].ends_with("] unreachable: 10.0.0.1"), "{log}");
+        assert!(lines[2].ends_with("] interrupted by signal"), "{log}");
+        assert!(lines[3].ends_with("] Final state:"), "{log}");
+        assert_eq!(lines[4], "10.0.0.1   0/1");
+        assert_eq!(
+            lines[5..7],
+            ["Failures by kind:", "    10.0.0.1  timeout 1"]
+        );
+        // One late timeout is below `--down-after`, and no time was monitored.
+        assert_eq!(lines[7], "Availability:");
+        assert_eq!(
+            lines[8],
+            "    10.0.0.1  - available, 0 outages, down 0h 00m 00s"
+        );
+    }
+}
@@ -0,0 +1,108 @@
+use std::{thread, time::Instant};
+
+use crate::{Engine, Target, app::Probing, compare, icmp, wan};
+
+/// What `bench` measured for one engine.
+struct BenchResult {
+    answered: usize,
+    seq_rate: f64,
+    par_rate: f64,
+    rtts: Vec<f64>,
+    walls: Vec<f64>,
+    gaps: Vec<f64>,
+}
+
+/// Wall-clock time and reported RTT of one probe.
+fn timed_probe(engine: &Engine, target: &Target) -> (f64, Option<f64>) {
+    let start = Instant::now();
+    let rtt = engine.probe(target).rtt_ms();
+    let wall_ms = start.elapsed().as_secs_f64() * 1000.0;
+    (wall_ms, rtt)
+}
+
+fn bench_engine(engine: &Engine, target: &Target, probes: u32, parallel: u32) -> BenchResult {
+    let start = Instant::now();
+    let sequential: Vec<(f64, Option<f64>)> =
+        (0..probes).map(|_| timed_probe(engine, target)).collect();
+    let seq_rate = probes as f64 / start.elapsed().as_secs_f64();
+
+    let start = Instant::now();
+    thread::scope(|scope| {
+        for worker in 0..parallel {
+            let share = probes / parallel + u32::from(worker < probes % parallel);
+            scope.spawn(move || {
+                for _ in 0..share {
+                    timed_probe(engine, target);
+                }
+            });
+        }
+    });
+    let par_rate = probes as f64 / start.elapsed().as_secs_f64();
+
+    let rtts: Vec<f64> = sequential.iter().filter_map(|(_, rtt)| *rtt).collect();
+    BenchResult {
+        answered: rtts.len(),
+        seq_rate,
+        par_rate,
+        rtts,
+        walls: sequential.iter().map(|(wall, _)| *wall).collect(),
+        // Everything beyond the reported RTT is process and parsing overhead.
+        gaps: sequential
+            .iter()
+            .filter_map(|(wall, rtt)| rtt.map(|r| wall - r))
+            .collect(),
+    }
+}
+
+/// `ping-plotter bench`: benchmarks the system ping and, where permitted,
+/// the native engine side by side; the one chosen with `--engine` comes
+/// first.
+pub fn run(probing: &Probing, target: &str, probes: u32, parallel: u32) {
+    let parallel = parallel.max(1);
+    let engine = |native| probing.engine(native, wan::Routes::default());
+    let mut engines = vec![("System-ping".to_string(), engine(None))];
+    match icmp::detect() {
+        Ok(socket) => {
+            let entry = (
+                format!("nativ ({})", socket.describe()),
+                engine(Some(socket)),
+            );
+            match probing.kind {
+                icmp::EngineKind::Native => engines.insert(0, entry),
+                icmp::EngineKind::System => engines.push(entry),
+            }
+        }
+        Err(err) => println!(
+            "Native Engine nicht möglich ({err}); {}",
+            icmp::permission_hint()
+        ),
+    }
+    println!("Ziel {target}, {probes} Pings je Messung");
+    let target = Target::new(target);
+    let results: Vec<BenchResult> = engines
+        .iter()
+        .map(|(_, engine)| bench_engine(engine, &target, probes, parallel))
+        .collect();
+    let row = |name: &str, cell: &dyn Fn(&BenchResult) -> String| {
+        let cells: Vec<String> = results.iter().map(|r| format!("{:<34}", cell(r))).collect();
+        println!("{name:<28} {}", cells.join(" ").trim_end());
+    };
+    let names: Vec<String> = engines
+        .iter()
+        .map(|(name, _)| format!("{name:<34}"))
+        .collect();
+    println!("{:<28} {}", "", names.join(" ").trim_end());
+    row("Antworten", &|r| format!("{}/{probes}", r.answered));
+    row("Sequenziell", &|r| format!("{:.1} Pings/s", r.seq_rate));
+    row(&format!("Parallel ({parallel} Threads)"), &|r| {
+        format!("{:.1} Pings/s", r.par_rate)
+    });
+    let summary = |values: &[f64]| match compare::summarize(values) {
+        Some(s) => format!("{:.3}/{:.3}/{:.3} ms", s.median, s.mean, s.stddev),
+        None => "-".to_string(),
+    };
+    println!("{:<28} (Median/Mittel/Standardabweichung)", "");
+    row("RTT (gemeldet)", &|r| summary(&r.rtts));
+    row("Dauer je Ping (gemessen)", &|r| summary(&r.walls));
+    row("Overhead (Dauer - RTT)", &|r| summary(&r.gaps));
+}
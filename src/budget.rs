@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+
+use crate::Target;
+
+/// Scheduling health of one target's worker: how many probes the cadence
+/// asked for, how many were actually sent, and how late/long they were.
+/// High delays or missing probes point at the machine (CPU, fork pressure)
+/// rather than the network. Workers schedule on absolute time (tick `n` is
+/// due at `first_tick + n * interval`), so a late probe doesn't shift the
+/// ones after it; the delay is the drift from that schedule, and the
+/// spacing deviation how far two consecutive probes were from `interval`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Budget {
+    // Targets added at runtime start late; ticks before that do not count.
+    first_tick: Option<u64>,
+    ticks: u64,
+    paused: u64,
+    pub sent: u64,
+    delay_sum_ms: f64,
+    pub max_delay_ms: f64,
+    duration_sum_ms: f64,
+    // Tick and delay of the last probe sent, for the spacing to the next.
+    last: Option<(u64, f64)>,
+    spacing_pairs: u64,
+    spacing_dev_sum_ms: f64,
+    pub max_spacing_dev_ms: f64,
+}
+
+impl Budget {
+    pub fn record(&mut self, tick: u64, paused: bool, delay_ms: f64, duration_ms: f64) {
+        self.first_tick = Some(self.first_tick.map_or(tick, |t| t.min(tick)));
+        self.ticks = self.ticks.max(tick + 1);
+        if paused {
+            self.paused += 1;
+            return;
+        }
+        self.sent += 1;
+        self.delay_sum_ms += delay_ms;
+        self.max_delay_ms = self.max_delay_ms.max(delay_ms);
+        self.duration_sum_ms += duration_ms;
+        // Consecutive probes started `interval` plus the change in delay
+        // apart; across skipped or paused ticks there is no pair.
+        if let Some((last_tick, last_delay_ms)) = self.last
+            && tick == last_tick + 1
+        {
+            let deviation = (delay_ms - last_delay_ms).abs();
+            self.spacing_pairs += 1;
+            self.spacing_dev_sum_ms += deviation;
+            self.max_spacing_dev_ms = self.max_spacing_dev_ms.max(deviation);
+        }
+        self.last = Some((tick, delay_ms));
+    }
+
+    /// Probes the cadence intended so far; ticks skipped after an overrun
+    /// count, paused ones do not.
+    pub fn intended(&self) -> u64 {
+        self.ticks - self.first_tick.unwrap_or(0) - self.paused
+    }
+
+    pub fn avg_delay_ms(&self) -> Option<f64> {
+        (self.sent > 0).then(|| self.delay_sum_ms / self.sent as f64)
+    }
+
+    pub fn avg_duration_ms(&self) -> Option<f64> {
+        (self.sent > 0).then(|| self.duration_sum_ms / self.sent as f64)
+    }
+
+    /// Mean deviation of the spacing between consecutive probes from the
+    /// interval.
+    pub fn avg_spacing_dev_ms(&self) -> Option<f64> {
+        (self.spacing_pairs > 0).then(|| self.spacing_dev_sum_ms / self.spacing_pairs as f64)
+    }
+}
+
+/// The `--probe-stats` section below the table, one row per target.
+pub fn lines(targets: &[Target], budgets: &HashMap<String, Budget>) -> Vec<String> {
+    let fmt = |v: Option<f64>| v.map_or_else(|| "-".to_string(), |n| format!("{n:.1}"));
+    let mut lines = vec![format!(
+        "{:<20} {:>8} {:>9} {:>14} {:>14} {:>11} {:>15} {:>15}",
+        "Takt",
+        "geplant",
+        "gesendet",
+        "Verz. avg (ms)",
+        "Verz. max (ms)",
+        "Dauer (ms)",
+        "Abst. ±avg (ms)",
+        "Abst. ±max (ms)"
+    )];
+    for target in targets {
+        let b = budgets.get(&target.name).copied().unwrap_or_default();
+        lines.push(format!(
+            "{:<20} {:>8} {:>9} {:>14} {:>14} {:>11} {:>15} {:>15}",
+            target.name,
+            b.intended(),
+            b.sent,
+            fmt(b.avg_delay_ms()),
+            fmt((b.sent > 0).then_some(b.max_delay_ms)),
+            fmt(b.avg_duration_ms()),
+            fmt(b.avg_spacing_dev_ms()),
+            fmt(b.avg_spacing_dev_ms().map(|_| b.max_spacing_dev_ms)),
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_skipped_ticks_as_intended() {
+        let mut b = Budget::default();
+        b.record(0, false, 2.0, 10.0);
+        // Tick 1 overran and was skipped, tick 2 was paused.
+        b.record(2, true, 0.0, 0.0);
+        b.record(3, false, 4.0, 30.0);
+        assert_eq!(b.intended(), 3);
+        assert_eq!(b.sent, 2);
+        assert_eq!(b.avg_delay_ms(), Some(3.0));
+        assert_eq!(b.max_delay_ms, 4.0);
+        assert_eq!(b.avg_duration_ms(), Some(20.0));
+        assert_eq!(Budget::default().avg_delay_ms(), None);
+
+        let mut late = Budget::default();
+        late.record(10, false, 1.0, 1.0);
+        late.record(11, false, 1.0, 1.0);
+        assert_eq!(late.intended(), 2);
+    }
+
+    #[test]
+    fn measures_spacing_of_consecutive_probes() {
+        let mut b = Budget::default();
+        b.record(0, false, 1.0, 5.0);
+        b.record(1, false, 7.0, 5.0);
+        b.record(2, false, 3.0, 5.0);
+        // Tick 3 was skipped: 2 and 4 are no pair.
+        b.record(4, false, 50.0, 5.0);
+        assert_eq!(b.avg_spacing_dev_ms(), Some(5.0));
+        assert_eq!(b.max_spacing_dev_ms, 6.0);
+        assert_eq!(Budget::default().avg_spacing_dev_ms(), None);
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local, TimeZone};
+
+/// Source of time for the scheduler: tick alignment, deadlines and the
+/// sample timestamps. `SystemClock` in production, `MockClock` in tests so
+/// they can run minutes of ticks without sleeping.
+pub trait Clock: Send + Sync {
+    /// Monotonic time for ticks and deadlines.
+    fn now(&self) -> Instant;
+    /// Wall-clock time as Unix milliseconds, for alignment and timestamps.
+    fn wall_ms(&self) -> i64;
+    fn sleep(&self, duration: Duration);
+
+    fn local(&self) -> DateTime<Local> {
+        Local
+            .timestamp_millis_opt(self.wall_ms())
+            .single()
+            .unwrap_or_else(Local::now)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn wall_ms(&self) -> i64 {
+        chrono::Utc::now().timestamp_millis()
+    }
+
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// Clock that only moves when slept on or advanced; both time scales move
+/// together. Lets tests run a `PingMonitor` without waiting for real ticks.
+#[derive(Debug)]
+pub struct MockClock {
+    start: Instant,
+    start_wall_ms: i64,
+    elapsed: Mutex<Duration>,
+}
+
+impl MockClock {
+    pub fn new(wall_ms: i64) -> MockClock {
+        MockClock {
+            start: Instant::now(),
+            start_wall_ms: wall_ms,
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        if let Ok(mut elapsed) = self.elapsed.lock() {
+            *elapsed += duration;
+        }
+    }
+
+    fn elapsed(&self) -> Duration {
+        self.elapsed.lock().map(|e| *e).unwrap_or_default()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        self.start + self.elapsed()
+    }
+
+    fn wall_ms(&self) -> i64 {
+        self.start_wall_ms + self.elapsed().as_millis() as i64
+    }
+
+    fn sleep(&self, duration: Duration) {
+        self.advance(duration);
+    }
+}
+
+/// First wall-clock multiple of `interval` strictly after the current time,
+/// as an instant on the clock's monotonic scale; an even second for the
+/// default of 2s.
+pub fn align_to_interval(clock: &dyn Clock, interval: Duration) -> Instant {
+    let now_ms = clock.wall_ms();
+    let interval_ms = (interval.as_millis() as i64).max(1);
+    let next_ms = (now_ms.div_euclid(interval_ms) + 1) * interval_ms;
+    clock.now() + Duration::from_millis((next_ms - now_ms) as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    #[test]
+    fn mock_clock_moves_only_when_told() {
+        let clock = MockClock::new(1_000);
+        let start = clock.now();
+        clock.sleep(Duration::from_millis(1_500));
+        assert_eq!(clock.now() - start, Duration::from_millis(1_500));
+        assert_eq!(clock.wall_ms(), 2_500);
+    }
+
+    proptest! {
+        #[test]
+        fn first_tick_is_the_next_interval_boundary(
+            wall_ms in 0i64..4_000_000_000_000,
+            interval_ms in 1u64..10_000,
+        ) {
+            let clock = MockClock::new(wall_ms);
+            let interval = Duration::from_millis(interval_ms);
+            let first = align_to_interval(&clock, interval);
+            let delay = first - clock.now();
+            prop_assert!(delay > Duration::ZERO && delay <= interval);
+            let tick_ms = wall_ms + delay.as_millis() as i64;
+            prop_assert_eq!(tick_ms % interval_ms as i64, 0);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Engine, Target, parse::ProbeOutcome};
+
+const ALPHA: f64 = 0.05;
+
+// Two-sided 95% critical values of Student's t for 1..=30 degrees of freedom.
+const T_975: [f64; 30] = [
+    12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+    2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+    2.052, 2.048, 2.045, 2.042,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub n: usize,
+    pub mean: f64,
+    pub median: f64,
+    pub stddev: f64,
+}
+
+pub fn summarize(samples: &[f64]) -> Option<Summary> {
+    if samples.is_empty() {
+        return None;
+    }
+    let n = samples.len();
+    let mean = samples.iter().sum::<f64>() / n as f64;
+    let var = if n > 1 {
+        samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1) as f64
+    } else {
+        0.0
+    };
+    let mut sorted = samples.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let median = if n % 2 == 1 {
+        sorted[n / 2]
+    } else {
+        (sorted[n / 2 - 1] + sorted[n / 2]) / 2.0
+    };
+    Some(Summary {
+        n,
+        mean,
+        median,
+        stddev: var.sqrt(),
+    })
+}
+
+/// 95% confidence interval for `mean(b) - mean(a)` (Welch, unequal variances).
+pub fn welch_interval(a: &Summary, b: &Summary) -> Option<(f64, f64, f64)> {
+    if a.n < 2 || b.n < 2 {
+        return None;
+    }
+    let va = a.stddev.powi(2) / a.n as f64;
+    let vb = b.stddev.powi(2) / b.n as f64;
+    let diff = b.mean - a.mean;
+    let se = (va + vb).sqrt();
+    if se == 0.0 {
+        return Some((diff, diff, diff));
+    }
+    let df =
+        (va + vb).powi(2) / (va.powi(2) / (a.n as f64 - 1.0) + vb.powi(2) / (b.n as f64 - 1.0));
+    let t = t_critical(df);
+    Some((diff, diff - t * se, diff + t * se))
+}
+
+fn t_critical(df: f64) -> f64 {
+    let df = df.floor().max(1.0) as usize;
+    T_975.get(df - 1).copied().unwrap_or(1.96)
+}
+
+/// Mann-Whitney U test with normal approximation and tie correction.
+/// Returns `(u, p_two_sided)`.
+pub fn mann_whitney(a: &[f64], b: &[f64]) -> Option<(f64, f64)> {
+    let (n1, n2) = (a.len(), b.len());
+    if n1 == 0 || n2 == 0 {
+        return None;
+    }
+    let mut all: Vec<(f64, bool)> = a
+        .iter()
+        .map(|&x| (x, true))
+        .chain(b.iter().map(|&x| (x, false)))
+        .collect();
+    all.sort_by(|x, y| x.0.total_cmp(&y.0));
+
+    let n = all.len();
+    let mut rank_sum_a = 0.0;
+    let mut tie_term = 0.0;
+    let mut i = 0;
+    while i < n {
+        let mut j = i;
+        while j + 1 < n && all[j + 1].0 == all[i].0 {
+            j += 1;
+        }
+        // Ranks are 1-based; tied values share the average rank.
+        let rank = (i + j) as f64 / 2.0 + 1.0;
+        let ties = (j - i + 1) as f64;
+        tie_term += ties.powi(3) - ties;
+        rank_sum_a += rank * all[i..=j].iter().filter(|(_, in_a)| *in_a).count() as f64;
+        i = j + 1;
+    }
+
+    let (n1f, n2f, nf) = (n1 as f64, n2 as f64, n as f64);
+    let u1 = rank_sum_a - n1f * (n1f + 1.0) / 2.0;
+    let u = u1.min(n1f * n2f - u1);
+    let mean_u = n1f * n2f / 2.0;
+    let var_u = n1f * n2f / 12.0 * ((nf + 1.0) - tie_term / (nf * (nf - 1.0)).max(1.0));
+    if var_u <= 0.0 {
+        return Some((u, 1.0));
+    }
+    let z = (u - mean_u).abs() / var_u.sqrt();
+    let p = (2.0 * (1.0 - normal_cdf(z))).clamp(0.0, 1.0);
+    Some((u, p))
+}
+
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * (1.0 + erf(z / std::f64::consts::SQRT_2))
+}
+
+// Abramowitz & Stegun 7.1.26, max error ~1.5e-7.
+fn erf(x: f64) -> f64 {
+    let sign = if x < 0.0 { -1.0 } else { 1.0 };
+    let x = x.abs();
+    let t = 1.0 / (1.0 + 0.327_591_1 * x);
+    let poly = t
+        * (0.254_829_592
+            + t * (-0.284_496_736
+                + t * (1.421_413_741 + t * (-1.453_152_027 + t * 1.061_405_429))));
+    sign * (1.0 - poly * (-x * x).exp())
+}
+
+pub struct Side<'a> {
+    pub name: &'a str,
+    pub rtts: &'a [f64],
+    pub lost: usize,
+}
+
+pub fn report(a: &Side, b: &Side) -> String {
+    let mut out = Vec::new();
+    out.push(format!(
+        "{:<20} {:>8} {:>8} {:>10} {:>10} {:>10}",
+        "Ziel", "Antw.", "Verlust", "median", "avg (ms)", "stddev"
+    ));
+    for side in [a, b] {
+        let total = side.rtts.len() + side.lost;
+        let loss = if total > 0 {
+            side.lost as f64 * 100.0 / total as f64
+        } else {
+            0.0
+        };
+        let line = match summarize(side.rtts) {
+            Some(s) => format!(
+                "{:<20} {:>8} {:>7.1}% {:>10.2} {:>10.2} {:>10.2}",
+                side.name, s.n, loss, s.median, s.mean, s.stddev
+            ),
+            None => format!(
+                "{:<20} {:>8} {:>7.1}% {:>10} {:>10} {:>10}",
+                side.name, 0, loss, "-", "-", "-"
+            ),
+        };
+        out.push(line);
+    }
+
+    let (Some(sa), Some(sb)) = (summarize(a.rtts), summarize(b.rtts)) else {
+        out.push("Not enough replies from both targets to compare.".to_string());
+        return out.join("\n");
+    };
+    if let Some((diff, lo, hi)) = welch_interval(&sa, &sb) {
+        out.push(format!(
+            "Difference {} - {}: {diff:+.2} ms (95% CI {lo:+.2} .. {hi:+.2} ms)",
+            b.name, a.name
+        ));
+    }
+    if let Some((u, p)) = mann_whitney(a.rtts, b.rtts) {
+        let verdict = if p < ALPHA {
+            let faster = if sa.median <= sb.median {
+                a.name
+            } else {
+                b.name
+            };
+            format!("significant at 5%: {faster} is faster")
+        } else {
+            "not significant at 5%".to_string()
+        };
+        out.push(format!("Mann-Whitney U = {u:.1}, p = {p:.4} -> {verdict}"));
+    }
+    out.join("\n")
+}
+
+/// `ping-plotter ab`: probes both targets at the same moment once per
+/// `interval` for `rounds` rounds and prints `report`.
+pub fn run(engine: &Engine, target_a: &str, target_b: &str, rounds: u32, interval: Duration) {
+    let (a, b) = (Target::new(target_a), Target::new(target_b));
+    let mut rtts: [Vec<f64>; 2] = [Vec::new(), Vec::new()];
+    let mut lost = [0usize; 2];
+    let start = Instant::now();
+    for round in 0..rounds {
+        let next = start + interval * round;
+        if let Some(wait) = next.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+        // Probe both at the same moment so shared path conditions affect both.
+        let results = thread::scope(|scope| {
+            let a = scope.spawn(|| engine.probe(&a));
+            let b = scope.spawn(|| engine.probe(&b));
+            [a.join(), b.join()]
+        });
+        for (idx, result) in results.into_iter().enumerate() {
+            match result {
+                Ok(ProbeOutcome::Reply(ms)) => rtts[idx].push(ms),
+                _ => lost[idx] += 1,
+            }
+        }
+        eprint!("\rRunde {}/{rounds}", round + 1);
+    }
+    eprintln!();
+
+    let report = report(
+        &Side {
+            name: target_a,
+            rtts: &rtts[0],
+            lost: lost[0],
+        },
+        &Side {
+            name: target_b,
+            rtts: &rtts[1],
+            lost: lost[1],
+        },
+    );
+    println!("{report}");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_samples() {
+        let s = summarize(&[1.0, 2.0, 3.0, 4.0]).unwrap();
+        assert_eq!(s.n, 4);
+        assert_eq!(s.mean, 2.5);
+        assert_eq!(s.median, 2.5);
+        assert!((s.stddev - 1.2910).abs() < 1e-3);
+        assert!(summarize(&[]).is_none());
+    }
+
+    #[test]
+    fn separated_samples_are_significant() {
+        let a: Vec<f64> = (0..30).map(|i| 10.0 + i as f64 * 0.1).collect();
+        let b: Vec<f64> = (0..30).map(|i| 20.0 + i as f64 * 0.1).collect();
+        let (u, p) = mann_whitney(&a, &b).unwrap();
+        assert_eq!(u, 0.0);
+        assert!(p < 1e-6, "p = {p}");
+
+        let (diff, lo, hi) =
+            welch_interval(&summarize(&a).unwrap(), &summarize(&b).unwrap()).unwrap();
+        assert!((diff - 10.0).abs() < 1e-9);
+        assert!(lo > 9.0 && hi < 11.0);
+    }
+
+    #[test]
+    fn identical_samples_are_not_significant() {
+        let a = [5.0, 6.0, 7.0, 5.0, 6.0, 7.0];
+        let (_, p) = mann_whitney(&a, &a).unwrap();
+        assert!(p > 0.9, "p = {p}");
+    }
+}
@@ -0,0 +1,914 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    ffi::OsString,
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use serde::Deserialize;
+
+use crate::{
+    Target,
+    error::RunError,
+    targets::{Entry, Group, TargetSource},
+    template::Template,
+    wol::Mac,
+};
+
+/// Most targets one `for` entry may expand to, so a typo like `1..100000`
+/// fails instead of flooding the table.
+const MAX_EXPANDED: usize = 10_000;
+
+/// Profile used when `--profile` is not given.
+pub const DEFAULT_PROFILE: &str = "default";
+
+/// The directory of the binary, where the default config file, `ips.txt`,
+/// `result.txt` and `anonymize.json` live; else the working directory.
+pub fn exe_dir() -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(|p| p.to_path_buf()))
+        .unwrap_or_else(|| env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
+}
+
+/// `--config`/`--profile`: named sets of flags in one TOML file, for
+/// laptops that move between networks:
+///
+/// ```toml
+/// [profile.home]
+/// ips = "/home/me/ips-home.txt"
+/// warning-loss = 20
+///
+/// [profile.office]
+/// ips = "/home/me/ips-office.txt"
+/// latency-budget = 50
+/// group = ["wan1=eth0", "wan2=eth1"]
+/// ```
+///
+/// Keys are long flag names; those outside a profile apply to all of them.
+/// The flags go in front of the command line, so flags given there still
+/// win. `[[target]]` tables (top level, or of the profile) list the targets
+/// with labels and groups, used when no `--ips` is given:
+///
+/// ```toml
+/// interval = "5s"
+///
+/// [[target]]
+/// address = "192.168.1.1"
+/// label = "Router"
+/// group = "LAN"
+///
+/// [[target]]
+/// address = "10.0.{{rack}}.1"
+/// label = "rack-{{rack}}-sw"
+/// for = { rack = "1..40" }
+/// ```
+pub fn expand(
+    args: Vec<OsString>,
+    default_file: &Path,
+    known: &[&str],
+) -> Result<Expanded, RunError> {
+    let (file, profile) = selected(&args);
+    let explicit = profile.is_some();
+    let file = file.unwrap_or_else(|| default_file.to_path_buf());
+    let unchanged = |args| Expanded {
+        args,
+        targets: None,
+    };
+    let text = match fs::read_to_string(&file) {
+        Ok(text) => text,
+        // Without `--profile`, the file and its default profile are optional.
+        Err(err) if err.kind() == io::ErrorKind::NotFound && !explicit => {
+            return Ok(unchanged(args));
+        }
+        Err(err) => {
+            let context = format!("Failed to read config {}", file.display());
+            return Err(RunError::io(context)(err));
+        }
+    };
+    let name = profile.unwrap_or_else(|| DEFAULT_PROFILE.to_string());
+    let invalid = |message: String| RunError::Invalid {
+        context: format!("Invalid config {}", file.display()),
+        message,
+    };
+    let config: toml::Table = toml::from_str(&text).map_err(|err| invalid(err.to_string()))?;
+    let profiles = config.get("profile").and_then(|p| p.as_table());
+    let table = profiles
+        .and_then(|p| p.get(&name))
+        .and_then(|t| t.as_table());
+    if table.is_none() && explicit {
+        let names: Vec<&str> = profiles
+            .into_iter()
+            .flat_map(|p| p.keys())
+            .map(|k| &**k)
+            .collect();
+        return Err(invalid(format!(
+            "no profile '{name}' (has: {})",
+            if names.is_empty() {
+                "none".to_string()
+            } else {
+                names.join(", ")
+            }
+        )));
+    }
+    let common: toml::Table = config
+        .iter()
+        .filter(|(key, _)| *key != "profile")
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    let mut extra = flags(&common, known).map_err(invalid)?;
+    if let Some(table) = table {
+        let own =
+            flags(table, known).map_err(|message| invalid(format!("profile {name}: {message}")))?;
+        extra.extend(own);
+    }
+    let targets = target_entries(&config, &name)
+        .map_err(invalid)?
+        .map(|targets| ConfigTargets {
+            origin: format!("config {}", file.display()),
+            targets,
+            modified: modified(&file),
+            file: Some(file.clone()),
+            profile: name.clone(),
+            refresh: None,
+        });
+    let mut expanded = args;
+    let rest = expanded.split_off(1.min(expanded.len()));
+    expanded.extend(extra.into_iter().map(OsString::from));
+    expanded.extend(rest);
+    Ok(Expanded {
+        args: expanded,
+        targets,
+    })
+}
+
+/// The `[[target]]`s of `profile`, or else the common ones.
+fn target_entries(config: &toml::Table, profile: &str) -> Result<Option<Vec<TargetEntry>>, String> {
+    let own = config
+        .get("profile")
+        .and_then(|p| p.get(profile))
+        .and_then(|t| t.get("target"));
+    let Some(value) = own.or(config.get("target")) else {
+        return Ok(None);
+    };
+    let entries = value
+        .clone()
+        .try_into()
+        .map_err(|err: toml::de::Error| format!("[[target]]: {}", err.message()))?;
+    expand_templates(entries)
+        .map(Some)
+        .map_err(|message| format!("[[target]]: {message}"))
+}
+
+fn modified(file: &Path) -> Option<SystemTime> {
+    fs::metadata(file).and_then(|m| m.modified()).ok()
+}
+
+/// The command line with the config's flags, and its targets if it has any.
+#[derive(Debug)]
+pub struct Expanded {
+    pub args: Vec<OsString>,
+    pub targets: Option<ConfigTargets>,
+}
+
+/// One `[[target]]` of the config file.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetEntry {
+    pub address: String,
+    /// Shown in the table instead of the address.
+    pub label: Option<String>,
+    pub group: Option<String>,
+    /// Importance in reports (weighted availability, incident impact);
+    /// 1 when left out.
+    pub weight: Option<f64>,
+    /// For `--wake-after`: sent a Wake-on-LAN packet when down that long.
+    pub mac: Option<String>,
+    /// `for = { rack = "1..40" }`: repeats the entry for every value, with
+    /// `{{rack}}` replaced in address, label, group and mac.
+    #[serde(rename = "for", default)]
+    pub each: BTreeMap<String, toml::Value>,
+}
+
+/// Replaces the entries with a `for` table by one entry per combination of
+/// its values, in order.
+fn expand_templates(entries: Vec<TargetEntry>) -> Result<Vec<TargetEntry>, String> {
+    let mut expanded = Vec::new();
+    for (idx, entry) in entries.into_iter().enumerate() {
+        if entry.each.is_empty() {
+            expanded.push(entry);
+            continue;
+        }
+        let fail = |message: String| format!("target {}: {message}", idx + 1);
+        let mut combinations: Vec<Vec<(&str, String)>> = vec![Vec::new()];
+        for (name, value) in &entry.each {
+            let values = template_values(value).map_err(|m| fail(format!("for {name}: {m}")))?;
+            combinations = combinations
+                .into_iter()
+                .flat_map(|vars| {
+                    values.iter().map(move |value| {
+                        let mut vars = vars.clone();
+                        vars.push((name.as_str(), value.clone()));
+                        vars
+                    })
+                })
+                .collect();
+            if combinations.len() > MAX_EXPANDED {
+                return Err(fail(format!("expands to more than {MAX_EXPANDED} targets")));
+            }
+        }
+        let names: Vec<&str> = entry.each.keys().map(String::as_str).collect();
+        let parse = |text: &str| {
+            let template = Template::parse(text)?;
+            template.check(&names)?;
+            Ok(template)
+        };
+        let address = parse(&entry.address).map_err(fail)?;
+        let label = entry
+            .label
+            .as_deref()
+            .map(parse)
+            .transpose()
+            .map_err(fail)?;
+        let group = entry
+            .group
+            .as_deref()
+            .map(parse)
+            .transpose()
+            .map_err(fail)?;
+        let mac = entry.mac.as_deref().map(parse).transpose().map_err(fail)?;
+        for vars in combinations {
+            expanded.push(TargetEntry {
+                address: address.render(&vars),
+                label: label.as_ref().map(|t| t.render(&vars)),
+                group: group.as_ref().map(|t| t.render(&vars)),
+                weight: entry.weight,
+                mac: mac.as_ref().map(|t| t.render(&vars)),
+                each: BTreeMap::new(),
+            });
+        }
+    }
+    Ok(expanded)
+}
+
+/// The values of one `for` variable: an inclusive range like `"1..40"`
+/// (`"01..40"` pads to two digits) or an array of strings and numbers.
+fn template_values(value: &toml::Value) -> Result<Vec<String>, String> {
+    match value {
+        toml::Value::String(range) => {
+            let no_range = || format!("'{range}' is no range like 1..40");
+            let (start, end) = range.split_once("..").ok_or_else(no_range)?;
+            let (start, end) = (start.trim(), end.trim());
+            let (from, to) = match (start.parse::<u64>(), end.parse::<u64>()) {
+                (Ok(from), Ok(to)) if from <= to => (from, to),
+                _ => return Err(no_range()),
+            };
+            if to - from >= MAX_EXPANDED as u64 {
+                return Err(format!("'{range}' has more than {MAX_EXPANDED} values"));
+            }
+            let width = if start.len() > 1 && start.starts_with('0') {
+                start.len().max(end.len())
+            } else {
+                0
+            };
+            Ok((from..=to).map(|n| format!("{n:0width$}")).collect())
+        }
+        toml::Value::Array(items) => items
+            .iter()
+            .map(|item| match item {
+                toml::Value::String(s) => Ok(s.clone()),
+                toml::Value::Integer(n) => Ok(n.to_string()),
+                other => Err(format!("unsupported {} value", other.type_str())),
+            })
+            .collect(),
+        other => Err(format!("unsupported {} value", other.type_str())),
+    }
+}
+
+/// The targets of the config file, as the target source of a run. With a
+/// refresh interval the file is read again when it changed, like an IP
+/// list; only its targets are taken over, flags need a restart.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigTargets {
+    pub origin: String,
+    pub targets: Vec<TargetEntry>,
+    file: Option<PathBuf>,
+    profile: String,
+    refresh: Option<Duration>,
+    // Modification time of `file` when `targets` were read.
+    modified: Option<SystemTime>,
+}
+
+impl ConfigTargets {
+    /// `--refresh-targets`.
+    pub fn refreshing(self, refresh: Option<Duration>) -> ConfigTargets {
+        ConfigTargets { refresh, ..self }
+    }
+
+    /// Takes over the targets of a changed file, if they are valid.
+    fn reload(&mut self) -> Result<(), String> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        let modified = modified(file);
+        if modified == self.modified {
+            return Ok(());
+        }
+        let text = fs::read_to_string(file)
+            .map_err(|err| format!("failed to read {}: {err}", file.display()))?;
+        let config: toml::Table =
+            toml::from_str(&text).map_err(|err| format!("{}: {err}", file.display()))?;
+        let targets = target_entries(&config, &self.profile)
+            .map_err(|err| format!("{}: {err}", file.display()))?
+            .unwrap_or_default();
+        let new = ConfigTargets {
+            targets,
+            modified,
+            ..self.clone()
+        };
+        new.parsed()?;
+        *self = new;
+        Ok(())
+    }
+
+    /// Normalized addresses with their entries, like lines of a list file.
+    fn parsed(&self) -> Result<Vec<(String, &TargetEntry)>, String> {
+        let mut parsed: Vec<(String, &TargetEntry)> = Vec::new();
+        let mut invalid = Vec::new();
+        for (idx, entry) in self.targets.iter().enumerate() {
+            match Entry::parse(entry.address.trim()) {
+                Ok(target) if parsed.iter().any(|(name, _)| *name == target.name()) => invalid
+                    .push(format!(
+                        "target {}: '{}' is listed twice",
+                        idx + 1,
+                        entry.address
+                    )),
+                Ok(_) if entry.weight.is_some_and(|w| !(w.is_finite() && w > 0.0)) => invalid.push(
+                    format!("target {}: weight must be a positive number", idx + 1),
+                ),
+                Ok(target) => match entry.mac.as_deref().map(str::parse::<Mac>) {
+                    Some(Err(err)) => invalid.push(format!("target {}: {err}", idx + 1)),
+                    _ => parsed.push((target.name(), entry)),
+                },
+                Err(err) => invalid.push(format!("target {}: '{}': {err}", idx + 1, entry.address)),
+            }
+        }
+        if invalid.is_empty() {
+            Ok(parsed)
+        } else {
+            Err(format!(
+                "invalid targets in {}: {}",
+                self.origin,
+                invalid.join("; ")
+            ))
+        }
+    }
+
+    /// The `weight`s given, keyed by target.
+    pub fn weights(&self) -> HashMap<String, f64> {
+        self.parsed()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(name, entry)| Some((name, entry.weight?)))
+            .collect()
+    }
+
+    /// The `mac`s given, keyed by target.
+    pub fn macs(&self) -> HashMap<String, Mac> {
+        self.parsed()
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(name, entry)| Some((name, entry.mac.as_deref()?.parse().ok()?)))
+            .collect()
+    }
+}
+
+impl TargetSource for ConfigTargets {
+    fn describe(&self) -> String {
+        self.origin.clone()
+    }
+
+    fn load(&mut self) -> Result<Vec<Target>, String> {
+        self.reload()?;
+        Ok(self
+            .parsed()?
+            .into_iter()
+            .map(|(name, entry)| Target {
+                label: entry.label.clone(),
+                ..Target::new(name)
+            })
+            .collect())
+    }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh
+    }
+
+    /// Groups numbered in order of appearance; entries without one share
+    /// an unnamed group.
+    fn groups(&self) -> HashMap<String, Group> {
+        let mut order: Vec<Option<&str>> = Vec::new();
+        let mut groups = HashMap::new();
+        for (target, entry) in self.parsed().unwrap_or_default() {
+            let group = entry.group.as_deref();
+            let index = order.iter().position(|g| *g == group).unwrap_or_else(|| {
+                order.push(group);
+                order.len() - 1
+            });
+            let name = group.map(str::to_string);
+            groups.insert(target, Group { index, name });
+        }
+        groups
+    }
+}
+
+/// `--config` and `--profile` from the raw arguments, before clap sees them.
+fn selected(args: &[OsString]) -> (Option<PathBuf>, Option<String>) {
+    let (mut file, mut profile) = (None, None);
+    let mut iter = args.iter().skip(1).map(|a| a.to_string_lossy());
+    while let Some(arg) = iter.next() {
+        let mut value = |flag: &str| match arg.strip_prefix(flag) {
+            Some("") => iter.next().map(|v| v.into_owned()),
+            Some(rest) => rest.strip_prefix('=').map(str::to_string),
+            None => None,
+        };
+        if arg == "--" {
+            break;
+        } else if let Some(v) = value("--config") {
+            file = Some(PathBuf::from(v));
+        } else if let Some(v) = value("--profile") {
+            profile = Some(v);
+        }
+    }
+    (file, profile)
+}
+
+/// The profile as command-line arguments: `true` is a bare flag, `false`
+/// leaves it out, arrays repeat it.
+fn flags(table: &toml::Table, known: &[&str]) -> Result<Vec<String>, String> {
+    let mut flags = Vec::new();
+    for (key, value) in table {
+        if key == "target" {
+            continue;
+        }
+        if !known.contains(&key.as_str()) || key == "config" || key == "profile" {
+            return Err(format!("unknown flag '{key}'"));
+        }
+        let values = match value {
+            toml::Value::Array(items) => items.iter().collect(),
+            value => vec![value],
+        };
+        for value in values {
+            let text = match value {
+                toml::Value::Boolean(true) => None,
+                toml::Value::Boolean(false) => continue,
+                toml::Value::String(s) => Some(s.clone()),
+                toml::Value::Integer(n) => Some(n.to_string()),
+                toml::Value::Float(n) => Some(n.to_string()),
+                other => {
+                    return Err(format!(
+                        "unsupported {} value for '{key}'",
+                        other.type_str()
+                    ));
+                }
+            };
+            flags.push(format!("--{key}"));
+            flags.extend(text);
+        }
+    }
+    Ok(flags)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OFFICE: &str = "[profile.default]\nwarning-loss = 10\n\n\
+         [profile.office]\nips = \"office.txt\"\nlatency-budget = 50.5\n\
+         locale-numbers = true\nanonymize = false\ngroup = [\"a=eth0\", \"b=eth1\"]\n";
+    const KNOWN: [&str; 8] = [
+        "ips",
+        "warning-loss",
+        "latency-budget",
+        "locale-numbers",
+        "anonymize",
+        "group",
+        "interval",
+        "timeout",
+    ];
+    const TARGETS: &str = "interval = \"5s\"\n\
+         [[target]]\naddress = \"192.168.001.1\"\nlabel = \"Router\"\ngroup = \"LAN\"\n\
+         weight = 5\n\
+         [[target]]\naddress = \"1.1.1.1\"\n\
+         [[target]]\naddress = \"NAS.lan\"\ngroup = \"LAN\"\nmac = \"00:1a:2b:3c:4d:5e\"\n\
+         [profile.office]\ntimeout = \"1s\"\n\
+         [[profile.office.target]]\naddress = \"10.0.0.1\"\n";
+
+    /// A config file of its own for each test, as they run in parallel.
+    fn write_config(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("config_{name}_{}.toml", std::process::id()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn args(list: &[&str]) -> Vec<OsString> {
+        list.iter().map(OsString::from).collect()
+    }
+
+    fn config_flag(path: &Path) -> String {
+        format!("--config={}", path.display())
+    }
+
+    /// `pp --config=<path> <rest>`.
+    fn expand_file(path: &Path, rest: &[&str]) -> Result<Expanded, RunError> {
+        let config = config_flag(path);
+        let list: Vec<&str> = ["pp", config.as_str()]
+            .into_iter()
+            .chain(rest.iter().copied())
+            .collect();
+        expand(args(&list), path, &KNOWN)
+    }
+
+    /// The targets of a config with `text`, loaded once.
+    fn load(name: &str, text: &str) -> Result<Vec<Target>, String> {
+        let path = write_config(name, text);
+        let result = expand_file(&path, &[]).unwrap().targets.unwrap().load();
+        let _ = fs::remove_file(&path);
+        result
+    }
+
+    fn names(targets: Vec<Target>) -> Vec<String> {
+        targets.into_iter().map(|t| t.name).collect()
+    }
+
+    #[test]
+    fn puts_the_profile_flags_before_the_command_line() {
+        let path = write_config("office", OFFICE);
+        let config = config_flag(&path);
+        let expanded = expand_file(&path, &["--profile", "office", "-d", "5"]).unwrap();
+        assert_eq!(
+            expanded.args,
+            args(&[
+                "pp",
+                "--group",
+                "a=eth0",
+                "--group",
+                "b=eth1",
+                "--ips",
+                "office.txt",
+                "--latency-budget",
+                "50.5",
+                "--locale-numbers",
+                &config,
+                "--profile",
+                "office",
+                "-d",
+                "5",
+            ])
+        );
+        assert!(expanded.targets.is_none());
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn the_default_profile_applies_without_profile() {
+        let path = write_config("default", OFFICE);
+        let config = config_flag(&path);
+        assert_eq!(
+            expand_file(&path, &[]).unwrap().args,
+            args(&["pp", "--warning-loss", "10", &config])
+        );
+        // `--profile` as a separate argument after `--` is the program's.
+        assert_eq!(
+            expand_file(&path, &["--", "--profile", "office"])
+                .unwrap()
+                .args,
+            args(&[
+                "pp",
+                "--warning-loss",
+                "10",
+                &config,
+                "--",
+                "--profile",
+                "office"
+            ])
+        );
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_missing_default_file_is_no_error() {
+        let missing = std::env::temp_dir().join("config_missing.toml");
+        let expanded = expand(args(&["pp", "-d", "5"]), &missing, &KNOWN).unwrap();
+        assert_eq!(expanded.args, args(&["pp", "-d", "5"]));
+        assert!(expanded.targets.is_none());
+    }
+
+    #[test]
+    fn a_missing_file_is_an_error_with_profile() {
+        let missing = std::env::temp_dir().join("config_missing_profile.toml");
+        let err = expand(args(&["pp", "--profile", "office"]), &missing, &KNOWN).unwrap_err();
+        assert!(matches!(err, RunError::Io { .. }), "{err}");
+        assert!(
+            err.to_string().starts_with("Failed to read config"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn an_empty_file_changes_nothing() {
+        let path = write_config("empty", "");
+        let config = config_flag(&path);
+        let expanded = expand_file(&path, &[]).unwrap();
+        assert_eq!(expanded.args, args(&["pp", &config]));
+        assert!(expanded.targets.is_none());
+        let err = expand_file(&path, &["--profile=office"]).unwrap_err();
+        assert!(
+            err.to_string().ends_with("no profile 'office' (has: none)"),
+            "{err}"
+        );
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn an_unknown_profile_lists_the_existing_ones() {
+        let path = write_config("cafe", OFFICE);
+        let err = expand_file(&path, &["--profile=cafe"]).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("no profile 'cafe' (has: default, office)"),
+            "{err}"
+        );
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn rejects_flags_the_program_does_not_know() {
+        let path = write_config("unknown", OFFICE);
+        let err = expand(
+            args(&["pp", &config_flag(&path), "--profile", "office"]),
+            &path,
+            &KNOWN[1..],
+        );
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .ends_with("profile office: unknown flag 'ips'")
+        );
+        // A config can't choose another config, even where it is a flag.
+        fs::write(&path, "config = \"other.toml\"\n").unwrap();
+        let err = expand(args(&["pp", &config_flag(&path)]), &path, &["config"]).unwrap_err();
+        assert!(err.to_string().ends_with("unknown flag 'config'"), "{err}");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn rejects_values_that_are_no_flag() {
+        let path = write_config("table", "interval = { every = 5 }\n");
+        let err = expand_file(&path, &[]).unwrap_err();
+        assert!(
+            err.to_string()
+                .ends_with("unsupported table value for 'interval'"),
+            "{err}"
+        );
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn rejects_a_file_that_is_no_toml() {
+        let path = write_config("broken", "interval = \n");
+        let err = expand_file(&path, &[]).unwrap_err();
+        assert!(matches!(err, RunError::Invalid { .. }), "{err}");
+        assert!(err.to_string().starts_with("Invalid config"), "{err}");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn reads_common_flags_and_labeled_targets() {
+        let path = write_config("targets", TARGETS);
+        let config = config_flag(&path);
+        let expanded = expand_file(&path, &[]).unwrap();
+        assert_eq!(expanded.args, args(&["pp", "--interval", "5s", &config]));
+        let mut targets = expanded.targets.unwrap();
+        let loaded = targets.load().unwrap();
+        assert_eq!(loaded[0].label.as_deref(), Some("Router"));
+        assert_eq!(loaded[1].label, None);
+        assert_eq!(names(loaded), ["192.168.1.1", "1.1.1.1", "nas.lan"]);
+        assert_eq!(
+            targets.weights(),
+            HashMap::from([("192.168.1.1".into(), 5.0)])
+        );
+        assert_eq!(targets.macs()["nas.lan"].to_string(), "00:1a:2b:3c:4d:5e");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn numbers_groups_in_order_and_shares_one_for_the_rest() {
+        let path = write_config("groups", TARGETS);
+        let groups = expand_file(&path, &[]).unwrap().targets.unwrap().groups();
+        assert_eq!(
+            groups["nas.lan"],
+            Group {
+                index: 0,
+                name: Some("LAN".to_string())
+            }
+        );
+        assert_eq!(
+            groups["1.1.1.1"],
+            Group {
+                index: 1,
+                name: None
+            }
+        );
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn a_profiles_own_targets_replace_the_common_ones() {
+        let path = write_config("office_targets", TARGETS);
+        let office = expand_file(&path, &["--profile", "office"]).unwrap();
+        assert_eq!(
+            office.args[1..5],
+            args(&["--interval", "5s", "--timeout", "1s"])
+        );
+        assert_eq!(names(office.targets.unwrap().load().unwrap()), ["10.0.0.1"]);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn weights_must_be_positive() {
+        let target =
+            |weight: &str| format!("[[target]]\naddress = \"1.1.1.1\"\nweight = {weight}\n");
+        for weight in ["0", "-1", "nan", "inf"] {
+            let err = load("weight", &target(weight)).unwrap_err();
+            assert!(
+                err.ends_with("target 1: weight must be a positive number"),
+                "{weight}: {err}"
+            );
+        }
+        assert!(load("weight", &target("0.01")).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_macs() {
+        let err = load(
+            "mac",
+            "[[target]]\naddress = \"1.1.1.1\"\n[[target]]\naddress = \"nas.lan\"\n\
+             mac = \"00:1a:2b\"\n",
+        )
+        .unwrap_err();
+        assert!(
+            err.ends_with("target 2: '00:1a:2b' is no MAC address like aa:bb:cc:dd:ee:ff"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn rejects_targets_listed_twice() {
+        let err = load(
+            "twice",
+            "[[target]]\naddress = \"192.168.1.1\"\n[[target]]\naddress = \"192.168.001.1\"\n",
+        )
+        .unwrap_err();
+        assert!(
+            err.ends_with("target 2: '192.168.001.1' is listed twice"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_target_fields() {
+        let path = write_config(
+            "field",
+            "[[target]]\naddress = \"1.1.1.1\"\nlable = \"x\"\n",
+        );
+        let err = expand_file(&path, &[]).unwrap_err();
+        assert!(err.to_string().contains("unknown field `lable`"), "{err}");
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn takes_over_edits_but_keeps_the_targets_on_a_broken_one() {
+        let path = write_config("edits", TARGETS);
+        let mut targets = expand_file(&path, &[]).unwrap().targets.unwrap();
+        assert_eq!(targets.load().unwrap().len(), 3);
+        let touch = |secs| {
+            fs::File::options()
+                .write(true)
+                .open(&path)
+                .unwrap()
+                .set_modified(SystemTime::now() + Duration::from_secs(secs))
+                .unwrap()
+        };
+        let edited = "[[target]]\naddress = \"1.1.1.1\"\n[[target]]\naddress = \"9.9.9.9\"\n";
+        fs::write(&path, edited).unwrap();
+        touch(5);
+        assert_eq!(names(targets.load().unwrap()), ["1.1.1.1", "9.9.9.9"]);
+        fs::write(&path, "[[target]]\naddress = \"1.1.1.1 2\"\n").unwrap();
+        touch(10);
+        assert!(targets.load().is_err());
+        assert_eq!(targets.targets.len(), 2);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn expands_for_ranges_and_lists() {
+        let loaded = load(
+            "racks",
+            "[[target]]\naddress = \"10.{{site}}.{{rack}}.1\"\nlabel = \"rack-{{rack}}-sw\"\n\
+             for = { rack = \"09..10\", site = [1, \"2\"] }\n",
+        )
+        .unwrap();
+        assert_eq!(loaded[1].label.as_deref(), Some("rack-09-sw"));
+        assert_eq!(
+            names(loaded),
+            ["10.1.9.1", "10.2.9.1", "10.1.10.1", "10.2.10.1"]
+        );
+    }
+
+    #[test]
+    fn a_range_of_one_value_expands_to_one_target() {
+        let loaded = load(
+            "one",
+            "[[target]]\naddress = \"10.0.{{rack}}.1\"\nfor = { rack = \"7..7\" }\n",
+        )
+        .unwrap();
+        assert_eq!(names(loaded), ["10.0.7.1"]);
+    }
+
+    #[test]
+    fn template_groups_follow_the_values() {
+        let path = write_config(
+            "site_groups",
+            "[[target]]\naddress = \"10.{{site}}.0.1\"\ngroup = \"site {{site}}\"\n\
+             for = { site = \"1..2\" }\n",
+        );
+        let groups = expand_file(&path, &[]).unwrap().targets.unwrap().groups();
+        assert_eq!(groups["10.2.0.1"].name.as_deref(), Some("site 2"));
+        assert_eq!(groups["10.2.0.1"].index, 1);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        let cases = [
+            (
+                "for = { rack = \"1..3\" }",
+                "{{rak}}",
+                "unknown template variable 'rak'",
+            ),
+            (
+                "for = { rack = \"3..1\" }",
+                "{{rack}}",
+                "'3..1' is no range like 1..40",
+            ),
+            (
+                "for = { rack = \"1-3\" }",
+                "{{rack}}",
+                "'1-3' is no range like 1..40",
+            ),
+            (
+                "for = { rack = \"0..10000\" }",
+                "{{rack}}",
+                "'0..10000' has more than 10000 values",
+            ),
+            (
+                "for = { rack = true }",
+                "{{rack}}",
+                "for rack: unsupported boolean value",
+            ),
+            (
+                "for = { rack = [1.5] }",
+                "{{rack}}",
+                "for rack: unsupported float value",
+            ),
+            (
+                "for = { a = \"1..100\", b = \"1..101\" }",
+                "{{a}}.{{b}}",
+                "expands to more than 10000 targets",
+            ),
+        ];
+        for (each, var, message) in cases {
+            let path = write_config(
+                "templates",
+                &format!("[[target]]\naddress = \"10.0.{var}.1\"\n{each}\n"),
+            );
+            let err = expand_file(&path, &[]).unwrap_err();
+            assert!(err.to_string().contains(message), "{each}: {err}");
+            let _ = fs::remove_file(&path);
+        }
+    }
+
+    #[test]
+    fn ranges_pad_like_their_start_up_to_the_limit() {
+        let values = |range: &str| template_values(&toml::Value::String(range.to_string()));
+        assert_eq!(values("08..10").unwrap(), ["08", "09", "10"]);
+        assert_eq!(values("8..10").unwrap(), ["8", "9", "10"]);
+        assert_eq!(values("0..9").unwrap()[0], "0");
+        assert_eq!(values(" 1 .. 2 ").unwrap(), ["1", "2"]);
+        assert_eq!(values("1..10000").unwrap().len(), MAX_EXPANDED);
+        assert!(values("0..10000").is_err());
+    }
+}
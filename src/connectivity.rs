@@ -0,0 +1,112 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use crate::store::Sample;
+
+/// `--connectivity-loss` default: only a round in which every target failed.
+pub const DEFAULT_FRACTION: f64 = 1.0;
+
+/// A round counts as "connectivity lost" when at least `fraction` of the
+/// probed targets failed. A single target can't tell its own outage apart
+/// from the uplink's, so at least two are needed.
+pub fn is_lost(failed: usize, probed: usize, fraction: f64) -> bool {
+    probed >= 2 && failed as f64 >= fraction * probed as f64
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change {
+    Lost,
+    /// `since_ms` is the first round of the condition.
+    Restored { since_ms: i64 },
+}
+
+/// Whether the run is currently in a connectivity-lost stretch.
+#[derive(Debug, Default)]
+pub struct Tracker {
+    since: Option<i64>,
+}
+
+impl Tracker {
+    pub fn update(&mut self, lost: bool, ts_ms: i64) -> Option<Change> {
+        match (self.since, lost) {
+            (None, true) => {
+                self.since = Some(ts_ms);
+                Some(Change::Lost)
+            }
+            (Some(since_ms), false) => {
+                self.since = None;
+                Some(Change::Restored { since_ms })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn since(&self) -> Option<i64> {
+        self.since
+    }
+}
+
+/// Probes of one round share an interval slot; `ts_ms` is taken right at
+/// the tick, so dividing by the interval groups stored samples by round.
+pub fn round_of(ts_ms: i64, interval: Duration) -> i64 {
+    ts_ms.div_euclid((interval.as_millis() as i64).max(1))
+}
+
+/// Rounds (as `round_of`) in which the stored samples meet `is_lost`.
+pub fn lost_rounds(samples: &[Sample], fraction: f64, interval: Duration) -> HashSet<i64> {
+    let mut rounds: HashMap<i64, (usize, usize)> = HashMap::new();
+    for sample in samples {
+        let (failed, probed) = rounds.entry(round_of(sample.ts_ms, interval)).or_default();
+        *failed += usize::from(!sample.success);
+        *probed += 1;
+    }
+    rounds
+        .into_iter()
+        .filter(|(_, (failed, probed))| is_lost(*failed, *probed, fraction))
+        .map(|(round, _)| round)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample(ts_ms: i64, target: &str, success: bool) -> Sample {
+        Sample {
+            ts_ms,
+            target: target.to_string(),
+            success,
+            latency_ms: success.then_some(1.0),
+        }
+    }
+
+    #[test]
+    fn reports_one_change_per_stretch_and_groups_samples_by_round() {
+        assert!(is_lost(3, 3, 1.0) && is_lost(2, 3, 0.5));
+        assert!(!is_lost(2, 3, 1.0) && !is_lost(1, 1, 1.0));
+
+        let mut tracker = Tracker::default();
+        assert_eq!(tracker.update(false, 0), None);
+        assert_eq!(tracker.update(true, 2_000), Some(Change::Lost));
+        assert_eq!(tracker.update(true, 4_000), None);
+        assert_eq!(
+            tracker.update(false, 6_000),
+            Some(Change::Restored { since_ms: 2_000 })
+        );
+
+        let samples = [
+            sample(2_003, "a", false),
+            sample(2_010, "b", false),
+            sample(4_002, "a", false),
+            sample(4_004, "b", true),
+        ];
+        let interval = Duration::from_secs(2);
+        assert_eq!(lost_rounds(&samples, 1.0, interval), HashSet::from([1]));
+        assert_eq!(lost_rounds(&samples, 0.5, interval), HashSet::from([1, 2]));
+        // At 1s the two failures of round 1 fall into separate rounds.
+        let interval = Duration::from_secs(1);
+        assert_eq!(lost_rounds(&samples, 1.0, interval), HashSet::from([2]));
+    }
+}
@@ -0,0 +1,489 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Condvar, Mutex, mpsc},
+    thread,
+    time::Duration,
+};
+
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+use crate::{Stats, error::RunError, parse::ProbeOutcome, ui};
+
+/// The live view as last drawn, shared with the `attach` connections.
+/// `generation` counts the frames so a waiting connection can tell a new
+/// frame from a spurious wakeup.
+#[derive(Debug, Default)]
+pub struct Broadcast {
+    frame: Mutex<(u64, Vec<String>)>,
+    changed: Condvar,
+}
+
+impl Broadcast {
+    pub fn publish(&self, lines: &[ui::Line]) {
+        if let Ok(mut frame) = self.frame.lock() {
+            frame.0 += 1;
+            frame.1 = lines.iter().map(encode).collect();
+        }
+        self.changed.notify_all();
+    }
+
+    /// The first frame newer than `seen`, or `None` after `timeout`.
+    fn next(&self, seen: u64, timeout: Duration) -> Option<(u64, Vec<String>)> {
+        let frame = self.frame.lock().ok()?;
+        let (frame, _) = self
+            .changed
+            .wait_timeout_while(frame, timeout, |f| f.0 == seen)
+            .ok()?;
+        (frame.0 != seen).then(|| frame.clone())
+    }
+}
+
+/// One line of a frame on the wire: a style letter, a space, the text.
+fn encode(line: &ui::Line) -> String {
+    let style = match line.style {
+        ui::Style::Plain => 'P',
+        ui::Style::Warning => 'W',
+        ui::Style::Critical => 'C',
+        ui::Style::Acked => 'A',
+    };
+    format!("{style} {}", line.text)
+}
+
+fn decode(wire: &str) -> ui::Line {
+    let (style, text) = wire.split_at_checked(2).unwrap_or(("P ", wire));
+    let style = match style {
+        "W " => ui::Style::Warning,
+        "C " => ui::Style::Critical,
+        "A " => ui::Style::Acked,
+        _ => ui::Style::Plain,
+    };
+    ui::Line {
+        text: text.to_string(),
+        style,
+        selected: false,
+    }
+}
+
+/// A command for the monitoring loop, parsed from one protocol line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// All targets, or just the given one.
+    Status(Option<String>),
+    Add(String),
+    Remove(String),
+    Pause,
+    Resume,
+    Snapshot,
+    /// Re-read the target list now instead of at the next refresh.
+    Reload,
+    /// `status --debug`: the internal event log (`debuglog`).
+    Debug,
+}
+
+impl Command {
+    pub fn parse(line: &str) -> Result<Command, String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let arg = words.next().map(str::to_string);
+        if words.next().is_some() {
+            return Err(format!("too many arguments for '{command}'"));
+        }
+        let needs = |arg: Option<String>| arg.ok_or_else(|| format!("'{command}' needs a target"));
+        let none = |command| match &arg {
+            Some(_) => Err(format!("'{line}' takes no argument")),
+            None => Ok(command),
+        };
+        match command {
+            "status" if arg.as_deref() == Some("--debug") => Ok(Command::Debug),
+            "status" => Ok(Command::Status(arg)),
+            "add" => needs(arg).map(Command::Add),
+            "remove" => needs(arg).map(Command::Remove),
+            "pause" => none(Command::Pause),
+            "resume" => none(Command::Resume),
+            "snapshot" => none(Command::Snapshot),
+            "reload" => none(Command::Reload),
+            _ => Err(format!("unknown command '{command}'")),
+        }
+    }
+}
+
+enum Reply {
+    Line(String),
+    Done(Result<(), String>),
+}
+
+/// A command waiting for the loop, which answers with data lines and then
+/// `done`.
+pub struct Request {
+    pub command: Command,
+    reply: mpsc::Sender<Reply>,
+}
+
+impl Request {
+    pub fn line(&self, line: String) {
+        let _ = self.reply.send(Reply::Line(line));
+    }
+
+    pub fn done(self, result: Result<(), String>) {
+        let _ = self.reply.send(Reply::Done(result));
+    }
+}
+
+/// `--socket`: the listening control socket. The socket file is removed
+/// again when the server is dropped at the end of the run.
+#[derive(Debug)]
+pub struct Server {
+    path: PathBuf,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Binds `path` and serves connections in the background. A socket file
+/// left behind by a crashed run is replaced; one that still answers is not.
+#[cfg(unix)]
+pub fn serve(
+    path: &Path,
+    broadcast: Arc<Broadcast>,
+    requests: mpsc::Sender<Request>,
+) -> Result<Server, RunError> {
+    let context = format!("Failed to listen on {}", path.display());
+    if path.exists() {
+        if UnixStream::connect(path).is_ok() {
+            return Err(RunError::Invalid {
+                context,
+                message: "another instance is listening there".to_string(),
+            });
+        }
+        std::fs::remove_file(path).map_err(RunError::io(context.clone()))?;
+    }
+    let listener = UnixListener::bind(path).map_err(RunError::io(context))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let broadcast = Arc::clone(&broadcast);
+            let requests = requests.clone();
+            thread::spawn(move || {
+                if let Err(err) = handle(stream, &broadcast, &requests) {
+                    tracing::debug!("control connection closed: {err}");
+                }
+            });
+        }
+    });
+    Ok(Server {
+        path: path.to_path_buf(),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn serve(
+    path: &Path,
+    _broadcast: Arc<Broadcast>,
+    _requests: mpsc::Sender<Request>,
+) -> Result<Server, RunError> {
+    Err(RunError::Invalid {
+        context: format!("Failed to listen on {}", path.display()),
+        message: "control sockets need a Unix system".to_string(),
+    })
+}
+
+/// Line protocol: the client sends a command per line and gets its data
+/// lines back, then `ok` or `error <message>`. `attach` instead turns the
+/// connection into a stream of frames, each `frame <n>` followed by `n`
+/// encoded lines.
+#[cfg(unix)]
+fn handle(
+    stream: UnixStream,
+    broadcast: &Broadcast,
+    requests: &mpsc::Sender<Request>,
+) -> io::Result<()> {
+    let mut out = stream.try_clone()?;
+    for line in BufReader::new(stream).lines() {
+        match line?.trim() {
+            "attach" => {
+                let mut seen = 0;
+                loop {
+                    // The timeout only bounds how long a dead client keeps
+                    // its thread once the run stops drawing.
+                    let Some((generation, lines)) = broadcast.next(seen, Duration::from_secs(5))
+                    else {
+                        continue;
+                    };
+                    seen = generation;
+                    let mut frame = format!("frame {}\n", lines.len());
+                    for line in &lines {
+                        frame.push_str(line);
+                        frame.push('\n');
+                    }
+                    out.write_all(frame.as_bytes())?;
+                }
+            }
+            "" => {}
+            line => {
+                let result = Command::parse(line).and_then(|command| {
+                    let (reply, replies) = mpsc::channel();
+                    requests
+                        .send(Request { command, reply })
+                        .map_err(|_| "the run is ending".to_string())?;
+                    // The loop answers after its next redraw, at most an
+                    // interval later.
+                    loop {
+                        match replies.recv_timeout(Duration::from_secs(10)) {
+                            Ok(Reply::Line(line)) => {
+                                writeln!(out, "{line}").map_err(|err| err.to_string())?
+                            }
+                            Ok(Reply::Done(result)) => break result,
+                            Err(_) => break Err("no answer from the run".to_string()),
+                        }
+                    }
+                });
+                match result {
+                    Ok(()) => writeln!(out, "ok")?,
+                    Err(err) => writeln!(out, "error {err}")?,
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sends one command line to the instance on `path` and returns the data
+/// lines of its reply.
+#[cfg(unix)]
+pub fn query(path: &Path, command: &str) -> Result<Vec<String>, RunError> {
+    let context = format!("Failed to query {}", path.display());
+    let mut stream = UnixStream::connect(path).map_err(RunError::io(context.clone()))?;
+    writeln!(stream, "{command}").map_err(RunError::io(context.clone()))?;
+    let mut data = Vec::new();
+    for line in BufReader::new(stream).lines() {
+        let line = line.map_err(RunError::io(context.clone()))?;
+        if line == "ok" {
+            return Ok(data);
+        }
+        if let Some(message) = line.strip_prefix("error ") {
+            return Err(RunError::Invalid {
+                context,
+                message: message.to_string(),
+            });
+        }
+        data.push(line);
+    }
+    Err(RunError::Invalid {
+        context,
+        message: "the connection closed before the reply ended".to_string(),
+    })
+}
+
+#[cfg(not(unix))]
+pub fn query(path: &Path, _command: &str) -> Result<Vec<String>, RunError> {
+    Err(RunError::Invalid {
+        context: format!("Failed to query {}", path.display()),
+        message: "control sockets need a Unix system".to_string(),
+    })
+}
+
+/// One target in `status` replies: whitespace-separated `key=value` pairs
+/// after the target, `-` for values not known yet. `state` is the last
+/// probe, `updown` the `--down-after`/`--up-after` state.
+pub fn status_line(
+    target: &str,
+    stats: &Stats,
+    last: Option<ProbeOutcome>,
+    severity: &str,
+    updown: &str,
+) -> String {
+    let fmt = |v: Option<f64>, decimals: usize| {
+        v.map_or_else(|| "-".to_string(), |n| format!("{n:.decimals$}"))
+    };
+    let state = match last {
+        Some(outcome) if outcome.success() => "up",
+        Some(_) => "down",
+        None => "-",
+    };
+    format!(
+        "{target} state={state} last_ms={} loss_pct={} avg_ms={} sent={} received={} \
+         severity={severity} updown={updown}",
+        fmt(last.and_then(ProbeOutcome::rtt_ms), 2),
+        fmt(stats.loss_pct(), 1),
+        fmt(stats.avg_ms(), 2),
+        stats.total,
+        stats.success,
+    )
+}
+
+/// `ping-plotter status`: prints the instance's `status` lines (or its
+/// internal event log with `debug`) and returns `RunError::Unreachable`
+/// for the targets with `state=down`.
+pub fn status(socket: &Path, target: Option<&str>, debug: bool) -> Result<(), RunError> {
+    let command = match target {
+        _ if debug => "status --debug".to_string(),
+        Some(target) => format!("status {target}"),
+        None => "status".to_string(),
+    };
+    let lines = query(socket, &command)?;
+    if debug {
+        lines.iter().for_each(|line| println!("{line}"));
+        return Ok(());
+    }
+    let mut down = Vec::new();
+    for line in &lines {
+        println!("{line}");
+        let mut words = line.split_whitespace();
+        if let Some(target) = words.next()
+            && words.any(|w| w == "state=down")
+        {
+            down.push(target.to_string());
+        }
+    }
+    if down.is_empty() {
+        Ok(())
+    } else {
+        Err(RunError::Unreachable(down))
+    }
+}
+
+/// `ping-plotter attach`: renders the live view of the instance listening
+/// on `path` until it exits or `q` is pressed. Read-only; keys other than
+/// `q`/`Esc`/`Ctrl+C` are ignored.
+#[cfg(unix)]
+pub fn attach(path: &Path) -> Result<(), RunError> {
+    let stream = UnixStream::connect(path).map_err(RunError::io(format!(
+        "Failed to connect to {}",
+        path.display()
+    )))?;
+    let mut out = stream
+        .try_clone()
+        .map_err(RunError::io("Failed to set up the connection"))?;
+    writeln!(out, "attach").map_err(RunError::io("Failed to send attach"))?;
+
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut lines = BufReader::new(stream).lines();
+        while let Some(Ok(header)) = lines.next() {
+            let Some(count) = header.strip_prefix("frame ").and_then(|n| n.parse().ok()) else {
+                continue;
+            };
+            let frame: Vec<ui::Line> = lines
+                .by_ref()
+                .take(count)
+                .map_while(Result::ok)
+                .map(|l| decode(&l))
+                .collect();
+            if tx.send(frame).is_err() {
+                break;
+            }
+        }
+    });
+
+    let term = ui::Terminal::start();
+    term.set_status(format!("Verbunden mit {} · q beenden", path.display()));
+    loop {
+        if let Some(ui::Input::Key(key)) = term.next_input(Duration::from_millis(100)) {
+            let ctrl_c = key.code == KeyCode::Char('c') && key.modifiers == KeyModifiers::CONTROL;
+            if ctrl_c || matches!(key.code, KeyCode::Char('q') | KeyCode::Esc) {
+                return Ok(());
+            }
+        }
+        match rx.try_recv() {
+            Ok(mut frame) => {
+                // Only the newest frame matters after a slow redraw.
+                frame = rx.try_iter().last().unwrap_or(frame);
+                term.draw(&frame);
+            }
+            Err(mpsc::TryRecvError::Empty) => {}
+            Err(mpsc::TryRecvError::Disconnected) => break,
+        }
+    }
+    drop(term);
+    println!("Verbindung zu {} beendet.", path.display());
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn attach(path: &Path) -> Result<(), RunError> {
+    Err(RunError::Invalid {
+        context: format!("Failed to connect to {}", path.display()),
+        message: "control sockets need a Unix system".to_string(),
+    })
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_commands_with_their_arguments() {
+        assert_eq!(Command::parse("status"), Ok(Command::Status(None)));
+        assert_eq!(
+            Command::parse("status 1.1.1.1"),
+            Ok(Command::Status(Some("1.1.1.1".to_string())))
+        );
+        assert_eq!(
+            Command::parse(" add  ::1 "),
+            Ok(Command::Add("::1".to_string()))
+        );
+        assert_eq!(Command::parse("pause"), Ok(Command::Pause));
+        assert_eq!(Command::parse("status --debug"), Ok(Command::Debug));
+        assert!(Command::parse("remove").is_err());
+        assert!(Command::parse("pause now").is_err());
+        assert!(Command::parse("add a b").is_err());
+        assert!(Command::parse("explode").is_err());
+    }
+
+    #[test]
+    fn answers_commands_and_streams_frames_to_attached_clients() {
+        let path = std::env::temp_dir().join(format!("control_{}.sock", std::process::id()));
+        let broadcast = Arc::new(Broadcast::default());
+        let (tx, rx) = mpsc::channel();
+        let server = serve(&path, Arc::clone(&broadcast), tx.clone()).unwrap();
+        assert!(serve(&path, Arc::clone(&broadcast), tx).is_err());
+        // Stands in for the monitoring loop.
+        thread::spawn(move || {
+            for request in rx {
+                let request: Request = request;
+                if let Command::Status(_) = request.command {
+                    request.line("1.1.1.1 state=up".to_string());
+                    request.done(Ok(()));
+                } else {
+                    request.done(Err("not here".to_string()));
+                }
+            }
+        });
+
+        let mut client = UnixStream::connect(&path).unwrap();
+        writeln!(client, "nonsense\nstatus\npause\nattach").unwrap();
+        let mut lines = BufReader::new(client.try_clone().unwrap()).lines();
+        let mut next = || lines.next().unwrap().unwrap();
+        assert_eq!(next(), "error unknown command 'nonsense'");
+        assert_eq!(next(), "1.1.1.1 state=up");
+        assert_eq!(next(), "ok");
+        assert_eq!(next(), "error not here");
+        broadcast.publish(&[
+            ui::Line::plain("IP  Status".to_string()),
+            ui::Line {
+                text: "10.0.0.1".to_string(),
+                style: ui::Style::Critical,
+                selected: true,
+            },
+        ]);
+        assert_eq!(next(), "frame 2");
+        assert_eq!(next(), "P IP  Status");
+        let critical = decode(&next());
+        assert_eq!(
+            (critical.text.as_str(), critical.style),
+            ("10.0.0.1", ui::Style::Critical)
+        );
+
+        assert_eq!(query(&path, "status").unwrap(), ["1.1.1.1 state=up"]);
+        assert!(matches!(query(&path, "pause"), Err(RunError::Invalid { .. })));
+
+        drop(server);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,81 @@
+use std::{collections::VecDeque, sync::Mutex};
+
+use chrono::{Local, TimeZone};
+
+use crate::store;
+
+/// Entries kept; older ones are dropped.
+const CAPACITY: usize = 500;
+
+// Global like `overhead`, so probe workers can record without threading a
+// handle through every call. Unlike the timeline these are the tool's own
+// workings (workers starting and stopping, overruns, reloads, pauses), for
+// finding out why a row went quiet rather than what the network did.
+static RING: Mutex<Ring> = Mutex::new(Ring::new());
+
+pub fn record(text: impl Into<String>) {
+    if let Ok(mut ring) = RING.lock() {
+        ring.push(store::now_ms(), text.into());
+    }
+}
+
+/// The last `count` entries, oldest first, with their time of day.
+pub fn recent(count: usize) -> Vec<String> {
+    RING.lock()
+        .map(|ring| ring.lines(count))
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+struct Ring {
+    entries: VecDeque<(i64, String)>,
+}
+
+impl Ring {
+    const fn new() -> Ring {
+        Ring {
+            entries: VecDeque::new(),
+        }
+    }
+
+    fn push(&mut self, ts_ms: i64, text: String) {
+        if self.entries.len() == CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back((ts_ms, text));
+    }
+
+    fn lines(&self, count: usize) -> Vec<String> {
+        self.entries
+            .iter()
+            .skip(self.entries.len().saturating_sub(count))
+            .map(|(ts_ms, text)| {
+                let time = Local
+                    .timestamp_millis_opt(*ts_ms)
+                    .single()
+                    .map(|t| t.format("%H:%M:%S%.3f").to_string())
+                    .unwrap_or_default();
+                format!("{time}  {text}")
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_latest_entries() {
+        let mut ring = Ring::new();
+        for i in 0..CAPACITY + 3 {
+            ring.push(0, format!("entry {i}"));
+        }
+        let all = ring.lines(usize::MAX);
+        assert_eq!(all.len(), CAPACITY);
+        assert!(all[0].ends_with("  entry 3"));
+        let last = ring.lines(2);
+        assert_eq!(last.len(), 2);
+        assert!(last[1].ends_with(&format!("  entry {}", CAPACITY + 2)));
+    }
+}
@@ -0,0 +1,289 @@
+use std::{net::IpAddr, time::Duration};
+
+use chrono::{Local, TimeZone};
+
+use crate::{Stats, events, graph, locale, parse::ProbeOutcome, report, severity, ui};
+
+/// Samples listed in the detail panel.
+pub const SAMPLES: usize = 50;
+const SAMPLES_PER_LINE: usize = 5;
+// Latest alerts and outages shown; older ones are in the log.
+const RECENT: usize = 10;
+
+/// Everything known about one target, for the detail panel (`Enter`).
+pub struct TargetDetail<'a> {
+    pub target: &'a str,
+    /// Where a host name currently resolves to (`Target::address`).
+    pub address: Option<IpAddr>,
+    pub stats: Stats,
+    pub severity: severity::Severity,
+    pub health: Option<&'a severity::Health>,
+    pub rules: &'a severity::Rules,
+    pub auto_disable_after: Option<Duration>,
+    pub acked: bool,
+    pub disabled: bool,
+    pub outages: &'a [report::Outage],
+    pub history: Option<&'a graph::History>,
+    pub timeline: &'a events::Timeline,
+    pub numbers: locale::Numbers,
+    pub now_ms: i64,
+    /// One line per failure kind instead of the compact list (`x`).
+    pub expanded: bool,
+}
+
+fn failure_label(kind: &str) -> &str {
+    match kind {
+        "timeout" => "Timeout",
+        "unreachable" => "unerreichbar",
+        "ttl_exceeded" => "TTL abgelaufen",
+        "prohibited" => "verboten",
+        "spawn_error" => "Startfehler",
+        "unparsed" => "nicht lesbar",
+        "local_down" => "lokales Netz weg",
+        other => other,
+    }
+}
+
+fn clock(ts_ms: i64, format: &str) -> String {
+    Local
+        .timestamp_millis_opt(ts_ms)
+        .single()
+        .map(|t| t.format(format).to_string())
+        .unwrap_or_default()
+}
+
+impl TargetDetail<'_> {
+    pub fn lines(&self) -> Vec<ui::Line> {
+        let fmt = |v: Option<f64>| v.map_or_else(|| "-".to_string(), |n| self.numbers.fixed(n, 2));
+        let mut state = self.severity.label().to_string();
+        if let Some(since) = self.health.and_then(|h| h.failing_since()) {
+            state.push_str(&format!(
+                ", nicht erreichbar seit {} ({}s)",
+                clock(since, "%Y-%m-%d %H:%M:%S"),
+                (self.now_ms - since) / 1000
+            ));
+        }
+        if self.acked {
+            state.push_str(", quittiert (ACK)");
+        }
+        if self.disabled {
+            state.push_str(", deaktiviert");
+        }
+        let s = &self.stats;
+        let mut thresholds = format!(
+            "Warnung ab {:.0}% Verlust in {}s, kritisch nach {}s",
+            self.rules.warning_loss_pct,
+            self.rules.window.as_secs(),
+            self.rules.critical_after.as_secs()
+        );
+        if let Some(limit) = self.auto_disable_after {
+            thresholds.push_str(&format!(", deaktivieren nach {}s", limit.as_secs()));
+        }
+        let mut lines = vec![
+            format!(
+                "Details: {} (Enter/Esc schließen, ↑/↓ anderes Ziel, x Fehlschläge)",
+                self.target
+            ),
+            String::new(),
+        ];
+        if let Some(addr) = self.address {
+            lines.push(format!("Adresse:       {addr} (per DNS)"));
+        }
+        lines.extend([
+            format!("Zustand:       {state}"),
+            format!(
+                "Statistik:     {}/{} erfolgreich, Verlust {}%, min/avg/max {}/{}/{} ms, \
+                 missed {}, ohne RTT {}",
+                s.success,
+                s.total,
+                s.loss_pct()
+                    .map_or_else(|| "-".to_string(), |l| self.numbers.fixed(l, 1)),
+                fmt(s.min_ms),
+                fmt(s.avg_ms()),
+                fmt(s.max_ms),
+                s.missed,
+                s.no_rtt()
+            ),
+        ]);
+        let failures = s.failures();
+        let failed: u64 = failures.iter().map(|(_, count)| count).sum();
+        if self.expanded {
+            lines.push(format!("Fehlschläge:   {failed} (x zuklappen)"));
+            for (kind, count) in failures {
+                let share = if failed > 0 {
+                    count as f64 * 100.0 / failed as f64
+                } else {
+                    0.0
+                };
+                lines.push(format!(
+                    "  {:<18} {count:>6} {:>8} %",
+                    failure_label(kind),
+                    self.numbers.fixed(share, 1)
+                ));
+            }
+        } else {
+            let kinds: Vec<String> = failures
+                .iter()
+                .filter(|(_, count)| *count > 0)
+                .map(|(kind, count)| format!("{} {count}", failure_label(kind)))
+                .collect();
+            let kinds = if kinds.is_empty() {
+                String::new()
+            } else {
+                format!(": {}", kinds.join(", "))
+            };
+            lines.push(format!("Fehlschläge:   {failed}{kinds} (x aufschlüsseln)"));
+        }
+        lines.push(format!("Schwellen:     {thresholds}"));
+        lines.push(String::new());
+
+        let mut outages: Vec<(i64, Option<i64>)> = self
+            .outages
+            .iter()
+            .filter(|o| o.target == self.target)
+            .map(|o| (o.start_ms, o.end_ms))
+            .collect();
+        if let Some(since) = self.health.and_then(|h| h.failing_since()) {
+            outages.push((since, None));
+        }
+        lines.push(format!("Ausfälle ({}):", outages.len()));
+        for (start, end) in outages.iter().skip(outages.len().saturating_sub(RECENT)) {
+            let until = end.map_or_else(|| "andauernd".to_string(), |e| clock(e, "%H:%M:%S"));
+            let secs = (end.unwrap_or(self.now_ms) - start) / 1000;
+            lines.push(format!(
+                "  {} – {until} ({secs}s)",
+                clock(*start, "%Y-%m-%d %H:%M:%S")
+            ));
+        }
+
+        let alerts: Vec<&events::Event> = self.timeline.for_target(self.target).collect();
+        lines.push(String::new());
+        lines.push(format!("Meldungen ({}):", alerts.len()));
+        let mut out: Vec<ui::Line> = lines.into_iter().map(ui::Line::plain).collect();
+        for event in alerts.iter().skip(alerts.len().saturating_sub(RECENT)) {
+            out.push(ui::Line {
+                text: format!("  {}", event.line()),
+                style: event.kind.style(),
+                selected: false,
+            });
+        }
+
+        out.push(ui::Line::plain(String::new()));
+        out.push(ui::Line::plain(format!("Letzte {SAMPLES} Proben:")));
+        let samples: Vec<String> = self
+            .history
+            .into_iter()
+            .flat_map(|h| h.recent(SAMPLES))
+            .map(|(ts, outcome)| {
+                let value = match outcome {
+                    ProbeOutcome::Reply(ms) => format!("{} ms", self.numbers.fixed(*ms, 2)),
+                    ProbeOutcome::Lost(loss) => loss.label().to_string(),
+                    ProbeOutcome::Unparsed => "?".to_string(),
+                };
+                format!("{} {value:<12}", clock(*ts, "%H:%M:%S"))
+            })
+            .collect();
+        for chunk in samples.chunks(SAMPLES_PER_LINE) {
+            out.push(ui::Line::plain(format!("  {}", chunk.join(" ").trim_end())));
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Loss;
+
+    #[test]
+    fn lists_state_outages_alerts_and_samples() {
+        let rules = severity::Rules {
+            warning_loss_pct: 20.0,
+            window: Duration::from_secs(60),
+            critical_after: Duration::from_secs(30),
+        };
+        let mut health = severity::Health::default();
+        let mut history = graph::History::default();
+        let mut stats = Stats::default();
+        for (tick, outcome) in [
+            ProbeOutcome::Reply(2.0),
+            ProbeOutcome::Lost(Loss::Unreachable),
+            ProbeOutcome::Lost(Loss::Unreachable),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            let ts = 1_700_000_000_000 + tick as i64 * 2_000;
+            health.update(&rules, ts, outcome.success());
+            history.record(ts, outcome);
+            stats.record(tick as u64, outcome);
+        }
+        let mut timeline = events::Timeline::default();
+        timeline.push(
+            0,
+            events::Kind::Down,
+            Some("10.0.0.1"),
+            "unreachable".to_string(),
+        );
+        timeline.push(
+            0,
+            events::Kind::Down,
+            Some("10.0.0.2"),
+            "timeout".to_string(),
+        );
+        let outages = [report::Outage {
+            target: "10.0.0.1".to_string(),
+            start_ms: 1_699_999_000_000,
+            end_ms: Some(1_699_999_010_000),
+        }];
+        let detail = TargetDetail {
+            target: "10.0.0.1",
+            address: None,
+            stats,
+            severity: severity::Severity::Warning,
+            health: Some(&health),
+            rules: &rules,
+            auto_disable_after: None,
+            acked: true,
+            disabled: false,
+            outages: &outages,
+            history: Some(&history),
+            timeline: &timeline,
+            numbers: locale::Numbers::Comma,
+            now_ms: 1_700_000_010_000,
+            expanded: false,
+        };
+        let text: Vec<String> = detail.lines().into_iter().map(|l| l.text).collect();
+        let find = |prefix: &str| text.iter().find(|l| l.starts_with(prefix)).unwrap().clone();
+        assert!(
+            find("Zustand:").contains("(8s), quittiert (ACK)"),
+            "{text:?}"
+        );
+        assert_eq!(
+            find("Fehlschläge:"),
+            "Fehlschläge:   2: unerreichbar 2 (x aufschlüsseln)"
+        );
+        assert_eq!(find("Ausfälle"), "Ausfälle (2):");
+        assert!(text.iter().any(|l| l.ends_with("(10s)")), "{text:?}");
+        assert_eq!(find("Meldungen"), "Meldungen (1):");
+        let samples = text.last().unwrap();
+        assert!(
+            samples.contains("2,00 ms") && samples.ends_with("unreachable"),
+            "{samples}"
+        );
+
+        let expanded = TargetDetail {
+            expanded: true,
+            ..detail
+        };
+        let text: Vec<String> = expanded.lines().into_iter().map(|l| l.text).collect();
+        assert!(
+            text.contains(&"  unerreichbar            2    100,0 %".to_string()),
+            "{text:?}"
+        );
+        assert!(
+            text.contains(&"  lokales Netz weg        0      0,0 %".to_string()),
+            "{text:?}"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+/// Free space is looked up at most this often.
+const CHECK_EVERY: Duration = Duration::from_secs(10);
+
+/// Bytes available to unprivileged writers on the volume holding `dir`.
+#[cfg(unix)]
+pub fn free_bytes(dir: &Path) -> io::Result<u64> {
+    use std::{ffi::CString, mem, os::unix::ffi::OsStrExt};
+
+    let path = CString::new(dir.as_os_str().as_bytes())
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?;
+    // SAFETY: statvfs is plain data, filled in below.
+    let mut st: libc::statvfs = unsafe { mem::zeroed() };
+    // SAFETY: `path` is NUL-terminated and `st` outlives the call.
+    if unsafe { libc::statvfs(path.as_ptr(), &mut st) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(st.f_bavail as u64 * st.f_frsize as u64)
+}
+
+#[cfg(not(unix))]
+pub fn free_bytes(_dir: &Path) -> io::Result<u64> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "free space is only checked on Unix systems",
+    ))
+}
+
+/// The directory a file is written in, for `free_bytes`.
+pub fn dir_of(file: &Path) -> PathBuf {
+    match file.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+/// The guard switched modes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// `dir` is the fullest volume, with `free` bytes left.
+    Low {
+        dir: PathBuf,
+        free: u64,
+    },
+    Recovered {
+        free: u64,
+    },
+}
+
+impl Change {
+    pub fn text(&self) -> String {
+        let mb = |bytes: u64| bytes / 1_000_000;
+        match self {
+            Change::Low { dir, free } => format!(
+                "low disk space: {} MB free on {}; raw samples are no longer written \
+                 (store, CSV, JSON Lines samples), events and the final state still are",
+                mb(*free),
+                dir.display()
+            ),
+            Change::Recovered { free } => {
+                format!(
+                    "disk space back to {} MB; writing raw samples again",
+                    mb(*free)
+                )
+            }
+        }
+    }
+}
+
+/// `--min-free-space`: watches the volumes of the log, CSV and store, and
+/// reports when raw sample persistence should pause because one of them is
+/// nearly full, so a long incident ends with its summary instead of a
+/// write error. Resumes once 10% above the threshold, so a volume hovering
+/// at the limit doesn't toggle every check.
+#[derive(Debug)]
+pub struct Guard {
+    min_free: u64,
+    dirs: Vec<PathBuf>,
+    low: bool,
+    next_check: Option<Instant>,
+}
+
+impl Guard {
+    pub fn new(min_free: u64, dirs: Vec<PathBuf>) -> Guard {
+        Guard {
+            min_free,
+            dirs,
+            low: false,
+            next_check: None,
+        }
+    }
+
+    /// Whether raw samples are held back.
+    pub fn is_low(&self) -> bool {
+        self.low
+    }
+
+    /// Looks up the free space if due. Volumes that can't be queried are
+    /// skipped.
+    pub fn check(&mut self, now: Instant) -> Option<Change> {
+        if self.min_free == 0 || self.next_check.is_some_and(|due| now < due) {
+            return None;
+        }
+        self.next_check = Some(now + CHECK_EVERY);
+        let (dir, free) = self
+            .dirs
+            .iter()
+            .filter_map(|dir| free_bytes(dir).ok().map(|free| (dir.clone(), free)))
+            .min_by_key(|(_, free)| *free)?;
+        self.update(&dir, free)
+    }
+
+    fn update(&mut self, dir: &Path, free: u64) -> Option<Change> {
+        if !self.low && free < self.min_free {
+            self.low = true;
+            Some(Change::Low {
+                dir: dir.to_path_buf(),
+                free,
+            })
+        } else if self.low && free >= self.min_free + self.min_free / 10 {
+            self.low = false;
+            Some(Change::Recovered { free })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pauses_below_the_threshold_and_resumes_above_the_margin() {
+        let mut guard = Guard::new(100, Vec::new());
+        let dir = Path::new("/data");
+        assert_eq!(guard.update(dir, 150), None);
+        assert_eq!(
+            guard.update(dir, 99),
+            Some(Change::Low {
+                dir: dir.to_path_buf(),
+                free: 99
+            })
+        );
+        assert!(guard.is_low());
+        assert_eq!(guard.update(dir, 105), None);
+        assert_eq!(
+            guard.update(dir, 110),
+            Some(Change::Recovered { free: 110 })
+        );
+        assert!(!guard.is_low());
+        assert_eq!(dir_of(Path::new("result.txt")), PathBuf::from("."));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_the_free_space_of_a_directory() {
+        assert!(free_bytes(&std::env::temp_dir()).is_ok());
+        let mut guard = Guard::new(u64::MAX, vec![std::env::temp_dir()]);
+        let now = Instant::now();
+        assert!(matches!(guard.check(now), Some(Change::Low { .. })));
+        // Not checked again before `CHECK_EVERY`.
+        guard.low = false;
+        assert_eq!(guard.check(now + Duration::from_secs(1)), None);
+    }
+}
@@ -0,0 +1,73 @@
+use std::{error::Error, fmt, io};
+
+/// Why `run` could not finish its job. `main` prints it and exits with
+/// `exit_code`, so embedders and tests get the cause instead of an exit.
+#[derive(Debug)]
+pub enum RunError {
+    /// The target list is missing, unreadable or empty.
+    Targets(String),
+    /// The simulation profile could not be loaded.
+    Profile(String),
+    /// Reading or writing a file or the sample store failed; `context` says
+    /// what was being done.
+    Io { context: String, source: io::Error },
+    /// An input file was read but its content is unusable.
+    Invalid { context: String, message: String },
+    /// Flags that parse on their own but don't fit together.
+    Usage(String),
+    /// `once`: these targets did not answer. Not a failure of the tool, but
+    /// scripts tell it apart by the exit code.
+    Unreachable(Vec<String>),
+}
+
+impl RunError {
+    pub fn io(context: impl Into<String>) -> impl FnOnce(io::Error) -> RunError {
+        let context = context.into();
+        move |source| RunError::Io { context, source }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunError::Unreachable(_) => 2,
+            _ => 1,
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RunError::Targets(message) => write!(f, "{message}"),
+            RunError::Profile(message) => write!(f, "Invalid simulation profile: {message}"),
+            RunError::Io { context, source } => write!(f, "{context}: {source}"),
+            RunError::Invalid { context, message } => write!(f, "{context}: {message}"),
+            RunError::Usage(message) => write!(f, "{message}"),
+            RunError::Unreachable(targets) => write!(f, "unreachable: {}", targets.join(", ")),
+        }
+    }
+}
+
+impl Error for RunError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RunError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_exit_codes_and_keeps_the_io_cause() {
+        let err = RunError::io("Failed to create out.csv")(io::Error::other("disk full"));
+        assert_eq!(err.to_string(), "Failed to create out.csv: disk full");
+        assert_eq!(err.exit_code(), 1);
+        assert!(err.source().is_some());
+        let err = RunError::Unreachable(vec!["10.0.0.1".to_string()]);
+        assert_eq!(err.exit_code(), 2);
+        assert_eq!(err.to_string(), "unreachable: 10.0.0.1");
+    }
+}
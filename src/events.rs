@@ -0,0 +1,171 @@
+use std::collections::VecDeque;
+
+use chrono::{Local, TimeZone};
+
+use crate::ui;
+
+// Older events are dropped; the log file keeps the full history.
+const CAPACITY: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Down,
+    Recovered,
+    /// Most targets failed at once (`--connectivity-loss`), or came back.
+    Connectivity,
+    /// A target started or stopped breaking `--latency-budget`.
+    Budget,
+    Warning,
+    Critical,
+    Normal,
+    Ack,
+    Disabled,
+    Enabled,
+    /// A Wake-on-LAN packet went to a down target (`--wake-after`).
+    Wake,
+    Targets,
+    Schedule,
+    Note,
+    /// Raw samples paused for low disk space (`--min-free-space`), or resumed.
+    Disk,
+}
+
+impl Kind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Kind::Down => "DOWN",
+            Kind::Recovered => "UP",
+            Kind::Connectivity => "NETWORK",
+            Kind::Budget => "BUDGET",
+            Kind::Warning => "WARNING",
+            Kind::Critical => "CRITICAL",
+            Kind::Normal => "INFO",
+            Kind::Ack => "ACK",
+            Kind::Disabled => "DISABLED",
+            Kind::Enabled => "ENABLED",
+            Kind::Wake => "WAKE",
+            Kind::Targets => "TARGETS",
+            Kind::Schedule => "SCHEDULE",
+            Kind::Note => "NOTE",
+            Kind::Disk => "DISK",
+        }
+    }
+
+    pub fn style(self) -> ui::Style {
+        match self {
+            Kind::Down | Kind::Connectivity | Kind::Critical | Kind::Disabled => {
+                ui::Style::Critical
+            }
+            Kind::Warning | Kind::Budget | Kind::Disk => ui::Style::Warning,
+            Kind::Ack => ui::Style::Acked,
+            _ => ui::Style::Plain,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    pub ts_ms: i64,
+    pub kind: Kind,
+    pub target: Option<String>,
+    pub text: String,
+}
+
+impl Event {
+    pub fn line(&self) -> String {
+        let time = Local
+            .timestamp_millis_opt(self.ts_ms)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        match &self.target {
+            Some(target) => format!("{time}  {:<9} {target}: {}", self.kind.label(), self.text),
+            None => format!("{time}  {:<9} {}", self.kind.label(), self.text),
+        }
+    }
+}
+
+/// Recent events of the run in the order they happened, for the events view.
+#[derive(Debug, Default)]
+pub struct Timeline {
+    events: VecDeque<Event>,
+}
+
+impl Timeline {
+    pub fn push(&mut self, ts_ms: i64, kind: Kind, target: Option<&str>, text: String) {
+        if self.events.len() == CAPACITY {
+            self.events.pop_front();
+        }
+        let target = target.map(String::from);
+        self.events.push_back(Event {
+            ts_ms,
+            kind,
+            target,
+            text,
+        });
+    }
+
+    /// Events about one target, oldest first.
+    pub fn for_target<'a>(&'a self, target: &'a str) -> impl Iterator<Item = &'a Event> + 'a {
+        self.events
+            .iter()
+            .filter(move |e| e.target.as_deref() == Some(target))
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// A page of events scrolled just far enough to show `cursor`, which is
+    /// highlighted.
+    pub fn lines(&self, cursor: usize, page: usize, highlight: bool) -> Vec<ui::Line> {
+        let start = (cursor + 1).saturating_sub(page);
+        self.events
+            .iter()
+            .enumerate()
+            .skip(start)
+            .take(page)
+            .map(|(idx, event)| ui::Line {
+                text: event.line(),
+                style: event.kind.style(),
+                selected: highlight && idx == cursor,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pages_follow_cursor_and_old_events_drop() {
+        let mut timeline = Timeline::default();
+        for i in 0..CAPACITY + 5 {
+            let target = (i % 2 == 0).then_some("10.0.0.1");
+            timeline.push(i as i64 * 1000, Kind::Note, target, format!("event {i}"));
+        }
+        assert_eq!(timeline.len(), CAPACITY);
+        let lines = timeline.lines(10, 3, true);
+        assert_eq!(lines.len(), 3);
+        assert!(
+            lines[0].text.ends_with("NOTE      event 13"),
+            "{}",
+            lines[0].text
+        );
+        assert!(
+            lines[1].text.ends_with("NOTE      10.0.0.1: event 14"),
+            "{}",
+            lines[1].text
+        );
+        assert_eq!(timeline.for_target("10.0.0.1").count(), CAPACITY / 2);
+        assert!(lines[2].selected && !lines[0].selected);
+        let top = timeline.lines(1, 20, true);
+        assert_eq!(top.len(), 20);
+        assert!(top[1].selected);
+    }
+}
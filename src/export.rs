@@ -0,0 +1,462 @@
+use std::{
+    fs,
+    io::{self, BufRead, BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    anonymize::Pseudonyms,
+    connectivity,
+    error::RunError,
+    locale,
+    lock::Lock,
+    schema,
+    store::{self, Gap, Sample},
+};
+
+const CSV_HEADER: &str = "ts_ms,time,target,success,rtt_ms,connectivity_lost,not_running_until";
+const CSV_HEADER_V3: &str = "ts_ms,time,target,success,rtt_ms,connectivity_lost";
+const CSV_HEADER_V2: &str = "ts_ms,time,target,success,rtt_ms";
+
+/// Version 1 was a bare JSON array or a CSV file starting with the column
+/// header; version 2 wraps JSON in `{"schema", "samples"}` and puts a
+/// `schema::header` line above the CSV columns; version 3 marks samples from
+/// connectivity-lost rounds (`connectivity_lost`); version 4 lists the
+/// times the monitor wasn't running (`gaps`, CSV rows with only
+/// `not_running_until`). Readers accept all.
+pub const SCHEMA_VERSION: u32 = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Json,
+    Csv,
+}
+
+/// JSON rows carry the ISO time next to `ts_ms` and the connectivity mark,
+/// like the CSV columns; `read_samples` ignores both.
+#[derive(Serialize)]
+struct JsonSample<'a> {
+    #[serde(flatten)]
+    sample: &'a Sample,
+    time: String,
+    connectivity_lost: bool,
+}
+
+#[derive(Serialize)]
+struct JsonGap {
+    #[serde(flatten)]
+    gap: Gap,
+    start: String,
+    end: String,
+}
+
+#[derive(Serialize)]
+struct JsonExport<'a> {
+    schema: u32,
+    samples: Vec<JsonSample<'a>>,
+    gaps: Vec<JsonGap>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum JsonInput {
+    Versioned { schema: u32, samples: Vec<Sample> },
+    V1(Vec<Sample>),
+}
+
+/// Writes `samples`; rounds of `interval` in which at least `fraction` of
+/// the targets failed (`connectivity::lost_rounds`) are marked
+/// `connectivity_lost`. `gaps` (from `store::read_gaps`) are written as
+/// such, so the missing samples don't read as uptime.
+pub fn write_samples(
+    samples: &[Sample],
+    gaps: &[Gap],
+    format: Format,
+    fraction: f64,
+    interval: Duration,
+    out: &mut dyn Write,
+) -> io::Result<()> {
+    let lost = connectivity::lost_rounds(samples, fraction, interval);
+    let is_lost = |s: &Sample| lost.contains(&connectivity::round_of(s.ts_ms, interval));
+    match format {
+        Format::Json => {
+            let export = JsonExport {
+                schema: SCHEMA_VERSION,
+                samples: samples
+                    .iter()
+                    .map(|sample| JsonSample {
+                        sample,
+                        time: locale::iso_ms(sample.ts_ms),
+                        connectivity_lost: is_lost(sample),
+                    })
+                    .collect(),
+                gaps: gaps
+                    .iter()
+                    .map(|&gap| JsonGap {
+                        gap,
+                        start: locale::iso_ms(gap.start_ms),
+                        end: locale::iso_ms(gap.end_ms),
+                    })
+                    .collect(),
+            };
+            serde_json::to_writer_pretty(&mut *out, &export).map_err(io::Error::other)?;
+            writeln!(out)
+        }
+        Format::Csv => {
+            writeln!(out, "{}", schema::header(schema::EXPORT, SCHEMA_VERSION))?;
+            writeln!(out, "{CSV_HEADER}")?;
+            let mut gaps = gaps.iter().peekable();
+            for s in samples {
+                while let Some(gap) = gaps.next_if(|g| g.start_ms <= s.ts_ms) {
+                    write_gap(out, gap)?;
+                }
+                let time = locale::iso_ms(s.ts_ms);
+                let rtt = s
+                    .latency_ms
+                    .map(|ms| format!("{ms:.3}"))
+                    .unwrap_or_default();
+                writeln!(
+                    out,
+                    "{},{},{},{},{},{},",
+                    s.ts_ms,
+                    time,
+                    s.target,
+                    if s.success { 1 } else { 0 },
+                    rtt,
+                    if is_lost(s) { 1 } else { 0 }
+                )?;
+            }
+            for gap in gaps {
+                write_gap(out, gap)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+/// A row without target, result or mark: no data from its time to
+/// `not_running_until`.
+fn write_gap(out: &mut dyn Write, gap: &Gap) -> io::Result<()> {
+    writeln!(
+        out,
+        "{},{},,,,,{}",
+        gap.start_ms,
+        locale::iso_ms(gap.start_ms),
+        locale::iso_ms(gap.end_ms)
+    )
+}
+
+pub fn read_samples(format: Format, input: &mut dyn BufRead) -> Result<Vec<Sample>, String> {
+    match format {
+        Format::Json => {
+            let mut text = String::new();
+            input
+                .read_to_string(&mut text)
+                .map_err(|err| err.to_string())?;
+            match serde_json::from_str(&text).map_err(|err| format!("invalid JSON: {err}"))? {
+                JsonInput::Versioned { schema, samples } => {
+                    check_version(schema)?;
+                    Ok(samples)
+                }
+                JsonInput::V1(samples) => Ok(samples),
+            }
+        }
+        Format::Csv => {
+            let mut samples = Vec::new();
+            for (idx, line) in input.lines().enumerate() {
+                let line = line.map_err(|err| err.to_string())?;
+                let line = line.trim();
+                if idx == 0
+                    && let Some(version) = schema::parse_header(schema::EXPORT, line)
+                {
+                    check_version(version)?;
+                    continue;
+                }
+                let header = [CSV_HEADER, CSV_HEADER_V3, CSV_HEADER_V2].contains(&line);
+                if line.is_empty() || (idx <= 1 && header) || is_gap_row(line) {
+                    continue;
+                }
+                let sample = parse_csv_row(line)
+                    .ok_or_else(|| format!("invalid CSV row on line {}: {line}", idx + 1))?;
+                samples.push(sample);
+            }
+            Ok(samples)
+        }
+    }
+}
+
+fn check_version(version: u32) -> Result<(), String> {
+    if version > SCHEMA_VERSION {
+        return Err(format!(
+            "export schema {version} is newer than this version of ping-plotter supports \
+             ({SCHEMA_VERSION})"
+        ));
+    }
+    Ok(())
+}
+
+/// Gaps aren't samples; an import leaves them out like every other time
+/// without samples.
+fn is_gap_row(line: &str) -> bool {
+    line.split(',')
+        .nth(6)
+        .is_some_and(|until| !until.is_empty())
+}
+
+fn parse_csv_row(line: &str) -> Option<Sample> {
+    let fields: Vec<&str> = line.split(',').collect();
+    // The connectivity mark (version 3) is derived, so it isn't read back.
+    let (&[ts_ms, _time, target, success, rtt]
+    | &[ts_ms, _time, target, success, rtt, _]
+    | &[ts_ms, _time, target, success, rtt, _, _]) = fields.as_slice()
+    else {
+        return None;
+    };
+    if target.is_empty() {
+        return None;
+    }
+    Some(Sample {
+        ts_ms: ts_ms.parse().ok()?,
+        target: target.to_string(),
+        success: match success {
+            "1" => true,
+            "0" => false,
+            _ => return None,
+        },
+        latency_ms: if rtt.is_empty() {
+            None
+        } else {
+            Some(rtt.parse().ok()?)
+        },
+    })
+}
+
+/// `ping-plotter export`: the store's samples (newer than `since`) and gaps
+/// to `output` or stdout.
+pub fn run(
+    store_dir: &Path,
+    since: Option<Duration>,
+    format: Format,
+    connectivity_loss: f64,
+    interval: Duration,
+    pseudonyms: Option<Pseudonyms>,
+    output: Option<&Path>,
+) -> Result<(), RunError> {
+    let now_ms = store::now_ms();
+    let since_ms = since.map(|d| now_ms - d.as_millis() as i64);
+    let context = format!("Failed to read sample store {}", store_dir.display());
+    let mut samples =
+        store::read_samples(store_dir, since_ms).map_err(RunError::io(context.clone()))?;
+    let gaps = store::read_gaps(store_dir, since_ms, now_ms).map_err(RunError::io(context))?;
+    if let Some(mut pseudonyms) = pseudonyms {
+        pseudonyms.samples(&mut samples);
+        pseudonyms.save()?;
+    }
+    let mut out: Box<dyn Write> = match output {
+        Some(path) => Box::new(BufWriter::new(
+            fs::File::create(path)
+                .map_err(RunError::io(format!("Failed to create {}", path.display())))?,
+        )),
+        None => Box::new(io::stdout().lock()),
+    };
+    write_samples(
+        &samples,
+        &gaps,
+        format,
+        connectivity_loss,
+        interval,
+        &mut out,
+    )
+    .and_then(|_| out.flush())
+    .map_err(RunError::io("Failed to write export"))
+}
+
+/// `ping-plotter import`: appends the samples of an export (`-` for stdin)
+/// to the store, holding its lock.
+pub fn import(store_dir: &Path, format: Format, input: &Path, force: bool) -> Result<(), RunError> {
+    let _lock = Lock::dir(store_dir, force)?;
+    let context = format!("Failed to read {}", input.display());
+    let parsed = if input == Path::new("-") {
+        read_samples(format, &mut io::stdin().lock())
+    } else {
+        let file = fs::File::open(input).map_err(RunError::io(context.clone()))?;
+        read_samples(format, &mut io::BufReader::new(file))
+    };
+    let samples = parsed.map_err(|message| RunError::Invalid { context, message })?;
+    let mut sample_store = store::Store::open(store_dir).map_err(RunError::io(format!(
+        "Failed to open sample store {}",
+        store_dir.display()
+    )))?;
+    for sample in &samples {
+        sample_store
+            .append(sample)
+            .map_err(RunError::io("Failed to write to sample store"))?;
+    }
+    sample_store
+        .flush()
+        .map_err(RunError::io("Failed to flush sample store"))?;
+    eprintln!(
+        "Imported {} samples into {}",
+        samples.len(),
+        store_dir.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::DEFAULT_INTERVAL;
+
+    fn samples() -> Vec<Sample> {
+        vec![
+            Sample {
+                ts_ms: 1_700_000_000_000,
+                target: "1.1.1.1".to_string(),
+                success: true,
+                latency_ms: Some(12.5),
+            },
+            Sample {
+                ts_ms: 1_700_000_002_000,
+                target: "1.1.1.1".to_string(),
+                success: false,
+                latency_ms: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn round_trips_both_formats() {
+        for format in [Format::Json, Format::Csv] {
+            let mut buf = Vec::new();
+            write_samples(&samples(), &[], format, 1.0, DEFAULT_INTERVAL, &mut buf).unwrap();
+            let back = read_samples(format, &mut buf.as_slice()).unwrap();
+            assert_eq!(back, samples(), "format {format:?}");
+        }
+    }
+
+    #[test]
+    fn rows_carry_rfc3339_time() {
+        let mut buf = Vec::new();
+        write_samples(
+            &samples(),
+            &[],
+            Format::Csv,
+            1.0,
+            DEFAULT_INTERVAL,
+            &mut buf,
+        )
+        .unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(
+            text.starts_with("# ping-plotter export v4\nts_ms,"),
+            "{text}"
+        );
+        assert!(text.contains("1700000000000,2023-11-14T22:13:20.000Z,1.1.1.1,1,12.500,0,"));
+
+        let mut buf = Vec::new();
+        write_samples(
+            &samples(),
+            &[],
+            Format::Json,
+            1.0,
+            DEFAULT_INTERVAL,
+            &mut buf,
+        )
+        .unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert!(
+            text.contains("\"time\": \"2023-11-14T22:13:20.000Z\""),
+            "{text}"
+        );
+        assert!(text.contains("\"latency_ms\": 12.5"), "{text}");
+        assert!(text.contains("\"schema\": 4"), "{text}");
+    }
+
+    #[test]
+    fn reads_version_one_and_rejects_newer_versions() {
+        let v1_json = r#"[{"ts_ms": 1700000000000, "target": "1.1.1.1", "success": true,
+            "latency_ms": 12.5}]"#;
+        let back = read_samples(Format::Json, &mut v1_json.as_bytes()).unwrap();
+        assert_eq!(back, samples()[..1]);
+        let v1_csv = format!("{CSV_HEADER_V2}\n1700000000000,x,1.1.1.1,1,12.5\n");
+        let back = read_samples(Format::Csv, &mut v1_csv.as_bytes()).unwrap();
+        assert_eq!(back, samples()[..1]);
+
+        let v3_csv = format!("{CSV_HEADER_V3}\n1700000000000,x,1.1.1.1,1,12.5,0\n");
+        let back = read_samples(Format::Csv, &mut v3_csv.as_bytes()).unwrap();
+        assert_eq!(back, samples()[..1]);
+
+        let newer = r#"{"schema": 5, "samples": []}"#;
+        let err = read_samples(Format::Json, &mut newer.as_bytes()).unwrap_err();
+        assert!(err.contains("schema 5"), "{err}");
+        let newer = format!("# ping-plotter export v5\n{CSV_HEADER}\n");
+        assert!(read_samples(Format::Csv, &mut newer.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn marks_rounds_where_every_target_failed() {
+        let mut all = samples();
+        all.push(Sample {
+            ts_ms: 1_700_000_002_004,
+            target: "8.8.8.8".to_string(),
+            success: false,
+            latency_ms: None,
+        });
+        let mut buf = Vec::new();
+        write_samples(&all, &[], Format::Csv, 1.0, DEFAULT_INTERVAL, &mut buf).unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        let marks: Vec<&str> = text.lines().skip(2).map(|l| &l[l.len() - 2..]).collect();
+        assert_eq!(marks, ["0,", "1,", "1,"], "{text}");
+    }
+
+    #[test]
+    fn marks_the_times_the_monitor_was_not_running() {
+        let gap = Gap {
+            start_ms: 1_700_000_001_000,
+            end_ms: 1_700_003_600_000,
+        };
+        let mut buf = Vec::new();
+        write_samples(
+            &samples(),
+            &[gap],
+            Format::Csv,
+            1.0,
+            DEFAULT_INTERVAL,
+            &mut buf,
+        )
+        .unwrap();
+        let text = String::from_utf8(buf).unwrap();
+        assert_eq!(
+            text.lines().nth(3),
+            Some("1700000001000,2023-11-14T22:13:21.000Z,,,,,2023-11-14T23:13:20.000Z"),
+            "{text}"
+        );
+        assert_eq!(
+            read_samples(Format::Csv, &mut text.as_bytes()).unwrap(),
+            samples()
+        );
+
+        let mut buf = Vec::new();
+        write_samples(
+            &samples(),
+            &[gap],
+            Format::Json,
+            1.0,
+            DEFAULT_INTERVAL,
+            &mut buf,
+        )
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_slice(&buf).unwrap();
+        assert_eq!(json["gaps"][0]["end"], "2023-11-14T23:13:20.000Z");
+        assert_eq!(json["gaps"][0]["start_ms"], 1_700_000_001_000_i64);
+        assert_eq!(
+            read_samples(Format::Json, &mut buf.as_slice()).unwrap(),
+            samples()
+        );
+    }
+}
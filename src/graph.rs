@@ -0,0 +1,101 @@
+use std::collections::VecDeque;
+
+use crate::parse::ProbeOutcome;
+
+/// Probes shown per target in the graphs view (two minutes at 2s).
+pub const WIDTH: usize = 60;
+
+/// Probes in the table's `Verlauf` column.
+pub const TABLE_WIDTH: usize = 20;
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Latest probe outcomes of one target, for its sparkline and detail view.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    samples: VecDeque<(i64, ProbeOutcome)>,
+}
+
+impl History {
+    pub fn record(&mut self, ts_ms: i64, outcome: ProbeOutcome) {
+        if self.samples.len() == WIDTH {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((ts_ms, outcome));
+    }
+
+    /// Up to `count` of the most recent samples, oldest first.
+    pub fn recent(&self, count: usize) -> impl Iterator<Item = &(i64, ProbeOutcome)> {
+        self.samples
+            .iter()
+            .skip(self.samples.len().saturating_sub(count))
+    }
+
+    /// Lowest and highest RTT in the window.
+    pub fn range(&self) -> Option<(f64, f64)> {
+        self.samples
+            .iter()
+            .filter_map(|(_, o)| o.rtt_ms())
+            .fold(None, |acc, ms| match acc {
+                None => Some((ms, ms)),
+                Some((lo, hi)) => Some((lo.min(ms), hi.max(ms))),
+            })
+    }
+
+    /// One bar per probe scaled to the window's range; `×` marks a lost
+    /// probe, `?` a reply without readable RTT.
+    pub fn sparkline(&self) -> String {
+        self.recent_sparkline(WIDTH)
+    }
+
+    /// Like `sparkline`, for the last `count` probes and scaled to theirs.
+    pub fn recent_sparkline(&self, count: usize) -> String {
+        let (lo, hi) = self
+            .recent(count)
+            .filter_map(|(_, o)| o.rtt_ms())
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), ms| {
+                (lo.min(ms), hi.max(ms))
+            });
+        self.recent(count)
+            .map(|(_, outcome)| match outcome {
+                ProbeOutcome::Reply(ms) if hi > lo => {
+                    let level = (ms - lo) / (hi - lo) * (BARS.len() - 1) as f64;
+                    BARS[level.round() as usize]
+                }
+                ProbeOutcome::Reply(_) => BARS[0],
+                ProbeOutcome::Lost(_) => '×',
+                ProbeOutcome::Unparsed => '?',
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Loss;
+
+    #[test]
+    fn sparkline_scales_to_window_and_marks_losses() {
+        let mut history = History::default();
+        for outcome in [
+            ProbeOutcome::Reply(10.0),
+            ProbeOutcome::Reply(45.0),
+            ProbeOutcome::Lost(Loss::Timeout),
+            ProbeOutcome::Reply(80.0),
+            ProbeOutcome::Unparsed,
+        ] {
+            history.record(0, outcome);
+        }
+        assert_eq!(history.sparkline(), "▁▅×█?");
+        assert_eq!(history.recent_sparkline(3), "×▁?");
+        assert_eq!(history.range(), Some((10.0, 80.0)));
+
+        for ts in 0..WIDTH as i64 {
+            history.record(ts, ProbeOutcome::Reply(5.0));
+        }
+        assert_eq!(history.sparkline(), "▁".repeat(WIDTH));
+        let recent: Vec<i64> = history.recent(2).map(|(ts, _)| *ts).collect();
+        assert_eq!(recent, vec![WIDTH as i64 - 2, WIDTH as i64 - 1]);
+    }
+}
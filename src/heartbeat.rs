@@ -0,0 +1,234 @@
+use std::{
+    process::Command,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::{sinks::Health, store};
+
+/// Seconds `curl` may take for one heartbeat.
+const SEND_TIMEOUT_S: &str = "10";
+
+/// What a heartbeat reports: `ok` while every target answers, `degraded`
+/// with the failing ones otherwise. Receivers like healthchecks.io keep the
+/// body; what matters to them is that it keeps coming.
+#[derive(Debug, Serialize)]
+pub struct Body<'a> {
+    pub status: &'static str,
+    pub targets: usize,
+    pub down: Vec<&'a str>,
+    pub rounds: u64,
+}
+
+impl<'a> Body<'a> {
+    pub fn new(targets: usize, down: Vec<&'a str>, rounds: u64) -> Body<'a> {
+        Body {
+            status: if down.is_empty() { "ok" } else { "degraded" },
+            targets,
+            down,
+            rounds,
+        }
+    }
+}
+
+/// `--heartbeat-url`: POSTs a `Body` every `--heartbeat-every`, so an
+/// external check notices when the monitor itself stops. Sent with `curl`
+/// on a thread, like the system `ping`, so a slow receiver never holds up
+/// the frame; one still in flight delays the next.
+#[derive(Debug)]
+pub struct Heartbeat {
+    url: String,
+    every: Duration,
+    next: Instant,
+    running: Option<mpsc::Receiver<Result<(), String>>>,
+    health: Health,
+}
+
+impl Heartbeat {
+    /// The first heartbeat is due right away.
+    pub fn new(url: String, every: Duration) -> Heartbeat {
+        Heartbeat {
+            url,
+            every,
+            next: Instant::now(),
+            running: None,
+            health: Health::default(),
+        }
+    }
+
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    pub fn due(&self, now: Instant) -> bool {
+        self.running.is_none() && now >= self.next
+    }
+
+    pub fn send(&mut self, now: Instant, body: &Body) {
+        self.next = now + self.every;
+        let (tx, rx) = mpsc::channel();
+        let url = self.url.clone();
+        let body = serde_json::to_string(body).unwrap_or_default();
+        thread::spawn(move || {
+            let _ = tx.send(post(&url, &body));
+        });
+        self.running = Some(rx);
+    }
+
+    /// Records a finished send. Returns a line for the log when delivery
+    /// starts failing or works again.
+    pub fn poll(&mut self) -> Option<String> {
+        let result = match self.running.as_ref()?.try_recv() {
+            Ok(result) => result,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => Err("sender stopped".to_string()),
+        };
+        self.running = None;
+        self.record(result)
+    }
+
+    fn record(&mut self, result: Result<(), String>) -> Option<String> {
+        let failing = self.health.last_error.is_some();
+        match result {
+            Ok(()) => {
+                self.health.succeeded(store::now_ms());
+                failing.then(|| format!("heartbeat to {} delivered again", self.url))
+            }
+            Err(err) => {
+                let text = format!("heartbeat to {} failed: {err}", self.url);
+                self.health.failed(err);
+                (!failing).then_some(text)
+            }
+        }
+    }
+}
+
+fn post(url: &str, body: &str) -> Result<(), String> {
+    let output = Command::new("curl")
+        .args(["-fsS", "-m", SEND_TIMEOUT_S, "-X", "POST"])
+        .args([
+            "-H",
+            "Content-Type: application/json",
+            "--data-binary",
+            body,
+            url,
+        ])
+        .output()
+        .map_err(|err| format!("failed to run curl: {err}"))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim().to_string();
+    Err(if stderr.is_empty() {
+        format!("curl {}", output.status)
+    } else {
+        stderr
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://hc.example/ping/x";
+
+    #[test]
+    fn the_body_lists_the_failing_targets() {
+        let body = Body::new(3, vec!["10.0.0.2"], 42);
+        assert_eq!(
+            serde_json::to_string(&body).unwrap(),
+            r#"{"status":"degraded","targets":3,"down":["10.0.0.2"],"rounds":42}"#
+        );
+        assert_eq!(Body::new(3, Vec::new(), 42).status, "ok");
+    }
+
+    #[test]
+    fn a_run_without_targets_or_rounds_is_ok() {
+        assert_eq!(
+            serde_json::to_string(&Body::new(0, Vec::new(), 0)).unwrap(),
+            r#"{"status":"ok","targets":0,"down":[],"rounds":0}"#
+        );
+    }
+
+    #[test]
+    fn due_right_away_then_every_interval() {
+        let heartbeat = Heartbeat::new(URL.to_string(), Duration::from_secs(60));
+        let start = heartbeat.next;
+        assert!(heartbeat.due(start));
+        // `send` moves `next` on; without the thread here, set it directly.
+        let heartbeat = Heartbeat {
+            next: start + heartbeat.every,
+            ..heartbeat
+        };
+        assert!(!heartbeat.due(start + Duration::from_secs(59)));
+        assert!(heartbeat.due(start + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn not_due_while_one_is_in_flight() {
+        let mut heartbeat = Heartbeat::new(URL.to_string(), Duration::ZERO);
+        let (tx, rx) = mpsc::channel();
+        heartbeat.running = Some(rx);
+        assert!(!heartbeat.due(Instant::now()));
+        assert_eq!(heartbeat.poll(), None);
+        tx.send(Ok(())).unwrap();
+        assert_eq!(heartbeat.poll(), None);
+        assert!(heartbeat.due(Instant::now()));
+        assert_eq!(heartbeat.health().ok, 1);
+    }
+
+    #[test]
+    fn nothing_to_poll_before_the_first_send() {
+        let mut heartbeat = Heartbeat::new(URL.to_string(), Duration::ZERO);
+        assert_eq!(heartbeat.poll(), None);
+        assert_eq!((heartbeat.health().ok, heartbeat.health().failed), (0, 0));
+    }
+
+    #[test]
+    fn a_stopped_sender_counts_as_a_failure() {
+        let mut heartbeat = Heartbeat::new(URL.to_string(), Duration::ZERO);
+        let (tx, rx) = mpsc::channel();
+        heartbeat.running = Some(rx);
+        drop(tx);
+        assert_eq!(
+            heartbeat.poll().as_deref(),
+            Some("heartbeat to https://hc.example/ping/x failed: sender stopped")
+        );
+        assert!(heartbeat.running.is_none());
+    }
+
+    #[test]
+    fn logs_only_when_delivery_starts_failing_or_works_again() {
+        let mut heartbeat = Heartbeat::new(URL.to_string(), Duration::ZERO);
+        assert_eq!(heartbeat.record(Ok(())), None);
+        assert_eq!(
+            heartbeat.record(Err("timed out".to_string())).as_deref(),
+            Some("heartbeat to https://hc.example/ping/x failed: timed out")
+        );
+        // Only the first of several failures is logged.
+        assert_eq!(heartbeat.record(Err("timed out".to_string())), None);
+        assert_eq!(
+            heartbeat.record(Ok(())).as_deref(),
+            Some("heartbeat to https://hc.example/ping/x delivered again")
+        );
+        assert_eq!(heartbeat.record(Ok(())), None);
+        assert_eq!((heartbeat.health().ok, heartbeat.health().failed), (3, 2));
+    }
+
+    #[test]
+    fn a_first_send_that_fails_is_logged() {
+        let mut heartbeat = Heartbeat::new(URL.to_string(), Duration::ZERO);
+        assert!(
+            heartbeat
+                .record(Err("curl exit status: 6".to_string()))
+                .is_some()
+        );
+        assert_eq!(
+            heartbeat.health().last_error.as_deref(),
+            Some("curl exit status: 6")
+        );
+    }
+}
@@ -0,0 +1,346 @@
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, ToSocketAddrs},
+    sync::atomic::{AtomicU16, Ordering},
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+
+use crate::parse::{Loss, ProbeOutcome};
+
+/// `--engine`: how probes are sent.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum EngineKind {
+    /// One system `ping` process per probe.
+    System,
+    /// ICMP echo from this process, falling back to `system` when the
+    /// sockets aren't permitted.
+    Native,
+}
+
+/// The ICMP socket the native engine could open. A datagram socket needs
+/// no privileges on macOS and on Linux within `net.ipv4.ping_group_range`;
+/// a raw socket needs root or `CAP_NET_RAW`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Socket {
+    Datagram,
+    Raw,
+}
+
+impl Socket {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Socket::Datagram => "ICMP-Datagram-Socket",
+            Socket::Raw => "Raw-Socket",
+        }
+    }
+}
+
+const ECHO_REPLY: u8 = 0;
+const DEST_UNREACHABLE: u8 = 3;
+const ECHO_REQUEST: u8 = 8;
+const TIME_EXCEEDED: u8 = 11;
+const PAYLOAD: &[u8; 16] = b"ping-plotter....";
+
+/// Sequence numbers shared by all workers, so concurrent probes on raw
+/// sockets (which see every reply) can tell theirs apart.
+static SEQUENCE: AtomicU16 = AtomicU16::new(0);
+
+/// The privilege check: the first socket type that can be opened.
+pub fn detect() -> io::Result<Socket> {
+    sys::open(Socket::Datagram)
+        .map(|_| Socket::Datagram)
+        .or_else(|_| sys::open(Socket::Raw).map(|_| Socket::Raw))
+}
+
+/// What allows `detect` to succeed, for the warning when it fails: on
+/// Linux `CAP_NET_RAW` on the binary or the user's group in
+/// `net.ipv4.ping_group_range`.
+pub fn permission_hint() -> String {
+    if cfg!(target_os = "linux") {
+        let exe = std::env::current_exe()
+            .map(|p| p.display().to_string())
+            .unwrap_or_else(|_| "ping-plotter".to_string());
+        format!(
+            "allow it with `sudo setcap cap_net_raw+ep {exe}`, or for all groups with \
+             `sudo sysctl -w net.ipv4.ping_group_range=\"0 2147483647\"`"
+        )
+    } else if cfg!(unix) {
+        "run it as root to allow raw ICMP sockets".to_string()
+    } else {
+        "the native engine needs a Unix system".to_string()
+    }
+}
+
+/// One echo request to `target`. `None` when the native engine can't
+/// reach it (no IPv4 address, or the socket failed to open); the caller
+/// uses the system `ping` then.
+pub fn probe(socket: Socket, target: &str, timeout: Duration) -> Option<ProbeOutcome> {
+    let addr = ipv4_of(target)?;
+    sys::probe(socket, addr, timeout)
+}
+
+fn ipv4_of(target: &str) -> Option<Ipv4Addr> {
+    match target.parse::<IpAddr>() {
+        Ok(IpAddr::V4(addr)) => Some(addr),
+        Ok(IpAddr::V6(_)) => None,
+        Err(_) => (target, 0)
+            .to_socket_addrs()
+            .ok()?
+            .find_map(|a| match a.ip() {
+                IpAddr::V4(addr) => Some(addr),
+                IpAddr::V6(_) => None,
+            }),
+    }
+}
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u32::from(u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)])))
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn echo_request(id: u16, seq: u16) -> [u8; 24] {
+    let mut packet = [0u8; 24];
+    packet[0] = ECHO_REQUEST;
+    packet[4..6].copy_from_slice(&id.to_be_bytes());
+    packet[6..8].copy_from_slice(&seq.to_be_bytes());
+    packet[8..].copy_from_slice(PAYLOAD);
+    let sum = checksum(&packet);
+    packet[2..4].copy_from_slice(&sum.to_be_bytes());
+    packet
+}
+
+/// What a received packet says about our request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Answer {
+    Echo,
+    Error(Loss),
+}
+
+/// Raw sockets (and datagram sockets on macOS) deliver the IP header too.
+fn strip_ip_header(packet: &[u8]) -> &[u8] {
+    match packet.first() {
+        Some(b) if b >> 4 == 4 => packet.get(usize::from(b & 0x0f) * 4..).unwrap_or(&[]),
+        _ => packet,
+    }
+}
+
+/// Matches a received packet against the request `seq`; `id` is checked
+/// only on raw sockets, since the kernel picks it for datagram sockets.
+fn answer(packet: &[u8], id: Option<u16>, seq: u16) -> Option<Answer> {
+    let icmp = strip_ip_header(packet);
+    if icmp.len() < 8 {
+        return None;
+    }
+    let is_ours = |header: &[u8]| {
+        header.len() >= 8
+            && u16::from_be_bytes([header[6], header[7]]) == seq
+            && id.is_none_or(|id| u16::from_be_bytes([header[4], header[5]]) == id)
+    };
+    match icmp[0] {
+        ECHO_REPLY if is_ours(icmp) => Some(Answer::Echo),
+        // Errors quote the IP header and the first 8 bytes of our request.
+        kind @ (DEST_UNREACHABLE | TIME_EXCEEDED) if is_ours(strip_ip_header(&icmp[8..])) => {
+            Some(Answer::Error(match (kind, icmp[1]) {
+                (TIME_EXCEEDED, _) => Loss::TtlExceeded,
+                // Communication administratively prohibited (9, 10, 13).
+                (_, 9 | 10 | 13) => Loss::Prohibited,
+                _ => Loss::Unreachable,
+            }))
+        }
+        _ => None,
+    }
+}
+
+/// Socket errors of a send or receive, in terms of the loss reasons.
+fn os_loss(err: &io::Error) -> Loss {
+    match err.kind() {
+        io::ErrorKind::NetworkUnreachable => Loss::LocalDown,
+        io::ErrorKind::HostUnreachable | io::ErrorKind::ConnectionRefused => Loss::Unreachable,
+        io::ErrorKind::PermissionDenied => Loss::Prohibited,
+        _ => Loss::Error,
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use std::mem;
+
+    use super::*;
+
+    /// Closes the socket on drop.
+    pub struct Fd(libc::c_int);
+
+    impl Drop for Fd {
+        fn drop(&mut self) {
+            // SAFETY: the descriptor is owned and closed only here.
+            unsafe { libc::close(self.0) };
+        }
+    }
+
+    pub fn open(socket: Socket) -> io::Result<Fd> {
+        let kind = match socket {
+            Socket::Datagram => libc::SOCK_DGRAM,
+            Socket::Raw => libc::SOCK_RAW,
+        };
+        // SAFETY: plain syscall; the result is checked before use.
+        let fd = unsafe { libc::socket(libc::AF_INET, kind, libc::IPPROTO_ICMP) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Fd(fd))
+    }
+
+    fn sockaddr(addr: Ipv4Addr) -> libc::sockaddr_in {
+        // SAFETY: all-zero is a valid `sockaddr_in`.
+        let mut sa: libc::sockaddr_in = unsafe { mem::zeroed() };
+        sa.sin_family = libc::AF_INET as libc::sa_family_t;
+        sa.sin_addr.s_addr = u32::from(addr).to_be();
+        sa
+    }
+
+    fn set_timeout(fd: &Fd, timeout: Duration) -> io::Result<()> {
+        let tv = libc::timeval {
+            tv_sec: timeout.as_secs() as libc::time_t,
+            // Zero would mean "no timeout".
+            tv_usec: timeout.subsec_micros().max(1) as libc::suseconds_t,
+        };
+        // SAFETY: `tv` outlives the call and its size is passed along.
+        let rc = unsafe {
+            libc::setsockopt(
+                fd.0,
+                libc::SOL_SOCKET,
+                libc::SO_RCVTIMEO,
+                (&tv as *const libc::timeval).cast(),
+                mem::size_of::<libc::timeval>() as libc::socklen_t,
+            )
+        };
+        if rc < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn probe(socket: Socket, addr: Ipv4Addr, timeout: Duration) -> Option<ProbeOutcome> {
+        let fd = open(socket).ok()?;
+        let seq = SEQUENCE.fetch_add(1, Ordering::Relaxed);
+        let id = (socket == Socket::Raw).then(|| std::process::id() as u16);
+        let packet = echo_request(id.unwrap_or(0), seq);
+        let dest = sockaddr(addr);
+        let start = Instant::now();
+        // SAFETY: buffer and address are valid for the given lengths.
+        let sent = unsafe {
+            libc::sendto(
+                fd.0,
+                packet.as_ptr().cast(),
+                packet.len(),
+                0,
+                (&dest as *const libc::sockaddr_in).cast(),
+                mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+            )
+        };
+        if sent < 0 {
+            return Some(ProbeOutcome::Lost(os_loss(&io::Error::last_os_error())));
+        }
+        let deadline = start + timeout;
+        let mut buf = [0u8; 1500];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || set_timeout(&fd, remaining).is_err() {
+                return Some(ProbeOutcome::Lost(Loss::Timeout));
+            }
+            // SAFETY: all-zero is a valid `sockaddr_in`.
+            let mut from: libc::sockaddr_in = unsafe { mem::zeroed() };
+            let mut from_len = mem::size_of::<libc::sockaddr_in>() as libc::socklen_t;
+            // SAFETY: buffer and address are valid for the given lengths.
+            let len = unsafe {
+                libc::recvfrom(
+                    fd.0,
+                    buf.as_mut_ptr().cast(),
+                    buf.len(),
+                    0,
+                    (&mut from as *mut libc::sockaddr_in).cast(),
+                    &mut from_len,
+                )
+            };
+            if len < 0 {
+                let err = io::Error::last_os_error();
+                match err.kind() {
+                    io::ErrorKind::Interrupted => continue,
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => {
+                        return Some(ProbeOutcome::Lost(Loss::Timeout));
+                    }
+                    _ => return Some(ProbeOutcome::Lost(os_loss(&err))),
+                }
+            }
+            let from_addr = Ipv4Addr::from(u32::from_be(from.sin_addr.s_addr));
+            match answer(&buf[..len as usize], id, seq) {
+                Some(Answer::Echo) if from_addr == addr => {
+                    return Some(ProbeOutcome::Reply(start.elapsed().as_secs_f64() * 1000.0));
+                }
+                Some(Answer::Error(loss)) => return Some(ProbeOutcome::Lost(loss)),
+                // Someone else's reply on a raw socket.
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use super::*;
+
+    pub fn open(_socket: Socket) -> io::Result<()> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the native engine needs a Unix system",
+        ))
+    }
+
+    pub fn probe(_socket: Socket, _addr: Ipv4Addr, _timeout: Duration) -> Option<ProbeOutcome> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_replies_and_errors_to_the_request() {
+        let request = echo_request(0x1234, 7);
+        assert_eq!(checksum(&request), 0);
+
+        let mut reply = request;
+        reply[0] = ECHO_REPLY;
+        assert_eq!(answer(&reply, Some(0x1234), 7), Some(Answer::Echo));
+        assert_eq!(answer(&reply, Some(0x1234), 8), None);
+        assert_eq!(answer(&reply, Some(0x4321), 7), None);
+        // The kernel chooses the id of datagram sockets.
+        assert_eq!(answer(&reply, None, 7), Some(Answer::Echo));
+
+        // Behind a 20-byte IP header, as on raw sockets.
+        let mut ip = vec![0x45u8];
+        ip.resize(20, 0);
+        let with_header = [ip.as_slice(), &reply].concat();
+        assert_eq!(answer(&with_header, Some(0x1234), 7), Some(Answer::Echo));
+
+        // Host unreachable / prohibited / TTL exceeded, quoting our request.
+        for (kind, code, loss) in [
+            (DEST_UNREACHABLE, 1, Loss::Unreachable),
+            (DEST_UNREACHABLE, 13, Loss::Prohibited),
+            (TIME_EXCEEDED, 0, Loss::TtlExceeded),
+        ] {
+            let error = [&[kind, code, 0, 0, 0, 0, 0, 0][..], &ip, &request[..8]].concat();
+            assert_eq!(answer(&error, None, 7), Some(Answer::Error(loss)));
+        }
+    }
+}
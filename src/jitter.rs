@@ -0,0 +1,109 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{Target, locale, parse::ProbeOutcome};
+
+/// `--jitter-buffer` without sizes: the usual range for real-time audio.
+pub const DEFAULT_BUFFERS: &str = "20,40,60";
+
+/// RTTs are kept as 0.1 ms buckets, which is finer than `ping` reports.
+const SCALE: f64 = 10.0;
+
+/// Estimated playout of one target's probes through a fixed jitter buffer.
+/// The fastest reply stands for a packet that arrives without queueing;
+/// a packet is discarded when it is lost or when it arrives later than that
+/// plus the buffer. The RTT stands in for the one-way delay, so this errs
+/// on the side of too many late packets.
+#[derive(Debug, Clone, Default)]
+pub struct Playout {
+    rtts: BTreeMap<u64, u64>,
+    lost: u64,
+    probes: u64,
+}
+
+impl Playout {
+    pub fn record(&mut self, outcome: ProbeOutcome) {
+        match outcome.rtt_ms() {
+            Some(ms) => *self.rtts.entry((ms * SCALE).round() as u64).or_default() += 1,
+            // A reply without an RTT can't be placed in time.
+            None if outcome.success() => return,
+            None => self.lost += 1,
+        }
+        self.probes += 1;
+    }
+
+    /// Percentage of probes lost or too late for a buffer of `buffer_ms`.
+    pub fn discard_pct(&self, buffer_ms: u32) -> Option<f64> {
+        if self.probes == 0 {
+            return None;
+        }
+        let late = match self.rtts.keys().next() {
+            Some(&fastest) => {
+                let deadline = fastest + (f64::from(buffer_ms) * SCALE) as u64;
+                self.rtts
+                    .range(deadline + 1..)
+                    .map(|(_, count)| count)
+                    .sum()
+            }
+            None => 0,
+        };
+        Some((self.lost + late) as f64 * 100.0 / self.probes as f64)
+    }
+}
+
+/// The jitter buffer section below the table, one column per buffer size.
+pub fn lines(
+    targets: &[Target],
+    playouts: &HashMap<String, Playout>,
+    buffers: &[u32],
+    numbers: locale::Numbers,
+) -> Vec<String> {
+    let mut header = format!("{:<20}", "Jitter-Puffer");
+    for buffer in buffers {
+        header.push_str(&format!(" {:>10}", format!("{buffer} ms")));
+    }
+    let mut lines = vec![
+        "Verworfen (verloren oder später als schnellste Antwort + Puffer):".to_string(),
+        header,
+    ];
+    for target in targets {
+        let mut line = format!("{:<20}", target.name);
+        for &buffer in buffers {
+            let pct = playouts
+                .get(&target.name)
+                .and_then(|p| p.discard_pct(buffer));
+            let text =
+                pct.map_or_else(|| "-".to_string(), |p| format!("{} %", numbers.fixed(p, 1)));
+            line.push_str(&format!(" {text:>10}"));
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Loss;
+
+    #[test]
+    fn larger_buffers_discard_fewer_late_packets() {
+        let mut playout = Playout::default();
+        for ms in [10.0, 12.0, 25.0, 35.0, 45.0, 55.0, 65.0, 75.0, 11.0] {
+            playout.record(ProbeOutcome::Reply(ms));
+        }
+        playout.record(ProbeOutcome::Lost(Loss::Timeout));
+        playout.record(ProbeOutcome::Unparsed);
+        // Deadlines 30/50/70 ms after the fastest reply of 10 ms.
+        assert_eq!(playout.discard_pct(20), Some(60.0));
+        assert_eq!(playout.discard_pct(40), Some(40.0));
+        assert_eq!(playout.discard_pct(60), Some(20.0));
+        assert_eq!(Playout::default().discard_pct(20), None);
+
+        let targets = [Target::new("1.1.1.1"), Target::new("8.8.8.8")];
+        let playouts = HashMap::from([(targets[0].name.clone(), playout)]);
+        let lines = lines(&targets, &playouts, &[20, 60], locale::Numbers::Point);
+        assert_eq!(lines[1], "Jitter-Puffer             20 ms      60 ms");
+        assert_eq!(lines[2], "1.1.1.1                  60.0 %     20.0 %");
+        assert_eq!(lines[3], "8.8.8.8                       -          -");
+    }
+}
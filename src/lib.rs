@@ -0,0 +1,67 @@
+//! Multi-target ping monitoring: `PingMonitor` probes the targets once per
+//! interval and streams `ProbeResult`s; a `Session` turns their rounds into
+//! stats, outages and events, and the other modules into what the
+//! `ping-plotter` binary shows, logs and exports. `run` does a whole run
+//! without the binary.
+
+use std::time::Duration;
+
+pub mod anonymize;
+pub mod app;
+pub mod bench;
+pub mod budget;
+pub mod clock;
+pub mod compare;
+pub mod config;
+pub mod connectivity;
+pub mod control;
+pub mod debuglog;
+pub mod detail;
+pub mod disk;
+pub mod error;
+pub mod events;
+pub mod export;
+pub mod graph;
+pub mod heartbeat;
+pub mod icmp;
+pub mod jitter;
+pub mod locale;
+pub mod lock;
+pub mod log;
+pub mod metrics;
+mod monitor;
+pub mod output;
+pub mod overhead;
+pub mod parse;
+pub mod preset;
+pub mod quality;
+pub mod quantile;
+pub mod report;
+pub mod resolver;
+pub mod schedule;
+pub mod schema;
+pub mod session;
+pub mod setup;
+pub mod severity;
+pub mod sim;
+pub mod sinks;
+pub mod sla;
+mod stats;
+pub mod store;
+pub mod targets;
+pub mod template;
+pub mod ui;
+pub mod updown;
+pub mod wan;
+pub mod windows;
+pub mod wol;
+
+pub use monitor::{
+    Engine, Options, PingMonitor, ProbeResult, Prober, RoundCollector, Target, ping_once, run_ping,
+};
+pub use session::{Session, run};
+pub use stats::{LossCounts, Stats};
+
+/// `--interval` default; files migrated without knowing the run's interval
+/// are grouped into rounds with it.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(2);
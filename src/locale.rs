@@ -0,0 +1,77 @@
+use std::env;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+
+// Languages writing decimals with a comma; regions listed in `POINT_REGIONS`
+// use a point despite the language (de_CH, it_CH).
+const COMMA_LANGUAGES: &[&str] = &[
+    "bg", "cs", "da", "de", "el", "es", "et", "fi", "fr", "hr", "hu", "id", "it", "lt", "lv", "nb",
+    "nl", "nn", "no", "pl", "pt", "ro", "ru", "sk", "sl", "sv", "tr", "uk",
+];
+const POINT_REGIONS: &[&str] = &["CH", "LI"];
+
+/// Timestamp for files and exports: ISO 8601 / RFC 3339 in UTC with
+/// milliseconds, independent of the system locale and time zone.
+pub fn iso_ms(ts_ms: i64) -> String {
+    DateTime::<Utc>::from_timestamp_millis(ts_ms)
+        .map(|t| t.to_rfc3339_opts(SecondsFormat::Millis, true))
+        .unwrap_or_default()
+}
+
+/// Decimal separator for numbers on screen (`--locale-numbers`). Exports,
+/// the log and templates always use `.`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Numbers {
+    #[default]
+    Point,
+    Comma,
+}
+
+impl Numbers {
+    /// Resolves the numeric locale like POSIX does: `LC_ALL`, then
+    /// `LC_NUMERIC`, then `LANG`.
+    pub fn from_env() -> Numbers {
+        ["LC_ALL", "LC_NUMERIC", "LANG"]
+            .iter()
+            .filter_map(|name| env::var(name).ok())
+            .find(|value| !value.is_empty())
+            .map_or_else(Numbers::default, |value| Numbers::from_locale(&value))
+    }
+
+    /// Separator for a locale name like `de_DE.UTF-8`; `C`, `POSIX` and
+    /// unknown languages keep the point.
+    pub fn from_locale(name: &str) -> Numbers {
+        let name = name.split(['.', '@']).next().unwrap_or_default();
+        let (language, region) = name.split_once(['_', '-']).unwrap_or((name, ""));
+        if COMMA_LANGUAGES.contains(&language) && !POINT_REGIONS.contains(&region) {
+            Numbers::Comma
+        } else {
+            Numbers::Point
+        }
+    }
+
+    pub fn fixed(self, value: f64, decimals: usize) -> String {
+        let text = format!("{value:.decimals$}");
+        match self {
+            Numbers::Point => text,
+            Numbers::Comma => text.replace('.', ","),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn picks_separator_from_locale_name() {
+        assert_eq!(Numbers::from_locale("de_DE.UTF-8"), Numbers::Comma);
+        assert_eq!(Numbers::from_locale("fr_FR@euro"), Numbers::Comma);
+        assert_eq!(Numbers::from_locale("de_CH.UTF-8"), Numbers::Point);
+        assert_eq!(Numbers::from_locale("en_US.UTF-8"), Numbers::Point);
+        assert_eq!(Numbers::from_locale("C"), Numbers::Point);
+        assert_eq!(Numbers::Comma.fixed(12.346, 2), "12,35");
+        assert_eq!(Numbers::Point.fixed(12.345, 1), "12.3");
+        assert_eq!(iso_ms(1_700_000_000_000), "2023-11-14T22:13:20.000Z");
+    }
+}
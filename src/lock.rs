@@ -0,0 +1,135 @@
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+use tracing::{info, warn};
+
+use crate::error::RunError;
+
+/// Lock file of a directory (the sample store), inside it.
+pub const DIR_LOCK: &str = "ping-plotter.lock";
+
+/// Holds the PID of the instance writing a log or store, so a duplicate
+/// started by accident (another tmux pane) stops instead of interleaving
+/// its writes with the first one's. Removed on drop.
+#[derive(Debug)]
+pub struct Lock {
+    path: PathBuf,
+}
+
+impl Lock {
+    /// `<file>.lock` next to a file the run appends to.
+    pub fn file(path: &Path, force: bool) -> Result<Lock, RunError> {
+        let mut name = path.as_os_str().to_owned();
+        name.push(".lock");
+        Lock::acquire(PathBuf::from(name), force)
+    }
+
+    /// `DIR_LOCK` in `dir`, which is created if missing.
+    pub fn dir(dir: &Path, force: bool) -> Result<Lock, RunError> {
+        let context = format!("Failed to create {}", dir.display());
+        fs::create_dir_all(dir).map_err(RunError::io(context))?;
+        Lock::acquire(dir.join(DIR_LOCK), force)
+    }
+
+    /// Takes the lock at `path`. One left behind by a process that is gone
+    /// is taken over; with `force` also one of a running process.
+    fn acquire(path: PathBuf, force: bool) -> Result<Lock, RunError> {
+        let context = format!("Failed to lock {}", path.display());
+        let own = std::process::id();
+        loop {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    writeln!(file, "{own}").map_err(RunError::io(context))?;
+                    return Ok(Lock { path });
+                }
+                Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+                Err(err) => return Err(RunError::io(context)(err)),
+            }
+            match owner(&path) {
+                Some(pid) if running(pid) && !force => {
+                    return Err(RunError::Invalid {
+                        context,
+                        message: format!(
+                            "in use by another ping-plotter (PID {pid}); stop that one or \
+                             start with --force"
+                        ),
+                    });
+                }
+                Some(pid) if running(pid) => {
+                    warn!("taking over {} from PID {pid} (--force)", path.display())
+                }
+                Some(pid) => info!("removing stale {} of PID {pid}", path.display()),
+                None => info!("removing unreadable {}", path.display()),
+            }
+            fs::remove_file(&path).map_err(RunError::io(context.clone()))?;
+        }
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        // After `--force` elsewhere the file may be someone else's now.
+        if owner(&self.path) == Some(std::process::id()) {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn owner(path: &Path) -> Option<u32> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+#[cfg(unix)]
+fn running(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 only checks that the process exists.
+    let alive = unsafe { libc::kill(pid, 0) } == 0;
+    // EPERM: it exists, but belongs to another user.
+    alive || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Without a cheap check the owner is assumed to still run; `--force`
+/// takes the lock over.
+#[cfg(not(unix))]
+fn running(_pid: u32) -> bool {
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn refuses_a_running_owner_and_replaces_a_stale_one() {
+        let log = std::env::temp_dir().join(format!("lock_{}.txt", std::process::id()));
+        let lock = Lock::file(&log, false).unwrap();
+        let path = lock.path.clone();
+        assert_eq!(owner(&path), Some(std::process::id()));
+        // Taken over by a running process (init) meanwhile: not ours to remove.
+        fs::write(&path, "1\n").unwrap();
+        drop(lock);
+        let err = Lock::file(&log, false).unwrap_err().to_string();
+        assert!(err.contains("(PID 1)"), "{err}");
+
+        let forced = Lock::file(&log, true).unwrap();
+        assert_eq!(owner(&path), Some(std::process::id()));
+        drop(forced);
+        assert!(!path.exists());
+
+        // No process has a PID this high.
+        fs::write(&path, "999999999\n").unwrap();
+        let lock = Lock::file(&log, false).unwrap();
+        assert_eq!(owner(&lock.path), Some(std::process::id()));
+        drop(lock);
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::Local;
+use clap::ValueEnum;
+use serde_json::json;
+use tracing::{error, warn};
+
+use crate::{
+    ProbeResult, locale,
+    output::{self, Retention},
+    overhead::{self, Phase},
+    schema, sinks, store,
+};
+
+/// `--log-format`: what goes into the log file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+    /// Timestamped event lines and the final state, for reading.
+    #[default]
+    Text,
+    /// One JSON object per line: every probe (`"type": "sample"`), the
+    /// events and the final state, for jq or pandas.
+    Jsonl,
+}
+
+/// The measurement log. Writes go through `event`, `line` and `sample`; the
+/// first failed write disables it, so a full disk doesn't stop the run.
+pub struct LogSink {
+    writer: Option<BufWriter<File>>,
+    path: PathBuf,
+    format: LogFormat,
+    retention: Option<Retention>,
+    health: sinks::Health,
+}
+
+impl LogSink {
+    /// Opens the log for appending. New text logs start with the version
+    /// header; older ones keep their content and get a hint to run `migrate`.
+    pub fn open(path: &Path, format: LogFormat) -> LogSink {
+        let mut health = sinks::Health::default();
+        let writer = match OpenOptions::new().create(true).append(true).open(path) {
+            Ok(file) => {
+                let mut writer = BufWriter::new(file);
+                let first = fs::read_to_string(path)
+                    .ok()
+                    .and_then(|c| c.lines().next().map(String::from));
+                match first {
+                    // JSON Lines files hold nothing but objects.
+                    _ if format == LogFormat::Jsonl => {}
+                    None => {
+                        let header = schema::header(schema::LOG, schema::LOG_VERSION);
+                        if let Err(err) = writeln!(writer, "{header}") {
+                            error!("Failed to write to log file {}: {err}", path.display());
+                        }
+                    }
+                    Some(line) if schema::parse_header(schema::LOG, &line).is_none() => warn!(
+                        "log file {} uses an older format; upgrade it with `ping-plotter migrate {}`",
+                        path.display(),
+                        path.display()
+                    ),
+                    Some(_) => {}
+                }
+                Some(writer)
+            }
+            Err(err) => {
+                error!("Failed to open log file {}: {err}", path.display());
+                health.failed(&err);
+                None
+            }
+        };
+        LogSink {
+            writer,
+            path: path.to_path_buf(),
+            format,
+            retention: None,
+            health,
+        }
+    }
+
+    /// A sink that writes nothing.
+    pub fn disabled() -> LogSink {
+        LogSink {
+            writer: None,
+            path: PathBuf::new(),
+            format: LogFormat::Text,
+            retention: None,
+            health: sinks::Health::default(),
+        }
+    }
+
+    /// `--retention`: like `CsvWriter::set_retention`, for the log's
+    /// events and samples.
+    pub fn set_retention(&mut self, keep: Duration) {
+        self.retention = Some(Retention::new(keep));
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn health(&self) -> &sinks::Health {
+        &self.health
+    }
+
+    /// Something that happened now, e.g. `target added: 10.0.0.1`; text logs
+    /// prefix the timestamp.
+    pub fn event(&mut self, text: &str) {
+        match self.format {
+            LogFormat::Text => {
+                self.write(&format!("[{}] {text}", schema::log_timestamp(Local::now())))
+            }
+            LogFormat::Jsonl => self.write(
+                &json!({
+                    "type": "event",
+                    "time": locale::iso_ms(store::now_ms()),
+                    "text": text,
+                })
+                .to_string(),
+            ),
+        }
+    }
+
+    /// A line already formatted for the text log: `--unreachable-template`
+    /// output and the final state.
+    pub fn line(&mut self, line: &str) {
+        match self.format {
+            LogFormat::Text => self.write(line),
+            LogFormat::Jsonl => self.write(&json!({ "type": "line", "text": line }).to_string()),
+        }
+    }
+
+    /// One recorded probe; only JSON Lines logs list them. `seq` is the
+    /// probe's tick.
+    pub fn sample(&mut self, result: &ProbeResult) {
+        if self.format != LogFormat::Jsonl {
+            return;
+        }
+        let record = json!({
+            "type": "sample",
+            "time": locale::iso_ms(result.ts_ms),
+            "target": result.ip,
+            "success": result.outcome.success(),
+            "rtt_ms": result.outcome.rtt_ms(),
+            "seq": result.tick,
+        });
+        self.write(&record.to_string());
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(w) = self.writer.as_mut()
+            && let Err(err) = w.flush()
+        {
+            error!("Failed to flush log file: {err}");
+            self.health.failed(err);
+        }
+        let Some(cutoff_ms) = self.retention.as_mut().and_then(Retention::due) else {
+            return;
+        };
+        if self.writer.take().is_none() {
+            return;
+        }
+        let format = self.format;
+        let time = |line: &str| match format {
+            LogFormat::Text => line
+                .strip_prefix('[')
+                .and_then(|rest| rest.split_once(']'))
+                .and_then(|(time, _)| output::parse_iso_ms(time)),
+            LogFormat::Jsonl => serde_json::from_str::<serde_json::Value>(line)
+                .ok()
+                .and_then(|record| output::parse_iso_ms(record["time"].as_str()?)),
+        };
+        if let Err(err) = output::prune_file(&self.path, cutoff_ms, time) {
+            error!("Failed to prune log file {}: {err}", self.path.display());
+            self.health.failed(err);
+        }
+        match OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+        {
+            Ok(file) => self.writer = Some(BufWriter::new(file)),
+            Err(err) => {
+                error!("Failed to reopen log file {}: {err}", self.path.display());
+                self.health.failed(err);
+            }
+        }
+    }
+
+    fn write(&mut self, line: &str) {
+        let Some(w) = self.writer.as_mut() else {
+            return;
+        };
+        match overhead::measure(Phase::Export, || writeln!(w, "{line}")) {
+            Ok(()) => self.health.succeeded(store::now_ms()),
+            Err(err) => {
+                error!("Failed to write to log file; disabling further logging");
+                self.health.failed(err);
+                self.writer = None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::{Loss, ProbeOutcome};
+
+    #[test]
+    fn jsonl_logs_every_sample_as_an_object() {
+        let path = std::env::temp_dir().join(format!("log_{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let mut log = LogSink::open(&path, LogFormat::Jsonl);
+        let mut result = ProbeResult {
+            ip: "10.0.0.1".to_string(),
+            tick: 7,
+            ts_ms: 1_700_000_000_000,
+            paused: false,
+            outcome: ProbeOutcome::Reply(1.5),
+            delay_ms: 0.0,
+            duration_ms: 0.0,
+        };
+        log.sample(&result);
+        result.outcome = ProbeOutcome::Lost(Loss::Timeout);
+        log.sample(&result);
+        log.event("target added: 10.0.0.2");
+        log.flush();
+        let text = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        let records: Vec<serde_json::Value> = text
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(
+            records[0],
+            json!({"type": "sample", "time": "2023-11-14T22:13:20.000Z", "target": "10.0.0.1",
+                   "success": true, "rtt_ms": 1.5, "seq": 7})
+        );
+        assert_eq!(
+            (&records[1]["success"], &records[1]["rtt_ms"]),
+            (&json!(false), &json!(null))
+        );
+        assert_eq!(records[2]["text"], "target added: 10.0.0.2");
+        assert_eq!(records.len(), 3);
+
+        // Text logs keep the header and skip the samples.
+        let mut log = LogSink::open(&path, LogFormat::Text);
+        log.sample(&result);
+        log.line("Final state:");
+        log.flush();
+        let text = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        let header = schema::header(schema::LOG, schema::LOG_VERSION);
+        assert_eq!(text, format!("{header}\nFinal state:\n"));
+    }
+}
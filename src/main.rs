@@ -1,36 +1,57 @@
 use std::{
     collections::HashMap,
     env,
-    fs,
     fs::OpenOptions,
-    io::{self, BufWriter, Write},
+    io,
+    net::SocketAddr,
     path::{Path, PathBuf},
-    process::{Command, Stdio},
-    sync::mpsc,
-    thread,
-    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
 };
 
-use chrono::Local;
-use clap::Parser;
-use crossterm::{
-    cursor::MoveTo,
-    execute,
-    terminal::{Clear, ClearType},
+use clap::{CommandFactory, Parser, Subcommand};
+use ping_plotter::{
+    anonymize, app, bench, compare, config, connectivity, control,
+    error::RunError,
+    export, icmp, jitter,
+    log::LogFormat,
+    preset, quality, schedule, schema, session, setup, severity, sla, targets, template, ui,
+    updown, wan,
 };
-use wait_timeout::ChildExt;
-
-const INTERVAL: Duration = Duration::from_secs(2);
-const PING_TIMEOUT_MS: u64 = 1900;
+use tracing::warn;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 
 #[derive(Parser, Debug)]
 #[command(name = "ping-plotter")]
 #[command(about = "Ping multiple IPs on a fixed interval and display stats", long_about = None)]
+// Flags from a --profile come first; repeating them on the command line overrides them.
+#[command(args_override_self = true)]
 struct Args {
+    /// Config file with named profiles (default: ping-plotter.toml next to the binary)
+    #[arg(long = "config", value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// Profile from the config file whose flags to use ([profile.NAME]; default: "default")
+    #[arg(long = "profile", value_name = "NAME")]
+    profile: Option<String>,
+
+    /// `[[target]]` entries of the config file, probed when no `--ips` is given.
+    #[arg(skip)]
+    config_targets: Option<config::ConfigTargets>,
+
     /// Run duration in seconds (omit to run forever)
     #[arg(short = 'd', long = "duration")]
     duration: Option<u64>,
 
+    /// Time between two probes of a target, e.g. 500ms or 5s
+    #[arg(long = "interval", value_parser = parse_duration, default_value = "2s")]
+    interval: Duration,
+
+    /// How long to wait for a reply; must be shorter than --interval [default: 1900ms, or 95% of
+    /// a shorter interval]
+    #[arg(long = "timeout", value_parser = parse_duration)]
+    timeout: Option<Duration>,
+
     /// Path to the IP list file
     #[arg(short = 'i', long = "ips")]
     ip_file: Option<PathBuf>,
@@ -38,447 +59,800 @@ struct Args {
     /// Path to the log file
     #[arg(short = 'l', long = "log")]
     log_file: Option<PathBuf>,
+
+    /// Log file format: timestamped text, or one JSON object per probe and event (jsonl)
+    #[arg(long = "log-format", value_enum, default_value = "text")]
+    log_format: LogFormat,
+
+    /// Also write every probe to this CSV file (RFC 3339 time, target, seq, success, RTT, loss)
+    #[arg(long = "csv", value_name = "PATH")]
+    csv: Option<PathBuf>,
+
+    /// Directory for the persistent sample store (disabled if omitted)
+    #[arg(long = "store")]
+    store_dir: Option<PathBuf>,
+
+    /// Increase diagnostic verbosity (-v info, -vv debug, -vvv trace)
+    #[arg(short = 'v', long = "verbose", action = clap::ArgAction::Count)]
+    verbose: u8,
+
+    /// Write internal diagnostics to this file instead of stderr
+    #[arg(long = "diagnostics")]
+    diagnostics: Option<PathBuf>,
+
+    /// Only probe while this cron expression matches (e.g. "* 9-16 * * MON-FRI")
+    #[arg(long = "schedule", value_parser = schedule::Schedule::parse)]
+    schedule: Option<schedule::Schedule>,
+
+    /// Template for the unreachable log line (variables: time, round, targets, count)
+    #[arg(long = "unreachable-template", value_parser = unreachable_template)]
+    unreachable_template: Option<template::Template>,
+
+    /// Template for the "Final state" log header (variables: time, rounds)
+    #[arg(long = "final-template", value_parser = final_template)]
+    final_template: Option<template::Template>,
+
+    /// Template for each target row of the final state (variables: target, success,
+    /// total, loss_pct, min_ms, avg_ms, max_ms, p50_ms, p95_ms, p99_ms, jitter_ms,
+    /// stddev_ms, missed)
+    #[arg(long = "final-row-template", value_parser = final_row_template)]
+    final_row_template: Option<template::Template>,
+
+    /// Loss percentage (over --severity-window) above which a target is a warning
+    #[arg(long = "warning-loss", default_value_t = 50.0)]
+    warning_loss: f64,
+
+    /// Window for the warning loss rule
+    #[arg(long = "severity-window", value_parser = parse_duration, default_value = "1m")]
+    severity_window: Duration,
+
+    /// Continuous failure time after which a target is critical
+    #[arg(long = "critical-after", value_parser = parse_duration, default_value = "5m")]
+    critical_after: Duration,
+
+    /// Consecutive failed probes after which a target is down (logged as DOWN)
+    #[arg(
+        long = "down-after",
+        value_name = "N",
+        value_parser = parse_count,
+        default_value_t = updown::DEFAULT_DOWN_AFTER
+    )]
+    down_after: u32,
+
+    /// Consecutive answered probes after which a failing target is up again (logged as UP)
+    #[arg(
+        long = "up-after",
+        value_name = "N",
+        value_parser = parse_count,
+        default_value_t = updown::DEFAULT_UP_AFTER
+    )]
+    up_after: u32,
+
+    /// Fraction of targets (0 < f <= 1) failing in the same round that counts as lost
+    /// connectivity: one log line and event instead of one per target
+    #[arg(
+        long = "connectivity-loss",
+        value_name = "FRACTION",
+        value_parser = parse_fraction,
+        default_value_t = connectivity::DEFAULT_FRACTION
+    )]
+    connectivity_loss: f64,
+
+    /// Latency budget in ms (e.g. 50 for gaming or VoIP): report the share of samples over it
+    /// and the bad seconds per hour, and log when a target starts or stops breaking it
+    #[arg(long = "latency-budget", value_name = "MS")]
+    latency_budget: Option<f64>,
+
+    /// Loss percentage within one --budget-window above which the window is bad
+    #[arg(long = "loss-budget", value_name = "PCT", default_value_t = 2.0)]
+    loss_budget: f64,
+
+    /// Length of the windows checked against the latency and loss budget
+    #[arg(long = "budget-window", value_parser = parse_duration, default_value = "10s")]
+    budget_window: Duration,
+
+    /// Estimate the share of packets a jitter buffer of these sizes in ms would discard
+    /// (lost or too late); without sizes 20, 40 and 60 ms
+    #[arg(
+        long = "jitter-buffer",
+        value_name = "MS,...",
+        value_delimiter = ',',
+        num_args = 0..=1,
+        default_missing_value = jitter::DEFAULT_BUFFERS
+    )]
+    jitter_buffers: Vec<u32>,
+
+    /// Number of initial rounds to show but exclude from statistics
+    #[arg(long = "warmup", default_value_t = 0)]
+    warmup: u64,
+
+    /// Add a smoothed latency column (EWMA) with this weight for new samples, 0 < alpha <= 1
+    #[arg(long = "smooth", value_name = "ALPHA", value_parser = parse_alpha)]
+    smooth: Option<f64>,
+
+    /// Show loss over the last 1m, 15m and 1h side by side
+    #[arg(long = "loss-windows")]
+    loss_windows: bool,
+
+    /// Also show loss, avg and max over this sliding window (e.g. 5m) next to the all-time values
+    #[arg(long = "window", value_parser = parse_duration)]
+    window: Option<Duration>,
+
+    /// Show scheduling health per target (intended vs. sent probes, delay, duration)
+    #[arg(long = "probe-stats")]
+    probe_stats: bool,
+
+    /// Measure time spent spawning, parsing, rendering and logging per interval
+    #[arg(long = "profile-overhead")]
+    profile_overhead: bool,
+
+    /// How often to re-read the IP list file for added/removed targets (0 = never)
+    #[arg(long = "refresh-targets", value_parser = parse_duration, default_value = "10s")]
+    refresh_targets: Duration,
+
+    /// File format for snapshots of the live view taken with the `s` key
+    #[arg(long = "snapshot-format", value_enum, default_value = "html")]
+    snapshot_format: ui::SnapshotFormat,
+
+    /// Show decimals on screen with the locale's separator (LC_ALL, LC_NUMERIC, LANG);
+    /// exports, log and summary always use a point
+    #[arg(long = "locale-numbers")]
+    locale_numbers: bool,
+
+    /// Skip invalid lines in the IP list with a warning instead of refusing to start
+    #[arg(long = "skip-invalid")]
+    skip_invalid: bool,
+
+    /// Refuse IP lists (CIDR ranges expanded) with more targets than this
+    #[arg(long = "max-targets", default_value_t = targets::DEFAULT_MAX_TARGETS)]
+    max_targets: usize,
+
+    /// Write a JSON summary (stats, outages) of the run to this file on exit
+    #[arg(long = "summary", value_name = "FILE")]
+    summary: Option<PathBuf>,
+
+    /// Listen on this Unix socket so `ping-plotter attach <PATH>` can watch the live view
+    #[arg(long = "socket", value_name = "PATH")]
+    socket: Option<PathBuf>,
+
+    /// Serve Prometheus metrics at /metrics on this address (e.g. 0.0.0.0:9123)
+    #[arg(long = "metrics-listen", value_name = "ADDR")]
+    metrics_listen: Option<SocketAddr>,
+
+    /// POST a heartbeat with the overall health to this URL (e.g. a healthchecks.io check)
+    #[arg(long = "heartbeat-url", value_name = "URL")]
+    heartbeat_url: Option<String>,
+
+    /// How often to send the heartbeat
+    #[arg(long = "heartbeat-every", value_parser = parse_duration, default_value = "5m")]
+    heartbeat_every: Duration,
+
+    /// Replace targets in the summary with stable pseudonyms (host-1, ...) for sharing
+    #[arg(long = "anonymize")]
+    anonymize: bool,
+
+    /// Mapping file of target to pseudonym, extended with new targets
+    /// (default: anonymize.json next to the binary)
+    #[arg(long = "anonymize-map", value_name = "FILE", requires = "anonymize")]
+    anonymize_map: Option<PathBuf>,
+
+    /// Save raw output of successful pings without a readable RTT to this directory
+    #[arg(long = "dump-unparsed", value_name = "DIR", conflicts_with = "simulate")]
+    dump_unparsed: Option<PathBuf>,
+
+    /// Send the probes of a named group through a source interface, e.g. "wan1 via eth0"
+    /// (repeatable); adds a side-by-side comparison of the groups below the table
+    #[arg(long = "group", value_name = "NAME via IFACE", value_parser = wan::parse_binding)]
+    bindings: Vec<wan::Binding>,
+
+    /// Monitor a packaged set of targets instead of an IP list and report which leg fails
+    /// (vpn-check: gateway, inside and outside the tunnel; isp-handoff: modem, first ISP hop,
+    /// public anchor)
+    #[arg(long = "preset", value_enum, conflicts_with_all = ["ip_file", "simulate"])]
+    preset: Option<preset::Preset>,
+
+    /// vpn-check: the VPN gateway (default: detected from the tunnel's route)
+    #[arg(long = "vpn-gateway", value_name = "HOST", requires = "preset")]
+    vpn_gateway: Option<String>,
+
+    /// vpn-check: a host only reachable through the tunnel
+    #[arg(long = "vpn-inside", value_name = "HOST", requires = "preset")]
+    vpn_inside: Option<String>,
+
+    /// vpn-check: a public host reached outside the tunnel
+    #[arg(long = "vpn-outside", value_name = "HOST", default_value = "1.1.1.1")]
+    vpn_outside: String,
+
+    /// isp-handoff: public host to traceroute towards and keep pinging
+    #[arg(long = "isp-anchor", value_name = "HOST", default_value = "1.1.1.1")]
+    isp_anchor: String,
+
+    /// Probe virtual targets from a simulation profile instead of the network
+    #[arg(long = "simulate", value_name = "PROFILE")]
+    simulate: Option<PathBuf>,
+
+    /// How to send probes: the system ping, or ICMP from this process (needs an ICMP
+    /// datagram or raw socket; falls back to the system ping otherwise)
+    #[arg(long = "engine", value_enum, default_value = "system")]
+    engine: icmp::EngineKind,
+
+    /// Stop probing targets that have been down continuously for this long (e.g. 24h)
+    #[arg(long = "auto-disable-after", value_parser = parse_duration)]
+    auto_disable_after: Option<Duration>,
+
+    /// Send a Wake-on-LAN packet to targets with a `mac` in the config file once they have
+    /// been down this long (e.g. 10m), once per outage
+    #[arg(long = "wake-after", value_parser = parse_duration)]
+    wake_after: Option<Duration>,
+
+    /// Drop samples and log lines older than this (e.g. 30d) from the store, the log and
+    /// the CSV file
+    #[arg(long = "retention", value_parser = parse_duration)]
+    retention: Option<Duration>,
+
+    /// Look up host name targets again this often, to follow DNS changes (e.g. 5m)
+    #[arg(long = "resolve-every", value_parser = parse_duration, default_value = "5m")]
+    resolve_every: Duration,
+
+    /// Probe host names over IPv4 only; IP addresses keep their own family
+    #[arg(short = '4', long = "ipv4", conflicts_with = "ipv6")]
+    ipv4: bool,
+
+    /// Probe host names over IPv6 only; IP addresses keep their own family
+    #[arg(short = '6', long = "ipv6")]
+    ipv6: bool,
+
+    /// Probe each host name over IPv4 and IPv6, as two rows `<name>@v4` and `<name>@v6`
+    #[arg(long = "dual-stack", conflicts_with_all = ["ipv4", "ipv6"])]
+    dual_stack: bool,
+
+    /// Stop writing raw samples (store, CSV, JSON Lines) while a volume they are on has less
+    /// than this many MB free; 0 disables the check
+    #[arg(long = "min-free-space", value_name = "MB", default_value_t = 100)]
+    min_free_space: u64,
+
+    /// Take over the log and store even if another instance holds their lock
+    #[arg(long = "force")]
+    force: bool,
+
+    #[command(subcommand)]
+    command: Option<Cmd>,
 }
 
-#[derive(Default, Clone, Copy)]
-struct Stats {
-    success: u64,
-    total: u64,
-    min_ms: Option<f64>,
-    max_ms: Option<f64>,
-    sum_ms: f64,
-    samples: u64,
+#[derive(Subcommand, Debug)]
+enum Cmd {
+    /// Export samples from the persistent store
+    Export {
+        /// Store directory to read from
+        #[arg(long = "store")]
+        store_dir: PathBuf,
+
+        /// Only export samples newer than this (e.g. 90s, 30m, 24h, 7d)
+        #[arg(long = "since", value_parser = parse_duration)]
+        since: Option<Duration>,
+
+        /// Output format
+        #[arg(long = "format", value_enum, default_value = "json")]
+        format: export::Format,
+
+        /// Output file (stdout if omitted)
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+
+        /// Mark samples from rounds in which at least this fraction of targets failed
+        #[arg(
+            long = "connectivity-loss",
+            value_name = "FRACTION",
+            value_parser = parse_fraction,
+            default_value_t = connectivity::DEFAULT_FRACTION
+        )]
+        connectivity_loss: f64,
+
+        /// Probe interval the samples were recorded with, to group them into rounds
+        #[arg(long = "interval", value_parser = parse_duration, default_value = "2s")]
+        interval: Duration,
+
+        /// Replace targets with stable pseudonyms (host-1, ...) for sharing
+        #[arg(long = "anonymize")]
+        anonymize: bool,
+
+        /// Mapping file of target to pseudonym (default: anonymize.json next to the binary)
+        #[arg(long = "anonymize-map", value_name = "FILE", requires = "anonymize")]
+        anonymize_map: Option<PathBuf>,
+    },
+    /// Import previously exported samples into the persistent store
+    Import {
+        /// Store directory to write to
+        #[arg(long = "store")]
+        store_dir: PathBuf,
+
+        /// Input format
+        #[arg(long = "format", value_enum, default_value = "json")]
+        format: export::Format,
+
+        /// File to import ("-" for stdin)
+        input: PathBuf,
+    },
+    /// Availability, downtime and worst outages per target and group for one month of the
+    /// persistent store; groups come from --ips or the config file's targets
+    Report {
+        /// Store directory to read from
+        #[arg(long = "store")]
+        store_dir: PathBuf,
+
+        /// Calendar month in local time, e.g. 2024-06
+        #[arg(long = "month", value_parser = sla::Month::parse)]
+        month: sla::Month,
+    },
+    /// Upgrade a log, export or summary written by an older version
+    Migrate {
+        /// File to upgrade; the original is kept as <FILE>.bak
+        file: PathBuf,
+
+        /// Write the upgraded file here instead of replacing the original
+        #[arg(short = 'o', long = "output")]
+        output: Option<PathBuf>,
+    },
+    /// Ask for targets, interval and log file and write them as the default profile of the
+    /// config file (--config), optionally with a systemd user service
+    Init,
+    /// Probe every target exactly once, print the results and exit
+    /// (0: all reachable, 2: at least one unreachable, 1: error)
+    Once,
+    /// Print the current state of a running instance started with --socket, one line per
+    /// target (exit code 2 if one is down)
+    Status {
+        /// Only this target
+        target: Option<String>,
+
+        /// The instance's --socket path
+        #[arg(long = "socket", value_name = "PATH")]
+        socket: PathBuf,
+
+        /// Print the instance's internal event log instead: workers started and stopped,
+        /// overrun probes, target list reloads, pauses
+        #[arg(long = "debug", conflicts_with = "target")]
+        debug: bool,
+    },
+    /// Watch the live view of a running instance started with --socket (read-only)
+    Attach {
+        /// The instance's --socket path
+        socket: PathBuf,
+    },
+    /// Probe two targets side by side and test whether their latency differs
+    Ab {
+        target_a: String,
+        target_b: String,
+
+        /// Number of probe rounds
+        #[arg(long = "rounds", default_value_t = 100)]
+        rounds: u32,
+    },
+    /// Measure achievable probes/second and RTT accuracy against a local target
+    Bench {
+        /// Target to probe; should answer instantly
+        #[arg(long = "target", default_value = "127.0.0.1")]
+        target: String,
+
+        /// Probes per measurement
+        #[arg(long = "probes", default_value_t = 50)]
+        probes: u32,
+
+        /// Worker threads for the parallel measurement
+        #[arg(long = "parallel", default_value_t = 4)]
+        parallel: u32,
+    },
 }
 
-impl Stats {
-    fn record(&mut self, success: bool, latency_ms: Option<f64>) {
-        self.total += 1;
-        if success {
-            self.success += 1;
-            if let Some(ms) = latency_ms {
-                self.min_ms = Some(self.min_ms.map_or(ms, |cur| cur.min(ms)));
-                self.max_ms = Some(self.max_ms.map_or(ms, |cur| cur.max(ms)));
-                self.sum_ms += ms;
-                self.samples += 1;
-            }
-        }
-    }
 
-    fn avg_ms(&self) -> Option<f64> {
-        if self.samples > 0 {
-            Some(self.sum_ms / self.samples as f64)
-        } else {
-            None
-        }
-    }
+fn parse_template(input: &str, allowed: &[&str]) -> Result<template::Template, String> {
+    let t = template::Template::parse(input)?;
+    t.check(allowed)?;
+    Ok(t)
 }
 
-struct PingResult {
-    ip: String,
-    success: bool,
-    latency_ms: Option<f64>,
+fn unreachable_template(input: &str) -> Result<template::Template, String> {
+    parse_template(input, session::UNREACHABLE_VARS)
 }
 
-fn parse_time(stdout: &[u8]) -> Option<f64> {
-    // Typical ping outputs: 'time=XX.XXX ms', 'Zeit=XXms', 'time<1ms'
-    let text = String::from_utf8_lossy(stdout);
-    for part in text.split_whitespace() {
-        let lower = part.to_ascii_lowercase();
-        if let Some(rest) = lower.strip_prefix("time<").or_else(|| lower.strip_prefix("zeit<")) {
-            let value = rest.trim_end_matches("ms");
-            if let Ok(ms) = value.parse::<f64>() {
-                return Some(ms.min(1.0) / 2.0); // treat <1ms as ~0.5ms
-            }
-        }
-        if let Some(rest) = lower.strip_prefix("time=").or_else(|| lower.strip_prefix("zeit=")) {
-            let value = rest.trim_end_matches("ms");
-            if value.starts_with('<') {
-                return Some(0.5);
-            }
-            if let Ok(ms) = value.parse::<f64>() {
-                return Some(ms);
-            }
-        }
-    }
-    None
+fn final_template(input: &str) -> Result<template::Template, String> {
+    parse_template(input, app::FINAL_VARS)
 }
 
-fn timestamp() -> String {
-    Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+fn final_row_template(input: &str) -> Result<template::Template, String> {
+    parse_template(input, app::FINAL_ROW_VARS)
 }
 
-fn open_log(path: &Path) -> Option<BufWriter<fs::File>> {
-    match OpenOptions::new().create(true).append(true).open(path) {
-        Ok(file) => Some(BufWriter::new(file)),
-        Err(err) => {
-            eprintln!("Failed to open log file {}: {err}", path.display());
-            None
-        }
+/// `--smooth`: an EWMA smoothing factor in (0, 1].
+fn parse_alpha(input: &str) -> Result<f64, String> {
+    let alpha: f64 = input
+        .parse()
+        .map_err(|_| format!("invalid number '{input}'"))?;
+    if alpha > 0.0 && alpha <= 1.0 {
+        Ok(alpha)
+    } else {
+        Err(format!("alpha must be in (0, 1], got {alpha}"))
     }
 }
 
-fn append_log_line(writer: &mut Option<BufWriter<fs::File>>, line: &str) {
-    if let Some(w) = writer.as_mut() {
-        if writeln!(w, "{line}").is_err() {
-            eprintln!("Failed to write to log file; disabling further logging");
-            *writer = None;
-        }
+fn parse_fraction(input: &str) -> Result<f64, String> {
+    let fraction: f64 = input
+        .parse()
+        .map_err(|_| format!("invalid number '{input}'"))?;
+    if fraction > 0.0 && fraction <= 1.0 {
+        Ok(fraction)
+    } else {
+        Err(format!("fraction must be in (0, 1], got {fraction}"))
     }
 }
 
-fn clear_screen() {
-    let mut stdout = io::stdout();
-    let _ = execute!(stdout, Clear(ClearType::All), MoveTo(0, 0));
+fn parse_count(input: &str) -> Result<u32, String> {
+    match input.parse() {
+        Ok(0) => Err("must be at least 1".to_string()),
+        Ok(count) => Ok(count),
+        Err(_) => Err(format!("invalid count '{input}'")),
+    }
 }
 
-fn ping_once(ip: &str) -> (bool, Option<f64>) {
-    // Use system ping to avoid raw socket requirements; capture output to keep console clean.
-    let mut cmd = if let Ok(mock) = env::var("PING_PLOTTER_MOCK") {
-        let mut c = Command::new(mock);
-        c.arg(ip);
-        c
-    } else {
-        let mut c = Command::new("ping");
-        if cfg!(target_os = "windows") {
-            c.args(["-n", "1", "-w", &PING_TIMEOUT_MS.to_string(), ip]);
-        } else if cfg!(target_os = "macos") {
-            c.args(["-c", "1", "-W", &PING_TIMEOUT_MS.to_string(), ip]);
-        } else {
-            let secs = ((PING_TIMEOUT_MS as f64) / 1000.0).ceil().max(1.0) as u64;
-            c.args(["-c", "1", "-W", &secs.to_string(), ip]); // iputils uses seconds
-        }
-        c
+/// Parses durations like `500ms`, `90s`, `30m`, `24h`, `7d`; bare numbers are seconds.
+fn parse_duration(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (value, unit) = input.split_at(split);
+    let value: f64 = value
+        .parse()
+        .map_err(|_| format!("invalid duration: {input}"))?;
+    let secs = match unit.trim() {
+        "ms" => value / 1000.0,
+        "" | "s" => value,
+        "m" => value * 60.0,
+        "h" => value * 3600.0,
+        "d" => value * 86400.0,
+        other => return Err(format!("unknown duration unit '{other}' in {input}")),
     };
-    cmd.stdout(Stdio::piped()).stderr(Stdio::null());
+    Ok(Duration::from_secs_f64(secs))
+}
 
-    let timeout = Duration::from_millis(PING_TIMEOUT_MS);
-    let mut child = match cmd.spawn() {
-        Ok(child) => child,
-        Err(_) => return (false, None),
+/// What `Session` takes from the flags.
+fn settings(args: &Args) -> session::Settings {
+    let macs = match (args.wake_after, &args.config_targets) {
+        (Some(_), Some(targets)) => targets.macs(),
+        _ => HashMap::new(),
     };
-
-    match child.wait_timeout(timeout) {
-        Ok(Some(_status)) => match child.wait_with_output() {
-            Ok(output) => {
-                let success = output.status.success();
-                let time_ms = if success { parse_time(&output.stdout) } else { None };
-                (success, time_ms)
-            }
-            Err(_) => (false, None),
+    if args.wake_after.is_some() && macs.is_empty() {
+        warn!("--wake-after has no effect: no [[target]] in the config file has a mac");
+    }
+    session::Settings {
+        warmup: args.warmup,
+        rules: severity::Rules {
+            warning_loss_pct: args.warning_loss,
+            window: args.severity_window,
+            critical_after: args.critical_after,
         },
-        Ok(None) => {
-            let _ = child.kill();
-            let _ = child.wait();
-            (false, None)
-        }
-        Err(_) => (false, None),
+        thresholds: updown::Thresholds {
+            down_after: args.down_after,
+            up_after: args.up_after,
+        },
+        connectivity_loss: args.connectivity_loss,
+        limits: args.latency_budget.map(|latency_ms| quality::Limits {
+            latency_ms,
+            loss_pct: args.loss_budget,
+            window: args.budget_window,
+        }),
+        jitter: !args.jitter_buffers.is_empty(),
+        smooth: args.smooth,
+        loss_windows: args.loss_windows,
+        window: args.window,
+        auto_disable_after: args.auto_disable_after,
+        wake_after: args.wake_after,
+        macs,
+        unreachable_template: args.unreachable_template.clone(),
     }
 }
 
-fn default_paths() -> (PathBuf, PathBuf) {
-    let exe_dir = env::current_exe()
-        .ok()
-        .and_then(|p| p.parent().map(|p| p.to_path_buf()))
-        .unwrap_or_else(|| env::current_dir().unwrap_or_else(|_| PathBuf::from(".")));
-    let default_ip = exe_dir.join("ips.txt");
-    let default_log = exe_dir.join("result.txt");
-    (default_ip, default_log)
-}
 
-fn align_to_even_second() -> Instant {
-    let now_sys = SystemTime::now();
-    let now_inst = Instant::now();
-    let secs = now_sys
-        .duration_since(UNIX_EPOCH)
-        .map(|d| d.as_secs())
-        .unwrap_or(0);
-    let next_even_secs = if secs % 2 == 0 { secs + 2 } else { secs + 1 };
-    let even_start_sys = UNIX_EPOCH + Duration::from_secs(next_even_secs);
-    let delay = even_start_sys
-        .duration_since(now_sys)
-        .unwrap_or_else(|_| Duration::from_secs(0));
-    let first_tick = now_inst + delay;
-    first_tick
+/// `-4`/`-6`.
+fn family(args: &Args) -> Option<targets::Family> {
+    if args.ipv4 {
+        Some(targets::Family::V4)
+    } else if args.ipv6 {
+        Some(targets::Family::V6)
+    } else {
+        None
+    }
 }
 
-fn spawn_workers(
-    ips: &[String],
-    tx: mpsc::Sender<PingResult>,
-    first_tick: Instant,
-    deadline: Option<Instant>,
-) -> Vec<thread::JoinHandle<()>> {
-    ips.iter()
-        .cloned()
-        .map(|ip| {
-            let tx = tx.clone();
-            thread::spawn(move || {
-                let mut next_tick = first_tick;
-                loop {
-                    let now = Instant::now();
-                    if let Some(end) = deadline {
-                        if now >= end {
-                            break;
-                        }
-                    }
-                    if now < next_tick {
-                        let sleep_dur = next_tick - now;
-                        if let Some(end) = deadline {
-                            if now + sleep_dur >= end {
-                                thread::sleep(end - now);
-                                break;
-                            }
-                        }
-                        thread::sleep(sleep_dur);
-                    }
-                    if let Some(end) = deadline {
-                        if Instant::now() >= end {
-                            break;
-                        }
-                    }
-                    let (success, latency_ms) = ping_once(&ip);
-                    if tx
-                        .send(PingResult {
-                            ip: ip.clone(),
-                            success,
-                            latency_ms,
-                        })
-                        .is_err()
-                    {
-                        break;
-                    }
-                    next_tick += INTERVAL;
-                }
-            })
-        })
-        .collect()
+
+/// `--timeout`, or 1900 ms and at most 95 % of `--interval` without it.
+fn timeout(args: &Args) -> Duration {
+    args.timeout
+        .unwrap_or(app::DEFAULT_TIMEOUT.min(args.interval * 19 / 20))
 }
 
-fn main() {
-    let args = Args::parse();
-    let (default_ip, default_log) = default_paths();
-
-    let ip_file = args.ip_file.unwrap_or(default_ip.clone());
-    let log_path = args.log_file.unwrap_or(default_log.clone());
-    let run_for = args.duration.map(Duration::from_secs);
-
-    if !ip_file.exists() {
-        eprintln!(
-            "IP list file not found: {} (default is ips.txt next to executable)",
-            ip_file.display()
-        );
-        std::process::exit(1);
+/// How the flags say to probe.
+fn probing(args: &Args) -> app::Probing {
+    app::Probing {
+        kind: args.engine,
+        timeout: timeout(args),
+        family: family(args),
+        dump_unparsed: args.dump_unparsed.clone(),
     }
+}
 
-    let content = fs::read_to_string(&ip_file).unwrap_or_else(|_| {
-        eprintln!("Failed to read IP list file: {}", ip_file.display());
-        std::process::exit(1);
+/// The monitoring run the flags describe.
+fn config(args: Args) -> app::Config {
+    let preset = args.preset.map(|preset| preset::Choice {
+        preset,
+        vpn_gateway: args.vpn_gateway.clone(),
+        vpn_inside: args.vpn_inside.clone(),
+        vpn_outside: args.vpn_outside.clone(),
+        isp_anchor: args.isp_anchor.clone(),
     });
-    let ips: Vec<String> = content
-        .lines()
-        .map(str::trim)
-        .filter(|line| !line.is_empty())
-        .map(String::from)
-        .collect();
-
-    if ips.is_empty() {
-        eprintln!("No IPs found in {}", ip_file.display());
-        std::process::exit(1);
+    app::Config {
+        settings: settings(&args),
+        interval: args.interval,
+        run_for: args.duration.map(Duration::from_secs),
+        probing: probing(&args),
+        preset,
+        schedule: args.schedule,
+        simulate: args.simulate,
+        ip_file: args.ip_file,
+        config_targets: args.config_targets,
+        skip_invalid: args.skip_invalid,
+        max_targets: args.max_targets,
+        refresh_targets: args.refresh_targets,
+        dual_stack: args.dual_stack,
+        resolve_every: args.resolve_every,
+        bindings: args.bindings,
+        log_file: args.log_file,
+        log_format: args.log_format,
+        csv: args.csv,
+        store_dir: args.store_dir,
+        retention: args.retention,
+        min_free_space: args.min_free_space,
+        force: args.force,
+        summary: args.summary,
+        anonymize: args.anonymize,
+        anonymize_map: args.anonymize_map,
+        socket: args.socket,
+        metrics_listen: args.metrics_listen,
+        heartbeat_url: args.heartbeat_url,
+        heartbeat_every: args.heartbeat_every,
+        probe_stats: args.probe_stats,
+        jitter_buffers: args.jitter_buffers,
+        locale_numbers: args.locale_numbers,
+        snapshot_format: args.snapshot_format,
+        final_template: args.final_template,
+        final_row_template: args.final_row_template,
+        profile_overhead: args.profile_overhead,
     }
+}
 
-    let first_tick = align_to_even_second();
-    let deadline = run_for.map(|d| first_tick + d);
-
-    let (tx, rx) = mpsc::channel::<PingResult>();
-    let handles = spawn_workers(&ips, tx, first_tick, deadline);
-
-    let mut stats: HashMap<String, Stats> = HashMap::new();
-    let mut prev_counts: HashMap<String, (u64, u64)> = HashMap::new();
-    let mut last_display: Vec<String> = Vec::new();
-    let mut log_writer = open_log(&log_path);
-
-    let mut next_render = first_tick;
-    loop {
-        for result in rx.try_iter() {
-            let entry = stats.entry(result.ip).or_default();
-            entry.record(result.success, result.latency_ms);
+fn init_diagnostics(verbose: u8, path: Option<&Path>) {
+    let level = match verbose {
+        0 => tracing::Level::WARN,
+        1 => tracing::Level::INFO,
+        2 => tracing::Level::DEBUG,
+        _ => tracing::Level::TRACE,
+    };
+    let file = path.map(|p| OpenOptions::new().create(true).append(true).open(p));
+    let (writer, ansi) = match file {
+        Some(Ok(file)) => (BoxMakeWriter::new(std::sync::Mutex::new(file)), false),
+        Some(Err(err)) => {
+            eprintln!("Failed to open diagnostics file: {err}; using stderr");
+            (BoxMakeWriter::new(io::stderr), true)
         }
+        None => (BoxMakeWriter::new(io::stderr), true),
+    };
+    // A subscriber installed earlier (by an embedder, or a previous `run`)
+    // stays in place.
+    let _ = tracing_subscriber::fmt()
+        .with_max_level(level)
+        .with_writer(writer)
+        .with_ansi(ansi)
+        .try_init();
+}
 
-        let mut lines: Vec<String> = Vec::new();
-        lines.push(format!(
-            "{:<20} {:>16} {:>10} {:>10} {:>10}",
-            "IP",
-            "Erfolg/Gesamt",
-            "min (ms)",
-            "avg (ms)",
-            "max (ms)"
-        ));
-
-        let mut unreachable: Vec<String> = Vec::new();
-        for ip in &ips {
-            let stat = stats.get(ip).copied().unwrap_or_default();
-            let fmt = |v: Option<f64>| -> String {
-                v.map(|n| format!("{:.2}", n))
-                    .unwrap_or_else(|| "-".to_string())
-            };
-            let count_line = format!(
-                "{:<20} {:>16} {:>10} {:>10} {:>10}",
-                ip,
-                format!("{}/{}", stat.success, stat.total),
-                fmt(stat.min_ms),
-                fmt(stat.avg_ms()),
-                fmt(stat.max_ms),
-            );
-            lines.push(count_line);
-
-            let prev = prev_counts.get(ip).copied().unwrap_or((0, 0));
-            let total_diff = stat.total.saturating_sub(prev.0);
-            let success_diff = stat.success.saturating_sub(prev.1);
-            if total_diff > 0 && success_diff == 0 {
-                unreachable.push(ip.clone());
+/// Set by the SIGINT/SIGTERM handler; the monitoring loop stops at its
+/// next turn.
+static STOP: AtomicBool = AtomicBool::new(false);
+
+fn main() {
+    let command = Args::command();
+    let known: Vec<&str> = command.get_arguments().filter_map(|a| a.get_long()).collect();
+    let default_config = config::exe_dir().join("ping-plotter.toml");
+    let result = config::expand(env::args_os().collect(), &default_config, &known)
+        .and_then(|expanded| {
+            let mut args = Args::parse_from(expanded.args);
+            args.config_targets = expanded.targets;
+            // Only monitoring shuts down through its final state; `once` and
+            // the subcommands just end on a signal.
+            if args.command.is_none()
+                && let Err(err) = ctrlc::set_handler(|| STOP.store(true, Ordering::SeqCst))
+            {
+                eprintln!("cannot handle termination signals: {err}");
             }
-            prev_counts.insert(ip.clone(), (stat.total, stat.success));
+            run(args)
+        });
+    if let Err(err) = result {
+        // `once` and `status` have already printed which targets are unreachable.
+        if !matches!(err, RunError::Unreachable(_)) {
+            eprintln!("{err}");
         }
+        std::process::exit(err.exit_code());
+    }
+}
 
-        last_display.clear();
-        last_display.extend(lines.iter().cloned());
-
-        clear_screen();
-        for line in &lines {
-            println!("{line}");
+/// Runs the command given by `args`: a subcommand, `once`, or monitoring
+/// until the duration ends or the user quits.
+fn run(args: Args) -> Result<(), RunError> {
+    // A probe still running at the next tick would make that tick count as missed.
+    if let Some(timeout) = args.timeout
+        && (timeout.is_zero() || timeout >= args.interval)
+    {
+        return Err(RunError::Usage(format!(
+            "--timeout must be above zero and shorter than --interval (got {timeout:?} and {:?})",
+            args.interval
+        )));
+    }
+    match &args.command {
+        Some(Cmd::Export {
+            store_dir,
+            since,
+            format,
+            output,
+            connectivity_loss,
+            interval,
+            anonymize,
+            anonymize_map,
+        }) => {
+            let pseudonyms = (*anonymize)
+                .then(|| anonymize::Pseudonyms::open(anonymize_map.as_deref()))
+                .transpose()?;
+            let output = output.as_deref();
+            let (loss, since) = (*connectivity_loss, *since);
+            return export::run(store_dir, since, *format, loss, *interval, pseudonyms, output);
         }
-
-        if !unreachable.is_empty() {
-            append_log_line(
-                &mut log_writer,
-                &format!("[{}] unreachable: {}", timestamp(), unreachable.join(", ")),
-            );
+        Some(Cmd::Import {
+            store_dir,
+            format,
+            input,
+        }) => return export::import(store_dir, *format, input, args.force),
+        Some(Cmd::Report { store_dir, month }) => {
+            // Groups come from the target list, if there is one.
+            let mut source: Option<Box<dyn targets::TargetSource>> = match &args.ip_file {
+                Some(path) => Some(Box::new(targets::FileSource::new(
+                    path.clone(),
+                    None,
+                    args.skip_invalid,
+                    args.max_targets,
+                ))),
+                None => (args.config_targets.clone())
+                    .map(|t| Box::new(t) as Box<dyn targets::TargetSource>),
+            };
+            let weights = (args.config_targets.as_ref())
+                .map(|t| t.weights())
+                .unwrap_or_default();
+            let source = source.as_deref_mut().map(|s| s as &mut dyn targets::TargetSource);
+            let thresholds = updown::Thresholds {
+                down_after: args.down_after,
+                up_after: args.up_after,
+            };
+            return sla::run(store_dir, *month, source, &weights, &thresholds);
         }
-
-        let now = Instant::now();
-        if let Some(end) = deadline {
-            if now >= end {
-                break;
-            }
+        Some(Cmd::Migrate { file, output }) => {
+            return schema::migrate_file(file, output.as_deref());
         }
-        if now < next_render {
-            let sleep_dur = next_render - now;
-            if let Some(end) = deadline {
-                if now + sleep_dur >= end {
-                    thread::sleep(end - now);
-                    break;
-                }
-            }
-            thread::sleep(sleep_dur);
+        Some(Cmd::Ab {
+            target_a,
+            target_b,
+            rounds,
+        }) => {
+            let engine = probing(&args).chosen();
+            compare::run(&engine, target_a, target_b, *rounds, args.interval);
+            return Ok(());
         }
-        next_render += INTERVAL;
-    }
-
-    for result in rx.try_iter() {
-        let entry = stats.entry(result.ip).or_default();
-        entry.record(result.success, result.latency_ms);
-    }
-
-    append_log_line(&mut log_writer, &format!("[{}] Final state:", timestamp()));
-    for line in &last_display {
-        append_log_line(&mut log_writer, line);
+        Some(Cmd::Bench {
+            target,
+            probes,
+            parallel,
+        }) => {
+            bench::run(&probing(&args), target, *probes, *parallel);
+            return Ok(());
+        }
+        Some(Cmd::Attach { socket }) => return control::attach(socket),
+        Some(Cmd::Status {
+            target,
+            socket,
+            debug,
+        }) => return control::status(socket, target.as_deref(), *debug),
+        Some(Cmd::Init) => return setup::run(args.config.as_deref(), &parse_duration),
+        Some(Cmd::Once) | None => {}
     }
-
-    drop(log_writer);
-    for handle in handles {
-        let _ = handle.join();
+    init_diagnostics(args.verbose, args.diagnostics.as_deref());
+    let once = matches!(args.command, Some(Cmd::Once));
+    let config = config(args);
+    if once {
+        app::once(&config)
+    } else {
+        app::run(config, None, ui::Terminal::start, &STOP)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use ping_plotter::store;
     use std::fs;
 
     #[test]
-    fn parses_common_time_formats() {
-        let samples = [
-            ("time=12.34 ms", Some(12.34)),
-            ("Zeit=56ms", Some(56.0)),
-            ("time<1ms", Some(0.5)),
-            ("no time here", None),
-        ];
-        for (input, expected) in samples {
-            let got = parse_time(input.as_bytes());
-            assert_eq!(got, expected, "failed on input {input}");
-        }
-    }
-
-    #[cfg(unix)]
-    fn make_mock_ping(script: &str) -> PathBuf {
-        use std::os::unix::fs::PermissionsExt;
-        let nanos = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap_or_else(|_| Duration::from_secs(0))
-            .as_nanos();
-        let unique = format!("mock_ping_{}_{}", std::process::id(), nanos);
-        let path = std::env::temp_dir().join(unique);
-        fs::write(&path, script).expect("write mock ping");
-        let mut perm = fs::metadata(&path).unwrap().permissions();
-        perm.set_mode(0o755);
-        fs::set_permissions(&path, perm).unwrap();
-        path
+    fn smoothing_factor_is_in_unit_range() {
+        assert!(parse_alpha("0").is_err());
+        assert!(parse_alpha("1.5").is_err());
+        assert_eq!(parse_alpha("0.2"), Ok(0.2));
     }
 
-    #[cfg(unix)]
-    fn with_mock<F: FnOnce()>(path: &Path, f: F) {
-        let prev = std::env::var("PING_PLOTTER_MOCK").ok();
-        unsafe { std::env::set_var("PING_PLOTTER_MOCK", path) };
-        f();
-        if let Some(val) = prev {
-            unsafe { std::env::set_var("PING_PLOTTER_MOCK", val) };
-        } else {
-            unsafe { std::env::remove_var("PING_PLOTTER_MOCK") };
-        }
-    }
-
-    #[cfg(unix)]
     #[test]
-    fn ping_once_reports_success_and_latency() {
-        let script = "#!/bin/sh\necho '64 bytes from 1.1.1.1: time=7.89 ms'\nexit 0\n";
-        let path = make_mock_ping(script);
-        with_mock(&path, || {
-            let (success, latency) = ping_once("1.1.1.1");
-            assert!(success);
-            assert_eq!(latency, Some(7.89));
-        });
-        let _ = fs::remove_file(path);
+    fn parses_durations_with_units() {
+        assert_eq!(parse_duration("500ms"), Ok(Duration::from_millis(500)));
+        assert_eq!(parse_duration("90"), Ok(Duration::from_secs(90)));
+        assert_eq!(parse_duration("30m"), Ok(Duration::from_secs(1800)));
+        assert_eq!(parse_duration("24h"), Ok(Duration::from_secs(86400)));
+        assert_eq!(parse_duration("7d"), Ok(Duration::from_secs(7 * 86400)));
+        assert!(parse_duration("5 weeks").is_err());
+        assert!(parse_duration("h").is_err());
     }
 
-    #[cfg(unix)]
     #[test]
-    fn ping_once_reports_failure() {
-        let script = "#!/bin/sh\nexit 1\n";
-        let path = make_mock_ping(script);
-        with_mock(&path, || {
-            let (success, latency) = ping_once("1.1.1.1");
-            assert!(!success);
-            assert_eq!(latency, None);
-        });
-        let _ = fs::remove_file(path);
-    }
-
-    #[cfg(unix)]
-    #[test]
-    fn ping_once_times_out() {
-        let script = "#!/bin/sh\nsleep 3\n";
-        let path = make_mock_ping(script);
-        with_mock(&path, || {
-            let start = Instant::now();
-            let (success, latency) = ping_once("1.1.1.1");
-            let elapsed = start.elapsed();
-            assert!(!success);
-            assert_eq!(latency, None);
-            assert!(
-                elapsed < Duration::from_secs(3),
-                "expected timeout to cut off sleep, got {:?}",
-                elapsed
-            );
-        });
-        let _ = fs::remove_file(path);
+    fn run_reports_errors_and_unreachable_targets_instead_of_exiting() {
+        let dir = std::env::temp_dir();
+        let unique = format!("{}_{}", std::process::id(), store::now_ms());
+        let missing = dir.join(format!("missing_ips_{unique}.txt"));
+        let err = run(Args::parse_from(["ping-plotter", "-i", missing.to_str().unwrap()]))
+            .unwrap_err();
+        assert!(matches!(err, RunError::Targets(_)), "{err}");
+        assert_eq!(err.exit_code(), 1);
+        // A short interval brings its own timeout; only an explicit one can clash.
+        let short = Args::parse_from(["ping-plotter", "--interval", "500ms"]);
+        assert_eq!(timeout(&short), Duration::from_millis(475));
+        assert_eq!(timeout(&Args::parse_from(["ping-plotter"])), Duration::from_millis(1900));
+        let err = run(Args::parse_from([
+            "ping-plotter",
+            "--interval",
+            "500ms",
+            "--timeout",
+            "600ms",
+            "once",
+        ]))
+        .unwrap_err();
+        assert!(matches!(err, RunError::Usage(_)), "{err}");
+
+        let profile = dir.join(format!("profile_{unique}.toml"));
+        fs::write(
+            &profile,
+            "[[target]]\nname = \"up\"\nlatency_ms = 5.0\n\n\
+             [[target]]\nname = \"down\"\nlatency_ms = 5.0\nloss_pct = 100.0\n",
+        )
+        .unwrap();
+        let log = dir.join(format!("once_{unique}.log"));
+        let err = run(Args::parse_from([
+            "ping-plotter",
+            "--interval",
+            "500ms",
+            "--simulate",
+            profile.to_str().unwrap(),
+            "-l",
+            log.to_str().unwrap(),
+            "once",
+        ]))
+        .unwrap_err();
+        let logged = fs::read_to_string(&log).unwrap();
+        let _ = fs::remove_file(&profile);
+        let _ = fs::remove_file(&log);
+        assert!(matches!(&err, RunError::Unreachable(t) if t == &["down"]), "{err}");
+        assert_eq!(err.exit_code(), 2);
+        assert!(logged.ends_with("] unreachable: down\n"), "{logged}");
     }
 }
@@ -0,0 +1,218 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use crate::{Stats, error::RunError, parse::ProbeOutcome, sinks, store};
+
+/// One metric family: name, type, help text and its value for a target,
+/// from the target's stats and latest probe. Targets without a value are
+/// left out of the family.
+type Family = (
+    &'static str,
+    &'static str,
+    &'static str,
+    fn(&Stats, Option<ProbeOutcome>) -> Option<f64>,
+);
+
+/// RTTs stay in milliseconds, like in the table and the exports.
+const FAMILIES: [Family; 7] = [
+    (
+        "ping_plotter_rtt_last_ms",
+        "gauge",
+        "RTT of the latest probe",
+        |_, last| last.and_then(ProbeOutcome::rtt_ms),
+    ),
+    ("ping_plotter_rtt_min_ms", "gauge", "Lowest RTT", |s, _| {
+        s.min_ms
+    }),
+    ("ping_plotter_rtt_avg_ms", "gauge", "Average RTT", |s, _| {
+        s.avg_ms()
+    }),
+    ("ping_plotter_rtt_max_ms", "gauge", "Highest RTT", |s, _| {
+        s.max_ms
+    }),
+    (
+        "ping_plotter_probes_total",
+        "counter",
+        "Probes sent",
+        |s, _| Some(s.total as f64),
+    ),
+    (
+        "ping_plotter_success_total",
+        "counter",
+        "Probes answered",
+        |s, _| Some(s.success as f64),
+    ),
+    (
+        "ping_plotter_up",
+        "gauge",
+        "1 if the latest probe was answered, else 0",
+        |_, last| last.map(|o| if o.success() { 1.0 } else { 0.0 }),
+    ),
+];
+
+/// `--metrics-listen`: the stats of the last frame in the Prometheus text
+/// format, shared with the HTTP connections.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    text: Mutex<String>,
+    scrapes: Mutex<sinks::Health>,
+}
+
+impl Metrics {
+    /// Replaces the served text with the given targets' stats and latest
+    /// probes.
+    pub fn publish<'a>(
+        &self,
+        targets: impl IntoIterator<Item = (&'a str, Stats, Option<ProbeOutcome>)>,
+    ) {
+        let text = render(&targets.into_iter().collect::<Vec<_>>());
+        if let Ok(mut current) = self.text.lock() {
+            *current = text;
+        }
+    }
+
+    fn text(&self) -> String {
+        self.text.lock().map(|t| t.clone()).unwrap_or_default()
+    }
+
+    /// Answered and failed requests for `/metrics`; the lag is the time
+    /// since the last scrape.
+    pub fn health(&self) -> sinks::Health {
+        self.scrapes.lock().map(|h| h.clone()).unwrap_or_default()
+    }
+}
+
+fn render(targets: &[(&str, Stats, Option<ProbeOutcome>)]) -> String {
+    let mut out = String::new();
+    for (name, kind, help, value) in FAMILIES {
+        out.push_str(&format!("# HELP {name} {help}\n# TYPE {name} {kind}\n"));
+        for (target, stats, last) in targets {
+            if let Some(v) = value(stats, *last) {
+                out.push_str(&format!("{name}{{target=\"{}\"}} {v}\n", escape(target)));
+            }
+        }
+    }
+    out
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Serves `GET /metrics` on `addr` in the background for the rest of the
+/// run; returns the bound address (the port, if `addr` asked for any).
+pub fn serve(addr: SocketAddr, metrics: Arc<Metrics>) -> Result<SocketAddr, RunError> {
+    let context = format!("Failed to listen on {addr}");
+    let listener = TcpListener::bind(addr).map_err(RunError::io(context.clone()))?;
+    let bound = listener.local_addr().map_err(RunError::io(context))?;
+    thread::spawn(move || {
+        for stream in listener.incoming().flatten() {
+            let metrics = Arc::clone(&metrics);
+            thread::spawn(move || {
+                if let Err(err) = handle(stream, &metrics) {
+                    tracing::debug!("metrics connection closed: {err}");
+                }
+            });
+        }
+    });
+    Ok(bound)
+}
+
+/// One request per connection: the request line and headers are read,
+/// answered, and the connection closed.
+fn handle(stream: TcpStream, metrics: &Metrics) -> io::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut out = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    let mut request = String::new();
+    reader.read_line(&mut request)?;
+    // Up to the blank line; no header changes the answer.
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 2 {
+        header.clear();
+    }
+    let mut parts = request.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path.split('?').next() == Some("/metrics") => {
+            ("200 OK", metrics.text())
+        }
+        (Some("GET"), _) => ("404 Not Found", "not found, try /metrics\n".to_string()),
+        _ => (
+            "405 Method Not Allowed",
+            "only GET is supported\n".to_string(),
+        ),
+    };
+    let sent = write!(
+        out,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )
+    .and_then(|()| out.flush());
+    if status == "200 OK"
+        && let Ok(mut scrapes) = metrics.scrapes.lock()
+    {
+        match &sent {
+            Ok(()) => scrapes.succeeded(store::now_ms()),
+            Err(err) => scrapes.failed(err),
+        }
+    }
+    sent
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Loss;
+    use std::io::Read;
+
+    #[test]
+    fn serves_the_published_stats() {
+        let metrics = Arc::new(Metrics::default());
+        let addr = serve("127.0.0.1:0".parse().unwrap(), Arc::clone(&metrics)).unwrap();
+        let mut up = Stats::default();
+        up.record(1, ProbeOutcome::Reply(12.5));
+        let mut down = up;
+        down.record(2, ProbeOutcome::Lost(Loss::Timeout));
+        metrics.publish([
+            ("1.1.1.1", up, Some(ProbeOutcome::Reply(12.5))),
+            ("nas\"1", down, Some(ProbeOutcome::Lost(Loss::Timeout))),
+            ("new", Stats::default(), None),
+        ]);
+
+        let get = |path: &str| {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            write!(stream, "GET {path} HTTP/1.1\r\nHost: x\r\n\r\n").unwrap();
+            let mut answer = String::new();
+            stream.read_to_string(&mut answer).unwrap();
+            answer
+        };
+        let answer = get("/metrics");
+        assert!(answer.starts_with("HTTP/1.1 200 OK\r\n"), "{answer}");
+        for line in [
+            "# TYPE ping_plotter_probes_total counter",
+            "ping_plotter_rtt_last_ms{target=\"1.1.1.1\"} 12.5",
+            "ping_plotter_probes_total{target=\"nas\\\"1\"} 2",
+            "ping_plotter_success_total{target=\"nas\\\"1\"} 1",
+            "ping_plotter_up{target=\"1.1.1.1\"} 1",
+            "ping_plotter_up{target=\"nas\\\"1\"} 0",
+            "ping_plotter_probes_total{target=\"new\"} 0",
+        ] {
+            assert!(
+                answer.lines().any(|l| l == line),
+                "{line} missing in {answer}"
+            );
+        }
+        assert!(!answer.contains("ping_plotter_up{target=\"new\"}"));
+        assert_eq!(metrics.health().ok, 1);
+        assert!(get("/").starts_with("HTTP/1.1 404"));
+    }
+}
@@ -0,0 +1,804 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    env, fs, mem,
+    net::IpAddr,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::{
+        Arc, Mutex,
+        atomic::{self, AtomicBool},
+        mpsc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use chrono::Local;
+use tracing::{debug, error, info, trace, warn};
+use wait_timeout::ChildExt;
+
+use crate::{
+    clock::{self, Clock},
+    debuglog, icmp,
+    overhead::{self, Phase},
+    parse::{self, Loss, ProbeOutcome},
+    schedule, sim,
+    targets::{self, Family},
+    wan,
+};
+
+/// A probed target. Stats, rows and log lines are keyed by its `name`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    /// IP address or host name, as normalized by `targets::Entry::name`.
+    pub name: String,
+    /// From `label="..."` or an inline comment in the list; shown instead
+    /// of the name.
+    pub label: Option<String>,
+    /// Where a host name currently resolves to (`resolver`); probes go to
+    /// it, so they don't wait for DNS each time. `None` for IP literals and
+    /// names that don't resolve, which are probed by name.
+    pub address: Option<IpAddr>,
+}
+
+impl Target {
+    pub fn new(name: impl Into<String>) -> Target {
+        Target {
+            name: name.into(),
+            label: None,
+            address: None,
+        }
+    }
+
+    /// The row title: the label, else the name.
+    pub fn title(&self) -> &str {
+        self.label.as_deref().unwrap_or(&self.name)
+    }
+
+    /// The target in log lines: its label with the name, if it has one.
+    pub fn named(&self) -> String {
+        match &self.label {
+            Some(label) => format!("{label} ({})", self.name),
+            None => self.name.clone(),
+        }
+    }
+}
+
+/// How `PingMonitor::start` probes its targets.
+pub struct Options {
+    pub interval: Duration,
+    /// Stop probing this long after the first tick; `None` runs until `stop`.
+    pub run_for: Option<Duration>,
+    /// `--schedule`: ticks outside it are reported as paused.
+    pub schedule: Option<Arc<schedule::Schedule>>,
+    pub engine: Engine,
+    pub clock: Arc<dyn Clock>,
+}
+
+/// Probes a changing set of targets once per interval, one worker thread
+/// per target, and streams every probe as a `ProbeResult`. Everything
+/// around it (stats, severities, the TUI, logs) is up to the caller.
+pub struct PingMonitor {
+    ctx: WorkerCtx,
+    rx: mpsc::Receiver<ProbeResult>,
+    handles: Vec<thread::JoinHandle<()>>,
+    generation: u64,
+}
+
+impl PingMonitor {
+    /// Starts probing `targets` at the next interval boundary.
+    pub fn start(targets: &[Target], options: Options) -> PingMonitor {
+        let Options {
+            interval,
+            run_for,
+            schedule,
+            engine,
+            clock,
+        } = options;
+        let first_tick = clock::align_to_interval(clock.as_ref(), interval);
+        let (tx, rx) = mpsc::channel();
+        let live = targets
+            .iter()
+            .map(|t| (t.name.clone(), (0, t.clone())))
+            .collect();
+        let ctx = WorkerCtx {
+            tx,
+            first_tick,
+            interval,
+            deadline: run_for.map(|d| first_tick + d),
+            schedule,
+            disabled: Arc::default(),
+            held: Arc::default(),
+            engine,
+            clock,
+            live: Arc::new(Mutex::new(live)),
+        };
+        let handles = spawn_workers(targets, 0, &ctx);
+        PingMonitor {
+            ctx,
+            rx,
+            handles,
+            generation: 0,
+        }
+    }
+
+    /// The results that arrived since the last call, without waiting.
+    pub fn results(&self) -> mpsc::TryIter<'_, ProbeResult> {
+        self.rx.try_iter()
+    }
+
+    /// When tick 0 was due.
+    pub fn first_tick(&self) -> Instant {
+        self.ctx.first_tick
+    }
+
+    pub fn deadline(&self) -> Option<Instant> {
+        self.ctx.deadline
+    }
+
+    /// The tick whose interval is running now.
+    pub fn current_slot(&self) -> u64 {
+        current_slot(
+            self.ctx.clock.as_ref(),
+            self.ctx.first_tick,
+            self.ctx.interval,
+        )
+    }
+
+    pub fn engine(&self) -> &Engine {
+        &self.ctx.engine
+    }
+
+    /// Names of the targets that keep reporting paused ticks instead of
+    /// being probed.
+    pub fn disabled(&self) -> &Arc<Mutex<HashSet<String>>> {
+        &self.ctx.disabled
+    }
+
+    /// While set, every target reports paused ticks.
+    pub fn held(&self) -> &Arc<AtomicBool> {
+        &self.ctx.held
+    }
+
+    /// Stops the workers of `removed` and starts `added` at the next tick,
+    /// which is returned; earlier ticks don't count as missed for them.
+    pub fn update(&mut self, added: &[Target], removed: &[Target]) -> u64 {
+        let start_tick = self.current_slot() + 1;
+        if let Ok(mut live) = self.ctx.live.lock() {
+            for target in removed {
+                live.remove(&target.name);
+            }
+            for target in added {
+                self.generation += 1;
+                live.insert(target.name.clone(), (self.generation, target.clone()));
+            }
+        }
+        // Workers of removed targets end on their own; joining them here
+        // keeps the list from growing with every edit of a long run.
+        let (ended, running) = mem::take(&mut self.handles)
+            .into_iter()
+            .partition(|h| h.is_finished());
+        join(ended);
+        self.handles = running;
+        self.handles
+            .extend(spawn_workers(added, start_tick, &self.ctx));
+        start_tick
+    }
+
+    /// Probes of `name` go to `address` from its next tick on.
+    pub fn set_address(&self, name: &str, address: Option<IpAddr>) {
+        if let Ok(mut live) = self.ctx.live.lock()
+            && let Some((_, target)) = live.get_mut(name)
+        {
+            target.address = address;
+        }
+    }
+
+    /// Stops all workers and returns the results still on their way,
+    /// including those of probes that were in flight.
+    pub fn stop(self) -> Vec<ProbeResult> {
+        // Workers stop before their next probe once no target is live.
+        if let Ok(mut live) = self.ctx.live.lock() {
+            live.clear();
+        }
+        join(self.handles);
+        self.rx.try_iter().collect()
+    }
+}
+
+fn join(handles: Vec<thread::JoinHandle<()>>) {
+    for handle in handles {
+        if handle.join().is_err() {
+            error!("a worker thread panicked");
+        }
+    }
+}
+
+/// One probe of one target, as sent by the workers.
+pub struct ProbeResult {
+    pub ip: String,
+    pub tick: u64,
+    pub ts_ms: i64,
+    // Tick fell outside --schedule or the target is disabled; no probe was sent.
+    pub paused: bool,
+    pub outcome: ProbeOutcome,
+    // How late the probe started after its tick, and how long it took.
+    pub delay_ms: f64,
+    pub duration_ms: f64,
+}
+
+/// Groups results by tick so that all targets' stats advance one complete
+/// round at a time.
+pub struct RoundCollector {
+    targets: usize,
+    next_round: u64,
+    pending: BTreeMap<u64, Vec<ProbeResult>>,
+}
+
+impl RoundCollector {
+    pub fn new(targets: usize) -> Self {
+        RoundCollector {
+            targets,
+            next_round: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+
+    pub fn push(&mut self, result: ProbeResult) {
+        if result.tick < self.next_round {
+            debug!(target_ip = %result.ip, tick = result.tick, "dropping result for closed round");
+            return;
+        }
+        self.pending.entry(result.tick).or_default().push(result);
+    }
+
+    /// Releases the oldest round once every target reported for it, or once
+    /// the scheduler is two slots past it (results for it can no longer arrive).
+    pub fn pop_complete(&mut self, current_slot: u64) -> Option<(u64, Vec<ProbeResult>)> {
+        let round = self.next_round;
+        let reported = self.pending.get(&round).map_or(0, Vec::len);
+        if reported < self.targets && current_slot < round + 2 {
+            return None;
+        }
+        self.next_round += 1;
+        Some((round, self.pending.remove(&round).unwrap_or_default()))
+    }
+
+    /// Releases every round still holding results, complete or not.
+    pub fn drain(&mut self) -> Vec<(u64, Vec<ProbeResult>)> {
+        let rounds = std::mem::take(&mut self.pending);
+        if let Some(last) = rounds.keys().next_back() {
+            self.next_round = last + 1;
+        }
+        rounds.into_iter().collect()
+    }
+
+    pub fn completed(&self) -> u64 {
+        self.next_round
+    }
+
+    pub fn set_targets(&mut self, targets: usize) {
+        self.targets = targets;
+    }
+}
+
+pub fn current_slot(clock: &dyn Clock, first_tick: Instant, interval: Duration) -> u64 {
+    (clock.now().saturating_duration_since(first_tick).as_nanos() / interval.as_nanos()) as u64
+}
+
+/// Replacement for `ping_once`, called with the target name for every probe.
+pub type Prober = Arc<dyn Fn(&str) -> ProbeOutcome + Send + Sync>;
+
+/// How probes are answered: the system `ping`, a simulation profile, or a
+/// `Prober` passed to `app::run` (tests).
+#[derive(Clone)]
+pub enum Engine {
+    /// `dump_dir` receives the raw output of probes that come back `Unparsed`;
+    /// `routes` names the source interface of bound targets (`--group`).
+    /// With `native`, probes go out over that ICMP socket instead of `ping`.
+    /// Host names are probed at their `Target::address`, if known;
+    /// `family` is the one chosen with `-4`/`-6`.
+    System {
+        dump_dir: Option<PathBuf>,
+        routes: wan::Routes,
+        timeout: Duration,
+        native: Option<icmp::Socket>,
+        family: Option<Family>,
+    },
+    Simulated(Arc<sim::Simulator>),
+    Custom(Prober),
+}
+
+impl Engine {
+    pub fn probe(&self, target: &Target) -> ProbeOutcome {
+        let ip = target.name.as_str();
+        match self {
+            Engine::System {
+                dump_dir,
+                routes,
+                timeout,
+                native,
+                family,
+            } => {
+                let interface = routes.interface(ip);
+                let addr = target.address.map(|a| a.to_string());
+                let dest = addr.as_deref().unwrap_or(targets::split_family(ip).0);
+                let family = targets::family_of(dest, targets::family_of(ip, *family));
+                // Binding to an interface needs more privileges, so bound
+                // targets (and IPv6 ones) keep using `ping`.
+                if let Some(socket) = native
+                    && interface.is_none()
+                    && family != Some(Family::V6)
+                    && let Some(outcome) = icmp::probe(*socket, dest, *timeout)
+                {
+                    return outcome;
+                }
+                let (outcome, stdout) = run_ping(dest, family, interface.as_deref(), *timeout);
+                if outcome == ProbeOutcome::Unparsed
+                    && let Some(dir) = dump_dir
+                {
+                    dump_raw_output(dir, ip, &stdout);
+                }
+                outcome
+            }
+            Engine::Simulated(sim) => sim.probe(ip),
+            Engine::Custom(prober) => prober(ip),
+        }
+    }
+}
+
+/// Saves unrecognized `ping` output as `<target>-<timestamp>.txt` so the
+/// format can be reported and added to the parser corpus.
+fn dump_raw_output(dir: &Path, ip: &str, stdout: &[u8]) {
+    let target: String = ip
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let name = format!("{target}-{}.txt", Local::now().format("%Y%m%d-%H%M%S%.3f"));
+    let result = fs::create_dir_all(dir).and_then(|_| fs::write(dir.join(&name), stdout));
+    match result {
+        Ok(()) => info!(target_ip = ip, "saved unparsed ping output to {name}"),
+        Err(err) => warn!(target_ip = ip, "failed to save unparsed ping output: {err}"),
+    }
+}
+
+/// One probe of an IP literal or host name, over its pinned family if it
+/// has one (`dns.google@v6`).
+pub fn ping_once(ip: &str, timeout: Duration) -> ProbeOutcome {
+    let family = targets::family_of(ip, None);
+    run_ping(targets::split_family(ip).0, family, None, timeout).0
+}
+
+/// Runs one system `ping`, over `family` if given and optionally bound to
+/// `interface`, and returns its outcome along with the raw stdout. The child
+/// is killed after `timeout`.
+pub fn run_ping(
+    ip: &str,
+    family: Option<Family>,
+    interface: Option<&str>,
+    timeout: Duration,
+) -> (ProbeOutcome, Vec<u8>) {
+    // Use system ping to avoid raw socket requirements; capture output to keep console clean.
+    let mut cmd = if let Ok(mock) = env::var("PING_PLOTTER_MOCK") {
+        let mut c = Command::new(mock);
+        c.arg(ip);
+        c.args(interface);
+        c
+    } else if cfg!(target_os = "macos") && family == Some(Family::V6) {
+        // The macOS `ping` is IPv4 only and `ping6` has no per-reply
+        // timeout (the child is killed after `timeout` anyway) and binds
+        // with `-B`.
+        let mut c = Command::new("ping6");
+        if let Some(interface) = interface {
+            c.args(["-B", interface]);
+        }
+        c.args(["-c", "1", ip]);
+        c
+    } else {
+        let mut c = Command::new("ping");
+        if let Some(interface) = interface {
+            c.args(wan::ping_args(interface));
+        }
+        let timeout_ms = timeout.as_millis().max(1).to_string();
+        if !cfg!(target_os = "macos") {
+            c.args(family.map(|f| match f {
+                Family::V4 => "-4",
+                Family::V6 => "-6",
+            }));
+        }
+        if cfg!(target_os = "windows") {
+            c.args(["-n", "1", "-w", &timeout_ms, ip]);
+        } else if cfg!(target_os = "macos") {
+            c.args(["-c", "1", "-W", &timeout_ms, ip]);
+        } else {
+            let secs = timeout.as_secs_f64().ceil().max(1.0) as u64;
+            c.args(["-c", "1", "-W", &secs.to_string(), ip]); // iputils uses seconds
+        }
+        c
+    };
+    // Local errors such as "Network is unreachable" only go to stderr.
+    cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
+
+    let mut child = match overhead::measure(Phase::Spawn, || cmd.spawn()) {
+        Ok(child) => child,
+        Err(err) => {
+            warn!(target_ip = ip, "failed to spawn ping: {err}");
+            return (ProbeOutcome::Lost(Loss::Error), Vec::new());
+        }
+    };
+
+    match child.wait_timeout(timeout) {
+        Ok(Some(_status)) => match child.wait_with_output() {
+            Ok(output) => {
+                let outcome = overhead::measure(Phase::Parse, || {
+                    let text = [output.stdout.as_slice(), &output.stderr].concat();
+                    parse::parse_output(&text, output.status.success())
+                });
+                if outcome == ProbeOutcome::Unparsed {
+                    debug!(
+                        target_ip = ip,
+                        "ping succeeded but its output matched no reply format"
+                    );
+                }
+                trace!(target_ip = ip, ?outcome, "ping finished");
+                (outcome, output.stdout)
+            }
+            Err(err) => {
+                warn!(target_ip = ip, "failed to collect ping output: {err}");
+                (ProbeOutcome::Lost(Loss::Error), Vec::new())
+            }
+        },
+        Ok(None) => {
+            debug!(target_ip = ip, "ping timed out, killing process");
+            let _ = child.kill();
+            let _ = child.wait();
+            (ProbeOutcome::Lost(Loss::Timeout), Vec::new())
+        }
+        Err(err) => {
+            warn!(target_ip = ip, "failed to wait for ping: {err}");
+            (ProbeOutcome::Lost(Loss::Error), Vec::new())
+        }
+    }
+}
+
+/// Everything a probe worker needs besides its target.
+#[derive(Clone)]
+struct WorkerCtx {
+    tx: mpsc::Sender<ProbeResult>,
+    first_tick: Instant,
+    interval: Duration,
+    deadline: Option<Instant>,
+    schedule: Option<Arc<schedule::Schedule>>,
+    disabled: Arc<Mutex<HashSet<String>>>,
+    /// Set by `pause` on the control socket; probes are skipped like
+    /// outside the schedule.
+    held: Arc<AtomicBool>,
+    engine: Engine,
+    clock: Arc<dyn Clock>,
+    // Each target by name, with the generation of the worker that currently
+    // owns it. A worker stops once its target is removed or re-added under a
+    // newer generation, and probes the target as listed here at each tick.
+    live: Arc<Mutex<HashMap<String, (u64, Target)>>>,
+}
+
+/// Starts one worker per target, beginning at `start_tick`. The targets must
+/// already be registered in `ctx.live`.
+fn spawn_workers(
+    targets: &[Target],
+    start_tick: u64,
+    ctx: &WorkerCtx,
+) -> Vec<thread::JoinHandle<()>> {
+    targets
+        .iter()
+        .map(|target| {
+            let ip = target.name.clone();
+            let ctx = ctx.clone();
+            let generation = ctx.live.lock().ok().and_then(|l| l.get(&ip).map(|e| e.0));
+            debug!(target_ip = %ip, start_tick, "spawning worker");
+            debuglog::record(format!("{ip}: worker started at tick {start_tick}"));
+            thread::spawn(move || run_worker(&ip, start_tick, generation, ctx))
+        })
+        .collect()
+}
+
+/// Probes `ip` once per tick until the deadline, the receiver hangs up or
+/// the target's `generation` is no longer the live one.
+fn run_worker(ip: &str, start_tick: u64, generation: Option<u64>, ctx: WorkerCtx) {
+    let WorkerCtx {
+        tx,
+        first_tick,
+        interval,
+        deadline,
+        schedule,
+        disabled,
+        held,
+        engine,
+        clock,
+        live,
+    } = ctx;
+    let mut tick = start_tick;
+    let reason = loop {
+        let next_tick = first_tick + interval * tick as u32;
+        let now = clock.now();
+        if let Some(end) = deadline
+            && now >= end
+        {
+            break "deadline reached";
+        }
+        if now < next_tick {
+            let sleep_dur = next_tick - now;
+            if let Some(end) = deadline
+                && now + sleep_dur >= end
+            {
+                clock.sleep(end - now);
+                break "deadline reached";
+            }
+            clock.sleep(sleep_dur);
+        }
+        if let Some(end) = deadline
+            && clock.now() >= end
+        {
+            break "deadline reached";
+        }
+        let target = live.lock().ok().and_then(|l| {
+            l.get(ip)
+                .filter(|(owner, _)| Some(*owner) == generation)
+                .map(|(_, target)| target.clone())
+        });
+        let Some(target) = target else {
+            debug!(target_ip = %ip, "target removed, stopping worker");
+            break "target removed";
+        };
+        let ts_ms = clock.wall_ms();
+        // Disabled targets keep reporting so their rounds still complete.
+        let paused = schedule
+            .as_ref()
+            .is_some_and(|s| !s.is_active(&clock.local()))
+            || held.load(atomic::Ordering::SeqCst)
+            || disabled.lock().is_ok_and(|d| d.contains(ip));
+        let started = clock.now();
+        let outcome = if paused {
+            ProbeOutcome::Lost(Loss::Timeout)
+        } else {
+            engine.probe(&target)
+        };
+        let finished = clock.now();
+        let delay_ms = started.saturating_duration_since(next_tick).as_secs_f64() * 1000.0;
+        let duration_ms = finished.saturating_duration_since(started).as_secs_f64() * 1000.0;
+        if tx
+            .send(ProbeResult {
+                ip: ip.to_string(),
+                tick,
+                ts_ms,
+                paused,
+                outcome,
+                delay_ms,
+                duration_ms,
+            })
+            .is_err()
+        {
+            break "receiver closed";
+        }
+        // Ticks whose slot already started while this probe ran are
+        // skipped instead of fired late; the stats count them as missed.
+        let slot = current_slot(clock.as_ref(), first_tick, interval);
+        if slot > tick {
+            debug!(target_ip = %ip, tick, slot, "probe overran its tick");
+            debuglog::record(format!(
+                "{ip}: probe of tick {tick} overran, skipping to tick {slot}"
+            ));
+        }
+        tick = slot.max(tick) + 1;
+    };
+    debuglog::record(format!("{ip}: worker stopped ({reason})"));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DEFAULT_INTERVAL, windows};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn result(ip: &str, tick: u64) -> ProbeResult {
+        ProbeResult {
+            ip: ip.to_string(),
+            tick,
+            ts_ms: 0,
+            paused: false,
+            outcome: ProbeOutcome::Reply(1.0),
+            delay_ms: 0.0,
+            duration_ms: 0.0,
+        }
+    }
+
+    #[test]
+    fn rounds_release_only_when_complete_or_expired() {
+        let mut rounds = RoundCollector::new(2);
+        rounds.push(result("a", 0));
+        rounds.push(result("a", 1));
+        assert!(rounds.pop_complete(1).is_none());
+        rounds.push(result("b", 0));
+        let (round, results) = rounds.pop_complete(1).unwrap();
+        assert_eq!((round, results.len()), (0, 2));
+
+        // "b" never reports round 1; it is released once slot 3 has started.
+        assert!(rounds.pop_complete(2).is_none());
+        let (round, results) = rounds.pop_complete(3).unwrap();
+        assert_eq!((round, results.len()), (1, 1));
+
+        rounds.push(result("b", 1));
+        assert!(rounds.drain().is_empty());
+        assert_eq!(rounds.completed(), 2);
+    }
+
+    #[test]
+    fn worker_follows_the_mock_clock_from_first_tick_to_deadline() {
+        let profile =
+            sim::Profile::parse("[[target]]\nname = \"down\"\nlatency_ms = 1.0\nloss_pct = 100.0")
+                .unwrap();
+        let clock = Arc::new(clock::MockClock::new(1_700_000_001_300));
+        let first_tick = clock::align_to_interval(clock.as_ref(), DEFAULT_INTERVAL);
+        let (tx, rx) = mpsc::channel();
+        let live = HashMap::from([("down".to_string(), (0, Target::new("down")))]);
+        let ctx = WorkerCtx {
+            tx,
+            first_tick,
+            interval: DEFAULT_INTERVAL,
+            deadline: Some(first_tick + Duration::from_secs(120)),
+            schedule: None,
+            disabled: Arc::default(),
+            held: Arc::default(),
+            engine: Engine::Simulated(Arc::new(sim::Simulator::new(&profile))),
+            clock: clock.clone(),
+            live: Arc::new(Mutex::new(live)),
+        };
+        run_worker("down", 0, Some(0), ctx);
+        let results: Vec<ProbeResult> = rx.try_iter().collect();
+        assert_eq!(results.len(), 60);
+        assert_eq!(clock.now(), first_tick + Duration::from_secs(120));
+        let mut windows = windows::LossWindows::default();
+        for (i, r) in results.iter().enumerate() {
+            assert_eq!(r.tick, i as u64);
+            assert_eq!(r.ts_ms, 1_700_000_002_000 + 2_000 * i as i64);
+            assert_eq!(r.delay_ms, 0.0);
+            windows.record(r.ts_ms, r.outcome.rtt_ms().is_some());
+        }
+        let (minute, quarter) = (windows::WINDOWS[0].1, windows::WINDOWS[1].1);
+        assert_eq!(windows.loss_pct(minute, clock.wall_ms()), Some(100.0));
+        clock.advance(Duration::from_secs(61));
+        assert_eq!(windows.loss_pct(minute, clock.wall_ms()), None);
+        assert_eq!(windows.loss_pct(quarter, clock.wall_ms()), Some(100.0));
+    }
+
+    #[test]
+    fn update_joins_the_workers_of_removed_targets() {
+        let prober: Prober = Arc::new(|_: &str| ProbeOutcome::Reply(1.0));
+        let mut monitor = PingMonitor::start(
+            &[Target::new("a")],
+            Options {
+                interval: Duration::from_millis(50),
+                run_for: None,
+                schedule: None,
+                engine: Engine::Custom(prober),
+                clock: Arc::new(clock::SystemClock),
+            },
+        );
+        let b = [Target::new("b")];
+        for _ in 0..5 {
+            monitor.update(&b, &[]);
+            monitor.update(&[], &b);
+            thread::sleep(Duration::from_millis(150));
+        }
+        monitor.update(&[], &[]);
+        assert_eq!(monitor.handles.len(), 1);
+        monitor.stop();
+    }
+
+    #[cfg(unix)]
+    fn make_mock_ping(script: &str) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_else(|_| Duration::from_secs(0))
+            .as_nanos();
+        let unique = format!("mock_ping_{}_{}", std::process::id(), nanos);
+        let path = std::env::temp_dir().join(unique);
+        fs::write(&path, script).expect("write mock ping");
+        let mut perm = fs::metadata(&path).unwrap().permissions();
+        perm.set_mode(0o755);
+        fs::set_permissions(&path, perm).unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    fn with_mock<F: FnOnce()>(path: &Path, f: F) {
+        // The mock is a process-wide env var; tests using it must not overlap.
+        static MOCK_LOCK: Mutex<()> = Mutex::new(());
+        let _guard = MOCK_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let prev = std::env::var("PING_PLOTTER_MOCK").ok();
+        unsafe { std::env::set_var("PING_PLOTTER_MOCK", path) };
+        f();
+        if let Some(val) = prev {
+            unsafe { std::env::set_var("PING_PLOTTER_MOCK", val) };
+        } else {
+            unsafe { std::env::remove_var("PING_PLOTTER_MOCK") };
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ping_once_reports_success_and_latency() {
+        let script = "#!/bin/sh\necho '64 bytes from 1.1.1.1: time=7.89 ms'\nexit 0\n";
+        let path = make_mock_ping(script);
+        with_mock(&path, || {
+            let outcome = ping_once("1.1.1.1", Duration::from_millis(1900));
+            assert_eq!(outcome, ProbeOutcome::Reply(7.89));
+        });
+        let _ = fs::remove_file(path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ping_once_reports_failure() {
+        let script = "#!/bin/sh\nexit 1\n";
+        let path = make_mock_ping(script);
+        with_mock(&path, || {
+            let outcome = ping_once("1.1.1.1", Duration::from_millis(1900));
+            assert_eq!(outcome, ProbeOutcome::Lost(Loss::Timeout));
+        });
+        let _ = fs::remove_file(path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unparsed_output_is_dumped() {
+        let script = "#!/bin/sh\necho 'PONG from somewhere'\nexit 0\n";
+        let path = make_mock_ping(script);
+        let dir = path.with_extension("dump");
+        let engine = Engine::System {
+            dump_dir: Some(dir.clone()),
+            routes: wan::Routes::default(),
+            timeout: Duration::from_millis(1900),
+            native: None,
+            family: None,
+        };
+        with_mock(&path, || {
+            assert_eq!(
+                engine.probe(&Target::new("fe80::1")),
+                ProbeOutcome::Unparsed
+            );
+        });
+        let dumps: Vec<_> = fs::read_dir(&dir).unwrap().flatten().collect();
+        assert_eq!(dumps.len(), 1);
+        let name = dumps[0].file_name().into_string().unwrap();
+        assert!(name.starts_with("fe80__1-"), "{name}");
+        assert_eq!(fs::read(dumps[0].path()).unwrap(), b"PONG from somewhere\n");
+        let _ = fs::remove_dir_all(dir);
+        let _ = fs::remove_file(path);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn ping_once_times_out() {
+        let script = "#!/bin/sh\nsleep 3\n";
+        let path = make_mock_ping(script);
+        with_mock(&path, || {
+            let start = Instant::now();
+            let outcome = ping_once("1.1.1.1", Duration::from_millis(500));
+            let elapsed = start.elapsed();
+            assert_eq!(outcome, ProbeOutcome::Lost(Loss::Timeout));
+            assert!(
+                elapsed < Duration::from_secs(2),
+                "expected timeout to cut off sleep, got {:?}",
+                elapsed
+            );
+        });
+        let _ = fs::remove_file(path);
+    }
+}
@@ -0,0 +1,268 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::DateTime;
+use tracing::error;
+
+use crate::{
+    ProbeResult, locale,
+    overhead::{self, Phase},
+    parse::ProbeOutcome,
+    sinks, store,
+};
+
+pub const CSV_HEADER: &str = "time,target,seq,success,rtt_ms,loss";
+
+/// `--csv`: one row per recorded probe, next to the log. Like `LogSink`, the
+/// first failed write disables it.
+pub struct CsvWriter {
+    writer: Option<BufWriter<File>>,
+    path: PathBuf,
+    retention: Option<Retention>,
+    health: sinks::Health,
+}
+
+impl CsvWriter {
+    /// Opens `path` for appending; an empty file gets the column header
+    /// first, so repeated runs can share one file.
+    pub fn open(path: &Path) -> CsvWriter {
+        let mut csv = CsvWriter::disabled();
+        csv.path = path.to_path_buf();
+        match open_appending(path) {
+            Ok(writer) => csv.writer = Some(writer),
+            Err(err) => {
+                error!("Failed to open CSV file {}: {err}", path.display());
+                csv.health.failed(err);
+            }
+        }
+        csv
+    }
+
+    /// A writer that writes nothing, for runs without `--csv`.
+    pub fn disabled() -> CsvWriter {
+        CsvWriter {
+            writer: None,
+            path: PathBuf::new(),
+            retention: None,
+            health: sinks::Health::default(),
+        }
+    }
+
+    /// `--retention`: rows older than `keep` are dropped from the file every
+    /// ten minutes, on `flush`.
+    pub fn set_retention(&mut self, keep: Duration) {
+        self.retention = Some(Retention::new(keep));
+    }
+
+    pub fn is_open(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    pub fn health(&self) -> &sinks::Health {
+        &self.health
+    }
+
+    /// `time` is RFC 3339 in UTC, `seq` the probe's tick; `rtt_ms` is empty
+    /// without a reply time and `loss` names why a probe failed.
+    pub fn write(&mut self, result: &ProbeResult) {
+        let Some(w) = self.writer.as_mut() else {
+            return;
+        };
+        let rtt = result
+            .outcome
+            .rtt_ms()
+            .map(|ms| format!("{ms:.3}"))
+            .unwrap_or_default();
+        let loss = match result.outcome {
+            ProbeOutcome::Lost(loss) => loss.label(),
+            ProbeOutcome::Reply(_) | ProbeOutcome::Unparsed => "",
+        };
+        let row = format!(
+            "{},{},{},{},{rtt},{loss}",
+            locale::iso_ms(result.ts_ms),
+            result.ip,
+            result.tick,
+            if result.outcome.success() { 1 } else { 0 },
+        );
+        match overhead::measure(Phase::Export, || writeln!(w, "{row}")) {
+            Ok(()) => self.health.succeeded(store::now_ms()),
+            Err(err) => {
+                error!("Failed to write to CSV file; disabling CSV output");
+                self.health.failed(err);
+                self.writer = None;
+            }
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Some(w) = self.writer.as_mut()
+            && let Err(err) = w.flush()
+        {
+            error!("Failed to flush CSV file: {err}");
+            self.health.failed(err);
+        }
+        let Some(cutoff_ms) = self.retention.as_mut().and_then(Retention::due) else {
+            return;
+        };
+        if self.writer.take().is_some() {
+            let time = |line: &str| line.split(',').next().and_then(parse_iso_ms);
+            if let Err(err) = prune_file(&self.path, cutoff_ms, time) {
+                error!("Failed to prune CSV file {}: {err}", self.path.display());
+                self.health.failed(err);
+            }
+            match open_appending(&self.path) {
+                Ok(writer) => self.writer = Some(writer),
+                Err(err) => {
+                    error!("Failed to reopen CSV file {}: {err}", self.path.display());
+                    self.health.failed(err);
+                }
+            }
+        }
+    }
+}
+
+fn open_appending(path: &Path) -> io::Result<BufWriter<File>> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let empty = file.metadata()?.len() == 0;
+    let mut writer = BufWriter::new(file);
+    if empty {
+        writeln!(writer, "{CSV_HEADER}")?;
+    }
+    Ok(writer)
+}
+
+/// How long a file output keeps its lines, and when it was last pruned.
+#[derive(Debug)]
+pub(crate) struct Retention {
+    keep: Duration,
+    pruned_ms: i64,
+}
+
+/// Pruning rewrites the whole file, so it doesn't happen every round.
+const PRUNE_EVERY_MS: i64 = 600_000;
+
+impl Retention {
+    pub(crate) fn new(keep: Duration) -> Retention {
+        Retention { keep, pruned_ms: 0 }
+    }
+
+    /// The cutoff when pruning is due, marking it done.
+    pub(crate) fn due(&mut self) -> Option<i64> {
+        let now = store::now_ms();
+        if now - self.pruned_ms < PRUNE_EVERY_MS {
+            return None;
+        }
+        self.pruned_ms = now;
+        Some(now - self.keep.as_millis() as i64)
+    }
+}
+
+/// An RFC 3339 time like the ones `locale::iso_ms` writes.
+pub(crate) fn parse_iso_ms(text: &str) -> Option<i64> {
+    DateTime::parse_from_rfc3339(text)
+        .ok()
+        .map(|t| t.timestamp_millis())
+}
+
+/// Rewrites `path` without the lines `time` dates before `cutoff_ms`. Lines
+/// without a time go with the line before them, so a header stays and the
+/// final state leaves with its run.
+pub(crate) fn prune_file(
+    path: &Path,
+    cutoff_ms: i64,
+    time: impl Fn(&str) -> Option<i64>,
+) -> io::Result<()> {
+    let text = fs::read_to_string(path)?;
+    let mut keep = true;
+    let mut kept = String::with_capacity(text.len());
+    for line in text.lines() {
+        if let Some(ts_ms) = time(line) {
+            keep = ts_ms >= cutoff_ms;
+        }
+        if keep {
+            kept.push_str(line);
+            kept.push('\n');
+        }
+    }
+    if kept.len() == text.len() {
+        return Ok(());
+    }
+    let tmp = path.with_extension("prune");
+    fs::write(&tmp, kept)?;
+    fs::rename(&tmp, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Loss;
+
+    #[test]
+    fn writes_one_row_per_result_below_a_single_header() {
+        let path = std::env::temp_dir().join(format!("output_{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut result = ProbeResult {
+            ip: "10.0.0.1".to_string(),
+            tick: 3,
+            ts_ms: 1_700_000_000_000,
+            paused: false,
+            outcome: ProbeOutcome::Reply(1.25),
+            delay_ms: 0.0,
+            duration_ms: 0.0,
+        };
+        let mut csv = CsvWriter::open(&path);
+        csv.write(&result);
+        csv.flush();
+        // A second run appends without repeating the header.
+        let mut csv = CsvWriter::open(&path);
+        result.tick = 4;
+        result.outcome = ProbeOutcome::Lost(Loss::Timeout);
+        csv.write(&result);
+        csv.flush();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(
+            text,
+            format!(
+                "{CSV_HEADER}\n2023-11-14T22:13:20.000Z,10.0.0.1,3,1,1.250,\n\
+                 2023-11-14T22:13:20.000Z,10.0.0.1,4,0,,timeout\n"
+            )
+        );
+    }
+
+    #[test]
+    fn retention_drops_old_rows_and_keeps_the_header() {
+        let path = std::env::temp_dir().join(format!("output_prune_{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut result = ProbeResult {
+            ip: "10.0.0.1".to_string(),
+            tick: 1,
+            ts_ms: 1_700_000_000_000,
+            paused: false,
+            outcome: ProbeOutcome::Reply(1.0),
+            delay_ms: 0.0,
+            duration_ms: 0.0,
+        };
+        let mut csv = CsvWriter::open(&path);
+        csv.write(&result);
+        result.tick = 2;
+        result.ts_ms = store::now_ms();
+        csv.write(&result);
+        csv.set_retention(Duration::from_secs(3600));
+        csv.flush();
+        result.tick = 3;
+        csv.write(&result);
+        csv.flush();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        let rows: Vec<&str> = text.lines().collect();
+        assert_eq!(rows.len(), 3);
+        assert_eq!(rows[0], CSV_HEADER);
+        assert!(rows[1].ends_with(",10.0.0.1,2,1,1.000,"));
+        assert!(rows[2].ends_with(",10.0.0.1,3,1,1.000,"));
+    }
+}
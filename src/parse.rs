@@ -0,0 +1,360 @@
+/// Result of one probe as read from the `ping` output.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeOutcome {
+    /// A reply with its round-trip time in milliseconds.
+    Reply(f64),
+    /// No reply, with the reason `ping` gave for it.
+    Lost(Loss),
+    /// `ping` reported success, but the output matched no known reply format.
+    Unparsed,
+}
+
+impl ProbeOutcome {
+    /// Whether the target answered; unparsed output still came from a
+    /// successful `ping`.
+    pub fn success(self) -> bool {
+        !matches!(self, ProbeOutcome::Lost(_))
+    }
+
+    pub fn rtt_ms(self) -> Option<f64> {
+        match self {
+            ProbeOutcome::Reply(ms) => Some(ms),
+            _ => None,
+        }
+    }
+}
+
+/// Why a probe got no reply. The kinds point at different causes: a timeout
+/// at congestion or a silent drop, "unreachable" at routing/ARP, an expired
+/// TTL at a routing loop, "prohibited" at a firewall.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Loss {
+    Timeout,
+    Unreachable,
+    TtlExceeded,
+    Prohibited,
+    /// `ping` could not be run or waited for; a local problem.
+    Error,
+    /// This host could not send at all ("Network is unreachable", Windows'
+    /// "General failure"): no interface or route, so nothing about the target.
+    LocalDown,
+}
+
+impl Loss {
+    pub fn label(self) -> &'static str {
+        match self {
+            Loss::Timeout => "timeout",
+            Loss::Unreachable => "unreachable",
+            Loss::TtlExceeded => "ttl exceeded",
+            Loss::Prohibited => "prohibited",
+            Loss::Error => "error",
+            Loss::LocalDown => "local network down",
+        }
+    }
+}
+
+// Keyword in front of the RTT on reply lines: iputils/BSD/busybox use
+// `time`, Windows translates it (de, fr, es, pt, nl, pl, it, sv/da/no, tr,
+// ru, cs).
+const TIME_KEYS: &[&str] = &[
+    "time",
+    "zeit",
+    "temps",
+    "tiempo",
+    "tempo",
+    "tijd",
+    "czas",
+    "durata",
+    "tid",
+    "süre",
+    "время",
+    "čas",
+];
+
+// Lines that mean "no reply" even if `ping` exited with 0, as Windows does
+// for "Destination host unreachable" sent by a router. Checked in order, so
+// the specific ICMP errors come before the generic loss summaries that
+// accompany them.
+const LOST_MARKERS: &[(&str, Loss)] = &[
+    ("network is unreachable", Loss::LocalDown),
+    ("sendto: no route to host", Loss::LocalDown),
+    ("general failure", Loss::LocalDown),
+    ("prohibited", Loss::Prohibited),
+    ("packet filtered", Loss::Prohibited),
+    ("time to live exceeded", Loss::TtlExceeded),
+    ("ttl expired", Loss::TtlExceeded),
+    ("ttl abgelaufen", Loss::TtlExceeded),
+    ("ttl expiré", Loss::TtlExceeded),
+    ("ttl caducó", Loss::TtlExceeded),
+    ("unreachable", Loss::Unreachable),
+    ("nicht erreichbar", Loss::Unreachable),
+    ("inaccessible", Loss::Unreachable),
+    ("inaccesible", Loss::Unreachable),
+    ("100% packet loss", Loss::Timeout),
+    ("100.0% packet loss", Loss::Timeout),
+    (" 0 received", Loss::Timeout),
+    (" 0 packets received", Loss::Timeout),
+    ("request timed out", Loss::Timeout),
+    ("zeitüberschreitung", Loss::Timeout),
+    ("100% verlust", Loss::Timeout),
+    ("délai d'attente", Loss::Timeout),
+    ("tiempo de espera", Loss::Timeout),
+    ("esgotado", Loss::Timeout),
+];
+
+/// Classifies the output (stdout, then stderr) of a single-probe `ping` run.
+pub fn parse_output(output: &[u8], exit_ok: bool) -> ProbeOutcome {
+    let text = String::from_utf8_lossy(output).to_lowercase();
+    if let Some(ms) = text.lines().find_map(parse_reply_line) {
+        return ProbeOutcome::Reply(ms);
+    }
+    if let Some(loss) = loss_reason(&text) {
+        return ProbeOutcome::Lost(loss);
+    }
+    // A failed run without any error line simply got no answer in time.
+    if !exit_ok {
+        return ProbeOutcome::Lost(Loss::Timeout);
+    }
+    ProbeOutcome::Unparsed
+}
+
+/// The kind of loss a (lowercased) `ping` output reports, if any.
+fn loss_reason(text: &str) -> Option<Loss> {
+    LOST_MARKERS
+        .iter()
+        .find(|(marker, _)| text.contains(marker))
+        .map(|(_, loss)| *loss)
+}
+
+/// RTT from a (lowercased) reply line such as `time=12.3 ms`, `Zeit<1ms`
+/// or `temps=12 ms`. Values given as `<x` are taken as `x / 2`.
+pub fn parse_reply_line(line: &str) -> Option<f64> {
+    for (start, _) in line.char_indices() {
+        // Keywords only match at a word start, so `ttl=` or `lifetime=` do not.
+        if start > 0
+            && line[..start]
+                .chars()
+                .next_back()
+                .is_some_and(char::is_alphanumeric)
+        {
+            continue;
+        }
+        let rest = &line[start..];
+        let Some(key) = TIME_KEYS.iter().find(|k| rest.starts_with(*k)) else {
+            continue;
+        };
+        let rest = &rest[key.len()..];
+        let (below, rest) = match rest.chars().next() {
+            Some('=') => (false, &rest[1..]),
+            Some('<') => (true, &rest[1..]),
+            _ => continue,
+        };
+        // Some outputs write "time=<1ms".
+        let (below, rest) = match rest.strip_prefix('<') {
+            Some(r) => (true, r),
+            None => (below, rest),
+        };
+        let number_len = rest
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+            .unwrap_or(rest.len());
+        let unit = rest[number_len..].trim_start();
+        if number_len == 0 || !(unit.starts_with("ms") || unit.starts_with("мс")) {
+            continue;
+        }
+        let Ok(value) = rest[..number_len].replace(',', ".").parse::<f64>() else {
+            continue;
+        };
+        if value.is_finite() {
+            return Some(if below { value / 2.0 } else { value });
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    // Captured outputs of `ping` with one probe; (file, exit ok, expected).
+    const CORPUS: &[(&str, &str, bool, ProbeOutcome)] = &[
+        (
+            "iputils-reply",
+            include_str!("../testdata/ping/iputils-reply.txt"),
+            true,
+            ProbeOutcome::Reply(12.4),
+        ),
+        (
+            "iputils-timeout",
+            include_str!("../testdata/ping/iputils-timeout.txt"),
+            false,
+            ProbeOutcome::Lost(Loss::Timeout),
+        ),
+        (
+            "iputils-unreachable",
+            include_str!("../testdata/ping/iputils-unreachable.txt"),
+            false,
+            ProbeOutcome::Lost(Loss::Unreachable),
+        ),
+        (
+            "macos-reply",
+            include_str!("../testdata/ping/macos-reply.txt"),
+            true,
+            ProbeOutcome::Reply(9.871),
+        ),
+        (
+            "busybox-reply",
+            include_str!("../testdata/ping/busybox-reply.txt"),
+            true,
+            ProbeOutcome::Reply(0.112),
+        ),
+        (
+            "windows-en-reply",
+            include_str!("../testdata/ping/windows-en-reply.txt"),
+            true,
+            ProbeOutcome::Reply(14.0),
+        ),
+        (
+            "windows-en-below-1ms",
+            include_str!("../testdata/ping/windows-en-below-1ms.txt"),
+            true,
+            ProbeOutcome::Reply(0.5),
+        ),
+        (
+            "windows-en-host-unreachable",
+            include_str!("../testdata/ping/windows-en-host-unreachable.txt"),
+            true,
+            ProbeOutcome::Lost(Loss::Unreachable),
+        ),
+        (
+            "windows-de-reply",
+            include_str!("../testdata/ping/windows-de-reply.txt"),
+            true,
+            ProbeOutcome::Reply(15.0),
+        ),
+        (
+            "windows-de-timeout",
+            include_str!("../testdata/ping/windows-de-timeout.txt"),
+            false,
+            ProbeOutcome::Lost(Loss::Timeout),
+        ),
+        (
+            "windows-fr-reply",
+            include_str!("../testdata/ping/windows-fr-reply.txt"),
+            true,
+            ProbeOutcome::Reply(21.0),
+        ),
+        (
+            "windows-es-reply",
+            include_str!("../testdata/ping/windows-es-reply.txt"),
+            true,
+            ProbeOutcome::Reply(18.0),
+        ),
+        (
+            "windows-ru-reply",
+            include_str!("../testdata/ping/windows-ru-reply.txt"),
+            true,
+            ProbeOutcome::Reply(7.0),
+        ),
+        (
+            "iputils-network-unreachable",
+            include_str!("../testdata/ping/iputils-network-unreachable.txt"),
+            false,
+            ProbeOutcome::Lost(Loss::LocalDown),
+        ),
+        (
+            "windows-en-general-failure",
+            include_str!("../testdata/ping/windows-en-general-failure.txt"),
+            false,
+            ProbeOutcome::Lost(Loss::LocalDown),
+        ),
+        (
+            "iputils-ttl-exceeded",
+            include_str!("../testdata/ping/iputils-ttl-exceeded.txt"),
+            false,
+            ProbeOutcome::Lost(Loss::TtlExceeded),
+        ),
+        (
+            "iputils-prohibited",
+            include_str!("../testdata/ping/iputils-prohibited.txt"),
+            false,
+            ProbeOutcome::Lost(Loss::Prohibited),
+        ),
+        (
+            "macos-prohibited",
+            include_str!("../testdata/ping/macos-prohibited.txt"),
+            false,
+            ProbeOutcome::Lost(Loss::Prohibited),
+        ),
+        (
+            "windows-en-ttl-expired",
+            include_str!("../testdata/ping/windows-en-ttl-expired.txt"),
+            true,
+            ProbeOutcome::Lost(Loss::TtlExceeded),
+        ),
+        (
+            "unknown-format",
+            include_str!("../testdata/ping/unknown-format.txt"),
+            true,
+            ProbeOutcome::Unparsed,
+        ),
+    ];
+
+    #[test]
+    fn classifies_corpus() {
+        for (name, output, exit_ok, expected) in CORPUS {
+            assert_eq!(
+                parse_output(output.as_bytes(), *exit_ok),
+                *expected,
+                "corpus file {name}"
+            );
+        }
+    }
+
+    #[test]
+    fn parses_common_time_formats() {
+        let samples = [
+            ("time=12.34 ms", Some(12.34)),
+            ("zeit=56ms", Some(56.0)),
+            ("time<1ms", Some(0.5)),
+            ("time=<1ms", Some(0.5)),
+            ("czas=3,5 ms", Some(3.5)),
+            ("ttl=57 lifetime=3ms", None),
+            ("time=fast", None),
+            ("no time here", None),
+        ];
+        for (input, expected) in samples {
+            assert_eq!(parse_reply_line(input), expected, "failed on input {input}");
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics_on_arbitrary_output(
+            bytes in proptest::collection::vec(any::<u8>(), 0..512),
+            ok: bool,
+        ) {
+            let _ = parse_output(&bytes, ok);
+        }
+
+        #[test]
+        fn recovers_rtt_from_reply_lines(
+            ms in 0.0f64..10_000.0,
+            key in proptest::sample::select(TIME_KEYS),
+            space in proptest::bool::ANY,
+        ) {
+            let sep = if space { " " } else { "" };
+            let line = format!("64 bytes from 10.0.0.1: icmp_seq=1 ttl=57 {key}={ms:.3}{sep}ms");
+            let got = parse_output(line.as_bytes(), true).rtt_ms().unwrap();
+            prop_assert!((got - ms).abs() < 0.001, "{line} -> {got}");
+        }
+
+        #[test]
+        fn output_without_rtt_is_never_a_reply(text in "[a-z0-9 :.%()\n]{0,200}", ok: bool) {
+            let outcome = parse_output(text.as_bytes(), ok);
+            prop_assert!(!matches!(outcome, ProbeOutcome::Reply(_)));
+            let lost = loss_reason(&text).is_some();
+            prop_assert_eq!(outcome.success(), ok && !lost);
+        }
+    }
+}
@@ -0,0 +1,428 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    net::{IpAddr, Ipv4Addr},
+    process::{Command, Stdio},
+};
+
+use clap::ValueEnum;
+use tracing::info;
+
+use crate::{
+    Target,
+    error::RunError,
+    targets::{Entry, Group, TargetSource},
+};
+
+/// Interface name prefixes of VPN tunnels (OpenVPN, WireGuard, PPP, macOS).
+const TUNNEL_PREFIXES: &[&str] = &["tun", "tap", "wg", "ppp", "utun", "ipsec"];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Preset {
+    /// VPN gateway, a host inside the tunnel and a public host outside it.
+    VpnCheck,
+    /// Modem, first ISP hop (found by traceroute) and a public anchor.
+    IspHandoff,
+}
+
+/// `--preset` with the flags for its legs.
+#[derive(Debug, Clone)]
+pub struct Choice {
+    pub preset: Preset,
+    /// `--vpn-gateway`; without it the gateway of the tunnel's route.
+    pub vpn_gateway: Option<String>,
+    pub vpn_inside: Option<String>,
+    pub vpn_outside: String,
+    pub isp_anchor: String,
+}
+
+impl Choice {
+    /// The legs to monitor: for `vpn-check` from the flags and the routing
+    /// table, for `isp-handoff` from one traceroute to the anchor.
+    pub fn check(&self) -> Result<Check, RunError> {
+        match self.preset {
+            Preset::VpnCheck => {
+                let gateway = (self.vpn_gateway.clone())
+                    .or_else(detect_gateway)
+                    .ok_or_else(|| {
+                        RunError::Targets(
+                            "No route through a VPN tunnel with a gateway found; name it with \
+                             --vpn-gateway"
+                                .to_string(),
+                        )
+                    })?;
+                let inside = self.vpn_inside.as_deref().ok_or_else(|| {
+                    RunError::Targets(
+                        "--preset vpn-check needs --vpn-inside <host>, a host only reachable \
+                         through the tunnel"
+                            .to_string(),
+                    )
+                })?;
+                VpnCheck::new(&gateway, inside, &self.vpn_outside)
+                    .map(Check::Vpn)
+                    .map_err(|err| RunError::Targets(format!("Invalid vpn-check target: {err}")))
+            }
+            Preset::IspHandoff => {
+                let anchor = &self.isp_anchor;
+                info!("tracing the route to {anchor} to find the ISP handoff");
+                run_traceroute(anchor)
+                    .and_then(|output| IspHandoff::from_traceroute(&output, anchor))
+                    .map(Check::Handoff)
+                    .map_err(|err| {
+                        RunError::Targets(format!("Could not find the ISP handoff: {err}"))
+                    })
+            }
+        }
+    }
+}
+
+/// Most hops a handoff traceroute looks at before giving up.
+const MAX_HOPS: u8 = 10;
+
+/// A preset's targets, which leg each one is, and how to read their state.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Check {
+    Vpn(VpnCheck),
+    Handoff(IspHandoff),
+}
+
+impl Check {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Check::Vpn(_) => "vpn-check",
+            Check::Handoff(_) => "isp-handoff",
+        }
+    }
+
+    /// Short prefix for the status line.
+    pub fn title(&self) -> &'static str {
+        match self {
+            Check::Vpn(_) => "VPN",
+            Check::Handoff(_) => "ISP",
+        }
+    }
+
+    /// Targets in display order with the label of their leg.
+    pub fn legs(&self) -> Vec<(String, &'static str)> {
+        match self {
+            Check::Vpn(c) => vec![
+                (c.gateway.clone(), "VPN-Gateway"),
+                (c.inside.clone(), "Im Tunnel"),
+                (c.outside.clone(), "Außerhalb des Tunnels"),
+            ],
+            Check::Handoff(c) => vec![
+                (c.modem.clone(), "Modem/Router"),
+                (c.isp_hop.clone(), "Erster ISP-Hop"),
+                (c.anchor.clone(), "Öffentlicher Anker"),
+            ],
+        }
+    }
+
+    pub fn diagnose(&self, up: impl Fn(&str) -> bool) -> Diagnosis {
+        match self {
+            Check::Vpn(c) => c.diagnose(up),
+            Check::Handoff(c) => c.diagnose(up),
+        }
+    }
+}
+
+/// The three legs of `--preset vpn-check`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VpnCheck {
+    pub gateway: String,
+    pub inside: String,
+    pub outside: String,
+}
+
+impl VpnCheck {
+    /// Normalizes the hosts like list entries; the legs must differ.
+    pub fn new(gateway: &str, inside: &str, outside: &str) -> Result<VpnCheck, String> {
+        let name = |host: &str| Entry::parse(host).map(|t| t.name());
+        let check = VpnCheck {
+            gateway: name(gateway)?,
+            inside: name(inside)?,
+            outside: name(outside)?,
+        };
+        if check.gateway == check.inside
+            || check.gateway == check.outside
+            || check.inside == check.outside
+        {
+            return Err("gateway, inside and outside host must differ".to_string());
+        }
+        Ok(check)
+    }
+
+    /// Which leg fails, from whether each target currently answers.
+    pub fn diagnose(&self, up: impl Fn(&str) -> bool) -> Diagnosis {
+        match (up(&self.gateway), up(&self.inside), up(&self.outside)) {
+            (true, true, true) => Diagnosis::Ok,
+            (false, false, false) => Diagnosis::NoUplink,
+            (false, _, true) => Diagnosis::Gateway,
+            (true, false, _) => Diagnosis::Tunnel,
+            (true, true, false) => Diagnosis::Outside,
+            // Gateway down but something behind it answers: routing is odd,
+            // which is still the gateway's leg.
+            (false, true, false) => Diagnosis::Gateway,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Diagnosis {
+    Ok,
+    /// Nothing answers: the local uplink is down.
+    NoUplink,
+    /// The internet answers but the VPN gateway doesn't.
+    Gateway,
+    /// The gateway answers but the host in the tunnel doesn't.
+    Tunnel,
+    /// The tunnel works, the public host outside it doesn't (split tunnel).
+    Outside,
+    /// The own modem or router doesn't answer.
+    Modem,
+    /// The modem answers, the provider's first router doesn't.
+    Isp,
+    /// The provider answers, the internet behind it doesn't.
+    Upstream,
+}
+
+impl Diagnosis {
+    /// Status line text for the live view.
+    pub fn text(self) -> &'static str {
+        match self {
+            Diagnosis::Ok => "alle Strecken erreichbar",
+            Diagnosis::NoUplink => "keine Verbindung – lokaler Anschluss gestört",
+            Diagnosis::Gateway => "VPN-Gateway nicht erreichbar",
+            Diagnosis::Tunnel => "Gateway erreichbar, Ziel im Tunnel nicht",
+            Diagnosis::Outside => "Tunnel in Ordnung, Internet außerhalb gestört",
+            Diagnosis::Modem => "Modem/Router nicht erreichbar – lokales Problem",
+            Diagnosis::Isp => "Modem erreichbar, erster ISP-Hop nicht – Störung beim Provider",
+            Diagnosis::Upstream => "Provider erreichbar, Internet dahinter nicht",
+        }
+    }
+
+    /// Log line text.
+    pub fn label(self) -> &'static str {
+        match self {
+            Diagnosis::Ok => "all legs up",
+            Diagnosis::NoUplink => "local uplink failing",
+            Diagnosis::Gateway => "gateway leg failing",
+            Diagnosis::Tunnel => "tunnel leg failing",
+            Diagnosis::Outside => "outside leg failing",
+            Diagnosis::Modem => "modem failing",
+            Diagnosis::Isp => "ISP handoff failing",
+            Diagnosis::Upstream => "upstream of ISP failing",
+        }
+    }
+}
+
+/// The legs of `--preset isp-handoff`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IspHandoff {
+    pub modem: String,
+    pub isp_hop: String,
+    pub anchor: String,
+}
+
+impl IspHandoff {
+    /// Picks the modem (first hop, inside the local network) and the ISP
+    /// hop (first one outside it) from traceroute output towards `anchor`.
+    pub fn from_traceroute(output: &str, anchor: &str) -> Result<IspHandoff, String> {
+        let anchor = Entry::parse(anchor)?.name();
+        let hops = traceroute_hops(output);
+        let modem = match hops.first() {
+            Some(Some(ip)) if is_local(ip) => ip.to_string(),
+            Some(Some(ip)) => return Err(format!("first hop {ip} is not a local address")),
+            _ => return Err("the first hop (modem) did not answer".to_string()),
+        };
+        let isp_hop = hops
+            .iter()
+            .flatten()
+            .find(|ip| !is_local(ip) && ip.to_string() != anchor)
+            .ok_or_else(|| format!("no hop outside the local network within {MAX_HOPS} hops"))?;
+        Ok(IspHandoff {
+            modem,
+            isp_hop: isp_hop.to_string(),
+            anchor,
+        })
+    }
+
+    /// The failing leg nearest to us is the one to blame.
+    pub fn diagnose(&self, up: impl Fn(&str) -> bool) -> Diagnosis {
+        match (up(&self.modem), up(&self.isp_hop), up(&self.anchor)) {
+            (false, _, _) => Diagnosis::Modem,
+            (true, false, _) => Diagnosis::Isp,
+            (true, true, false) => Diagnosis::Upstream,
+            (true, true, true) => Diagnosis::Ok,
+        }
+    }
+}
+
+/// Addresses of a home or office network; the first hop beyond them
+/// belongs to the provider (carrier-grade NAT included).
+fn is_local(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => v4.is_private() || v4.is_link_local() || v4.is_loopback(),
+        IpAddr::V6(v6) => {
+            let first = v6.segments()[0];
+            v6.is_loopback() || (first & 0xfe00) == 0xfc00 || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+/// The answering address per hop of `traceroute -n`/`tracert -d` output;
+/// `None` for hops that timed out.
+pub fn traceroute_hops(output: &str) -> Vec<Option<IpAddr>> {
+    output
+        .lines()
+        .filter(|line| {
+            line.split_whitespace()
+                .next()
+                .is_some_and(|n| n.parse::<u8>().is_ok())
+        })
+        .map(|line| {
+            line.split_whitespace()
+                .skip(1)
+                .find_map(|token| token.trim_matches(['(', ')', '[', ']']).parse().ok())
+        })
+        .collect()
+}
+
+/// Runs the system traceroute once towards `anchor`. `PING_PLOTTER_MOCK_TRACEROUTE`
+/// replaces the command, like `PING_PLOTTER_MOCK` does for `ping`.
+pub fn run_traceroute(anchor: &str) -> Result<String, String> {
+    let hops = MAX_HOPS.to_string();
+    let mut cmd = if let Ok(mock) = env::var("PING_PLOTTER_MOCK_TRACEROUTE") {
+        Command::new(mock)
+    } else if cfg!(target_os = "windows") {
+        let mut c = Command::new("tracert");
+        c.args(["-d", "-w", "1000", "-h", &hops]);
+        c
+    } else {
+        let mut c = Command::new("traceroute");
+        c.args(["-n", "-q", "1", "-w", "1", "-m", &hops]);
+        c
+    };
+    let output = cmd
+        .arg(anchor)
+        .stderr(Stdio::null())
+        .output()
+        .map_err(|err| format!("failed to run traceroute: {err}"))?;
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Gateway of the first route through a tunnel interface in a Linux
+/// `/proc/net/route` table. Point-to-point tunnels (e.g. WireGuard) have no
+/// gateway; the user has to name it then.
+pub fn gateway_from_routes(table: &str) -> Option<String> {
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let (iface, gateway) = (fields.first()?, fields.get(2)?);
+        if !TUNNEL_PREFIXES.iter().any(|p| iface.starts_with(p)) {
+            return None;
+        }
+        // The kernel prints the address as a little-endian hex word.
+        let raw = u32::from_str_radix(gateway, 16).ok().filter(|g| *g != 0)?;
+        Some(Ipv4Addr::from(raw.swap_bytes()).to_string())
+    })
+}
+
+pub fn detect_gateway() -> Option<String> {
+    fs::read_to_string("/proc/net/route")
+        .ok()
+        .and_then(|table| gateway_from_routes(&table))
+}
+
+/// Target list of a preset, with one named group per leg.
+pub struct PresetSource {
+    pub check: Check,
+}
+
+impl TargetSource for PresetSource {
+    fn describe(&self) -> String {
+        format!("preset {}", self.check.name())
+    }
+
+    fn load(&mut self) -> Result<Vec<Target>, String> {
+        Ok(self
+            .check
+            .legs()
+            .into_iter()
+            .map(|(target, _)| Target::new(target))
+            .collect())
+    }
+
+    fn groups(&self) -> HashMap<String, Group> {
+        self.check
+            .legs()
+            .into_iter()
+            .enumerate()
+            .map(|(index, (target, label))| {
+                let group = Group {
+                    index,
+                    name: Some(label.to_string()),
+                };
+                (target, group)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_tunnel_gateway_and_names_the_failing_leg() {
+        let table = "Iface\tDestination\tGateway\tFlags\n\
+                     eth0\t00000000\t010200C0\t0003\n\
+                     wg0\t0000080A\t00000000\t0001\n\
+                     tun0\t0000080A\t0100080A\t0003\n";
+        assert_eq!(gateway_from_routes(table).as_deref(), Some("10.8.0.1"));
+        assert_eq!(gateway_from_routes("Iface\tDestination\tGateway\n"), None);
+
+        assert!(VpnCheck::new("10.8.0.1", "10.8.0.1", "1.1.1.1").is_err());
+        let check = VpnCheck::new("10.8.0.1", "010.8.1.5", "1.1.1.1").unwrap();
+        let legs = Check::Vpn(check.clone()).legs();
+        assert_eq!(legs[1], ("10.8.1.5".to_string(), "Im Tunnel"));
+        assert_eq!(check.inside, "10.8.1.5");
+        let down = |targets: &'static [&'static str]| move |t: &str| !targets.contains(&t);
+        assert_eq!(check.diagnose(down(&[])), Diagnosis::Ok);
+        assert_eq!(check.diagnose(down(&["10.8.1.5"])), Diagnosis::Tunnel);
+        assert_eq!(
+            check.diagnose(down(&["10.8.0.1", "10.8.1.5"])),
+            Diagnosis::Gateway
+        );
+        assert_eq!(check.diagnose(down(&["1.1.1.1"])), Diagnosis::Outside);
+        assert_eq!(
+            check.diagnose(down(&["10.8.0.1", "10.8.1.5", "1.1.1.1"])),
+            Diagnosis::NoUplink
+        );
+    }
+
+    #[test]
+    fn finds_the_isp_hop_in_traceroute_output() {
+        let linux = "traceroute to 1.1.1.1 (1.1.1.1), 10 hops max, 60 byte packets\n \
+                     1  192.168.178.1  0.512 ms\n \
+                     2  *\n \
+                     3  100.64.0.1  8.1 ms\n \
+                     4  62.155.1.2  9.0 ms\n \
+                     5  1.1.1.1  10.2 ms\n";
+        let handoff = IspHandoff::from_traceroute(linux, "1.1.1.1").unwrap();
+        assert_eq!(handoff.modem, "192.168.178.1");
+        assert_eq!(handoff.isp_hop, "100.64.0.1");
+        let windows = "Routenverfolgung zu 1.1.1.1 über maximal 10 Hops\n\n  \
+                       1    <1 ms    <1 ms    <1 ms  10.0.0.138\n  \
+                       2     7 ms     6 ms     7 ms  62.155.1.2\n";
+        let handoff = IspHandoff::from_traceroute(windows, "1.1.1.1").unwrap();
+        assert_eq!(
+            (handoff.modem.as_str(), handoff.isp_hop.as_str()),
+            ("10.0.0.138", "62.155.1.2")
+        );
+        assert!(IspHandoff::from_traceroute(" 1  *\n 2  62.155.1.2  1 ms\n", "1.1.1.1").is_err());
+
+        let down = |t: &str| t != "62.155.1.2";
+        assert_eq!(handoff.diagnose(down), Diagnosis::Isp);
+        assert_eq!(handoff.diagnose(|t| t != "10.0.0.138"), Diagnosis::Modem);
+    }
+}
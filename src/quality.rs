@@ -0,0 +1,200 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::{Target, locale, parse::ProbeOutcome};
+
+/// `--latency-budget`: what a gamer or VoIP call tolerates. A window of
+/// `window` breaks the budget once one reply is slower than `latency_ms`
+/// or more than `loss_pct` of its probes are lost.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    pub latency_ms: f64,
+    pub loss_pct: f64,
+    pub window: Duration,
+}
+
+impl Limits {
+    fn window_ms(&self) -> i64 {
+        (self.window.as_millis() as i64).max(1)
+    }
+
+    pub fn describe(&self, numbers: locale::Numbers) -> String {
+        format!(
+            "> {} ms oder > {} % Verlust je {} s",
+            numbers.fixed(self.latency_ms, 0),
+            numbers.fixed(self.loss_pct, 1),
+            self.window.as_secs()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Window {
+    index: i64,
+    probes: u64,
+    lost: u64,
+    max_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Change {
+    /// The first bad window after good ones.
+    Bad { max_ms: Option<f64>, loss_pct: f64 },
+    /// Back within budget; `since_ms` is the start of the first bad window.
+    Good { since_ms: i64 },
+}
+
+impl Change {
+    /// Log and event text; `ts_ms` is the sample that completed the window.
+    pub fn text(self, ts_ms: i64) -> String {
+        match self {
+            Change::Bad { max_ms, loss_pct } => {
+                let max = max_ms.map_or_else(|| "-".to_string(), |ms| format!("{ms:.1} ms"));
+                format!("over budget (max {max}, {loss_pct:.0}% lost)")
+            }
+            Change::Good { since_ms } => {
+                format!("within budget again after {}s", (ts_ms - since_ms) / 1000)
+            }
+        }
+    }
+}
+
+/// One target's budget counters. Samples are grouped into fixed windows;
+/// a window counts once it is complete, i.e. when the next one starts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Quality {
+    current: Option<Window>,
+    windows: u64,
+    bad_windows: u64,
+    bad_since: Option<i64>,
+    samples: u64,
+    over: u64,
+}
+
+impl Quality {
+    pub fn record(&mut self, limits: &Limits, ts_ms: i64, outcome: ProbeOutcome) -> Option<Change> {
+        let index = ts_ms.div_euclid(limits.window_ms());
+        let mut change = None;
+        if let Some(window) = self.current.filter(|w| w.index != index) {
+            change = self.close(limits, window);
+            self.current = None;
+        }
+        let window = self.current.get_or_insert(Window {
+            index,
+            ..Window::default()
+        });
+        window.probes += 1;
+        self.samples += 1;
+        match outcome.rtt_ms() {
+            Some(ms) => {
+                window.max_ms = Some(window.max_ms.map_or(ms, |m| m.max(ms)));
+                self.over += u64::from(ms > limits.latency_ms);
+            }
+            None if outcome.success() => {}
+            None => {
+                window.lost += 1;
+                self.over += 1;
+            }
+        }
+        change
+    }
+
+    fn close(&mut self, limits: &Limits, window: Window) -> Option<Change> {
+        let loss_pct = window.lost as f64 * 100.0 / window.probes as f64;
+        let bad =
+            loss_pct > limits.loss_pct || window.max_ms.is_some_and(|m| m > limits.latency_ms);
+        self.windows += 1;
+        self.bad_windows += u64::from(bad);
+        match (self.bad_since, bad) {
+            (None, true) => {
+                self.bad_since = Some(window.index * limits.window_ms());
+                Some(Change::Bad {
+                    max_ms: window.max_ms,
+                    loss_pct,
+                })
+            }
+            (Some(since_ms), false) => {
+                self.bad_since = None;
+                Some(Change::Good { since_ms })
+            }
+            _ => None,
+        }
+    }
+
+    /// Share of samples that were lost or slower than the budget.
+    pub fn over_pct(&self) -> Option<f64> {
+        (self.samples > 0).then(|| self.over as f64 * 100.0 / self.samples as f64)
+    }
+
+    /// Seconds in bad windows, scaled to one hour of completed windows.
+    pub fn bad_seconds_per_hour(&self) -> Option<f64> {
+        (self.windows > 0).then(|| self.bad_windows as f64 * 3600.0 / self.windows as f64)
+    }
+}
+
+/// The budget section below the table, one row per target.
+pub fn lines(
+    targets: &[Target],
+    qualities: &HashMap<String, Quality>,
+    limits: &Limits,
+    numbers: locale::Numbers,
+) -> Vec<String> {
+    let fmt = |v: Option<f64>, decimals| {
+        v.map_or_else(|| "-".to_string(), |n| numbers.fixed(n, decimals))
+    };
+    let mut lines = vec![
+        format!("Latenzbudget ({}):", limits.describe(numbers)),
+        format!(
+            "{:<20} {:>14} {:>18}",
+            "Ziel", "über Budget", "schlechte s/h"
+        ),
+    ];
+    for target in targets {
+        let q = qualities.get(&target.name).copied().unwrap_or_default();
+        lines.push(format!(
+            "{:<20} {:>14} {:>18}",
+            target.name,
+            format!("{} %", fmt(q.over_pct(), 1)),
+            fmt(q.bad_seconds_per_hour(), 0),
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Loss;
+
+    #[test]
+    fn counts_bad_windows_as_seconds_per_hour() {
+        let limits = Limits {
+            latency_ms: 50.0,
+            loss_pct: 2.0,
+            window: Duration::from_secs(10),
+        };
+        let mut q = Quality::default();
+        let mut changes = Vec::new();
+        // Six 10s windows of five probes: one spike, one loss, four clean.
+        for i in 0..31i64 {
+            let outcome = match i {
+                7 => ProbeOutcome::Reply(80.0),
+                12 => ProbeOutcome::Lost(Loss::Timeout),
+                _ => ProbeOutcome::Reply(20.0),
+            };
+            changes.extend(q.record(&limits, i * 2_000, outcome));
+        }
+        assert_eq!(
+            changes,
+            [
+                Change::Bad {
+                    max_ms: Some(80.0),
+                    loss_pct: 0.0
+                },
+                Change::Good { since_ms: 10_000 },
+            ]
+        );
+        assert_eq!(q.bad_seconds_per_hour(), Some(2.0 * 3600.0 / 6.0));
+        assert_eq!(q.over_pct(), Some(2.0 * 100.0 / 31.0));
+        assert_eq!(Quality::default().bad_seconds_per_hour(), None);
+    }
+}
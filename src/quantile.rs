@@ -0,0 +1,112 @@
+/// Streaming estimate of one quantile with the P² algorithm (Jain and
+/// Chlamtac, 1985): five markers whose heights follow the quantile, so the
+/// memory stays fixed however long the run. Exact until the fifth value.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct P2 {
+    p: f64,
+    count: u64,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    steps: [f64; 5],
+}
+
+impl P2 {
+    pub fn new(p: f64) -> P2 {
+        P2 {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [0.0, 1.0, 2.0, 3.0, 4.0],
+            desired: [0.0, 2.0 * p, 4.0 * p, 2.0 + 2.0 * p, 4.0],
+            steps: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    pub fn add(&mut self, x: f64) {
+        if self.count < 5 {
+            self.heights[self.count as usize] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(f64::total_cmp);
+            }
+            return;
+        }
+        self.count += 1;
+        let q = &mut self.heights;
+        let k = if x < q[0] {
+            q[0] = x;
+            0
+        } else if x >= q[4] {
+            q[4] = x;
+            3
+        } else {
+            (1..5).find(|&i| x < q[i]).map_or(3, |i| i - 1)
+        };
+        for n in &mut self.positions[k + 1..] {
+            *n += 1.0;
+        }
+        for (desired, step) in self.desired.iter_mut().zip(self.steps) {
+            *desired += step;
+        }
+        for i in 1..4 {
+            let n = &mut self.positions;
+            let d = self.desired[i] - n[i];
+            if (d >= 1.0 && n[i + 1] - n[i] > 1.0) || (d <= -1.0 && n[i - 1] - n[i] < -1.0) {
+                let d = d.signum();
+                let parabolic = q[i]
+                    + d / (n[i + 1] - n[i - 1])
+                        * ((n[i] - n[i - 1] + d) * (q[i + 1] - q[i]) / (n[i + 1] - n[i])
+                            + (n[i + 1] - n[i] - d) * (q[i] - q[i - 1]) / (n[i] - n[i - 1]));
+                q[i] = if q[i - 1] < parabolic && parabolic < q[i + 1] {
+                    parabolic
+                } else {
+                    let j = if d > 0.0 { i + 1 } else { i - 1 };
+                    q[i] + d * (q[j] - q[i]) / (n[j] - n[i])
+                };
+                n[i] += d;
+            }
+        }
+    }
+
+    /// The estimate; the nearest-rank value while fewer than five were added.
+    pub fn value(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            1..5 => {
+                let mut first = self.heights[..self.count as usize].to_vec();
+                first.sort_by(f64::total_cmp);
+                let rank = (self.p * self.count as f64).ceil() as usize;
+                Some(first[rank.clamp(1, first.len()) - 1])
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn follows_the_quantiles_of_a_stream() {
+        let mut p50 = P2::new(0.5);
+        let mut p99 = P2::new(0.99);
+        assert_eq!(p50.value(), None);
+        for x in [30.0, 10.0, 20.0] {
+            p50.add(x);
+            p99.add(x);
+        }
+        assert_eq!((p50.value(), p99.value()), (Some(20.0), Some(30.0)));
+
+        // 1..=10000 in a scrambled order.
+        let (mut p50, mut p95) = (P2::new(0.5), P2::new(0.95));
+        for i in 0..10_000u64 {
+            let x = (i * 7_919 % 10_000 + 1) as f64;
+            p50.add(x);
+            p95.add(x);
+        }
+        assert!((p50.value().unwrap() - 5_000.0).abs() < 100.0, "{p50:?}");
+        assert!((p95.value().unwrap() - 9_500.0).abs() < 100.0, "{p95:?}");
+    }
+}
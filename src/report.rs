@@ -0,0 +1,892 @@
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{Local, TimeZone, Timelike};
+use serde::{Deserialize, Serialize};
+
+use crate::{Stats, parse::ProbeOutcome, schema};
+
+/// Bumped whenever a field changes meaning or is removed; adding fields
+/// keeps the version, so readers should ignore unknown ones.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Machine-readable state of a run (`--summary`), shared by everything that
+/// needs the numbers rather than the rendered table.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Summary {
+    pub schema: u32,
+    pub generated_ms: i64,
+    /// `generated_ms` as ISO 8601 in UTC.
+    #[serde(default)]
+    pub generated: String,
+    pub rounds: u64,
+    pub targets: Vec<TargetSummary>,
+    pub outages: Vec<Outage>,
+    /// Stretches in which most targets failed together (`--connectivity-loss`).
+    #[serde(default)]
+    pub connectivity_lost: Vec<Window>,
+    /// Stretches in which this host could not send at all (LOCAL-DOWN);
+    /// their rounds are left out of the targets' stats.
+    #[serde(default)]
+    pub local_down: Vec<Window>,
+    /// `outages` grouped by overlap (`incidents`).
+    #[serde(default)]
+    pub incidents: Vec<Incident>,
+    /// The targets' `availability_pct` (monitored time not spent in
+    /// outages) averaged with their `weight`.
+    #[serde(default)]
+    pub weighted_availability_pct: Option<f64>,
+    /// All targets' probes by local hour of day, hours without probes left out.
+    #[serde(default)]
+    pub by_hour: Vec<Hour>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TargetSummary {
+    pub target: String,
+    pub stats: Stats,
+    // Derived values, included so consumers don't have to recompute them.
+    pub loss_pct: Option<f64>,
+    pub avg_ms: Option<f64>,
+    /// RTT percentiles, estimated (see `Stats::p50_ms`).
+    #[serde(default)]
+    pub p50_ms: Option<f64>,
+    #[serde(default)]
+    pub p95_ms: Option<f64>,
+    #[serde(default)]
+    pub p99_ms: Option<f64>,
+    #[serde(default)]
+    pub jitter_ms: Option<f64>,
+    #[serde(default)]
+    pub stddev_ms: Option<f64>,
+    pub no_rtt: u64,
+    /// Failed or unusable probes by kind (`timeout`, ..., `local_down`).
+    #[serde(default)]
+    pub failures: BTreeMap<String, u64>,
+    pub severity: String,
+    /// From the config's `[[target]]`; 1 when not given.
+    #[serde(default = "one")]
+    pub weight: f64,
+    /// Only with `--latency-budget`.
+    #[serde(default)]
+    pub budget: Option<BudgetSummary>,
+    /// Share of the monitored time not spent in an outage (`Uptime`).
+    #[serde(default)]
+    pub availability_pct: Option<f64>,
+    /// The target's entries in `Summary::outages`, and the monitored time
+    /// they cover.
+    #[serde(default)]
+    pub outage_count: usize,
+    #[serde(default)]
+    pub downtime_ms: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct BudgetSummary {
+    pub over_budget_pct: Option<f64>,
+    pub bad_seconds_per_hour: Option<f64>,
+}
+
+impl TargetSummary {
+    pub fn new(target: &str, stats: Stats, severity: &str) -> TargetSummary {
+        TargetSummary {
+            target: target.to_string(),
+            stats,
+            loss_pct: stats.loss_pct(),
+            avg_ms: stats.avg_ms(),
+            p50_ms: stats.p50_ms(),
+            p95_ms: stats.p95_ms(),
+            p99_ms: stats.p99_ms(),
+            jitter_ms: stats.jitter_ms(),
+            stddev_ms: stats.stddev_ms(),
+            no_rtt: stats.no_rtt(),
+            failures: stats
+                .failures()
+                .into_iter()
+                .map(|(kind, count)| (kind.to_string(), count))
+                .collect(),
+            severity: severity.to_string(),
+            weight: 1.0,
+            budget: None,
+            availability_pct: None,
+            outage_count: 0,
+            downtime_ms: 0,
+        }
+    }
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+/// A target's importance; targets without a configured weight count 1.
+pub fn weight_of(weights: &HashMap<String, f64>, target: &str) -> f64 {
+    weights.get(target).copied().unwrap_or(1.0)
+}
+
+/// Average of `(availability, weight)` pairs by weight; `None` without any.
+pub fn weighted_availability(values: impl IntoIterator<Item = (f64, f64)>) -> Option<f64> {
+    let (sum, total) = values
+        .into_iter()
+        .fold((0.0, 0.0), |(sum, total), (pct, w)| {
+            (sum + pct * w, total + w)
+        });
+    (total > 0.0).then(|| sum / total)
+}
+
+/// A stretch of consecutive failed probes. `end_ms` is the first successful
+/// probe afterwards, or `None` while the outage is still going on.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Outage {
+    pub target: String,
+    pub start_ms: i64,
+    pub end_ms: Option<i64>,
+}
+
+/// A connectivity-lost stretch; `end_ms` is the first round afterwards that
+/// no longer met the condition.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Window {
+    pub start_ms: i64,
+    pub end_ms: Option<i64>,
+}
+
+/// Outages that overlap, directly or through a chain of overlapping ones,
+/// and so probably share a cause. Open outages overlap everything after
+/// their start.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Incident {
+    pub start_ms: i64,
+    pub end_ms: Option<i64>,
+    /// Affected targets in order of their first outage.
+    pub targets: Vec<String>,
+    pub outages: Vec<Outage>,
+    /// `Incident::impact`, set where weights are known.
+    #[serde(default)]
+    pub impact: f64,
+}
+
+impl Incident {
+    /// Seconds of downtime times the summed weights of the affected
+    /// targets, so an hour of the core router outranks one of a test VM.
+    /// Open incidents count until `now_ms`.
+    pub fn impact(&self, weights: &HashMap<String, f64>, now_ms: i64) -> f64 {
+        let secs = (self.end_ms.unwrap_or(now_ms) - self.start_ms) as f64 / 1000.0;
+        secs * self
+            .targets
+            .iter()
+            .map(|t| weight_of(weights, t))
+            .sum::<f64>()
+    }
+
+    /// Report lines: when and how long, then each outage relative to the
+    /// incident's start. `name` is how the log shows a target.
+    pub fn describe(&self, name: impl Fn(&str) -> String) -> Vec<String> {
+        let start = log_time(self.start_ms);
+        let length = match self.end_ms {
+            Some(end) => format!("{}s", (end - self.start_ms) / 1000),
+            None => "ongoing".to_string(),
+        };
+        let names: Vec<String> = self.targets.iter().map(|t| name(t)).collect();
+        let mut lines = vec![format!("  {start}, {length}: {}", names.join(", "))];
+        let width = names.iter().map(|n| n.chars().count()).max().unwrap_or(0);
+        for outage in &self.outages {
+            let from = (outage.start_ms - self.start_ms) / 1000;
+            let to = match outage.end_ms {
+                Some(end) => format!("to +{}s", (end - self.start_ms) / 1000),
+                None => "ongoing".to_string(),
+            };
+            lines.push(format!(
+                "    {:<width$}  +{from}s {to}",
+                name(&outage.target)
+            ));
+        }
+        lines
+    }
+}
+
+/// Loss and latency of every probe sent in one hour of the day (0-23,
+/// local time), across all days of the run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hour {
+    pub hour: u32,
+    pub probes: u64,
+    pub lost: u64,
+    pub loss_pct: f64,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct HourCounts {
+    probes: u64,
+    lost: u64,
+    sum_ms: f64,
+    samples: u64,
+    max_ms: Option<f64>,
+}
+
+/// Accumulates probes by hour of day, to show daily patterns such as
+/// evening congestion.
+#[derive(Debug, Clone, Default)]
+pub struct ByHour([HourCounts; 24]);
+
+impl ByHour {
+    pub fn record(&mut self, ts_ms: i64, outcome: ProbeOutcome) {
+        if let Some(time) = Local.timestamp_millis_opt(ts_ms).single() {
+            self.record_at(time.hour(), outcome);
+        }
+    }
+
+    fn record_at(&mut self, hour: u32, outcome: ProbeOutcome) {
+        let counts = &mut self.0[hour as usize % 24];
+        counts.probes += 1;
+        counts.lost += u64::from(!outcome.success());
+        if let Some(ms) = outcome.rtt_ms() {
+            counts.sum_ms += ms;
+            counts.samples += 1;
+            counts.max_ms = Some(counts.max_ms.map_or(ms, |m| m.max(ms)));
+        }
+    }
+
+    pub fn hours(&self) -> Vec<Hour> {
+        (0u32..)
+            .zip(self.0)
+            .filter(|(_, c)| c.probes > 0)
+            .map(|(hour, c)| Hour {
+                hour,
+                probes: c.probes,
+                lost: c.lost,
+                loss_pct: c.lost as f64 * 100.0 / c.probes as f64,
+                avg_ms: (c.samples > 0).then(|| c.sum_ms / c.samples as f64),
+                max_ms: c.max_ms,
+            })
+            .collect()
+    }
+}
+
+/// The time-of-day section of the final state; empty unless the run
+/// covered at least two hours.
+pub fn hour_lines(hours: &[Hour]) -> Vec<String> {
+    if hours.len() < 2 {
+        return Vec::new();
+    }
+    let fmt = |v: Option<f64>| v.map_or_else(|| "-".to_string(), |n| format!("{n:.2}"));
+    let mut lines = vec![
+        "By hour of day:".to_string(),
+        format!(
+            "  {:<5} {:>8} {:>9} {:>10} {:>10}",
+            "hour", "probes", "loss", "avg (ms)", "max (ms)"
+        ),
+    ];
+    for h in hours {
+        lines.push(format!(
+            "  {:<5} {:>8} {:>9} {:>10} {:>10}",
+            format!("{:02}h", h.hour),
+            h.probes,
+            format!("{:.1} %", h.loss_pct),
+            fmt(h.avg_ms),
+            fmt(h.max_ms),
+        ));
+    }
+    lines
+}
+
+/// A target's monitored time and the part of it in outages. Only the
+/// spans between probes of consecutive rounds count, so paused, disabled,
+/// warm-up and LOCAL-DOWN stretches are neither up nor down.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Uptime {
+    pub monitored_ms: i64,
+    pub down_ms: i64,
+    last: Option<(u64, i64)>,
+    // Counted since the first failure of a streak outside an outage; it
+    // becomes downtime when the streak turns into one.
+    failing_ms: Option<i64>,
+}
+
+impl Uptime {
+    /// One counted probe. `in_outage`: the target was down before it (an
+    /// `updown::Transition::Down` without its `Up` yet); `went_down`: this
+    /// probe started an outage, which began at the streak's first failure.
+    pub fn record(
+        &mut self,
+        tick: u64,
+        ts_ms: i64,
+        success: bool,
+        in_outage: bool,
+        went_down: bool,
+    ) {
+        let span_ms = match self.last {
+            Some((last_tick, last_ms)) if last_tick + 1 == tick => ts_ms - last_ms,
+            _ => 0,
+        };
+        self.last = Some((tick, ts_ms));
+        self.monitored_ms += span_ms;
+        if in_outage {
+            self.down_ms += span_ms;
+        }
+        self.failing_ms = match self.failing_ms {
+            _ if success || in_outage => None,
+            Some(ms) => Some(ms + span_ms),
+            None => Some(0),
+        };
+        if went_down {
+            self.down_ms += self.failing_ms.unwrap_or(0);
+        }
+    }
+
+    /// `availability_pct` of the monitored and down time; none before two
+    /// probes in a row.
+    pub fn availability_pct(&self) -> Option<f64> {
+        availability_pct(self.monitored_ms, self.down_ms)
+    }
+}
+
+/// `(monitored - down) / monitored` in percent; `None` without monitored
+/// time.
+pub fn availability_pct(monitored_ms: i64, down_ms: i64) -> Option<f64> {
+    let up_ms = monitored_ms - down_ms;
+    (monitored_ms > 0).then(|| up_ms as f64 * 100.0 / monitored_ms as f64)
+}
+
+/// The availability section of the final state: per target the share of
+/// the monitored time it was up, the number of outages and the downtime,
+/// then each outage with start, end and length. `name` is how the log shows
+/// a target.
+pub fn availability_lines<'a>(
+    targets: impl IntoIterator<Item = (&'a str, Uptime)>,
+    outages: &[Outage],
+    now_ms: i64,
+    name: impl Fn(&str) -> String,
+) -> Vec<String> {
+    let mut lines = vec!["Availability:".to_string()];
+    for (target, uptime) in targets {
+        let mut own: Vec<&Outage> = outages.iter().filter(|o| o.target == target).collect();
+        let count = own.len();
+        let pct = uptime
+            .availability_pct()
+            .map_or_else(|| "-".to_string(), |pct| format!("{pct:.3}%"));
+        let plural = if count == 1 { "" } else { "s" };
+        lines.push(format!(
+            "    {}  {pct} available, {count} outage{plural}, down {}",
+            name(target),
+            hms(uptime.down_ms)
+        ));
+        own.sort_by_key(|o| o.start_ms);
+        for o in own {
+            let start = log_time(o.start_ms);
+            lines.push(match o.end_ms {
+                Some(end) => format!(
+                    "        {start} to {}, {}",
+                    log_time(end),
+                    hms(end - o.start_ms)
+                ),
+                None => format!(
+                    "        {start}, still down after {}",
+                    hms(now_ms - o.start_ms)
+                ),
+            });
+        }
+    }
+    lines
+}
+
+/// `1h 02m 03s`; days are counted as hours.
+fn hms(ms: i64) -> String {
+    let secs = ms.max(0) / 1000;
+    format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+fn log_time(ms: i64) -> String {
+    Local
+        .timestamp_millis_opt(ms)
+        .single()
+        .map(schema::log_timestamp)
+        .unwrap_or_default()
+}
+
+/// Clusters `outages` into incidents, ordered by start. A window ends at
+/// the first successful probe, so outages that merely touch stay apart.
+pub fn incidents(outages: &[Outage]) -> Vec<Incident> {
+    let mut sorted: Vec<&Outage> = outages.iter().collect();
+    sorted.sort_by_key(|o| o.start_ms);
+    let mut incidents: Vec<Incident> = Vec::new();
+    for outage in sorted {
+        let overlaps = incidents
+            .last()
+            .is_some_and(|i| i.end_ms.is_none_or(|end| outage.start_ms < end));
+        if !overlaps {
+            incidents.push(Incident {
+                start_ms: outage.start_ms,
+                end_ms: outage.end_ms,
+                targets: Vec::new(),
+                outages: Vec::new(),
+                impact: 0.0,
+            });
+        }
+        let incident = incidents.last_mut().expect("pushed above");
+        incident.end_ms = match (incident.end_ms, outage.end_ms) {
+            (Some(a), Some(b)) => Some(a.max(b)),
+            _ => None,
+        };
+        if !incident.targets.contains(&outage.target) {
+            incident.targets.push(outage.target.clone());
+        }
+        incident.outages.push(outage.clone());
+    }
+    incidents
+}
+
+/// The two sentences printed to stderr at exit, for pasting into a chat:
+/// how long the run went and how many incidents it saw, then the target
+/// with the most loss and the longest outage. Open outages count until
+/// `now_ms`; `name` is how the log shows a target.
+pub fn highlights<'a>(
+    run_ms: i64,
+    targets: impl IntoIterator<Item = (&'a str, Stats)>,
+    outages: &[Outage],
+    now_ms: i64,
+    name: impl Fn(&str) -> String,
+) -> String {
+    let run = hms(run_ms);
+    let all = incidents(outages);
+    let shared = all.iter().filter(|i| i.targets.len() > 1).count();
+    let mut text = match (all.len(), shared) {
+        (0, _) => format!("Ran {run} without incidents."),
+        (1, _) => format!("Ran {run}, 1 incident."),
+        (n, 0) => format!("Ran {run}, {n} incidents."),
+        (n, shared) => format!("Ran {run}, {n} incidents ({shared} affecting several targets)."),
+    };
+    let worst = targets
+        .into_iter()
+        .filter_map(|(target, stats)| Some((target, stats.loss_pct()?)))
+        .filter(|(_, loss)| *loss > 0.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1));
+    match worst {
+        Some((target, loss)) => text.push_str(&format!(
+            " Worst target: {} with {loss:.1}% loss",
+            name(target)
+        )),
+        None => text.push_str(" No probe was lost"),
+    }
+    let longest = outages
+        .iter()
+        .max_by_key(|o| o.end_ms.unwrap_or(now_ms) - o.start_ms);
+    if let Some(outage) = longest {
+        let secs = (outage.end_ms.unwrap_or(now_ms) - outage.start_ms) / 1000;
+        let start = Local
+            .timestamp_millis_opt(outage.start_ms)
+            .single()
+            .map(|t| t.format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let ongoing = if outage.end_ms.is_none() {
+            ", still down"
+        } else {
+            ""
+        };
+        text.push_str(&format!(
+            "; longest outage: {} for {}m {:02}s from {start}{ongoing}",
+            name(&outage.target),
+            secs / 60,
+            secs % 60
+        ));
+    }
+    text.push('.');
+    text
+}
+
+impl Summary {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::Loss;
+
+    fn outage(target: &str, start_ms: i64, end_ms: Option<i64>) -> Outage {
+        Outage {
+            target: target.to_string(),
+            start_ms,
+            end_ms,
+        }
+    }
+
+    fn angled(target: &str) -> String {
+        format!("<{target}>")
+    }
+
+    /// One answered probe, and with `lost` one lost after it.
+    fn stats(lost: bool) -> Stats {
+        let mut stats = Stats::default();
+        stats.record(0, ProbeOutcome::Reply(2.0));
+        if lost {
+            stats.record(1, ProbeOutcome::Lost(Loss::Timeout));
+        }
+        stats
+    }
+
+    #[test]
+    fn highlights_name_the_worst_target_and_longest_outage() {
+        let outages = [
+            outage("a", 10_000, Some(20_000)),
+            outage("b", 15_000, Some(25_000)),
+            outage("b", 100_000, None),
+        ];
+        let text = highlights(
+            3_725_000,
+            [("a", stats(false)), ("b", stats(true))],
+            &outages,
+            230_000,
+            angled,
+        );
+        assert!(
+            text.starts_with(
+                "Ran 1h 02m 05s, 2 incidents (1 affecting several targets). \
+                 Worst target: <b> with 50.0% loss; longest outage: <b> for 2m 10s from "
+            ),
+            "{text}"
+        );
+        assert!(text.ends_with(", still down."), "{text}");
+    }
+
+    #[test]
+    fn highlights_of_a_quiet_run() {
+        assert_eq!(
+            highlights(59_000, [("a", stats(false))], &[], 0, angled),
+            "Ran 0h 00m 59s without incidents. No probe was lost."
+        );
+        assert_eq!(
+            highlights(0, std::iter::empty(), &[], 0, angled),
+            "Ran 0h 00m 00s without incidents. No probe was lost."
+        );
+    }
+
+    #[test]
+    fn highlights_count_incidents() {
+        let one = [outage("a", 10_000, Some(20_000))];
+        let text = highlights(60_000, [("a", stats(true))], &one, 60_000, angled);
+        assert!(
+            text.starts_with(
+                "Ran 0h 01m 00s, 1 incident. Worst target: <a> with 50.0% loss; \
+                 longest outage: <a> for 0m 10s from "
+            ),
+            "{text}"
+        );
+        // Apart from each other, neither affects several targets.
+        let apart = [
+            outage("a", 10_000, Some(20_000)),
+            outage("b", 30_000, Some(40_000)),
+        ];
+        let text = highlights(60_000, std::iter::empty(), &apart, 60_000, angled);
+        assert!(
+            text.starts_with("Ran 0h 01m 00s, 2 incidents. No probe was lost;"),
+            "{text}"
+        );
+    }
+
+    #[test]
+    fn highlights_of_loss_without_outage() {
+        assert_eq!(
+            highlights(
+                60_000,
+                [("a", stats(false)), ("b", stats(true))],
+                &[],
+                0,
+                angled
+            ),
+            "Ran 0h 01m 00s without incidents. Worst target: <b> with 50.0% loss."
+        );
+    }
+
+    #[test]
+    fn a_lone_loss_is_no_downtime() {
+        let mut uptime = Uptime::default();
+        uptime.record(0, 0, true, false, false);
+        uptime.record(1, 10_000, false, false, false);
+        uptime.record(2, 20_000, true, false, false);
+        assert_eq!((uptime.monitored_ms, uptime.down_ms), (20_000, 0));
+        assert_eq!(uptime.availability_pct(), Some(100.0));
+    }
+
+    #[test]
+    fn downtime_starts_at_the_first_failure_of_the_streak() {
+        // Down on the second failure, counted from the first.
+        let mut uptime = Uptime::default();
+        uptime.record(0, 0, true, false, false);
+        uptime.record(1, 10_000, false, false, false);
+        uptime.record(2, 20_000, false, false, true);
+        assert_eq!((uptime.monitored_ms, uptime.down_ms), (20_000, 10_000));
+        uptime.record(3, 30_000, false, true, false);
+        assert_eq!((uptime.monitored_ms, uptime.down_ms), (30_000, 20_000));
+        // The answer that ends the outage closes its last span.
+        uptime.record(4, 40_000, true, true, false);
+        assert_eq!((uptime.monitored_ms, uptime.down_ms), (40_000, 30_000));
+        assert_eq!(uptime.availability_pct(), Some(25.0));
+    }
+
+    #[test]
+    fn a_skipped_round_counts_neither_way() {
+        let mut uptime = Uptime::default();
+        uptime.record(0, 0, false, false, true);
+        uptime.record(1, 10_000, false, true, false);
+        // Paused for round 2.
+        uptime.record(3, 30_000, true, true, false);
+        uptime.record(4, 40_000, true, false, false);
+        assert_eq!((uptime.monitored_ms, uptime.down_ms), (20_000, 10_000));
+    }
+
+    #[test]
+    fn no_availability_before_two_probes_in_a_row() {
+        assert_eq!(Uptime::default().availability_pct(), None);
+        let mut uptime = Uptime::default();
+        uptime.record(0, 0, true, false, false);
+        assert_eq!(uptime.availability_pct(), None);
+        uptime.record(2, 20_000, true, false, false);
+        assert_eq!(uptime.availability_pct(), None);
+    }
+
+    #[test]
+    fn availability_is_the_share_of_monitored_time_up() {
+        assert_eq!(availability_pct(0, 0), None);
+        assert_eq!(availability_pct(-1, 0), None);
+        assert_eq!(availability_pct(10_000, 0), Some(100.0));
+        assert_eq!(availability_pct(10_000, 10_000), Some(0.0));
+        assert_eq!(availability_pct(40_000, 10_000), Some(75.0));
+    }
+
+    #[test]
+    fn weighted_availability_averages_by_weight() {
+        assert_eq!(
+            weighted_availability([(100.0, 5.0), (40.0, 1.0)]),
+            Some(90.0)
+        );
+        assert_eq!(weighted_availability([(40.0, 2.0)]), Some(40.0));
+        assert_eq!(weighted_availability([]), None);
+        assert_eq!(weighted_availability([(40.0, 0.0)]), None);
+        let weights = HashMap::from([("a".to_string(), 5.0)]);
+        assert_eq!(
+            (weight_of(&weights, "a"), weight_of(&weights, "b")),
+            (5.0, 1.0)
+        );
+    }
+
+    #[test]
+    fn lists_downtime_and_outages_per_target() {
+        let mut bad = Uptime::default();
+        bad.record(0, 0, true, false, false);
+        bad.record(1, 10_000, false, false, false);
+        bad.record(2, 20_000, false, false, true);
+        bad.record(3, 30_000, false, true, false);
+        let outages = [
+            outage("b", 100_000, None),
+            outage("b", 15_000, Some(57_000)),
+        ];
+        let lines = availability_lines(
+            [("a", Uptime::default()), ("b", bad)],
+            &outages,
+            130_000,
+            angled,
+        );
+        assert_eq!(
+            lines[..3],
+            [
+                "Availability:",
+                "    <a>  - available, 0 outages, down 0h 00m 00s",
+                "    <b>  33.333% available, 2 outages, down 0h 00m 20s",
+            ]
+        );
+        // Oldest first.
+        assert!(lines[3].ends_with(", 0h 00m 42s"), "{lines:?}");
+        assert!(
+            lines[4].ends_with(", still down after 0h 00m 30s"),
+            "{lines:?}"
+        );
+        assert_eq!(lines.len(), 5);
+    }
+
+    #[test]
+    fn one_outage_is_singular() {
+        let mut uptime = Uptime::default();
+        uptime.record(0, 0, true, false, false);
+        uptime.record(1, 10_000, false, false, true);
+        uptime.record(2, 20_000, true, true, false);
+        let lines = availability_lines(
+            [("a", uptime)],
+            &[outage("a", 10_000, Some(20_000))],
+            20_000,
+            str::to_string,
+        );
+        assert_eq!(
+            lines[1],
+            "    a  50.000% available, 1 outage, down 0h 00m 10s"
+        );
+    }
+
+    #[test]
+    fn availability_without_targets_is_just_the_heading() {
+        let lines = availability_lines(std::iter::empty(), &[], 0, angled);
+        assert_eq!(lines, ["Availability:"]);
+    }
+
+    #[test]
+    fn round_trips_through_json() {
+        let mut stats = Stats::default();
+        stats.record(0, ProbeOutcome::Reply(2.0));
+        stats.record(1, ProbeOutcome::Lost(Loss::Unreachable));
+        let summary = Summary {
+            schema: SCHEMA_VERSION,
+            generated_ms: 1_700_000_000_000,
+            generated: crate::locale::iso_ms(1_700_000_000_000),
+            rounds: 2,
+            targets: vec![TargetSummary::new("1.1.1.1", stats, "info")],
+            outages: vec![outage("1.1.1.1", 1_700_000_002_000, None)],
+            connectivity_lost: vec![Window {
+                start_ms: 1_700_000_002_000,
+                end_ms: Some(1_700_000_006_000),
+            }],
+            local_down: Vec::new(),
+            incidents: Vec::new(),
+            weighted_availability_pct: None,
+            by_hour: Vec::new(),
+        };
+        let json = summary.to_json();
+        assert!(json.contains("\"loss_pct\": 50.0"), "{json}");
+        assert!(
+            json.contains("\"generated\": \"2023-11-14T22:13:20.000Z\""),
+            "{json}"
+        );
+        assert!(json.contains("\"unreachable\": 1"), "{json}");
+        // Internal bookkeeping (the last tick) is not part of the schema.
+        let back: Summary = serde_json::from_str(&json).unwrap();
+        assert_eq!(back.schema, SCHEMA_VERSION);
+        assert_eq!(back.to_json(), json);
+    }
+
+    #[test]
+    fn reads_incidents_written_without_impact() {
+        let json = r#"{"start_ms":0,"end_ms":null,"targets":["a"],"outages":[]}"#;
+        let incident: Incident = serde_json::from_str(json).unwrap();
+        assert_eq!((incident.end_ms, incident.impact), (None, 0.0));
+    }
+
+    #[test]
+    fn groups_overlapping_outages_into_incidents() {
+        let found = incidents(&[
+            outage("b", 4_000, Some(10_000)),
+            outage("a", 2_000, Some(6_000)),
+            // Overlaps only "b", still the same incident.
+            outage("c", 8_000, Some(12_000)),
+            outage("d", 20_000, Some(22_000)),
+        ]);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].targets, ["a", "b", "c"]);
+        assert_eq!((found[0].start_ms, found[0].end_ms), (2_000, Some(12_000)));
+        assert_eq!(found[1].targets, ["d"]);
+        assert!(incidents(&[]).is_empty());
+    }
+
+    #[test]
+    fn outages_that_merely_touch_stay_apart() {
+        let found = incidents(&[outage("a", 0, Some(5_000)), outage("b", 5_000, Some(9_000))]);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].end_ms, Some(5_000));
+    }
+
+    #[test]
+    fn an_open_outage_joins_everything_after_it() {
+        let found = incidents(&[
+            outage("a", 0, None),
+            outage("b", 100_000, Some(110_000)),
+            // The same target twice is listed once.
+            outage("a", 200_000, Some(210_000)),
+        ]);
+        assert_eq!(found.len(), 1);
+        assert_eq!((found[0].end_ms, found[0].targets.len()), (None, 2));
+        assert_eq!(found[0].outages.len(), 3);
+    }
+
+    #[test]
+    fn describes_incidents_relative_to_their_start() {
+        let found = incidents(&[
+            outage("b", 4_000, Some(10_000)),
+            outage("a", 2_000, Some(6_000)),
+            outage("c", 8_000, Some(12_000)),
+            outage("a", 12_000, None),
+        ]);
+        let name = |t: &str| match t {
+            "a" => "Router (a)".to_string(),
+            t => t.to_string(),
+        };
+        let lines = found[0].describe(name);
+        assert!(
+            lines[0].ends_with(", 10s: Router (a), b, c"),
+            "{}",
+            lines[0]
+        );
+        assert_eq!(lines[1], "    Router (a)  +0s to +4s");
+        assert_eq!(lines[3], "    c           +6s to +10s");
+        let open = found[1].describe(name);
+        assert!(open[0].ends_with(", ongoing: Router (a)"), "{}", open[0]);
+        assert_eq!(open[1], "    Router (a)  +0s ongoing");
+    }
+
+    #[test]
+    fn impact_weighs_the_length_by_the_affected_targets() {
+        let found = incidents(&[
+            outage("a", 2_000, Some(6_000)),
+            outage("b", 4_000, Some(12_000)),
+            outage("a", 12_000, None),
+        ]);
+        // a matters five times as much; the open incident runs until 30s.
+        let weights = HashMap::from([("a".to_string(), 5.0)]);
+        assert_eq!(found[0].impact(&weights, 30_000), 10.0 * 6.0);
+        assert_eq!(found[1].impact(&weights, 30_000), 18.0 * 5.0);
+        assert_eq!(found[0].impact(&HashMap::new(), 30_000), 10.0 * 2.0);
+    }
+
+    #[test]
+    fn aggregates_probes_by_hour_of_day() {
+        let mut by_hour = ByHour::default();
+        by_hour.record_at(20, ProbeOutcome::Reply(30.0));
+        by_hour.record_at(20, ProbeOutcome::Reply(90.0));
+        by_hour.record_at(20, ProbeOutcome::Lost(Loss::Timeout));
+        by_hour.record_at(20, ProbeOutcome::Unparsed);
+        by_hour.record_at(8, ProbeOutcome::Reply(10.0));
+        let hours = by_hour.hours();
+        assert_eq!(hours.len(), 2);
+        assert_eq!((hours[0].hour, hours[0].probes, hours[0].lost), (8, 1, 0));
+        assert_eq!((hours[1].probes, hours[1].loss_pct), (4, 25.0));
+        assert_eq!((hours[1].avg_ms, hours[1].max_ms), (Some(60.0), Some(90.0)));
+        let lines = hour_lines(&hours);
+        assert_eq!(lines[3], "  20h          4    25.0 %      60.00      90.00");
+    }
+
+    #[test]
+    fn an_hour_without_answers_has_no_latency() {
+        let mut by_hour = ByHour::default();
+        by_hour.record_at(0, ProbeOutcome::Lost(Loss::Timeout));
+        by_hour.record_at(23, ProbeOutcome::Reply(5.0));
+        let hours = by_hour.hours();
+        assert_eq!(
+            (hours[0].hour, hours[0].avg_ms, hours[0].max_ms),
+            (0, None, None)
+        );
+        assert_eq!(
+            hour_lines(&hours)[2],
+            "  00h          1   100.0 %          -          -"
+        );
+    }
+
+    #[test]
+    fn less_than_two_hours_get_no_section() {
+        assert!(ByHour::default().hours().is_empty());
+        assert!(hour_lines(&[]).is_empty());
+        let mut by_hour = ByHour::default();
+        by_hour.record_at(8, ProbeOutcome::Reply(10.0));
+        by_hour.record_at(8, ProbeOutcome::Reply(20.0));
+        assert!(hour_lines(&by_hour.hours()).is_empty());
+    }
+}
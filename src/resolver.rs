@@ -0,0 +1,195 @@
+use std::{
+    collections::HashSet,
+    net::IpAddr,
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    Target,
+    targets::{self, Family},
+};
+
+/// A name's address after its first lookup, or a different one than at
+/// the last; `None` when it didn't resolve (then it is probed by name, and
+/// `ping` reports the failure).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    pub target: String,
+    pub old: Option<IpAddr>,
+    pub new: Option<IpAddr>,
+}
+
+impl Change {
+    pub fn text(&self) -> String {
+        match (self.old, self.new) {
+            (None, Some(new)) => format!("{} resolves to {new}", self.target),
+            (Some(old), Some(new)) => {
+                format!("{} now resolves to {new} (was {old})", self.target)
+            }
+            (Some(old), None) => format!("{} no longer resolves (was {old})", self.target),
+            (None, None) => format!("{} does not resolve", self.target),
+        }
+    }
+}
+
+impl Change {
+    /// Sets the new address on the target it is for.
+    pub fn apply(&self, targets: &mut [Target]) {
+        if let Some(target) = targets.iter_mut().find(|t| t.name == self.target) {
+            target.address = self.new;
+        }
+    }
+}
+
+/// Looks up the host names among `targets` with `lookup`: a change for each
+/// whose address differs from its `Target::address`, and for each not in
+/// `looked_up` yet, so a name that never resolves is reported once.
+pub fn refresh(
+    targets: &[Target],
+    looked_up: &HashSet<String>,
+    lookup: impl Fn(&str) -> Option<IpAddr>,
+) -> Vec<Change> {
+    targets
+        .iter()
+        .filter(|t| is_host_name(&t.name))
+        .filter_map(|t| {
+            let new = lookup(&t.name);
+            (new != t.address || !looked_up.contains(&t.name)).then(|| Change {
+                target: t.name.clone(),
+                old: t.address,
+                new,
+            })
+        })
+        .collect()
+}
+
+fn is_host_name(target: &str) -> bool {
+    target.parse::<IpAddr>().is_err() && !target.contains('%')
+}
+
+/// First address of a host name from the system resolver, of its pinned
+/// family or else of `prefer` (`-4`/`-6`).
+pub fn lookup(host: &str, prefer: Option<Family>) -> Option<IpAddr> {
+    let family = targets::split_family(host).1.or(prefer);
+    targets::resolve(host)
+        .into_iter()
+        .find(|addr| family.is_none_or(|f| Family::of(*addr) == f))
+}
+
+/// `--resolve-every`: re-resolves the host names in the background, so a
+/// slow DNS server never holds up the frame.
+#[derive(Debug)]
+pub struct Resolver {
+    // Names reported at least once.
+    looked_up: HashSet<String>,
+    every: Duration,
+    family: Option<Family>,
+    next: Instant,
+    running: Option<mpsc::Receiver<Vec<Change>>>,
+}
+
+impl Resolver {
+    /// Resolves `targets` once right away, before the first probe.
+    pub fn start(
+        targets: &[Target],
+        every: Duration,
+        family: Option<Family>,
+    ) -> (Resolver, Vec<Change>) {
+        let changes = refresh(targets, &HashSet::new(), |t| lookup(t, family));
+        let resolver = Resolver {
+            looked_up: changes.iter().map(|c| c.target.clone()).collect(),
+            every,
+            family,
+            next: Instant::now() + every,
+            running: None,
+        };
+        (resolver, changes)
+    }
+
+    /// Makes the next `poll` look up again, e.g. for added targets.
+    pub fn refresh_soon(&mut self) {
+        self.next = Instant::now();
+    }
+
+    /// Starts a lookup of `targets` when due and returns the changes of
+    /// one that finished, relative to the targets it started with.
+    pub fn poll(&mut self, now: Instant, targets: &[Target]) -> Vec<Change> {
+        if let Some(running) = &self.running {
+            return match running.try_recv() {
+                Ok(changes) => {
+                    self.running = None;
+                    self.looked_up
+                        .extend(changes.iter().map(|c| c.target.clone()));
+                    changes
+                }
+                Err(mpsc::TryRecvError::Empty) => Vec::new(),
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    self.running = None;
+                    Vec::new()
+                }
+            };
+        }
+        if now >= self.next {
+            self.next = now + self.every;
+            let (tx, rx) = mpsc::channel();
+            let targets = targets.to_vec();
+            let looked_up = self.looked_up.clone();
+            let family = self.family;
+            thread::spawn(move || {
+                let _ = tx.send(refresh(&targets, &looked_up, |t| lookup(t, family)));
+            });
+            self.running = Some(rx);
+        }
+        Vec::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_first_lookups_and_changed_addresses() {
+        let mut targets: Vec<Target> = ["1.1.1.1", "dns.example", "gone.example"]
+            .map(Target::new)
+            .to_vec();
+        let mut looked_up = HashSet::new();
+        let a: IpAddr = "192.0.2.1".parse().unwrap();
+        let b: IpAddr = "192.0.2.2".parse().unwrap();
+        let mut resolve = |targets: &mut Vec<Target>, addr: Option<IpAddr>| {
+            let changes = refresh(targets, &looked_up, |t| addr.filter(|_| t == "dns.example"));
+            for change in &changes {
+                change.apply(targets);
+                looked_up.insert(change.target.clone());
+            }
+            changes
+        };
+        let changes = resolve(&mut targets, Some(a));
+        let texts: Vec<String> = changes.iter().map(Change::text).collect();
+        assert_eq!(
+            texts,
+            [
+                "dns.example resolves to 192.0.2.1",
+                "gone.example does not resolve"
+            ]
+        );
+        assert_eq!(targets[1].address, Some(a));
+        assert_eq!(targets[0].address, None);
+
+        // Unchanged names, including the one still failing, stay quiet.
+        assert!(resolve(&mut targets, Some(a)).is_empty());
+        let changes = resolve(&mut targets, Some(b));
+        assert_eq!(
+            changes[0].text(),
+            "dns.example now resolves to 192.0.2.2 (was 192.0.2.1)"
+        );
+        let changes = resolve(&mut targets, None);
+        assert_eq!(
+            changes[0].text(),
+            "dns.example no longer resolves (was 192.0.2.2)"
+        );
+        assert_eq!(targets[1].address, None);
+    }
+}
@@ -0,0 +1,154 @@
+use chrono::{DateTime, Datelike, TimeZone, Timelike};
+
+const MONTHS: [&str; 12] = [
+    "JAN", "FEB", "MAR", "APR", "MAY", "JUN", "JUL", "AUG", "SEP", "OCT", "NOV", "DEC",
+];
+const WEEKDAYS: [&str; 7] = ["SUN", "MON", "TUE", "WED", "THU", "FRI", "SAT"];
+
+/// Cron-style schedule (`minute hour day-of-month month day-of-week`). A point
+/// in time is inside the schedule when its minute matches the expression.
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    minutes: u64,
+    hours: u64,
+    days: u64,
+    months: u64,
+    weekdays: u64,
+    // Classic cron rule: if both day fields are restricted, either may match.
+    days_restricted: bool,
+    weekdays_restricted: bool,
+}
+
+impl Schedule {
+    pub fn parse(expr: &str) -> Result<Schedule, String> {
+        let fields: Vec<&str> = expr.split_whitespace().collect();
+        let [minute, hour, day, month, weekday] = fields.as_slice() else {
+            return Err(format!(
+                "schedule needs 5 fields (minute hour day month weekday), got {}",
+                fields.len()
+            ));
+        };
+        let mut weekdays = parse_field(weekday, 0, 7, &WEEKDAYS, 0)?;
+        // Both 0 and 7 mean Sunday.
+        if weekdays & (1 << 7) != 0 {
+            weekdays |= 1;
+        }
+        Ok(Schedule {
+            minutes: parse_field(minute, 0, 59, &[], 0)?,
+            hours: parse_field(hour, 0, 23, &[], 0)?,
+            days: parse_field(day, 1, 31, &[], 0)?,
+            months: parse_field(month, 1, 12, &MONTHS, 1)?,
+            weekdays,
+            days_restricted: *day != "*",
+            weekdays_restricted: *weekday != "*",
+        })
+    }
+
+    pub fn is_active<Tz: TimeZone>(&self, t: &DateTime<Tz>) -> bool {
+        let bit = |set: u64, v: u32| set & (1 << v) != 0;
+        let day_ok = bit(self.days, t.day());
+        let weekday_ok = bit(self.weekdays, t.weekday().num_days_from_sunday());
+        let date_ok = if self.days_restricted && self.weekdays_restricted {
+            day_ok || weekday_ok
+        } else {
+            day_ok && weekday_ok
+        };
+        bit(self.minutes, t.minute())
+            && bit(self.hours, t.hour())
+            && bit(self.months, t.month())
+            && date_ok
+    }
+}
+
+fn parse_field(
+    field: &str,
+    min: u32,
+    max: u32,
+    names: &[&str],
+    name_base: u32,
+) -> Result<u64, String> {
+    let mut set = 0u64;
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => {
+                let step: u32 = step
+                    .parse()
+                    .ok()
+                    .filter(|s| *s > 0)
+                    .ok_or_else(|| format!("invalid step in '{part}'"))?;
+                (range, step)
+            }
+            None => (part, 1),
+        };
+        let (start, end) = if range == "*" {
+            (min, max)
+        } else if let Some((a, b)) = range.split_once('-') {
+            (
+                parse_value(a, names, name_base)?,
+                parse_value(b, names, name_base)?,
+            )
+        } else {
+            let v = parse_value(range, names, name_base)?;
+            // `5/15` means "from 5 to the end in steps of 15".
+            (v, if step > 1 { max } else { v })
+        };
+        if start < min || end > max || start > end {
+            return Err(format!("'{part}' is outside {min}-{max}"));
+        }
+        for v in (start..=end).step_by(step as usize) {
+            set |= 1 << v;
+        }
+    }
+    Ok(set)
+}
+
+fn parse_value(value: &str, names: &[&str], name_base: u32) -> Result<u32, String> {
+    if let Some(idx) = names.iter().position(|n| n.eq_ignore_ascii_case(value)) {
+        return Ok(idx as u32 + name_base);
+    }
+    value
+        .parse()
+        .map_err(|_| format!("invalid schedule value '{value}'"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{NaiveDate, Utc};
+
+    fn at(y: i32, m: u32, d: u32, h: u32, min: u32) -> DateTime<Utc> {
+        NaiveDate::from_ymd_opt(y, m, d)
+            .unwrap()
+            .and_hms_opt(h, min, 0)
+            .unwrap()
+            .and_utc()
+    }
+
+    #[test]
+    fn office_hours_on_weekdays() {
+        let s = Schedule::parse("* 9-16 * * MON-FRI").unwrap();
+        // 2024-06-03 is a Monday.
+        assert!(s.is_active(&at(2024, 6, 3, 9, 0)));
+        assert!(s.is_active(&at(2024, 6, 3, 16, 59)));
+        assert!(!s.is_active(&at(2024, 6, 3, 17, 0)));
+        assert!(!s.is_active(&at(2024, 6, 1, 10, 0)));
+    }
+
+    #[test]
+    fn lists_steps_and_names() {
+        let s = Schedule::parse("*/15 0 1,15 jan-mar 7").unwrap();
+        assert!(s.is_active(&at(2024, 2, 15, 0, 45)));
+        assert!(!s.is_active(&at(2024, 2, 15, 0, 40)));
+        assert!(!s.is_active(&at(2024, 4, 1, 0, 0)));
+        // Day-of-month and weekday both restricted: Sunday 2024-02-04 matches too.
+        assert!(s.is_active(&at(2024, 2, 4, 0, 0)));
+    }
+
+    #[test]
+    fn rejects_bad_expressions() {
+        assert!(Schedule::parse("* * *").is_err());
+        assert!(Schedule::parse("60 * * * *").is_err());
+        assert!(Schedule::parse("* * * * FOO").is_err());
+        assert!(Schedule::parse("*/0 * * * *").is_err());
+    }
+}
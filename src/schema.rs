@@ -0,0 +1,257 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Local, NaiveDateTime, TimeZone};
+
+use crate::{DEFAULT_INTERVAL, connectivity, error::RunError, export, report};
+
+pub const LOG: &str = "log";
+pub const EXPORT: &str = "export";
+
+/// Version 1 logs had no header line and local `[YYYY-MM-DD HH:MM:SS]`
+/// timestamps without offset; version 2 starts with `header(LOG, 2)` and uses
+/// RFC 3339 timestamps with the UTC offset.
+pub const LOG_VERSION: u32 = 2;
+
+const V1_TIMESTAMP: &str = "%Y-%m-%d %H:%M:%S";
+
+/// First line of versioned text files, e.g. `# ping-plotter log v2`.
+pub fn header(kind: &str, version: u32) -> String {
+    format!("# ping-plotter {kind} v{version}")
+}
+
+pub fn parse_header(kind: &str, line: &str) -> Option<u32> {
+    line.trim()
+        .strip_prefix("# ping-plotter ")?
+        .strip_prefix(kind)?
+        .strip_prefix(" v")?
+        .parse()
+        .ok()
+}
+
+/// Timestamp of log lines and the `time` template variable.
+pub fn log_timestamp(time: DateTime<Local>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%S%:z").to_string()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Log,
+    JsonExport,
+    CsvExport,
+    Summary,
+}
+
+impl Kind {
+    pub fn label(self) -> &'static str {
+        match self {
+            Kind::Log => "log",
+            Kind::JsonExport => "JSON export",
+            Kind::CsvExport => "CSV export",
+            Kind::Summary => "summary",
+        }
+    }
+
+    fn current(self) -> u32 {
+        match self {
+            Kind::Log => LOG_VERSION,
+            Kind::JsonExport | Kind::CsvExport => export::SCHEMA_VERSION,
+            Kind::Summary => report::SCHEMA_VERSION,
+        }
+    }
+}
+
+/// Result of `migrate`; `content` equals the input when `from == to`.
+#[derive(Debug)]
+pub struct Migrated {
+    pub kind: Kind,
+    pub from: u32,
+    pub to: u32,
+    pub content: String,
+}
+
+/// Recognizes a file written by ping-plotter and its version.
+pub fn detect(content: &str) -> Result<(Kind, u32), String> {
+    let first = content
+        .lines()
+        .find(|l| !l.trim().is_empty())
+        .unwrap_or_default();
+    if let Some(version) = parse_header(LOG, first) {
+        return Ok((Kind::Log, version));
+    }
+    if let Some(version) = parse_header(EXPORT, first) {
+        return Ok((Kind::CsvExport, version));
+    }
+    if first.starts_with("ts_ms,") {
+        return Ok((Kind::CsvExport, 1));
+    }
+    if let Ok(value) = serde_json::from_str::<serde_json::Value>(content) {
+        let version = |v: &serde_json::Value| v.get("schema").and_then(|s| s.as_u64());
+        return match &value {
+            serde_json::Value::Array(_) => Ok((Kind::JsonExport, 1)),
+            v if v.get("samples").is_some() => {
+                Ok((Kind::JsonExport, version(v).unwrap_or(1) as u32))
+            }
+            v if v.get("targets").is_some() => Ok((Kind::Summary, version(v).unwrap_or(1) as u32)),
+            _ => Err("JSON file is neither an export nor a summary".to_string()),
+        };
+    }
+    if v1_log_time(first).is_some() {
+        return Ok((Kind::Log, 1));
+    }
+    Err("not a ping-plotter log, export or summary".to_string())
+}
+
+/// Upgrades a log, export or summary to the version this build writes.
+pub fn migrate(content: &str) -> Result<Migrated, String> {
+    let (kind, from) = detect(content)?;
+    let to = kind.current();
+    if from > to {
+        return Err(format!(
+            "{} version {from} is newer than this version of ping-plotter supports ({to})",
+            kind.label()
+        ));
+    }
+    let content = match kind {
+        _ if from == to => content.to_string(),
+        Kind::Log => migrate_log(content),
+        Kind::JsonExport | Kind::CsvExport => {
+            let format = if kind == Kind::JsonExport {
+                export::Format::Json
+            } else {
+                export::Format::Csv
+            };
+            let samples = export::read_samples(format, &mut content.as_bytes())?;
+            let mut out = Vec::new();
+            let fraction = connectivity::DEFAULT_FRACTION;
+            // Older exports don't know when the monitor wasn't running.
+            export::write_samples(&samples, &[], format, fraction, DEFAULT_INTERVAL, &mut out)
+                .map_err(|err| err.to_string())?;
+            String::from_utf8(out).map_err(|err| err.to_string())?
+        }
+        // Summaries have only had version 1 so far.
+        Kind::Summary => content.to_string(),
+    };
+    Ok(Migrated {
+        kind,
+        from,
+        to,
+        content,
+    })
+}
+
+/// `ping-plotter migrate`: upgrades `file` in place, keeping the original as
+/// `<file>.bak`, or writes the upgrade to `output`.
+pub fn migrate_file(file: &Path, output: Option<&Path>) -> Result<(), RunError> {
+    let content = fs::read_to_string(file)
+        .map_err(RunError::io(format!("Failed to read {}", file.display())))?;
+    let migrated = migrate(&content).map_err(|message| RunError::Invalid {
+        context: format!("Cannot migrate {}", file.display()),
+        message,
+    })?;
+    let kind = migrated.kind.label();
+    if migrated.from == migrated.to && output.is_none() {
+        eprintln!(
+            "{} is already a current {kind} (v{})",
+            file.display(),
+            migrated.to
+        );
+        return Ok(());
+    }
+    let target = match output {
+        Some(path) => path.to_path_buf(),
+        None => {
+            let mut backup = file.as_os_str().to_owned();
+            backup.push(".bak");
+            let backup = PathBuf::from(backup);
+            fs::copy(file, &backup).map_err(RunError::io(format!(
+                "Failed to back up {} to {}",
+                file.display(),
+                backup.display()
+            )))?;
+            eprintln!("Original kept as {}", backup.display());
+            file.to_path_buf()
+        }
+    };
+    fs::write(&target, &migrated.content).map_err(RunError::io(format!(
+        "Failed to write {}",
+        target.display()
+    )))?;
+    eprintln!(
+        "Upgraded {kind} {} from v{} to v{}",
+        target.display(),
+        migrated.from,
+        migrated.to
+    );
+    Ok(())
+}
+
+fn v1_log_time(line: &str) -> Option<DateTime<Local>> {
+    let rest = line.strip_prefix('[')?;
+    let stamp = rest.get(..19)?;
+    if rest.get(19..20) != Some("]") {
+        return None;
+    }
+    let naive = NaiveDateTime::parse_from_str(stamp, V1_TIMESTAMP).ok()?;
+    // Ambiguous local times (DST fold) take the earlier instant.
+    Local.from_local_datetime(&naive).earliest()
+}
+
+/// Adds the header and rewrites v1 timestamps at the start of lines; lines
+/// already in the new format (appended after an upgrade) are kept.
+fn migrate_log(content: &str) -> String {
+    let mut out = header(LOG, LOG_VERSION);
+    out.push('\n');
+    for line in content.lines() {
+        match v1_log_time(line) {
+            Some(time) => {
+                out.push_str(&format!("[{}{}", log_timestamp(time), &line[20..]));
+            }
+            None => out.push_str(line),
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn upgrades_v1_logs_and_leaves_current_files_alone() {
+        let v1 = "[2024-01-02 03:04:05] unreachable: 8.8.8.8\n\
+                  [2024-01-02 03:04:07] Final state:\n\
+                  IP   Erfolg/Gesamt\n";
+        assert_eq!(detect(v1), Ok((Kind::Log, 1)));
+        let migrated = migrate(v1).unwrap();
+        assert_eq!((migrated.from, migrated.to), (1, LOG_VERSION));
+        let lines: Vec<&str> = migrated.content.lines().collect();
+        assert_eq!(lines[0], "# ping-plotter log v2");
+        assert!(lines[1].starts_with("[2024-01-02T03:04:05"), "{}", lines[1]);
+        assert!(lines[1].ends_with("] unreachable: 8.8.8.8"), "{}", lines[1]);
+        assert_eq!(lines[3], "IP   Erfolg/Gesamt");
+
+        let again = migrate(&migrated.content).unwrap();
+        assert_eq!(again.from, again.to);
+        assert_eq!(again.content, migrated.content);
+
+        let v1_export = r#"[{"ts_ms": 1, "target": "1.1.1.1", "success": false,
+            "latency_ms": null}]"#;
+        let migrated = migrate(v1_export).unwrap();
+        assert_eq!(migrated.kind, Kind::JsonExport);
+        assert!(
+            migrated.content.contains("\"schema\": 4"),
+            "{}",
+            migrated.content
+        );
+        assert!(
+            migrate("# ping-plotter log v9\n")
+                .unwrap_err()
+                .contains("newer")
+        );
+        assert!(migrate("hello\n").is_err());
+    }
+}
@@ -0,0 +1,1261 @@
+//! The round pipeline of a monitoring run. `Session` takes the rounds a
+//! `PingMonitor` completes and keeps what follows from them: stats,
+//! severity, up/down states, outages, connectivity and the display
+//! trackers. It hands back `Event`s instead of writing them; `Outputs`
+//! writes them with the samples, and `run` drives a whole run headless.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use chrono::Local;
+use tracing::{debug, error};
+
+use crate::{
+    Options, PingMonitor, ProbeResult, RoundCollector, Stats, Target, budget, connectivity,
+    debuglog,
+    error::RunError,
+    events::Kind,
+    graph, jitter,
+    log::LogSink,
+    output::CsvWriter,
+    overhead::{self, Phase},
+    parse::{Loss, ProbeOutcome},
+    quality, report, resolver, schema, severity, sinks, store, template, updown, windows, wol,
+};
+
+/// How a `Session` judges the rounds; the binary fills it from its flags.
+#[derive(Debug, Clone)]
+pub struct Settings {
+    /// `--warmup`: rounds that are shown but kept out of the stats,
+    /// severity and outages.
+    pub warmup: u64,
+    pub rules: severity::Rules,
+    pub thresholds: updown::Thresholds,
+    /// `--connectivity-loss`: share of failed targets that counts as lost
+    /// connectivity.
+    pub connectivity_loss: f64,
+    /// `--latency-budget`, `--loss-budget` and `--budget-window`.
+    pub limits: Option<quality::Limits>,
+    /// `--jitter-buffers` was given, so the playouts are fed.
+    pub jitter: bool,
+    /// `--smooth`: EWMA factor of the displayed latency.
+    pub smooth: Option<f64>,
+    pub loss_windows: bool,
+    /// `--window`: span of the rolling loss, avg and max.
+    pub window: Option<Duration>,
+    pub auto_disable_after: Option<Duration>,
+    /// `--wake-after`, for the targets with a MAC address in `macs`.
+    pub wake_after: Option<Duration>,
+    pub macs: HashMap<String, wol::Mac>,
+    pub unreachable_template: Option<template::Template>,
+}
+
+impl Default for Settings {
+    /// The `ping-plotter` defaults.
+    fn default() -> Settings {
+        Settings {
+            warmup: 0,
+            rules: severity::Rules {
+                warning_loss_pct: 50.0,
+                window: Duration::from_secs(60),
+                critical_after: Duration::from_secs(300),
+            },
+            thresholds: updown::Thresholds {
+                down_after: updown::DEFAULT_DOWN_AFTER,
+                up_after: updown::DEFAULT_UP_AFTER,
+            },
+            connectivity_loss: connectivity::DEFAULT_FRACTION,
+            limits: None,
+            jitter: false,
+            smooth: None,
+            loss_windows: false,
+            window: None,
+            auto_disable_after: None,
+            wake_after: None,
+            macs: HashMap::new(),
+            unreachable_template: None,
+        }
+    }
+}
+
+/// Something a round, or a change made through the session, has to say.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A log event (`LogSink::event`), e.g. `DOWN router: 3 failures in a
+    /// row since 12:00:01`.
+    Log(String),
+    /// A preformatted log line (`LogSink::line`): a round's unreachable
+    /// targets.
+    Line(String),
+    /// An entry for the events view (`events::Timeline::push`).
+    Timeline {
+        ts_ms: i64,
+        kind: Kind,
+        target: Option<String>,
+        text: String,
+    },
+}
+
+fn timeline(ts_ms: i64, kind: Kind, target: Option<&str>, text: String) -> Event {
+    Event::Timeline {
+        ts_ms,
+        kind,
+        target: target.map(String::from),
+        text,
+    }
+}
+
+/// A completed round: its events, and the probes it newly recorded in the
+/// stats for the raw outputs.
+#[derive(Default)]
+pub struct Round {
+    pub events: Vec<Event>,
+    pub samples: Vec<ProbeResult>,
+}
+
+/// The state of a monitoring run, fed one round at a time. Everything is
+/// keyed by target name; the fields are for display, changes go through
+/// the methods.
+pub struct Session {
+    /// The monitored targets in list order.
+    pub targets: Vec<Target>,
+    pub settings: Settings,
+    rounds: RoundCollector,
+    disabled: Arc<Mutex<HashSet<String>>>,
+    pub stats: HashMap<String, Stats>,
+    /// Warm-up rounds (ARP, cold caches), shown until the first counted
+    /// round is in.
+    pub warmup_stats: HashMap<String, Stats>,
+    /// Rounds in which at least one target was probed.
+    pub probed_rounds: u64,
+    pub health: HashMap<String, severity::Health>,
+    /// Severity as last reported by `severity_changes`.
+    pub severities: HashMap<String, severity::Severity>,
+    // Targets whose severity changed while connectivity was lost; their
+    // return to normal is covered by the "restored" line as well.
+    quieted: HashSet<String>,
+    pub states: HashMap<String, updown::Tracker>,
+    /// Ended outages, from the `updown::Transition`s.
+    pub outages: Vec<report::Outage>,
+    pub uptimes: HashMap<String, report::Uptime>,
+    pub uplink: connectivity::Tracker,
+    pub connectivity_lost: Vec<report::Window>,
+    /// Stretches in which this host could not send at all (LOCAL-DOWN).
+    pub local: connectivity::Tracker,
+    pub local_windows: Vec<report::Window>,
+    /// Acknowledged targets, silent until they recover.
+    pub acked: HashSet<String>,
+    // Targets woken in their current outage.
+    woken: HashSet<String>,
+    pub budgets: HashMap<String, budget::Budget>,
+    pub qualities: HashMap<String, quality::Quality>,
+    pub playouts: HashMap<String, jitter::Playout>,
+    pub by_hour: report::ByHour,
+    // Display only; stats, log and store keep the raw samples.
+    pub smoothed: HashMap<String, f64>,
+    pub loss_windows: HashMap<String, windows::LossWindows>,
+    pub recent: HashMap<String, windows::Recent>,
+    pub histories: HashMap<String, graph::History>,
+}
+
+impl Session {
+    /// A session for the targets `PingMonitor::start` was given; `disabled`
+    /// is the monitor's set, so `--auto-disable-after` stops the probes.
+    pub fn new(
+        targets: Vec<Target>,
+        settings: Settings,
+        disabled: Arc<Mutex<HashSet<String>>>,
+    ) -> Session {
+        Session {
+            rounds: RoundCollector::new(targets.len()),
+            targets,
+            settings,
+            disabled,
+            stats: HashMap::new(),
+            warmup_stats: HashMap::new(),
+            probed_rounds: 0,
+            health: HashMap::new(),
+            severities: HashMap::new(),
+            quieted: HashSet::new(),
+            states: HashMap::new(),
+            outages: Vec::new(),
+            uptimes: HashMap::new(),
+            uplink: connectivity::Tracker::default(),
+            connectivity_lost: Vec::new(),
+            local: connectivity::Tracker::default(),
+            local_windows: Vec::new(),
+            acked: HashSet::new(),
+            woken: HashSet::new(),
+            budgets: HashMap::new(),
+            qualities: HashMap::new(),
+            playouts: HashMap::new(),
+            by_hour: report::ByHour::default(),
+            smoothed: HashMap::new(),
+            loss_windows: HashMap::new(),
+            recent: HashMap::new(),
+            histories: HashMap::new(),
+        }
+    }
+
+    pub fn completed(&self) -> u64 {
+        self.rounds.completed()
+    }
+
+    /// Until the first counted round is in, the warm-up values are shown.
+    pub fn in_warmup(&self) -> bool {
+        let warmup = self.settings.warmup;
+        warmup > 0 && self.probed_rounds <= warmup
+    }
+
+    /// The stats to display: the warm-up ones during `in_warmup`.
+    pub fn shown(&self) -> &HashMap<String, Stats> {
+        if self.in_warmup() {
+            &self.warmup_stats
+        } else {
+            &self.stats
+        }
+    }
+
+    /// The target called `name` in log lines, by `Target::named`.
+    pub fn named(&self, name: &str) -> String {
+        named(&self.targets, name)
+    }
+
+    /// The rounds `monitor` completed since the last call.
+    pub fn poll(&mut self, monitor: &PingMonitor) -> Vec<Round> {
+        for result in monitor.results() {
+            self.rounds.push(result);
+        }
+        let mut done = Vec::new();
+        while let Some((round, results)) = self.rounds.pop_complete(monitor.current_slot()) {
+            done.push(self.round(round, results));
+        }
+        done
+    }
+
+    /// The last rounds at shutdown. `results` are what `PingMonitor::stop`
+    /// returned; it joins the workers first, so probes still in flight make
+    /// it into them.
+    pub fn finish(&mut self, results: Vec<ProbeResult>) -> Vec<Round> {
+        for result in results {
+            self.rounds.push(result);
+        }
+        let open = self.rounds.drain();
+        open.into_iter()
+            .map(|(round, results)| self.round(round, results))
+            .collect()
+    }
+
+    fn round(&mut self, round: u64, mut results: Vec<ProbeResult>) -> Round {
+        let mut events = Vec::new();
+        let warmup = self.settings.warmup;
+        let local_down = hold_local_down(&mut results, &mut self.stats);
+        let round_ms = results.iter().map(|r| r.ts_ms).min().unwrap_or_default();
+        if let Some(change) = self.local.update(local_down, round_ms) {
+            let text = local_down_text(change, round_ms);
+            events.push(Event::Log(text.clone()));
+            events.push(timeline(round_ms, Kind::Connectivity, None, text));
+            if let connectivity::Change::Restored { since_ms } = change {
+                self.local_windows.push(report::Window {
+                    start_ms: since_ms,
+                    end_ms: Some(round_ms),
+                });
+            }
+        }
+        let probed = results.iter().any(|r| !r.paused);
+        let warming = probed && self.probed_rounds < warmup;
+        if warming {
+            for r in &results {
+                self.stats.entry(r.ip.clone()).or_default().skip(r.tick);
+            }
+        }
+        if probed {
+            self.probed_rounds += 1;
+        }
+        for r in &results {
+            self.budgets.entry(r.ip.clone()).or_default().record(
+                r.tick,
+                r.paused,
+                r.delay_ms,
+                r.duration_ms,
+            );
+            if let Some(alpha) = self.settings.smooth
+                && let Some(ms) = r.outcome.rtt_ms()
+            {
+                let prev = self.smoothed.get(&r.ip).copied();
+                self.smoothed.insert(r.ip.clone(), ewma(prev, ms, alpha));
+            }
+        }
+        let failures = round_failures(&results);
+        let lost = connectivity::is_lost(failures.0, failures.1, self.settings.connectivity_loss);
+        // A LOCAL-DOWN round neither starts nor ends a remote outage.
+        let change = if local_down {
+            None
+        } else {
+            self.uplink.update(lost, failures.2)
+        };
+        if let Some(change) = change {
+            let text = connectivity_text(change, failures);
+            events.push(Event::Log(text.clone()));
+            events.push(timeline(failures.2, Kind::Connectivity, None, text));
+            if let connectivity::Change::Restored { since_ms } = change {
+                self.connectivity_lost.push(report::Window {
+                    start_ms: since_ms,
+                    end_ms: Some(failures.2),
+                });
+            }
+        }
+        // The connectivity line stands in for the per-target ones.
+        let quiet = lost || change.is_some();
+        for r in results.iter().filter(|r| !r.paused) {
+            self.histories
+                .entry(r.ip.clone())
+                .or_default()
+                .record(r.ts_ms, r.outcome);
+            if self.settings.loss_windows {
+                self.loss_windows
+                    .entry(r.ip.clone())
+                    .or_default()
+                    .record(r.ts_ms, r.outcome.success());
+            }
+            if let Some(span) = self.settings.window {
+                self.recent
+                    .entry(r.ip.clone())
+                    .or_insert_with(|| windows::Recent::new(span))
+                    .record(r.ts_ms, r.outcome);
+            }
+            // Warm-up rounds only feed the display, not severity, outages
+            // or availability.
+            if self.probed_rounds <= warmup {
+                continue;
+            }
+            events.extend(self.probed(r, quiet));
+        }
+
+        let target_stats = if warming {
+            &mut self.warmup_stats
+        } else {
+            &mut self.stats
+        };
+        let mut unreachable = Vec::new();
+        let mut samples = Vec::new();
+        for result in results {
+            let entry = target_stats.entry(result.ip.clone()).or_default();
+            if result.paused {
+                entry.skip(result.tick);
+                continue;
+            }
+            if !result.outcome.success() {
+                unreachable.push(result.ip.clone());
+            }
+            if entry.record(result.tick, result.outcome) {
+                samples.push(result);
+            } else {
+                debug!(target_ip = %result.ip, tick = result.tick, "dropping duplicate result");
+            }
+        }
+        // Acknowledged targets stay silent until they recover.
+        unreachable.retain(|ip| !self.acked.contains(ip));
+        if !unreachable.is_empty() && !quiet {
+            unreachable.sort_by_key(|ip| self.targets.iter().position(|t| t.name == *ip));
+            let names: Vec<String> = unreachable.iter().map(|ip| self.named(ip)).collect();
+            let template = self.settings.unreachable_template.as_ref();
+            events.push(Event::Line(unreachable_line(template, round, &names)));
+        }
+        Round { events, samples }
+    }
+
+    /// Health, state, outages and the other per-target consequences of one
+    /// counted probe. `quiet` leaves out what the connectivity line covers.
+    fn probed(&mut self, r: &ProbeResult, quiet: bool) -> Vec<Event> {
+        let mut events = Vec::new();
+        let name = named(&self.targets, &r.ip);
+        let ip = Some(r.ip.as_str());
+        let target_health = self.health.entry(r.ip.clone()).or_default();
+        let was_failing = target_health.failing_since().is_some();
+        let ended = target_health.update(&self.settings.rules, r.ts_ms, r.outcome.success());
+        let down_ms = target_health.failing_for_ms(r.ts_ms).unwrap_or(0);
+        let state = self.states.entry(r.ip.clone()).or_default();
+        let in_outage = state.down_since().is_some();
+        let change = state.update(&self.settings.thresholds, r.ts_ms, r.outcome.success());
+        let went_down = matches!(change, Some(updown::Transition::Down { .. }));
+        self.uptimes.entry(r.ip.clone()).or_default().record(
+            r.tick,
+            r.ts_ms,
+            r.outcome.success(),
+            in_outage,
+            went_down,
+        );
+        if let Some(updown::Transition::Up { since_ms, ts_ms }) = change {
+            self.outages.push(report::Outage {
+                target: r.ip.clone(),
+                start_ms: since_ms,
+                end_ms: Some(ts_ms),
+            });
+        }
+        if let Some(change) = change
+            && !quiet
+            && !self.acked.contains(&r.ip)
+        {
+            events.push(Event::Log(change.text(&name)));
+        }
+        if !was_failing && !r.outcome.success() && !quiet {
+            let reason = match r.outcome {
+                ProbeOutcome::Lost(loss) => loss.label(),
+                _ => "no reply",
+            };
+            events.push(timeline(r.ts_ms, Kind::Down, ip, reason.to_string()));
+        }
+        self.by_hour.record(r.ts_ms, r.outcome);
+        if self.settings.jitter {
+            self.playouts
+                .entry(r.ip.clone())
+                .or_default()
+                .record(r.outcome);
+        }
+        if let Some(limits) = &self.settings.limits
+            && let Some(change) = self
+                .qualities
+                .entry(r.ip.clone())
+                .or_default()
+                .record(limits, r.ts_ms, r.outcome)
+        {
+            let text = change.text(r.ts_ms);
+            events.push(Event::Log(format!("BUDGET {name}: {text}")));
+            events.push(timeline(r.ts_ms, Kind::Budget, ip, text));
+        }
+        if let Some(start_ms) = ended {
+            self.woken.remove(&r.ip);
+            let secs = (r.ts_ms - start_ms) / 1000;
+            if !quiet {
+                let text = format!("back after {secs}s");
+                events.push(timeline(r.ts_ms, Kind::Recovered, ip, text));
+            }
+        }
+        if r.outcome.success() && self.acked.remove(&r.ip) {
+            events.push(Event::Log(format!("ACK cleared {name}: recovered")));
+            let text = "cleared: recovered".to_string();
+            events.push(timeline(r.ts_ms, Kind::Ack, ip, text));
+        }
+        if let Some(limit) = self.settings.auto_disable_after
+            && down_ms >= limit.as_millis() as i64
+            && let Ok(mut d) = self.disabled.lock()
+            && d.insert(r.ip.clone())
+        {
+            self.acked.remove(&r.ip);
+            let text = format!("down for {}s, no longer probed", down_ms / 1000);
+            events.push(Event::Log(format!("DISABLED {name}: {text}")));
+            events.push(timeline(r.ts_ms, Kind::Disabled, ip, text));
+        }
+        if let Some(after) = self.settings.wake_after
+            && down_ms >= after.as_millis() as i64
+            && let Some(mac) = self.settings.macs.get(&r.ip)
+            && self.woken.insert(r.ip.clone())
+        {
+            let secs = down_ms / 1000;
+            let text = match wol::wake(mac) {
+                Ok(()) => format!("down for {secs}s, sent Wake-on-LAN to {mac}"),
+                Err(err) => format!("down for {secs}s, Wake-on-LAN to {mac} failed: {err}"),
+            };
+            events.push(Event::Log(format!("WAKE {name}: {text}")));
+            events.push(timeline(r.ts_ms, Kind::Wake, ip, text));
+        }
+        events
+    }
+
+    /// Re-evaluates every target's severity as of `now_ms` and reports the
+    /// changes; acknowledged and disabled targets change silently.
+    pub fn severity_changes(&mut self, now_ms: i64) -> Vec<Event> {
+        let disabled = self.disabled.lock().map(|d| d.clone()).unwrap_or_default();
+        let mut events = Vec::new();
+        for target in &self.targets {
+            let ip = &target.name;
+            let sev = self
+                .health
+                .get(ip)
+                .map(|h| h.severity(&self.settings.rules, now_ms))
+                .unwrap_or_default();
+            let prev = self.severities.insert(ip.clone(), sev).unwrap_or_default();
+            if sev == prev || self.acked.contains(ip) || disabled.contains(ip) {
+                continue;
+            }
+            let offline = self.uplink.since().is_some();
+            let covered =
+                offline || (sev == severity::Severity::Info && self.quieted.remove(ip.as_str()));
+            if offline {
+                self.quieted.insert(ip.clone());
+            }
+            if covered {
+                continue;
+            }
+            let detail = severity_detail(self.health.get(ip), sev, now_ms);
+            let name = target.named();
+            events.push(Event::Log(format!(
+                "{} {name}: {detail}",
+                sev.label().to_uppercase()
+            )));
+            let kind = match sev {
+                severity::Severity::Info => Kind::Normal,
+                severity::Severity::Warning => Kind::Warning,
+                severity::Severity::Critical => Kind::Critical,
+            };
+            events.push(timeline(now_ms, kind, Some(ip), detail));
+        }
+        events
+    }
+
+    /// Acknowledges `name` while it is failing, which silences it until it
+    /// recovers, or takes an acknowledgement back.
+    pub fn toggle_ack(&mut self, name: &str, now_ms: i64) -> Vec<Event> {
+        let failing = self
+            .health
+            .get(name)
+            .is_some_and(|h| h.failing_for_ms(now_ms).is_some());
+        let (log, text) = if self.acked.remove(name) {
+            (format!("ACK removed {}", self.named(name)), "removed")
+        } else if failing {
+            self.acked.insert(name.to_string());
+            let log = format!("ACK {}: acknowledged while down", self.named(name));
+            (log, "acknowledged while down")
+        } else {
+            return Vec::new();
+        };
+        vec![
+            Event::Log(log),
+            timeline(now_ms, Kind::Ack, Some(name), text.to_string()),
+        ]
+    }
+
+    /// Probes a disabled target again. Its health and state start over, so
+    /// the old outage ends now and does not disable it right away.
+    pub fn enable(&mut self, name: &str, now_ms: i64) -> Vec<Event> {
+        if !self.disabled.lock().is_ok_and(|mut d| d.remove(name)) {
+            return Vec::new();
+        }
+        self.health.remove(name);
+        self.severities.remove(name);
+        if let Some(start_ms) = self.states.remove(name).and_then(|s| s.down_since()) {
+            self.outages.push(report::Outage {
+                target: name.to_string(),
+                start_ms,
+                end_ms: Some(now_ms),
+            });
+        }
+        self.woken.remove(name);
+        let text = "re-enabled manually".to_string();
+        vec![
+            Event::Log(format!("ENABLED {}: {text}", self.named(name))),
+            timeline(now_ms, Kind::Enabled, Some(name), text),
+        ]
+    }
+
+    /// Switches to the list `new` and tells `monitor` which workers to start
+    /// and stop. Targets that stay keep their address until the next lookup.
+    pub fn retarget(&mut self, monitor: &mut PingMonitor, mut new: Vec<Target>) -> Vec<Event> {
+        for target in &mut new {
+            let kept = self.targets.iter().find(|t| t.name == target.name);
+            target.address = kept.and_then(|t| t.address);
+        }
+        let (added, removed) = crate::targets::diff(&self.targets, &new);
+        let start_tick = monitor.update(&added, &removed);
+        debuglog::record(format!(
+            "target list applied: {} added, {} removed, new workers from tick {start_tick}",
+            added.len(),
+            removed.len()
+        ));
+        let now_ms = store::now_ms();
+        let mut events = Vec::new();
+        for ip in added.iter().map(|t| &t.name) {
+            self.stats
+                .entry(ip.clone())
+                .or_default()
+                .skip(start_tick - 1);
+            self.warmup_stats
+                .entry(ip.clone())
+                .or_default()
+                .skip(start_tick - 1);
+            events.push(Event::Log(format!("target added: {ip}")));
+            events.push(timeline(
+                now_ms,
+                Kind::Targets,
+                Some(ip),
+                "added".to_string(),
+            ));
+        }
+        for ip in removed.iter().map(|t| &t.name) {
+            events.push(Event::Log(format!("target removed: {ip}")));
+            events.push(timeline(
+                now_ms,
+                Kind::Targets,
+                Some(ip),
+                "removed".to_string(),
+            ));
+        }
+        self.targets = new;
+        self.rounds.set_targets(self.targets.len());
+        events
+    }
+
+    /// A new address from `resolver::Resolver::poll`, for the target and
+    /// its worker.
+    pub fn resolved(&mut self, monitor: &PingMonitor, change: &resolver::Change) -> Vec<Event> {
+        change.apply(&mut self.targets);
+        monitor.set_address(&change.target, change.new);
+        dns_events(change)
+    }
+
+    /// The outages so far; those still going on have an open end.
+    pub fn all_outages(&self) -> Vec<report::Outage> {
+        let open = self.targets.iter().filter_map(|t| {
+            let start_ms = self.states.get(&t.name)?.down_since()?;
+            Some(report::Outage {
+                target: t.name.clone(),
+                start_ms,
+                end_ms: None,
+            })
+        });
+        self.outages.iter().cloned().chain(open).collect()
+    }
+
+    fn target_uptimes(&self) -> impl Iterator<Item = (&str, report::Uptime)> {
+        let uptime = |t: &Target| self.uptimes.get(&t.name).copied().unwrap_or_default();
+        self.targets
+            .iter()
+            .map(move |t| (t.name.as_str(), uptime(t)))
+    }
+
+    fn target_stats(&self) -> impl Iterator<Item = (&str, Stats)> {
+        let stats = |t: &Target| self.stats.get(&t.name).copied().unwrap_or_default();
+        self.targets
+            .iter()
+            .map(move |t| (t.name.as_str(), stats(t)))
+    }
+
+    /// The end of the final state in the log: failures by kind,
+    /// availability, correlated outages and loss by hour.
+    pub fn report(&self, now_ms: i64) -> Vec<String> {
+        let mut lines = Vec::new();
+        let breakdown: Vec<String> = self
+            .target_stats()
+            .filter_map(|(ip, stat)| {
+                let kinds: Vec<String> = stat
+                    .failures()
+                    .iter()
+                    .filter(|(_, count)| *count > 0)
+                    .map(|(kind, count)| format!("{} {count}", kind.replace('_', " ")))
+                    .collect();
+                let name = self.named(ip);
+                (!kinds.is_empty()).then(|| format!("    {name}  {}", kinds.join(", ")))
+            })
+            .collect();
+        if !breakdown.is_empty() {
+            lines.push("Failures by kind:".to_string());
+            lines.extend(breakdown);
+        }
+        let outages = self.all_outages();
+        lines.extend(report::availability_lines(
+            self.target_uptimes(),
+            &outages,
+            now_ms,
+            |target| self.named(target),
+        ));
+        let incidents = report::incidents(&outages);
+        let correlated: Vec<&report::Incident> =
+            incidents.iter().filter(|i| i.targets.len() > 1).collect();
+        if !correlated.is_empty() {
+            lines.push("Correlated outages:".to_string());
+            lines.extend(
+                correlated
+                    .iter()
+                    .flat_map(|i| i.describe(|t| self.named(t))),
+            );
+        }
+        lines.extend(report::hour_lines(&self.by_hour.hours()));
+        lines
+    }
+
+    /// `report::highlights` of a run that went `run_ms`.
+    pub fn highlights(&self, run_ms: i64, now_ms: i64) -> String {
+        let outages = self.all_outages();
+        report::highlights(run_ms, self.target_stats(), &outages, now_ms, |target| {
+            self.named(target)
+        })
+    }
+
+    /// The `--summary` file; `weights` are the targets' `weight`s from the
+    /// config file.
+    pub fn summary(&self, weights: &HashMap<String, f64>, generated_ms: i64) -> report::Summary {
+        let outages = self.all_outages();
+        let mut incidents = report::incidents(&outages);
+        for incident in &mut incidents {
+            incident.impact = incident.impact(weights, generated_ms);
+        }
+        let open = |since: Option<i64>| {
+            since.map(|start_ms| report::Window {
+                start_ms,
+                end_ms: None,
+            })
+        };
+        let mut connectivity_lost = self.connectivity_lost.clone();
+        connectivity_lost.extend(open(self.uplink.since()));
+        let mut local_down = self.local_windows.clone();
+        local_down.extend(open(self.local.since()));
+        let targets = self
+            .target_stats()
+            .map(|(ip, stat)| {
+                let sev = self.severities.get(ip).copied().unwrap_or_default();
+                let mut target = report::TargetSummary::new(ip, stat, sev.label());
+                target.weight = report::weight_of(weights, ip);
+                let uptime = self.uptimes.get(ip).copied().unwrap_or_default();
+                target.availability_pct = uptime.availability_pct();
+                target.downtime_ms = uptime.down_ms;
+                target.outage_count = outages.iter().filter(|o| o.target == ip).count();
+                if self.settings.limits.is_some() {
+                    let q = self.qualities.get(ip).copied().unwrap_or_default();
+                    target.budget = Some(report::BudgetSummary {
+                        over_budget_pct: q.over_pct(),
+                        bad_seconds_per_hour: q.bad_seconds_per_hour(),
+                    });
+                }
+                target
+            })
+            .collect();
+        let mut summary = report::Summary {
+            schema: report::SCHEMA_VERSION,
+            generated_ms,
+            generated: crate::locale::iso_ms(generated_ms),
+            rounds: self.completed(),
+            targets,
+            outages,
+            connectivity_lost,
+            local_down,
+            incidents,
+            weighted_availability_pct: None,
+            by_hour: self.by_hour.hours(),
+        };
+        summary.weighted_availability_pct = report::weighted_availability(
+            summary
+                .targets
+                .iter()
+                .filter_map(|t| Some((t.availability_pct?, t.weight))),
+        );
+        summary
+    }
+}
+
+/// The target called `name` in log lines, by `Target::named`.
+fn named(targets: &[Target], name: &str) -> String {
+    targets
+        .iter()
+        .find(|t| t.name == name)
+        .map_or_else(|| name.to_string(), Target::named)
+}
+
+/// The log entries of an address lookup.
+pub fn dns_events(change: &resolver::Change) -> Vec<Event> {
+    let target = Some(change.target.as_str());
+    vec![
+        Event::Log(format!("DNS: {}", change.text())),
+        timeline(store::now_ms(), Kind::Targets, target, change.text()),
+    ]
+}
+
+/// The variables of `--unreachable-template`.
+pub const UNREACHABLE_VARS: &[&str] = &["time", "round", "targets", "count"];
+
+/// The `--unreachable-template` line of `round`, or `[time] unreachable: a, b`.
+pub fn unreachable_line(
+    tpl: Option<&template::Template>,
+    round: u64,
+    targets: &[String],
+) -> String {
+    let time = schema::log_timestamp(Local::now());
+    match tpl {
+        Some(t) => t.render(&[
+            ("time", time),
+            ("round", round.to_string()),
+            ("targets", targets.join(", ")),
+            ("count", targets.len().to_string()),
+        ]),
+        None => format!("[{time}] unreachable: {}", targets.join(", ")),
+    }
+}
+
+fn severity_detail(
+    health: Option<&severity::Health>,
+    sev: severity::Severity,
+    now_ms: i64,
+) -> String {
+    let loss = health.and_then(|h| h.loss_pct()).unwrap_or(0.0);
+    match sev {
+        severity::Severity::Critical => {
+            let secs = health.and_then(|h| h.failing_for_ms(now_ms)).unwrap_or(0) / 1000;
+            format!("down for {secs}s")
+        }
+        severity::Severity::Warning => format!("loss {loss:.0}%"),
+        severity::Severity::Info => format!("back to normal (loss {loss:.0}%)"),
+    }
+}
+
+/// Exponentially weighted moving average; the first sample starts the average.
+fn ewma(prev: Option<f64>, sample: f64, alpha: f64) -> f64 {
+    prev.map_or(sample, |p| p + alpha * (sample - p))
+}
+
+/// Failed and probed targets of a round, for `connectivity::is_lost`, and
+/// the time of its first probe.
+fn round_failures(results: &[ProbeResult]) -> (usize, usize, i64) {
+    let probed: Vec<&ProbeResult> = results.iter().filter(|r| !r.paused).collect();
+    let failed = probed.iter().filter(|r| !r.outcome.success()).count();
+    let ts_ms = results.iter().map(|r| r.ts_ms).min().unwrap_or_default();
+    (failed, probed.len(), ts_ms)
+}
+
+/// A round in which every probe failed because this host could not send
+/// at all says nothing about the targets: its results are kept out of the
+/// stats like paused ones and only counted as `local_down`.
+fn hold_local_down(results: &mut [ProbeResult], stats: &mut HashMap<String, Stats>) -> bool {
+    let probed = || results.iter().filter(|r| !r.paused);
+    let local_down = probed().next().is_some()
+        && probed().all(|r| r.outcome == ProbeOutcome::Lost(Loss::LocalDown));
+    if local_down {
+        for r in results.iter_mut().filter(|r| !r.paused) {
+            r.paused = true;
+            stats.entry(r.ip.clone()).or_default().local_down += 1;
+        }
+    }
+    local_down
+}
+
+fn local_down_text(change: connectivity::Change, ts_ms: i64) -> String {
+    match change {
+        connectivity::Change::Lost => {
+            "LOCAL-DOWN: network unreachable from this host, probes not counted".to_string()
+        }
+        connectivity::Change::Restored { since_ms } => {
+            format!("local network back after {}s", (ts_ms - since_ms) / 1000)
+        }
+    }
+}
+
+fn connectivity_text(
+    change: connectivity::Change,
+    (failed, total, ts_ms): (usize, usize, i64),
+) -> String {
+    match change {
+        connectivity::Change::Lost => format!("connectivity lost: {failed}/{total} targets down"),
+        connectivity::Change::Restored { since_ms } => {
+            format!("connectivity restored after {}s", (ts_ms - since_ms) / 1000)
+        }
+    }
+}
+
+/// Where a run's samples and events go: the log, `--csv` and the sample
+/// store. Like the writers, the store is dropped at its first error.
+pub struct Outputs {
+    pub log: LogSink,
+    pub csv: CsvWriter,
+    pub store: Option<store::Store>,
+    pub store_health: sinks::Health,
+    /// False while `disk::Guard` holds the raw samples back; the events are
+    /// logged either way.
+    pub raw: bool,
+}
+
+impl Outputs {
+    /// Outputs that write nothing.
+    pub fn disabled() -> Outputs {
+        Outputs {
+            log: LogSink::disabled(),
+            csv: CsvWriter::disabled(),
+            store: None,
+            store_health: sinks::Health::default(),
+            raw: true,
+        }
+    }
+
+    /// Logs the `Log` and `Line` events; the `Timeline` ones are for the
+    /// caller's events view.
+    pub fn events(&mut self, events: &[Event]) {
+        for event in events {
+            match event {
+                Event::Log(text) => self.log.event(text),
+                Event::Line(line) => self.log.line(line),
+                Event::Timeline { .. } => {}
+            }
+        }
+    }
+
+    /// Writes a round's samples, then its events, and flushes the CSV file
+    /// and the store.
+    pub fn record(&mut self, round: &Round) {
+        if self.raw {
+            for result in &round.samples {
+                self.sample(result);
+            }
+        }
+        self.events(&round.events);
+        self.flush_store();
+        self.csv.flush();
+    }
+
+    fn sample(&mut self, result: &ProbeResult) {
+        self.log.sample(result);
+        self.csv.write(result);
+        if let Some(s) = self.store.as_mut() {
+            let sample = store::Sample {
+                ts_ms: result.ts_ms,
+                target: result.ip.clone(),
+                success: result.outcome.success(),
+                latency_ms: result.outcome.rtt_ms(),
+            };
+            match overhead::measure(Phase::Export, || s.append(&sample)) {
+                Ok(()) => self.store_health.succeeded(store::now_ms()),
+                Err(err) => {
+                    error!("Failed to write to sample store; disabling persistence");
+                    self.store_health.failed(err);
+                    self.store = None;
+                }
+            }
+        }
+    }
+
+    fn flush_store(&mut self) {
+        if let Some(s) = self.store.as_mut()
+            && let Err(err) = overhead::measure(Phase::Export, || s.flush())
+        {
+            error!("Failed to flush sample store; disabling persistence");
+            self.store_health.failed(err);
+            self.store = None;
+        }
+    }
+
+    /// Flushes everything at the end of a run.
+    pub fn flush(&mut self) {
+        self.log.flush();
+        self.csv.flush();
+        if let Some(s) = self.store.as_mut()
+            && let Err(err) = s.flush()
+        {
+            error!("Failed to flush sample store: {err}");
+            self.store_health.failed(err);
+        }
+    }
+}
+
+/// Monitors `targets` without a terminal until `options.run_for` is over or
+/// `stop` is set, writing to `outputs`. The returned session has the
+/// report and the summary.
+pub fn run(
+    targets: Vec<Target>,
+    options: Options,
+    settings: Settings,
+    outputs: &mut Outputs,
+    stop: &AtomicBool,
+) -> Result<Session, RunError> {
+    if targets.is_empty() {
+        return Err(RunError::Targets("no targets to monitor".to_string()));
+    }
+    let (clock, interval) = (Arc::clone(&options.clock), options.interval);
+    let monitor = PingMonitor::start(&targets, options);
+    let mut session = Session::new(targets, settings, Arc::clone(monitor.disabled()));
+    loop {
+        for round in session.poll(&monitor) {
+            outputs.record(&round);
+        }
+        let events = session.severity_changes(store::now_ms());
+        outputs.events(&events);
+        let now = clock.now();
+        let deadline = monitor.deadline();
+        if stop.load(Ordering::SeqCst) || deadline.is_some_and(|end| now >= end) {
+            break;
+        }
+        clock.sleep(deadline.map_or(interval, |end| interval.min(end - now)));
+    }
+    for round in session.finish(monitor.stop()) {
+        outputs.record(&round);
+    }
+    outputs.flush();
+    Ok(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Engine, Prober};
+
+    fn result(ip: &str, tick: u64, outcome: ProbeOutcome) -> ProbeResult {
+        ProbeResult {
+            ip: ip.to_string(),
+            tick,
+            ts_ms: 1_700_000_000_000 + tick as i64 * 1000,
+            paused: false,
+            outcome,
+            delay_ms: 0.0,
+            duration_ms: 0.0,
+        }
+    }
+
+    #[test]
+    fn rounds_without_local_network_are_not_counted() {
+        let down = |ip| result(ip, 0, ProbeOutcome::Lost(Loss::LocalDown));
+        let mut stats = HashMap::new();
+        let mut results = vec![down("a"), down("b")];
+        assert!(hold_local_down(&mut results, &mut stats));
+        assert!(results.iter().all(|r| r.paused));
+        assert_eq!(stats["a"].local_down, 1);
+
+        // One target still answers: the interface is fine, the other just failed.
+        let mut results = vec![down("a"), result("b", 0, ProbeOutcome::Reply(1.0))];
+        assert!(!hold_local_down(&mut results, &mut stats));
+        assert!(!results[0].paused);
+        assert!(!hold_local_down(&mut [], &mut stats));
+    }
+
+    #[test]
+    fn ewma_smooths_towards_new_samples() {
+        let mut avg = ewma(None, 10.0, 0.25);
+        assert_eq!(avg, 10.0);
+        avg = ewma(Some(avg), 30.0, 0.25);
+        assert_eq!(avg, 15.0);
+        assert_eq!(ewma(Some(avg), 30.0, 1.0), 30.0);
+    }
+
+    fn session(targets: &[&str], thresholds: updown::Thresholds) -> Session {
+        let settings = Settings {
+            thresholds,
+            connectivity_loss: 1.0,
+            ..Settings::default()
+        };
+        let targets = targets.iter().map(|t| Target::new(*t)).collect();
+        Session::new(targets, settings, Arc::new(Mutex::new(HashSet::new())))
+    }
+
+    fn thresholds(down_after: u32, up_after: u32) -> updown::Thresholds {
+        updown::Thresholds {
+            down_after,
+            up_after,
+        }
+    }
+
+    /// The log lines and events of one round, without the timeline.
+    fn feed(session: &mut Session, tick: u64, outcomes: &[(&str, ProbeOutcome)]) -> Vec<Event> {
+        let results = outcomes
+            .iter()
+            .map(|&(ip, outcome)| result(ip, tick, outcome))
+            .collect();
+        session
+            .round(tick, results)
+            .events
+            .into_iter()
+            .filter(|e| !matches!(e, Event::Timeline { .. }))
+            .collect()
+    }
+
+    fn logs(events: &[Event], prefix: &str) -> usize {
+        events
+            .iter()
+            .filter(|e| matches!(e, Event::Log(t) if t.starts_with(prefix)))
+            .count()
+    }
+
+    const LOST: ProbeOutcome = ProbeOutcome::Lost(Loss::Timeout);
+    const REPLY: ProbeOutcome = ProbeOutcome::Reply(1.0);
+
+    #[test]
+    fn rounds_turn_into_events_and_samples() {
+        let mut session = session(&["a", "b"], thresholds(2, 1));
+        let mut logged = Vec::new();
+        for (tick, outcome) in [(1, LOST), (2, LOST), (3, REPLY)] {
+            logged.extend(feed(&mut session, tick, &[("a", outcome), ("b", REPLY)]));
+        }
+        assert!(matches!(&logged[0], Event::Line(l) if l.ends_with("unreachable: a")));
+        assert!(matches!(&logged[1], Event::Log(t) if t.starts_with("DOWN a: 2 failures")));
+        assert!(matches!(&logged[3], Event::Log(t) if t.starts_with("UP a: recovered after 2s")));
+        assert_eq!(logged.len(), 4, "{logged:?}");
+        assert_eq!(
+            (session.stats["a"].success, session.stats["a"].total),
+            (1, 3)
+        );
+        let summary = session.summary(&HashMap::new(), 0);
+        assert_eq!(summary.outages.len(), 1);
+        // Down from its first failure until it answered again.
+        let a = &summary.targets[0];
+        assert_eq!((a.availability_pct, a.downtime_ms), (Some(0.0), 2000));
+    }
+
+    #[test]
+    fn every_round_gives_one_sample_per_target() {
+        let mut session = session(&["a", "b"], thresholds(2, 1));
+        let round = session.round(1, vec![result("a", 1, REPLY), result("b", 1, LOST)]);
+        assert_eq!(round.samples.len(), 2);
+        // The same tick again is a duplicate: counted once, written once.
+        let round = session.round(1, vec![result("b", 1, REPLY)]);
+        assert!(round.samples.is_empty());
+        assert_eq!(session.stats["b"].total, 1);
+    }
+
+    #[test]
+    fn an_empty_round_changes_nothing() {
+        let mut session = session(&["a"], thresholds(1, 1));
+        let round = session.round(1, Vec::new());
+        assert!(round.samples.is_empty() && round.events.is_empty());
+        assert_eq!(session.stats.get("a").map_or(0, |s| s.total), 0);
+    }
+
+    #[test]
+    fn down_after_failures_in_a_row_log_down() {
+        let mut session = session(&["a"], thresholds(3, 1));
+        for tick in 1..=2 {
+            let events = feed(&mut session, tick, &[("a", LOST)]);
+            assert_eq!(logs(&events, "DOWN"), 0, "{events:?}");
+        }
+        let events = feed(&mut session, 3, &[("a", LOST)]);
+        assert_eq!(logs(&events, "DOWN a: 3 failures"), 1, "{events:?}");
+        // While down, further failures log nothing new.
+        assert_eq!(logs(&feed(&mut session, 4, &[("a", LOST)]), "DOWN"), 0);
+    }
+
+    #[test]
+    fn up_needs_up_after_answers_in_a_row() {
+        let mut session = session(&["a"], thresholds(1, 2));
+        feed(&mut session, 1, &[("a", LOST)]);
+        assert_eq!(logs(&feed(&mut session, 2, &[("a", REPLY)]), "UP"), 0);
+        // Failing while recovering starts over.
+        assert_eq!(logs(&feed(&mut session, 3, &[("a", LOST)]), "DOWN"), 0);
+        assert_eq!(logs(&feed(&mut session, 4, &[("a", REPLY)]), "UP"), 0);
+        let events = feed(&mut session, 5, &[("a", REPLY)]);
+        assert_eq!(logs(&events, "UP a: recovered after 4s"), 1, "{events:?}");
+        let summary = session.summary(&HashMap::new(), 0);
+        assert_eq!(summary.outages.len(), 1);
+        assert_eq!(summary.targets[0].downtime_ms, 4000);
+    }
+
+    #[test]
+    fn an_outage_still_going_on_is_open_in_the_summary() {
+        let mut session = session(&["a"], thresholds(1, 1));
+        feed(&mut session, 1, &[("a", REPLY)]);
+        feed(&mut session, 2, &[("a", LOST)]);
+        feed(&mut session, 3, &[("a", LOST)]);
+        let summary = session.summary(&HashMap::new(), 0);
+        assert_eq!(summary.outages.len(), 1);
+        assert_eq!(summary.outages[0].end_ms, None);
+        let a = &summary.targets[0];
+        assert_eq!(
+            (a.availability_pct, a.outage_count, a.downtime_ms),
+            (Some(50.0), 1, 1000)
+        );
+    }
+
+    #[test]
+    fn a_session_without_rounds_has_no_availability() {
+        let session = session(&["a"], thresholds(1, 1));
+        let summary = session.summary(&HashMap::new(), 0);
+        assert!(summary.outages.is_empty() && summary.incidents.is_empty());
+        assert!(summary.targets.iter().all(|t| t.availability_pct.is_none()));
+        assert_eq!(summary.weighted_availability_pct, None);
+    }
+
+    #[test]
+    fn a_single_round_has_no_monitored_time() {
+        let mut session = session(&["a"], thresholds(1, 1));
+        feed(&mut session, 1, &[("a", REPLY)]);
+        let summary = session.summary(&HashMap::new(), 0);
+        assert_eq!(summary.targets[0].availability_pct, None);
+        assert_eq!(summary.weighted_availability_pct, None);
+    }
+
+    #[test]
+    fn connectivity_is_lost_once_enough_targets_fail() {
+        let round = [("a", LOST), ("b", REPLY)];
+        // Every target has to fail by default.
+        let mut all = session(&["a", "b"], thresholds(1, 1));
+        let events = feed(&mut all, 1, &round);
+        assert_eq!(logs(&events, "connectivity lost"), 0, "{events:?}");
+
+        let mut half = session(&["a", "b"], thresholds(1, 1));
+        half.settings.connectivity_loss = 0.5;
+        let events = feed(&mut half, 1, &round);
+        assert_eq!(
+            logs(&events, "connectivity lost: 1/2 targets down"),
+            1,
+            "{events:?}"
+        );
+        // The connectivity line stands in for the per-target ones.
+        assert!(
+            !events.iter().any(|e| matches!(e, Event::Line(_))),
+            "{events:?}"
+        );
+        let events = feed(&mut half, 3, &[("a", REPLY), ("b", REPLY)]);
+        assert_eq!(
+            logs(&events, "connectivity restored after 2s"),
+            1,
+            "{events:?}"
+        );
+        let summary = half.summary(&HashMap::new(), 0);
+        assert_eq!(summary.connectivity_lost.len(), 1);
+    }
+
+    #[test]
+    fn one_target_alone_never_loses_connectivity() {
+        let mut session = session(&["a"], thresholds(1, 1));
+        session.settings.connectivity_loss = 0.1;
+        let events = feed(&mut session, 1, &[("a", LOST)]);
+        assert_eq!(logs(&events, "connectivity lost"), 0, "{events:?}");
+    }
+
+    #[test]
+    fn run_drives_a_monitor_without_the_binary() {
+        let prober: Prober = Arc::new(|target: &str| match target {
+            "up" => ProbeOutcome::Reply(1.0),
+            _ => ProbeOutcome::Lost(Loss::Timeout),
+        });
+        let options = |run_for| Options {
+            interval: Duration::from_millis(100),
+            run_for,
+            schedule: None,
+            engine: Engine::Custom(Arc::clone(&prober)),
+            clock: Arc::new(crate::clock::SystemClock),
+        };
+        let stop = AtomicBool::new(false);
+        let mut outputs = Outputs::disabled();
+        let targets = vec![Target::new("up"), Target::new("down")];
+        let run_for = Some(Duration::from_millis(350));
+        let session = run(
+            targets,
+            options(run_for),
+            Settings::default(),
+            &mut outputs,
+            &stop,
+        )
+        .unwrap();
+        assert!(session.completed() >= 3, "{}", session.completed());
+        assert_eq!(session.stats["up"].success, session.stats["up"].total);
+        assert_eq!(session.stats["down"].success, 0);
+    }
+
+    #[test]
+    fn run_needs_targets() {
+        let options = Options {
+            interval: Duration::from_millis(100),
+            run_for: None,
+            schedule: None,
+            engine: Engine::Custom(Arc::new(|_: &str| ProbeOutcome::Reply(1.0))),
+            clock: Arc::new(crate::clock::SystemClock),
+        };
+        let err = run(
+            Vec::new(),
+            options,
+            Settings::default(),
+            &mut Outputs::disabled(),
+            &AtomicBool::new(false),
+        );
+        assert!(matches!(err, Err(RunError::Targets(_))));
+    }
+}
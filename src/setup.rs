@@ -0,0 +1,395 @@
+use std::{
+    env, fs,
+    io::{self, BufRead, Write},
+    net::IpAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::{DEFAULT_INTERVAL, config, error::RunError, targets::Entry};
+
+/// Offered as "the internet" when the user has nothing better.
+pub const INTERNET: &str = "1.1.1.1";
+
+/// A target `ping-plotter init` offers, with the group it goes into.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Suggestion {
+    pub group: &'static str,
+    pub target: String,
+}
+
+/// The answers of `ping-plotter init`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Setup {
+    /// Named groups when the suggestions were taken, one unnamed group
+    /// for a typed list.
+    pub groups: Vec<(Option<String>, Vec<String>)>,
+    pub interval: Duration,
+    pub log: PathBuf,
+    pub service: bool,
+}
+
+/// Gateway, DNS servers and an internet target from the host's
+/// `/proc/net/route` and `/etc/resolv.conf`, where available.
+pub fn suggestions(routes: Option<&str>, resolv_conf: Option<&str>) -> Vec<Suggestion> {
+    let gateway = routes.and_then(default_gateway).map(|target| Suggestion {
+        group: "Gateway",
+        target,
+    });
+    let dns = resolv_conf
+        .map(nameservers)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|target| Suggestion {
+            group: "DNS",
+            target,
+        });
+    let internet = Suggestion {
+        group: "Internet",
+        target: INTERNET.to_string(),
+    };
+    gateway.into_iter().chain(dns).chain([internet]).collect()
+}
+
+/// Gateway of the default route in a Linux `/proc/net/route` table.
+fn default_gateway(table: &str) -> Option<String> {
+    table.lines().skip(1).find_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) != Some(&"00000000") {
+            return None;
+        }
+        // The kernel prints the address as a little-endian hex word.
+        let raw = u32::from_str_radix(fields.get(2)?, 16)
+            .ok()
+            .filter(|g| *g != 0)?;
+        Some(std::net::Ipv4Addr::from(raw.swap_bytes()).to_string())
+    })
+}
+
+/// `nameserver` entries, without local stub resolvers (systemd-resolved's
+/// 127.0.0.53), which answer even when the uplink is down.
+fn nameservers(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| line.trim().strip_prefix("nameserver"))
+        .filter_map(|rest| rest.trim().parse::<IpAddr>().ok())
+        .filter(|addr| !addr.is_loopback())
+        .map(|addr| addr.to_string())
+        .collect()
+}
+
+/// Asks for targets, interval, log file and (with `offer_service`) the
+/// service unit. Invalid answers are asked again; the input ending aborts.
+pub fn ask(
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+    suggested: &[Suggestion],
+    default_log: &Path,
+    parse_interval: &dyn Fn(&str) -> Result<Duration, String>,
+    offer_service: bool,
+) -> io::Result<Setup> {
+    writeln!(out, "Vorgeschlagene Ziele:")?;
+    for s in suggested {
+        writeln!(out, "  {:<10} {}", s.group, s.target)?;
+    }
+    let groups = loop {
+        let answer = prompt(
+            input,
+            out,
+            "Ziele, durch Komma getrennt (Enter übernimmt die Vorschläge)",
+            "",
+        )?;
+        if answer.is_empty() {
+            let mut groups: Vec<(Option<String>, Vec<String>)> = Vec::new();
+            for s in suggested {
+                match groups.last_mut() {
+                    Some((Some(group), targets)) if group == s.group => {
+                        targets.push(s.target.clone())
+                    }
+                    _ => groups.push((Some(s.group.to_string()), vec![s.target.clone()])),
+                }
+            }
+            break groups;
+        }
+        let parsed: Result<Vec<String>, String> = answer
+            .split([',', ' '])
+            .filter(|t| !t.is_empty())
+            .map(|t| {
+                Entry::parse(t)
+                    .map(|t| t.name())
+                    .map_err(|e| format!("{t}: {e}"))
+            })
+            .collect();
+        match parsed {
+            Ok(targets) => break vec![(None, targets)],
+            Err(err) => writeln!(out, "  {err}")?,
+        }
+    };
+    let default_interval = format!("{}s", DEFAULT_INTERVAL.as_secs());
+    let interval = loop {
+        let answer = prompt(input, out, "Intervall", &default_interval)?;
+        match parse_interval(&answer) {
+            Ok(interval) if !interval.is_zero() => break interval,
+            Ok(_) => writeln!(out, "  Das Intervall muss größer als 0 sein.")?,
+            Err(err) => writeln!(out, "  {err}")?,
+        }
+    };
+    let log = PathBuf::from(prompt(
+        input,
+        out,
+        "Log-Datei",
+        &default_log.display().to_string(),
+    )?);
+    let service = offer_service && {
+        let answer = prompt(
+            input,
+            out,
+            "Als systemd-Benutzerdienst einrichten? (j/n)",
+            "n",
+        )?;
+        answer.starts_with(['j', 'J', 'y', 'Y'])
+    };
+    Ok(Setup {
+        groups,
+        interval,
+        log,
+        service,
+    })
+}
+
+/// Asks until a line comes in; an empty one is `default`.
+pub fn prompt(
+    input: &mut dyn BufRead,
+    out: &mut dyn Write,
+    question: &str,
+    default: &str,
+) -> io::Result<String> {
+    if default.is_empty() {
+        write!(out, "{question}: ")?;
+    } else {
+        write!(out, "{question} [{default}]: ")?;
+    }
+    out.flush()?;
+    let mut line = String::new();
+    if input.read_line(&mut line)? == 0 {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "input ended"));
+    }
+    let answer = line.trim();
+    Ok(if answer.is_empty() { default } else { answer }.to_string())
+}
+
+/// The target list file, with a name comment above each group.
+pub fn target_list(setup: &Setup) -> String {
+    let groups: Vec<String> = setup
+        .groups
+        .iter()
+        .map(|(name, targets)| {
+            let name = name
+                .as_ref()
+                .map(|n| format!("# {n}\n"))
+                .unwrap_or_default();
+            name + &targets.join("\n") + "\n"
+        })
+        .collect();
+    groups.join("\n")
+}
+
+/// `[profile.default]` with the answers, read by `config::expand`; a short
+/// interval brings its own `--timeout`.
+pub fn config(setup: &Setup, ips: &Path) -> String {
+    let lines = [
+        "# Written by `ping-plotter init`.".to_string(),
+        "[profile.default]".to_string(),
+        format!("ips = {}", quote(&ips.display().to_string())),
+        format!("interval = \"{}\"", duration(setup.interval)),
+        format!("log = {}", quote(&setup.log.display().to_string())),
+    ];
+    lines.join("\n") + "\n"
+}
+
+/// A systemd user unit running the default profile, with a control socket
+/// in the runtime directory for `ping-plotter attach`. The live view is
+/// discarded; the log file and `tracing` diagnostics are what remains.
+pub fn service_unit(exe: &Path, config: &Path) -> String {
+    format!(
+        "[Unit]\n\
+         Description=ping-plotter\n\
+         After=network-online.target\n\
+         \n\
+         [Service]\n\
+         ExecStart={} --config {} --socket %t/ping-plotter.sock\n\
+         StandardOutput=null\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=default.target\n",
+        quote(&exe.display().to_string()),
+        quote(&config.display().to_string()),
+    )
+}
+
+/// Where systemd looks for user units.
+pub fn unit_path(config_home: &Path) -> PathBuf {
+    config_home.join("systemd/user/ping-plotter.service")
+}
+
+/// A double-quoted string, valid in TOML and in a unit's `ExecStart`.
+fn quote(text: &str) -> String {
+    serde_json::to_string(text).unwrap_or_default()
+}
+
+fn duration(d: Duration) -> String {
+    if d.subsec_millis() == 0 {
+        format!("{}s", d.as_secs())
+    } else {
+        format!("{}ms", d.as_millis())
+    }
+}
+
+/// `ping-plotter init`: the wizard on stdin/stdout. Writes the config file
+/// (default `ping-plotter.toml` next to the binary, where `config::expand`
+/// looks for it) and `ips.txt` beside it, and on Linux, if asked, a systemd
+/// user unit.
+pub fn run(
+    explicit: Option<&Path>,
+    parse_interval: &dyn Fn(&str) -> Result<Duration, String>,
+) -> Result<(), RunError> {
+    let file = explicit.map_or_else(
+        || config::exe_dir().join("ping-plotter.toml"),
+        Path::to_path_buf,
+    );
+    let ips = file.with_file_name("ips.txt");
+    let (stdin, stdout) = (io::stdin(), io::stdout());
+    let (mut input, mut out) = (stdin.lock(), stdout.lock());
+    let existing: Vec<String> = [&file, &ips]
+        .iter()
+        .filter(|p| p.exists())
+        .map(|p| p.display().to_string())
+        .collect();
+    if !existing.is_empty() {
+        let question = format!("{} überschreiben? (j/n)", existing.join(" und "));
+        let answer =
+            prompt(&mut input, &mut out, &question, "n").map_err(RunError::io("Setup aborted"))?;
+        if !answer.starts_with(['j', 'J', 'y', 'Y']) {
+            println!("Nichts geschrieben.");
+            return Ok(());
+        }
+    }
+    let read = |path: &str| fs::read_to_string(path).ok();
+    let suggested = suggestions(
+        read("/proc/net/route").as_deref(),
+        read("/etc/resolv.conf").as_deref(),
+    );
+    let default_log = config::exe_dir().join("result.txt");
+    let offer_service = cfg!(target_os = "linux");
+    let answers = ask(
+        &mut input,
+        &mut out,
+        &suggested,
+        &default_log,
+        parse_interval,
+        offer_service,
+    )
+    .map_err(RunError::io("Setup aborted"))?;
+    let write = |path: &Path, text: String| {
+        let context = format!("Failed to write {}", path.display());
+        fs::write(path, text).map_err(RunError::io(context))?;
+        println!("{} geschrieben.", path.display());
+        Ok::<_, RunError>(())
+    };
+    write(&ips, target_list(&answers))?;
+    write(&file, config(&answers, &ips))?;
+    if answers.service {
+        let config_home = env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+            .ok_or_else(|| RunError::Usage("HOME is not set".to_string()))?;
+        let unit = unit_path(&config_home);
+        if let Some(dir) = unit.parent() {
+            let context = format!("Failed to create {}", dir.display());
+            fs::create_dir_all(dir).map_err(RunError::io(context))?;
+        }
+        let exe = env::current_exe().map_err(RunError::io("Failed to locate the binary"))?;
+        write(&unit, service_unit(&exe, &file))?;
+        println!("Starten mit: systemctl --user enable --now ping-plotter");
+        println!("Live-Ansicht: ping-plotter attach $XDG_RUNTIME_DIR/ping-plotter.sock");
+    } else {
+        let exe = env::current_exe().unwrap_or_default();
+        match explicit {
+            Some(path) => println!("Starten mit: {} --config {}", exe.display(), path.display()),
+            None => println!("Starten mit: {}", exe.display()),
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn asks_again_until_answers_are_valid_and_writes_the_profile() {
+        let routes = "Iface\tDestination\tGateway\tFlags\n\
+                      eth0\t0000A8C0\t00000000\t0001\n\
+                      eth0\t00000000\t0101A8C0\t0003\n";
+        let resolv = "# generated\nnameserver 127.0.0.53\nnameserver 9.9.9.9\n";
+        let suggested = suggestions(Some(routes), Some(resolv));
+        let targets: Vec<&str> = suggested.iter().map(|s| s.target.as_str()).collect();
+        assert_eq!(targets, ["192.168.1.1", "9.9.9.9", "1.1.1.1"]);
+
+        let parse = |s: &str| match s {
+            "500ms" => Ok(Duration::from_millis(500)),
+            "2s" => Ok(Duration::from_secs(2)),
+            _ => Err(format!("invalid duration: {s}")),
+        };
+        let mut out = Vec::new();
+        let setup = ask(
+            &mut "\nsoon\n500ms\n/var/log/pp.txt\nj\n".as_bytes(),
+            &mut out,
+            &suggested,
+            Path::new("result.txt"),
+            &parse,
+            true,
+        )
+        .unwrap();
+        let out = String::from_utf8(out).unwrap();
+        assert!(
+            out.contains("Intervall [2s]:   invalid duration: soon\nIntervall"),
+            "{out}"
+        );
+        assert!(setup.service);
+        assert_eq!(
+            target_list(&setup),
+            "# Gateway\n192.168.1.1\n\n# DNS\n9.9.9.9\n\n# Internet\n1.1.1.1\n"
+        );
+        assert_eq!(
+            config(&setup, Path::new("/etc/pp/ips.txt")),
+            "# Written by `ping-plotter init`.\n[profile.default]\nips = \"/etc/pp/ips.txt\"\n\
+             interval = \"500ms\"\nlog = \"/var/log/pp.txt\"\n"
+        );
+
+        let typed = ask(
+            &mut "10.0.0.1, 10.0.0.256\nROUTER.lan 010.0.0.2\n\n\n".as_bytes(),
+            &mut Vec::new(),
+            &suggested,
+            Path::new("result.txt"),
+            &parse,
+            false,
+        )
+        .unwrap();
+        let names = vec!["router.lan".to_string(), "10.0.0.2".to_string()];
+        assert_eq!(typed.groups, [(None, names)]);
+        assert_eq!((typed.interval, typed.service), (DEFAULT_INTERVAL, false));
+        assert_eq!(typed.log, PathBuf::from("result.txt"));
+        assert!(
+            ask(
+                &mut "".as_bytes(),
+                &mut Vec::new(),
+                &[],
+                Path::new("x"),
+                &parse,
+                false
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use std::{collections::VecDeque, time::Duration};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum Severity {
+    #[default]
+    Info,
+    Warning,
+    Critical,
+}
+
+impl Severity {
+    pub fn label(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Rules {
+    /// Loss percentage within `window` above which a target is a warning.
+    pub warning_loss_pct: f64,
+    pub window: Duration,
+    /// Continuous failure for this long makes a target critical.
+    pub critical_after: Duration,
+}
+
+/// Recent results of one target, enough to evaluate the severity rules.
+#[derive(Debug, Clone, Default)]
+pub struct Health {
+    recent: VecDeque<(i64, bool)>,
+    failing_since: Option<i64>,
+}
+
+impl Health {
+    /// Returns the start of the outage this result ends, if any.
+    pub fn update(&mut self, rules: &Rules, ts_ms: i64, success: bool) -> Option<i64> {
+        self.recent.push_back((ts_ms, success));
+        let cutoff = ts_ms - rules.window.as_millis() as i64;
+        while self.recent.front().is_some_and(|(ts, _)| *ts < cutoff) {
+            self.recent.pop_front();
+        }
+        if success {
+            self.failing_since.take()
+        } else {
+            self.failing_since.get_or_insert(ts_ms);
+            None
+        }
+    }
+
+    pub fn failing_since(&self) -> Option<i64> {
+        self.failing_since
+    }
+
+    pub fn loss_pct(&self) -> Option<f64> {
+        if self.recent.is_empty() {
+            return None;
+        }
+        let lost = self.recent.iter().filter(|(_, ok)| !ok).count();
+        Some(lost as f64 * 100.0 / self.recent.len() as f64)
+    }
+
+    /// Milliseconds the target has been failing continuously as of `now_ms`.
+    pub fn failing_for_ms(&self, now_ms: i64) -> Option<i64> {
+        self.failing_since.map(|since| now_ms - since)
+    }
+
+    pub fn severity(&self, rules: &Rules, now_ms: i64) -> Severity {
+        if self
+            .failing_for_ms(now_ms)
+            .is_some_and(|ms| ms >= rules.critical_after.as_millis() as i64)
+        {
+            Severity::Critical
+        } else if self.loss_pct().is_some_and(|loss| loss > rules.warning_loss_pct) {
+            Severity::Warning
+        } else {
+            Severity::Info
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: Rules = Rules {
+        warning_loss_pct: 50.0,
+        window: Duration::from_secs(60),
+        critical_after: Duration::from_secs(300),
+    };
+
+    #[test]
+    fn escalates_from_warning_to_critical() {
+        let mut h = Health::default();
+        h.update(&RULES, 0, true);
+        h.update(&RULES, 2_000, false);
+        assert_eq!(h.severity(&RULES, 2_000), Severity::Info);
+        h.update(&RULES, 4_000, false);
+        assert_eq!(h.severity(&RULES, 4_000), Severity::Warning);
+
+        let mut ts = 4_000;
+        while ts < 302_000 {
+            ts += 2_000;
+            h.update(&RULES, ts, false);
+        }
+        assert_eq!(h.severity(&RULES, ts), Severity::Critical);
+        assert_eq!(h.loss_pct(), Some(100.0));
+
+        assert_eq!(h.update(&RULES, ts + 2_000, true), Some(2_000));
+        assert_eq!(h.severity(&RULES, ts + 2_000), Severity::Warning);
+    }
+}
@@ -0,0 +1,187 @@
+use std::{collections::HashMap, fs, path::Path, sync::Mutex};
+
+use serde::Deserialize;
+
+use crate::parse::{Loss, ProbeOutcome};
+
+/// Simulation profile (`--simulate profile.toml`): virtual targets whose
+/// replies are drawn from configured distributions.
+///
+/// ```toml
+/// seed = 42
+///
+/// [[target]]
+/// name = "sim-dsl"
+/// latency_ms = 25.0
+/// jitter_ms = 4.0
+/// distribution = "normal"   # normal | uniform | exponential
+/// loss_pct = 2.0
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    #[serde(default)]
+    pub seed: u64,
+    #[serde(rename = "target")]
+    pub targets: Vec<SimTarget>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SimTarget {
+    pub name: String,
+    pub latency_ms: f64,
+    #[serde(default)]
+    pub jitter_ms: f64,
+    #[serde(default)]
+    pub distribution: Distribution,
+    #[serde(default)]
+    pub loss_pct: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Distribution {
+    /// `latency_ms` ± normal noise with standard deviation `jitter_ms`.
+    #[default]
+    Normal,
+    /// Evenly spread over `latency_ms ± jitter_ms`.
+    Uniform,
+    /// `latency_ms` plus an exponential tail with mean `jitter_ms`.
+    Exponential,
+}
+
+impl Profile {
+    pub fn load(path: &Path) -> Result<Profile, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {e}", path.display()))?;
+        Profile::parse(&text).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn parse(text: &str) -> Result<Profile, String> {
+        let profile: Profile = toml::from_str(text).map_err(|e| e.to_string())?;
+        if profile.targets.is_empty() {
+            return Err("profile defines no [[target]]".to_string());
+        }
+        for t in &profile.targets {
+            if t.latency_ms < 0.0 || t.jitter_ms < 0.0 || !(0.0..=100.0).contains(&t.loss_pct) {
+                return Err(format!("target {}: values out of range", t.name));
+            }
+        }
+        Ok(profile)
+    }
+}
+
+/// Answers probes for the virtual targets of a profile. Each target has its
+/// own generator, so its sequence of replies only depends on the seed and
+/// its position in the profile, not on thread timing.
+pub struct Simulator {
+    targets: HashMap<String, Mutex<(SimTarget, Rng)>>,
+}
+
+impl Simulator {
+    pub fn new(profile: &Profile) -> Simulator {
+        let targets = profile
+            .targets
+            .iter()
+            .enumerate()
+            .map(|(idx, t)| {
+                let rng =
+                    Rng::new(profile.seed ^ (idx as u64 + 1).wrapping_mul(0xA076_1D64_78BD_642F));
+                (t.name.clone(), Mutex::new((t.clone(), rng)))
+            })
+            .collect();
+        Simulator { targets }
+    }
+
+    pub fn probe(&self, name: &str) -> ProbeOutcome {
+        let Some(slot) = self.targets.get(name) else {
+            return ProbeOutcome::Lost(Loss::Unreachable);
+        };
+        let Ok(mut slot) = slot.lock() else {
+            return ProbeOutcome::Lost(Loss::Error);
+        };
+        let (target, rng) = &mut *slot;
+        if rng.next_f64() * 100.0 < target.loss_pct {
+            return ProbeOutcome::Lost(Loss::Timeout);
+        }
+        let noise = match target.distribution {
+            Distribution::Normal => rng.next_normal() * target.jitter_ms,
+            Distribution::Uniform => (rng.next_f64() * 2.0 - 1.0) * target.jitter_ms,
+            Distribution::Exponential => -(1.0 - rng.next_f64()).ln() * target.jitter_ms,
+        };
+        ProbeOutcome::Reply((target.latency_ms + noise).max(0.0))
+    }
+}
+
+/// SplitMix64; small, fast and good enough for synthetic latencies.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    // Box-Muller; one of the pair is discarded to keep the state simple.
+    fn next_normal(&mut self) -> f64 {
+        let u1 = 1.0 - self.next_f64();
+        let u2 = self.next_f64();
+        (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILE: &str = r#"
+        seed = 7
+
+        [[target]]
+        name = "a"
+        latency_ms = 20.0
+        jitter_ms = 2.0
+        loss_pct = 10.0
+
+        [[target]]
+        name = "b"
+        latency_ms = 5.0
+        distribution = "uniform"
+    "#;
+
+    #[test]
+    fn same_seed_gives_same_replies() {
+        let profile = Profile::parse(PROFILE).unwrap();
+        let (x, y) = (Simulator::new(&profile), Simulator::new(&profile));
+        let run = |s: &Simulator| (0..50).map(|_| s.probe("a")).collect::<Vec<_>>();
+        let replies = run(&x);
+        assert_eq!(replies, run(&y));
+
+        let lost = replies.iter().filter(|o| !o.success()).count();
+        assert!((1..15).contains(&lost), "lost {lost}");
+        assert_eq!(x.probe("b"), ProbeOutcome::Reply(5.0));
+        assert_eq!(x.probe("unknown"), ProbeOutcome::Lost(Loss::Unreachable));
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        assert!(Profile::parse("seed = 1").is_err());
+        assert!(
+            Profile::parse("[[target]]\nname = \"a\"\nlatency_ms = 1.0\nloss_pct = 120.0").is_err()
+        );
+        assert!(Profile::parse("[[target]]\nname = \"a\"\nlatency_ms = 1.0\nlatency = 2").is_err());
+    }
+}
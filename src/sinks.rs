@@ -0,0 +1,167 @@
+use std::fmt::Display;
+
+use crate::ui;
+
+/// An output the run writes to or serves probes through.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Log,
+    Csv,
+    Store,
+    Metrics,
+    Heartbeat,
+}
+
+impl Kind {
+    /// Name in `status` lines.
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Log => "log",
+            Kind::Csv => "csv",
+            Kind::Store => "store",
+            Kind::Metrics => "metrics",
+            Kind::Heartbeat => "heartbeat",
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Kind::Log => "Log",
+            Kind::Csv => "CSV",
+            Kind::Store => "Speicher",
+            Kind::Metrics => "Metriken",
+            Kind::Heartbeat => "Heartbeat",
+        }
+    }
+}
+
+/// Delivery health of one output: writes (or, for metrics, scrapes) that
+/// went through and that failed, the latest error, and when the last one
+/// succeeded. The writers stop at their first error, so a full disk shows
+/// up here instead of only once in the diagnostics. A later success (a
+/// scrape, a heartbeat) clears the error.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Health {
+    pub ok: u64,
+    pub failed: u64,
+    pub last_ok_ms: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+impl Health {
+    pub fn succeeded(&mut self, now_ms: i64) {
+        self.ok += 1;
+        self.last_ok_ms = Some(now_ms);
+        self.last_error = None;
+    }
+
+    pub fn failed(&mut self, err: impl Display) {
+        self.failed += 1;
+        self.last_error = Some(err.to_string());
+    }
+
+    /// Time since the last success; `None` before the first one.
+    pub fn lag_ms(&self, now_ms: i64) -> Option<i64> {
+        self.last_ok_ms.map(|t| (now_ms - t).max(0))
+    }
+
+    fn state(&self) -> &'static str {
+        match (&self.last_error, self.ok) {
+            (Some(_), _) => "failed",
+            (None, 0) => "idle",
+            (None, _) => "ok",
+        }
+    }
+}
+
+/// One `status` line, e.g. `output=csv state=failed written=120 failed=1
+/// lag_s=64 error=No space left on device (os error 28)`; the error, which
+/// may contain spaces, comes last.
+pub fn status_line(kind: Kind, health: &Health, now_ms: i64) -> String {
+    let lag = health
+        .lag_ms(now_ms)
+        .map_or_else(|| "-".to_string(), |ms| (ms / 1000).to_string());
+    let mut line = format!(
+        "output={} state={} written={} failed={} lag_s={lag}",
+        kind.name(),
+        health.state(),
+        health.ok,
+        health.failed
+    );
+    if let Some(err) = &health.last_error {
+        line.push_str(&format!(" error={err}"));
+    }
+    line
+}
+
+/// The footer under every view: one entry per configured output, critical
+/// while any of them is failing.
+pub fn footer(outputs: &[(Kind, &Health)], now_ms: i64) -> ui::Line {
+    let entries: Vec<String> = outputs
+        .iter()
+        .map(|(kind, health)| {
+            let state = match (&health.last_error, health.lag_ms(now_ms)) {
+                (Some(err), _) => format!("FEHLER: {err}"),
+                (None, Some(ms)) => format!("ok, vor {}s", ms / 1000),
+                (None, None) => "bereit".to_string(),
+            };
+            format!("{} {state}", kind.label())
+        })
+        .collect();
+    let failing = outputs.iter().any(|(_, h)| h.last_error.is_some());
+    ui::Line {
+        text: format!("Ausgaben: {}", entries.join(" · ")),
+        style: if failing {
+            ui::Style::Critical
+        } else {
+            ui::Style::Plain
+        },
+        selected: false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_lag_and_the_latest_error() {
+        let mut log = Health::default();
+        log.succeeded(1_000);
+        log.succeeded(2_000);
+        let mut csv = Health::default();
+        csv.succeeded(1_000);
+        csv.failed("No space left on device");
+        let metrics = Health::default();
+        assert_eq!(log.lag_ms(7_500), Some(5_500));
+
+        assert_eq!(
+            status_line(Kind::Log, &log, 7_500),
+            "output=log state=ok written=2 failed=0 lag_s=5"
+        );
+        assert_eq!(
+            status_line(Kind::Csv, &csv, 7_500),
+            "output=csv state=failed written=1 failed=1 lag_s=6 error=No space left on device"
+        );
+        assert_eq!(
+            status_line(Kind::Metrics, &metrics, 7_500),
+            "output=metrics state=idle written=0 failed=0 lag_s=-"
+        );
+
+        let line = footer(&[(Kind::Log, &log), (Kind::Metrics, &metrics)], 7_500);
+        assert_eq!(line.text, "Ausgaben: Log ok, vor 5s · Metriken bereit");
+        assert_eq!(line.style, ui::Style::Plain);
+        let line = footer(&[(Kind::Log, &log), (Kind::Csv, &csv)], 7_500);
+        assert_eq!(
+            line.text,
+            "Ausgaben: Log ok, vor 5s · CSV FEHLER: No space left on device"
+        );
+        assert_eq!(line.style, ui::Style::Critical);
+
+        csv.succeeded(8_000);
+        assert_eq!(
+            status_line(Kind::Csv, &csv, 8_000),
+            "output=csv state=ok written=2 failed=1 lag_s=0"
+        );
+    }
+}
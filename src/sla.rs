@@ -0,0 +1,814 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    path::Path,
+};
+
+use chrono::{Local, NaiveDate, TimeZone};
+
+use crate::{
+    error::RunError,
+    report::{self, Incident, Outage, Uptime},
+    schema,
+    store::{self, Gap, Sample},
+    targets::{self, Group, TargetSource},
+    updown::{self, Thresholds, Transition},
+};
+
+/// Outages listed per target and incidents per group.
+const WORST: usize = 3;
+
+/// `report --month`: a calendar month in local time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Month {
+    pub year: i32,
+    pub month: u32,
+}
+
+impl Month {
+    /// `YYYY-MM`, e.g. `2024-06`.
+    pub fn parse(input: &str) -> Result<Month, String> {
+        let invalid = || format!("invalid month '{input}' (expected YYYY-MM, e.g. 2024-06)");
+        let (year, month) = input.trim().split_once('-').ok_or_else(invalid)?;
+        let month = Month {
+            year: year.parse().map_err(|_| invalid())?,
+            month: month.parse().map_err(|_| invalid())?,
+        };
+        month.first_day().ok_or_else(invalid)?;
+        Ok(month)
+    }
+
+    fn first_day(self) -> Option<NaiveDate> {
+        NaiveDate::from_ymd_opt(self.year, self.month, 1)
+    }
+
+    fn next(self) -> Month {
+        match self.month {
+            12 => Month {
+                year: self.year + 1,
+                month: 1,
+            },
+            m => Month {
+                year: self.year,
+                month: m + 1,
+            },
+        }
+    }
+
+    /// Local midnight of the first day, in ms.
+    fn start_ms(self) -> i64 {
+        self.first_day()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .and_then(|t| Local.from_local_datetime(&t).earliest())
+            .map_or(0, |t| t.timestamp_millis())
+    }
+
+    /// Start and end (exclusive) in ms.
+    pub fn range_ms(self) -> (i64, i64) {
+        (self.start_ms(), self.next().start_ms())
+    }
+}
+
+impl fmt::Display for Month {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+/// Probes and downtime of one target in the month.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TargetSla {
+    pub target: String,
+    pub probes: u64,
+    pub failed: u64,
+    /// Time between probes of the same run, as `report::Uptime` counts it.
+    pub monitored_ms: i64,
+    /// The part of `monitored_ms` in outages.
+    pub downtime_ms: i64,
+    /// Longest outages first, at most `WORST`.
+    pub worst: Vec<Outage>,
+    /// `report::weight_of` the target.
+    pub weight: f64,
+}
+
+/// A named group of the target list: its targets' probes, and as downtime
+/// the time in which at least one of them was down.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GroupSla {
+    pub name: String,
+    pub targets: Vec<String>,
+    pub probes: u64,
+    pub failed: u64,
+    /// The longest `monitored_ms` of its targets, which are probed in the
+    /// same rounds.
+    pub monitored_ms: i64,
+    pub downtime_ms: i64,
+    /// Longest incidents first, at most `WORST`.
+    pub worst: Vec<Incident>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlaReport {
+    pub month: Month,
+    pub targets: Vec<TargetSla>,
+    pub groups: Vec<GroupSla>,
+    /// The targets' availability averaged by weight.
+    pub weighted_availability_pct: Option<f64>,
+    /// Incidents across all targets with the highest `Incident::impact`
+    /// first, at most `WORST`.
+    pub by_impact: Vec<Incident>,
+    /// Times in the month the monitor wasn't running; the availability
+    /// only covers the rest.
+    pub gaps: Vec<Gap>,
+}
+
+fn length_ms(start_ms: i64, end_ms: Option<i64>) -> i64 {
+    end_ms.map_or(0, |end| end - start_ms)
+}
+
+/// Outages and uptime of `samples` (one target, sorted by time), with
+/// `--down-after`/`--up-after` like the live run. An outage lasts from the
+/// streak's first failure to the `updown::Transition::Up`; one going on
+/// when the monitor stopped ends at the start of the gap, one still going
+/// on when the samples end at the last probe.
+fn track(
+    target: &str,
+    samples: &[&Sample],
+    gaps: &[Gap],
+    thresholds: &Thresholds,
+) -> (Vec<Outage>, Uptime) {
+    let outage = |start_ms, end_ms| Outage {
+        target: target.to_string(),
+        start_ms,
+        end_ms: Some(end_ms),
+    };
+    let mut outages = Vec::new();
+    let mut state = updown::Tracker::default();
+    let mut uptime = Uptime::default();
+    // Probes of one run are consecutive rounds; a gap breaks the count.
+    let mut tick = 0;
+    let mut last: Option<i64> = None;
+    for s in samples {
+        tick += 1;
+        if let Some(last) = last
+            && let Some(gap) = gaps
+                .iter()
+                .find(|g| g.start_ms >= last && g.start_ms < s.ts_ms)
+        {
+            if let Some(since_ms) = state.down_since() {
+                uptime.record(tick, gap.start_ms, false, true, false);
+                outages.push(outage(since_ms, gap.start_ms));
+            }
+            // The next run starts from scratch.
+            state = updown::Tracker::default();
+            tick += 2;
+        }
+        last = Some(s.ts_ms);
+        let in_outage = state.down_since().is_some();
+        let change = state.update(thresholds, s.ts_ms, s.success);
+        let went_down = matches!(change, Some(Transition::Down { .. }));
+        uptime.record(tick, s.ts_ms, s.success, in_outage, went_down);
+        if let Some(Transition::Up { since_ms, ts_ms }) = change {
+            outages.push(outage(since_ms, ts_ms));
+        }
+    }
+    if let (Some(since_ms), Some(last)) = (state.down_since(), last) {
+        outages.push(outage(since_ms, last));
+    }
+    (outages, uptime)
+}
+
+/// The report for `month` from the store's samples. Groups come from the
+/// target list, unnamed ones left out; `weights` from the config's targets,
+/// `gaps` from `store::read_gaps` and `thresholds` from `--down-after` and
+/// `--up-after`.
+pub fn build(
+    samples: &[Sample],
+    gaps: &[Gap],
+    month: Month,
+    groups: &HashMap<String, Group>,
+    weights: &HashMap<String, f64>,
+    thresholds: &Thresholds,
+) -> SlaReport {
+    let (start, end) = month.range_ms();
+    let mut by_target: BTreeMap<&str, Vec<&Sample>> = BTreeMap::new();
+    for s in samples.iter().filter(|s| (start..end).contains(&s.ts_ms)) {
+        by_target.entry(&s.target).or_default().push(s);
+    }
+
+    let mut all_outages: HashMap<&str, Vec<Outage>> = HashMap::new();
+    let targets: Vec<TargetSla> = by_target
+        .into_iter()
+        .map(|(target, mut list)| {
+            list.sort_by_key(|s| s.ts_ms);
+            let (found, uptime) = track(target, &list, gaps, thresholds);
+            let mut worst = found.clone();
+            worst.sort_by_key(|o| std::cmp::Reverse(length_ms(o.start_ms, o.end_ms)));
+            worst.truncate(WORST);
+            let sla = TargetSla {
+                target: target.to_string(),
+                probes: list.len() as u64,
+                failed: list.iter().filter(|s| !s.success).count() as u64,
+                monitored_ms: uptime.monitored_ms,
+                downtime_ms: uptime.down_ms,
+                worst,
+                weight: report::weight_of(weights, target),
+            };
+            all_outages.insert(target, found);
+            sla
+        })
+        .collect();
+
+    // In the order of the list.
+    let mut named: BTreeMap<usize, (&Group, Vec<String>)> = BTreeMap::new();
+    for (target, group) in groups.iter().filter(|(_, g)| g.name.is_some()) {
+        let (_, members) = named.entry(group.index).or_insert((group, Vec::new()));
+        members.push(target.clone());
+    }
+    let groups = named
+        .into_values()
+        .map(|(group, mut members)| {
+            members.sort();
+            let member_slas: Vec<&TargetSla> = targets
+                .iter()
+                .filter(|t| members.contains(&t.target))
+                .collect();
+            let member_outages: Vec<Outage> = members
+                .iter()
+                .flat_map(|m| all_outages.get(m.as_str()).cloned().unwrap_or_default())
+                .collect();
+            let mut incidents = report::incidents(&member_outages);
+            let downtime_ms = incidents
+                .iter()
+                .map(|i| length_ms(i.start_ms, i.end_ms))
+                .sum();
+            incidents.sort_by_key(|i| std::cmp::Reverse(length_ms(i.start_ms, i.end_ms)));
+            incidents.truncate(WORST);
+            GroupSla {
+                name: group.name.clone().unwrap_or_default(),
+                targets: members,
+                probes: member_slas.iter().map(|t| t.probes).sum(),
+                failed: member_slas.iter().map(|t| t.failed).sum(),
+                monitored_ms: member_slas
+                    .iter()
+                    .map(|t| t.monitored_ms)
+                    .max()
+                    .unwrap_or(0),
+                downtime_ms,
+                worst: incidents,
+            }
+        })
+        .collect();
+
+    let all: Vec<Outage> = all_outages.into_values().flatten().collect();
+    let mut by_impact = report::incidents(&all);
+    for incident in &mut by_impact {
+        incident.impact = incident.impact(weights, end);
+    }
+    by_impact.sort_by(|a, b| b.impact.total_cmp(&a.impact));
+    by_impact.truncate(WORST);
+    let weighted_availability_pct = report::weighted_availability(
+        targets
+            .iter()
+            .filter_map(|t| Some((t.availability_pct()?, t.weight))),
+    );
+    SlaReport {
+        month,
+        targets,
+        groups,
+        weighted_availability_pct,
+        by_impact,
+        gaps: gaps
+            .iter()
+            .filter(|g| g.end_ms > start && g.start_ms < end)
+            .copied()
+            .collect(),
+    }
+}
+
+/// `1h 02m 03s`; days are counted as hours.
+fn hms(ms: i64) -> String {
+    let secs = ms / 1000;
+    format!("{}h {:02}m {:02}s", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+impl TargetSla {
+    /// `report::availability_pct` of the monitored time.
+    pub fn availability_pct(&self) -> Option<f64> {
+        report::availability_pct(self.monitored_ms, self.downtime_ms)
+    }
+}
+
+fn local_time(ms: i64) -> String {
+    Local
+        .timestamp_millis_opt(ms)
+        .single()
+        .map(schema::log_timestamp)
+        .unwrap_or_default()
+}
+
+impl SlaReport {
+    /// The printed report: a table of targets, then groups, each followed
+    /// by its worst outages.
+    pub fn lines(&self) -> Vec<String> {
+        let mut lines = vec![format!("Verfügbarkeitsbericht {}", self.month)];
+        if self.targets.is_empty() {
+            lines.push("Keine Messwerte in diesem Monat.".to_string());
+            return lines;
+        }
+        let pct = |monitored_ms, downtime_ms| {
+            report::availability_pct(monitored_ms, downtime_ms)
+                .map_or_else(|| "-".to_string(), |p| format!("{p:.3} %"))
+        };
+        let row = |name: &str, probes: u64, failed: u64, monitored_ms: i64, downtime_ms: i64| {
+            format!(
+                "{name:<24} {:>14} {:>10} {:>10} {:>14}",
+                pct(monitored_ms, downtime_ms),
+                probes,
+                failed,
+                hms(downtime_ms)
+            )
+        };
+        let header = |first: &str| {
+            format!(
+                "{first:<24} {:>14} {:>10} {:>10} {:>14}",
+                "Verfügbarkeit", "Messungen", "Verloren", "Ausfallzeit"
+            )
+        };
+        lines.push(String::new());
+        lines.push(header("Ziel"));
+        for t in &self.targets {
+            lines.push(row(
+                &t.target,
+                t.probes,
+                t.failed,
+                t.monitored_ms,
+                t.downtime_ms,
+            ));
+        }
+        // Without weights the impact order is just the length order.
+        let weighted = self.targets.iter().any(|t| t.weight != 1.0);
+        if weighted && let Some(p) = self.weighted_availability_pct {
+            lines.push(format!("Gewichtete Verfügbarkeit: {p:.3} %"));
+        }
+        if !self.gaps.is_empty() {
+            let total = self.gaps.iter().map(|g| g.end_ms - g.start_ms).sum();
+            lines.push(String::new());
+            lines.push(format!(
+                "Nicht überwacht: {} in {} Lücke(n), die Verfügbarkeit gilt nur \
+                 für die übrige Zeit:",
+                hms(total),
+                self.gaps.len()
+            ));
+            let mut longest = self.gaps.clone();
+            longest.sort_by_key(|g| std::cmp::Reverse(g.end_ms - g.start_ms));
+            for g in longest.iter().take(WORST) {
+                lines.push(format!(
+                    "  {}  {}",
+                    local_time(g.start_ms),
+                    hms(g.end_ms - g.start_ms)
+                ));
+            }
+        }
+        if !self.groups.is_empty() {
+            lines.push(String::new());
+            lines.push(header("Gruppe"));
+            for g in &self.groups {
+                lines.push(row(
+                    &g.name,
+                    g.probes,
+                    g.failed,
+                    g.monitored_ms,
+                    g.downtime_ms,
+                ));
+            }
+        }
+        let worst_targets = self.targets.iter().filter(|t| !t.worst.is_empty());
+        for t in worst_targets {
+            lines.push(String::new());
+            lines.push(format!("Längste Ausfälle {}:", t.target));
+            for o in &t.worst {
+                let length = hms(length_ms(o.start_ms, o.end_ms));
+                lines.push(format!("  {}  {length}", local_time(o.start_ms)));
+            }
+        }
+        for g in self.groups.iter().filter(|g| !g.worst.is_empty()) {
+            lines.push(String::new());
+            lines.push(format!("Längste Störungen {}:", g.name));
+            for incident in &g.worst {
+                lines.extend(incident.describe(str::to_string));
+            }
+        }
+        if weighted && !self.by_impact.is_empty() {
+            lines.push(String::new());
+            lines.push("Störungen nach Auswirkung (Dauer × Gewicht der Ziele):".to_string());
+            for incident in &self.by_impact {
+                lines.push(format!("  Auswirkung {:.0}", incident.impact));
+                lines.extend(incident.describe(str::to_string));
+            }
+        }
+        lines
+    }
+}
+
+/// `ping-plotter report`: prints the month's report from the store, grouped
+/// like the list of `source` when one is given.
+pub fn run(
+    store_dir: &Path,
+    month: Month,
+    source: Option<&mut dyn TargetSource>,
+    weights: &HashMap<String, f64>,
+    thresholds: &Thresholds,
+) -> Result<(), RunError> {
+    let (start, end) = month.range_ms();
+    let context = format!("Failed to read sample store {}", store_dir.display());
+    let mut samples =
+        store::read_samples(store_dir, Some(start)).map_err(RunError::io(context.clone()))?;
+    samples.retain(|s| s.ts_ms < end);
+    let gaps = store::read_gaps(store_dir, Some(start), end.min(store::now_ms()))
+        .map_err(RunError::io(context))?;
+    let groups = match source {
+        Some(source) => {
+            targets::load(source)?;
+            source.groups()
+        }
+        None => HashMap::new(),
+    };
+    let report = build(&samples, &gaps, month, &groups, weights, thresholds);
+    for line in report.lines() {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn june() -> Month {
+        Month::parse("2024-06").unwrap()
+    }
+
+    /// Probes of `target` at the given seconds into June 2024.
+    fn samples(target: &str, probes: &[(i64, bool)]) -> Vec<Sample> {
+        let (start, _) = june().range_ms();
+        probes
+            .iter()
+            .map(|&(sec, success)| Sample {
+                ts_ms: start + sec * 1000,
+                target: target.to_string(),
+                success,
+                latency_ms: success.then_some(10.0),
+            })
+            .collect()
+    }
+
+    fn thresholds(down_after: u32, up_after: u32) -> Thresholds {
+        Thresholds {
+            down_after,
+            up_after,
+        }
+    }
+
+    fn build_plain(samples: &[Sample], gaps: &[Gap], thresholds: &Thresholds) -> SlaReport {
+        build(
+            samples,
+            gaps,
+            june(),
+            &HashMap::new(),
+            &HashMap::new(),
+            thresholds,
+        )
+    }
+
+    /// Start and end of each of a target's worst outages, in seconds into
+    /// June 2024.
+    fn spans(sla: &TargetSla) -> Vec<(i64, Option<i64>)> {
+        let (start, _) = june().range_ms();
+        sla.worst
+            .iter()
+            .map(|o| {
+                (
+                    (o.start_ms - start) / 1000,
+                    o.end_ms.map(|e| (e - start) / 1000),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn parses_months() {
+        assert_eq!(
+            Month::parse("2024-06"),
+            Ok(Month {
+                year: 2024,
+                month: 6
+            })
+        );
+        assert_eq!(Month::parse(" 2024-12 "), Month::parse("2024-12"));
+    }
+
+    #[test]
+    fn rejects_invalid_months() {
+        for input in ["2024-13", "2024-00", "June", "2024", "2024-ab", ""] {
+            let err = Month::parse(input).unwrap_err();
+            assert!(err.contains("expected YYYY-MM"), "{input}: {err}");
+        }
+    }
+
+    #[test]
+    fn months_cover_every_day() {
+        let days = |month: &str| {
+            let (start, end) = Month::parse(month).unwrap().range_ms();
+            // Give or take a DST switch.
+            ((end - start) as f64 / 86_400_000.0).round() as i64
+        };
+        assert_eq!(days("2024-12"), 31);
+        assert_eq!(days("2024-02"), 29);
+        assert_eq!(days("2023-02"), 28);
+    }
+
+    #[test]
+    fn a_month_without_samples_has_no_targets() {
+        let report = build_plain(&[], &[], &thresholds(1, 1));
+        assert!(report.targets.is_empty() && report.groups.is_empty());
+        assert_eq!(report.weighted_availability_pct, None);
+        assert_eq!(report.lines()[1], "Keine Messwerte in diesem Monat.");
+        assert_eq!(report.lines().len(), 2);
+    }
+
+    #[test]
+    fn a_single_probe_has_no_availability() {
+        let report = build_plain(&samples("a", &[(0, true)]), &[], &thresholds(1, 1));
+        let a = &report.targets[0];
+        assert_eq!((a.probes, a.monitored_ms, a.downtime_ms), (1, 0, 0));
+        assert_eq!(a.availability_pct(), None);
+        assert_eq!(report.weighted_availability_pct, None);
+        let row = report
+            .lines()
+            .into_iter()
+            .find(|l| l.starts_with("a "))
+            .unwrap();
+        assert_eq!(row.split_whitespace().nth(1), Some("-"));
+    }
+
+    #[test]
+    fn samples_outside_the_month_are_ignored() {
+        let (start, end) = june().range_ms();
+        let mut list = samples("a", &[(-10, false), (0, true), (10, true)]);
+        list.extend(samples("a", &[((end - start) / 1000, false)]));
+        let report = build_plain(&list, &[], &thresholds(1, 1));
+        let a = &report.targets[0];
+        assert_eq!((a.probes, a.failed, a.monitored_ms), (2, 0, 10_000));
+        assert!(a.worst.is_empty());
+    }
+
+    #[test]
+    fn single_failures_below_down_after_are_no_outage() {
+        let probes = [true, false, true, false, false, true, true];
+        let list: Vec<(i64, bool)> = (0..).step_by(10).zip(probes).collect();
+        let report = build_plain(&samples("a", &list), &[], &thresholds(3, 2));
+        let a = &report.targets[0];
+        assert!(a.worst.is_empty(), "{a:?}");
+        assert_eq!((a.failed, a.monitored_ms, a.downtime_ms), (3, 60_000, 0));
+        assert_eq!(a.availability_pct(), Some(100.0));
+    }
+
+    #[test]
+    fn down_after_failures_start_an_outage_at_the_first_one() {
+        let list = samples(
+            "a",
+            &[(0, true), (10, false), (20, false), (30, false), (40, true)],
+        );
+        let report = build_plain(&list, &[], &thresholds(3, 1));
+        let a = &report.targets[0];
+        assert_eq!(spans(a), [(10, Some(40))]);
+        assert_eq!((a.monitored_ms, a.downtime_ms), (40_000, 30_000));
+        assert_eq!(a.availability_pct(), Some(25.0));
+
+        // One failure less stays below the threshold.
+        let report = build_plain(&list, &[], &thresholds(4, 1));
+        assert!(report.targets[0].worst.is_empty());
+        assert_eq!(report.targets[0].downtime_ms, 0);
+    }
+
+    #[test]
+    fn an_outage_ends_after_up_after_answers() {
+        let list = samples("a", &[(0, true), (10, false), (20, true), (30, true)]);
+        let a = &build_plain(&list, &[], &thresholds(1, 2)).targets[0];
+        assert_eq!(spans(a), [(10, Some(30))]);
+        assert_eq!(a.downtime_ms, 20_000);
+        let a = &build_plain(&list, &[], &thresholds(1, 1)).targets[0];
+        assert_eq!(spans(a), [(10, Some(20))]);
+        assert_eq!(a.downtime_ms, 10_000);
+    }
+
+    #[test]
+    fn failing_while_recovering_continues_the_outage() {
+        let list = samples(
+            "a",
+            &[
+                (0, true),
+                (10, false),
+                (20, true),
+                (30, false),
+                (40, true),
+                (50, true),
+            ],
+        );
+        let a = &build_plain(&list, &[], &thresholds(1, 2)).targets[0];
+        assert_eq!(spans(a), [(10, Some(50))]);
+        assert_eq!((a.monitored_ms, a.downtime_ms), (50_000, 40_000));
+    }
+
+    #[test]
+    fn sums_downtime_and_lists_the_longest_outages_first() {
+        let list = samples(
+            "a",
+            &[
+                (0, true),
+                (10, false),
+                (20, false),
+                (30, true),
+                (40, false),
+                (50, true),
+            ],
+        );
+        let report = build_plain(&list, &[], &thresholds(1, 1));
+        let a = &report.targets[0];
+        assert_eq!(
+            (a.probes, a.failed, a.monitored_ms, a.downtime_ms),
+            (6, 3, 50_000, 30_000)
+        );
+        assert_eq!(spans(a), [(10, Some(30)), (40, Some(50))]);
+        assert_eq!(a.availability_pct(), Some(40.0));
+        assert!(report.lines().contains(&"Längste Ausfälle a:".to_string()));
+    }
+
+    #[test]
+    fn only_the_worst_outages_are_listed() {
+        let list: Vec<(i64, bool)> = (0..).step_by(10).zip((0..11).map(|i| i % 2 == 0)).collect();
+        let a = &build_plain(&samples("a", &list), &[], &thresholds(1, 1)).targets[0];
+        assert_eq!(a.worst.len(), WORST);
+        assert_eq!(a.downtime_ms, 50_000);
+    }
+
+    #[test]
+    fn an_outage_going_on_at_the_end_ends_at_the_last_probe() {
+        let list = samples("a", &[(0, true), (10, false), (20, false)]);
+        let a = &build_plain(&list, &[], &thresholds(1, 1)).targets[0];
+        assert_eq!(spans(a), [(10, Some(20))]);
+        assert_eq!((a.monitored_ms, a.downtime_ms), (20_000, 10_000));
+    }
+
+    #[test]
+    fn an_outage_ends_where_the_monitor_stopped() {
+        let (start, _) = june().range_ms();
+        let gap = Gap {
+            start_ms: start + 70_000,
+            end_ms: start + 190_000,
+        };
+        let list = samples("c", &[(60, false), (200, true), (210, true)]);
+        let report = build_plain(&list, &[gap], &thresholds(1, 1));
+        let c = &report.targets[0];
+        // Counted until the gap, then not at all; the restart is a new run.
+        assert_eq!(spans(c), [(60, Some(70))]);
+        assert_eq!((c.monitored_ms, c.downtime_ms), (20_000, 10_000));
+        assert_eq!(report.gaps, [gap]);
+        assert!(
+            report
+                .lines()
+                .iter()
+                .any(|l| l.starts_with("Nicht überwacht: 0h 02m 00s in 1 Lücke(n)"))
+        );
+    }
+
+    #[test]
+    fn a_gap_resets_the_failure_count() {
+        let (start, _) = june().range_ms();
+        let gap = Gap {
+            start_ms: start + 15_000,
+            end_ms: start + 25_000,
+        };
+        let list = samples("a", &[(0, true), (10, false), (30, false), (40, true)]);
+        let a = &build_plain(&list, &[gap], &thresholds(2, 1)).targets[0];
+        assert!(a.worst.is_empty(), "{a:?}");
+    }
+
+    /// a is down 10-30 and 40-50, b 25-45.
+    fn dns_samples() -> Vec<Sample> {
+        let mut list = samples(
+            "a",
+            &[
+                (0, true),
+                (10, false),
+                (20, false),
+                (30, true),
+                (40, false),
+                (50, true),
+            ],
+        );
+        list.extend(samples("b", &[(0, true), (25, false), (45, true)]));
+        list
+    }
+
+    #[test]
+    fn a_group_is_down_while_any_member_is() {
+        let dns = Group {
+            index: 0,
+            name: Some("DNS".to_string()),
+        };
+        let unnamed = Group {
+            index: 1,
+            name: None,
+        };
+        let groups = HashMap::from([
+            ("a".to_string(), dns.clone()),
+            ("b".to_string(), dns),
+            ("c".to_string(), unnamed),
+        ]);
+        let mut list = dns_samples();
+        list.extend(samples("c", &[(0, false), (10, false)]));
+        let report = build(
+            &list,
+            &[],
+            june(),
+            &groups,
+            &HashMap::new(),
+            &thresholds(1, 1),
+        );
+        // Unnamed groups are left out.
+        assert_eq!(report.groups.len(), 1);
+        let dns = &report.groups[0];
+        assert_eq!(
+            (
+                dns.name.as_str(),
+                dns.targets.as_slice(),
+                dns.probes,
+                dns.monitored_ms,
+                dns.downtime_ms
+            ),
+            (
+                "DNS",
+                ["a".to_string(), "b".to_string()].as_slice(),
+                9,
+                50_000,
+                40_000
+            )
+        );
+        assert_eq!(dns.worst.len(), 1);
+        assert!(
+            report
+                .lines()
+                .iter()
+                .any(|l| l.starts_with("Längste Störungen DNS:"))
+        );
+    }
+
+    #[test]
+    fn weights_scale_availability_and_impact() {
+        let weights = HashMap::from([("b".to_string(), 3.0)]);
+        let report = build(
+            &dns_samples(),
+            &[],
+            june(),
+            &HashMap::new(),
+            &weights,
+            &thresholds(1, 1),
+        );
+        // a is up 20 of 50s, b 25 of 45s; the shared incident (10-50s)
+        // outweighs a's own.
+        let weighted = report.weighted_availability_pct.unwrap();
+        assert!((weighted - (40.0 + 3.0 * 2500.0 / 45.0) / 4.0).abs() < 1e-9);
+        assert_eq!(report.by_impact[0].impact, 40.0 * 4.0);
+        let lines = report.lines();
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("Gewichtete Verfügbarkeit:"))
+        );
+        assert!(
+            lines
+                .iter()
+                .any(|l| l.starts_with("Störungen nach Auswirkung"))
+        );
+    }
+
+    #[test]
+    fn without_weights_there_is_no_weighted_section() {
+        let report = build_plain(&dns_samples(), &[], &thresholds(1, 1));
+        let weighted = report.weighted_availability_pct.unwrap();
+        assert!((weighted - (40.0 + 2500.0 / 45.0) / 2.0).abs() < 1e-9);
+        let lines = report.lines();
+        assert!(
+            !lines
+                .iter()
+                .any(|l| l.starts_with("Gewichtete Verfügbarkeit:"))
+        );
+        assert!(
+            !lines
+                .iter()
+                .any(|l| l.starts_with("Störungen nach Auswirkung"))
+        );
+    }
+}
@@ -0,0 +1,251 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    parse::{Loss, ProbeOutcome},
+    quantile::P2,
+};
+
+/// Probe counters of one target over a run.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub success: u64,
+    pub total: u64,
+    pub min_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    pub sum_ms: f64,
+    pub samples: u64,
+    pub missed: u64,
+    #[serde(default)]
+    pub losses: LossCounts,
+    /// LOCAL-DOWN rounds, not counted in `total`.
+    #[serde(default)]
+    pub local_down: u64,
+    /// Running mean and sum of squared deviations of the RTTs (Welford),
+    /// for `stddev_ms`.
+    #[serde(default)]
+    pub mean_ms: f64,
+    #[serde(default)]
+    pub m2_ms: f64,
+    /// Summed differences between consecutive RTTs, lost probes in between
+    /// skipped, for `jitter_ms`.
+    #[serde(default)]
+    pub jitter_sum_ms: f64,
+    #[serde(default)]
+    pub jitter_pairs: u64,
+    #[serde(skip)]
+    last_tick: Option<u64>,
+    #[serde(skip)]
+    last_rtt_ms: Option<f64>,
+    #[serde(skip)]
+    percentiles: Percentiles,
+}
+
+/// p50, p95 and p99 of the RTTs; not kept in summaries, which carry the
+/// values instead.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Percentiles([P2; 3]);
+
+impl Default for Percentiles {
+    fn default() -> Percentiles {
+        Percentiles([P2::new(0.5), P2::new(0.95), P2::new(0.99)])
+    }
+}
+
+/// Lost probes by reason, see [`Loss`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LossCounts {
+    pub timeout: u64,
+    pub unreachable: u64,
+    pub ttl_exceeded: u64,
+    pub prohibited: u64,
+    pub error: u64,
+    #[serde(default)]
+    pub local_down: u64,
+}
+
+impl LossCounts {
+    pub fn add(&mut self, loss: Loss) {
+        let count = match loss {
+            Loss::Timeout => &mut self.timeout,
+            Loss::Unreachable => &mut self.unreachable,
+            Loss::TtlExceeded => &mut self.ttl_exceeded,
+            Loss::Prohibited => &mut self.prohibited,
+            Loss::Error => &mut self.error,
+            // Only reached when some targets could still be probed, e.g. a
+            // --group interface went down; see `hold_local_down`.
+            Loss::LocalDown => &mut self.local_down,
+        };
+        *count += 1;
+    }
+
+    /// Compact column value in the order of the `T/U/X/P/E` header; `E`
+    /// covers both local causes.
+    pub fn column(&self) -> String {
+        format!(
+            "{}/{}/{}/{}/{}",
+            self.timeout,
+            self.unreachable,
+            self.ttl_exceeded,
+            self.prohibited,
+            self.error + self.local_down
+        )
+    }
+}
+
+impl Stats {
+    /// Records the result for scheduled tick `tick`. Results for a tick that was
+    /// already seen are ignored; skipped ticks are counted as missed.
+    pub fn record(&mut self, tick: u64, outcome: ProbeOutcome) -> bool {
+        let expected = self.last_tick.map_or(0, |t| t + 1);
+        if tick < expected {
+            return false;
+        }
+        self.missed += tick - expected;
+        self.last_tick = Some(tick);
+        self.total += 1;
+        if let ProbeOutcome::Lost(loss) = outcome {
+            self.losses.add(loss);
+        } else {
+            self.success += 1;
+            if let Some(ms) = outcome.rtt_ms() {
+                self.min_ms = Some(self.min_ms.map_or(ms, |cur| cur.min(ms)));
+                self.max_ms = Some(self.max_ms.map_or(ms, |cur| cur.max(ms)));
+                self.sum_ms += ms;
+                self.samples += 1;
+                let delta = ms - self.mean_ms;
+                self.mean_ms += delta / self.samples as f64;
+                self.m2_ms += delta * (ms - self.mean_ms);
+                for estimate in &mut self.percentiles.0 {
+                    estimate.add(ms);
+                }
+                if let Some(last) = self.last_rtt_ms.replace(ms) {
+                    self.jitter_sum_ms += (ms - last).abs();
+                    self.jitter_pairs += 1;
+                }
+            }
+        }
+        true
+    }
+
+    /// Marks `tick` as handled without counting it (paused or warm-up).
+    pub fn skip(&mut self, tick: u64) {
+        if self.last_tick.is_none_or(|last| tick > last) {
+            self.last_tick = Some(tick);
+        }
+    }
+
+    pub fn loss_pct(&self) -> Option<f64> {
+        (self.total > 0).then(|| (self.total - self.success) as f64 * 100.0 / self.total as f64)
+    }
+
+    /// Successful probes whose output had no readable RTT.
+    pub fn no_rtt(&self) -> u64 {
+        self.success - self.samples
+    }
+
+    /// Failed or unusable probes by kind, for the breakdowns in the detail
+    /// panel, the final log and the summary: the loss reasons, replies
+    /// without a readable RTT, and LOCAL-DOWN probes whether counted or held.
+    pub fn failures(&self) -> [(&'static str, u64); 7] {
+        let l = &self.losses;
+        [
+            ("timeout", l.timeout),
+            ("unreachable", l.unreachable),
+            ("ttl_exceeded", l.ttl_exceeded),
+            ("prohibited", l.prohibited),
+            ("spawn_error", l.error),
+            ("unparsed", self.no_rtt()),
+            ("local_down", l.local_down + self.local_down),
+        ]
+    }
+
+    pub fn avg_ms(&self) -> Option<f64> {
+        if self.samples > 0 {
+            Some(self.sum_ms / self.samples as f64)
+        } else {
+            None
+        }
+    }
+
+    /// RTT percentiles over the run, estimated in fixed memory (`P2`), for
+    /// the tail latency min/avg/max don't show.
+    pub fn p50_ms(&self) -> Option<f64> {
+        self.percentiles.0[0].value()
+    }
+
+    pub fn p95_ms(&self) -> Option<f64> {
+        self.percentiles.0[1].value()
+    }
+
+    pub fn p99_ms(&self) -> Option<f64> {
+        self.percentiles.0[2].value()
+    }
+
+    /// Sample standard deviation of the RTTs; needs two of them.
+    pub fn stddev_ms(&self) -> Option<f64> {
+        (self.samples > 1).then(|| (self.m2_ms / (self.samples - 1) as f64).sqrt())
+    }
+
+    /// Mean absolute difference between consecutive RTTs, the instability
+    /// an average hides (what VoIP suffers from).
+    pub fn jitter_ms(&self) -> Option<f64> {
+        (self.jitter_pairs > 0).then(|| self.jitter_sum_ms / self.jitter_pairs as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_count_missed_ticks_and_drop_duplicates() {
+        let mut stats = Stats::default();
+        assert!(stats.record(0, ProbeOutcome::Reply(1.0)));
+        assert!(stats.record(3, ProbeOutcome::Reply(3.0)));
+        assert!(!stats.record(3, ProbeOutcome::Lost(Loss::Timeout)));
+        assert!(!stats.record(1, ProbeOutcome::Reply(1.0)));
+        assert_eq!((stats.total, stats.success, stats.missed), (2, 2, 2));
+        assert_eq!(stats.avg_ms(), Some(2.0));
+
+        let mut skipped = Stats::default();
+        skipped.skip(0);
+        skipped.skip(1);
+        assert!(!skipped.record(1, ProbeOutcome::Unparsed));
+        assert!(skipped.record(2, ProbeOutcome::Unparsed));
+        assert_eq!((skipped.total, skipped.missed), (1, 0));
+        assert_eq!((skipped.no_rtt(), stats.no_rtt()), (1, 0));
+    }
+
+    #[test]
+    fn stats_count_losses_by_reason() {
+        let mut stats = Stats::default();
+        stats.record(0, ProbeOutcome::Lost(Loss::Timeout));
+        stats.record(1, ProbeOutcome::Lost(Loss::Unreachable));
+        stats.record(2, ProbeOutcome::Lost(Loss::Unreachable));
+        stats.record(3, ProbeOutcome::Lost(Loss::Prohibited));
+        stats.record(4, ProbeOutcome::Reply(1.0));
+        assert_eq!(stats.losses.column(), "1/2/0/1/0");
+        assert_eq!(stats.loss_pct(), Some(80.0));
+        stats.record(5, ProbeOutcome::Lost(Loss::LocalDown));
+        stats.local_down += 2;
+        assert_eq!(stats.losses.column(), "1/2/0/1/1");
+        let failures = stats.failures();
+        assert_eq!(failures[1], ("unreachable", 2));
+        assert_eq!(failures[6], ("local_down", 3));
+    }
+
+    #[test]
+    fn stats_track_spread_and_jitter() {
+        let mut stats = Stats::default();
+        stats.record(0, ProbeOutcome::Reply(10.0));
+        assert_eq!((stats.stddev_ms(), stats.jitter_ms()), (None, None));
+        stats.record(1, ProbeOutcome::Reply(14.0));
+        stats.record(2, ProbeOutcome::Lost(Loss::Timeout));
+        stats.record(3, ProbeOutcome::Reply(12.0));
+        stats.record(4, ProbeOutcome::Reply(12.0));
+        // RTTs 10, 14, 12, 12: variance 8/3; steps 4, 2 and 0.
+        assert!((stats.stddev_ms().unwrap() - (8.0_f64 / 3.0).sqrt()).abs() < 1e-9);
+        assert_eq!(stats.jitter_ms(), Some(2.0));
+        assert_eq!((stats.p50_ms(), stats.p99_ms()), (Some(12.0), Some(14.0)));
+    }
+}
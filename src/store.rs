@@ -0,0 +1,519 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+// Segments roll over once they reach this size; closed segments smaller than
+// COMPACT_TARGET_BYTES are merged with their neighbours on open/roll.
+const SEGMENT_MAX_BYTES: u64 = 4 * 1024 * 1024;
+const COMPACT_TARGET_BYTES: u64 = 16 * 1024 * 1024;
+const SEGMENT_PREFIX: &str = "segment-";
+const SEGMENT_SUFFIX: &str = ".tsv";
+const PRUNE_EVERY: Duration = Duration::from_secs(600);
+// One `start\tend` line per monitoring run; the running one's end moves
+// forward at most every RUN_MARK_EVERY_MS, so a crash loses that much.
+const RUNS_FILE: &str = "runs.tsv";
+const RUN_MARK_EVERY_MS: i64 = 10_000;
+/// Shorter pauses between runs (a quick restart) are no gap.
+pub const GAP_MIN_MS: i64 = 60_000;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub ts_ms: i64,
+    pub target: String,
+    pub success: bool,
+    pub latency_ms: Option<f64>,
+}
+
+impl Sample {
+    fn to_line(&self) -> String {
+        let latency = self
+            .latency_ms
+            .map(|ms| format!("{ms:.3}"))
+            .unwrap_or_else(|| "-".to_string());
+        format!(
+            "{}\t{}\t{}\t{}",
+            self.ts_ms,
+            if self.success { 1 } else { 0 },
+            latency,
+            self.target
+        )
+    }
+
+    fn from_line(line: &str) -> Option<Sample> {
+        let mut parts = line.splitn(4, '\t');
+        let ts_ms = parts.next()?.parse().ok()?;
+        let success = match parts.next()? {
+            "1" => true,
+            "0" => false,
+            _ => return None,
+        };
+        let latency_ms = match parts.next()? {
+            "-" => None,
+            value => Some(value.parse().ok()?),
+        };
+        let target = parts.next()?.to_string();
+        if target.is_empty() {
+            return None;
+        }
+        Some(Sample {
+            ts_ms,
+            target,
+            success,
+            latency_ms,
+        })
+    }
+}
+
+/// A monitoring run that wrote to the store, from `runs.tsv`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Run {
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+/// A stretch in which no run was monitoring, so the store has no samples
+/// for it although the targets may well have been up or down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Gap {
+    pub start_ms: i64,
+    pub end_ms: i64,
+}
+
+/// Append-only sample store made of time-ordered segment files in one directory.
+pub struct Store {
+    dir: PathBuf,
+    writer: BufWriter<File>,
+    // Shared with the pruning thread: holding the lock serializes segment
+    // rewrites, and the value tells it which segment must not be touched.
+    active_path: Arc<Mutex<PathBuf>>,
+    active_bytes: u64,
+    // All recorded runs, the last one this process's; empty until `start_run`.
+    runs: Vec<Run>,
+    marked_ms: i64,
+}
+
+impl Store {
+    /// Opens (or creates) the store directory, compacts closed segments and
+    /// starts a fresh active segment.
+    pub fn open(dir: &Path) -> io::Result<Store> {
+        fs::create_dir_all(dir)?;
+        compact(dir)?;
+        let (active_path, writer) = new_segment(dir, now_ms())?;
+        Ok(Store {
+            dir: dir.to_path_buf(),
+            writer,
+            active_path: Arc::new(Mutex::new(active_path)),
+            active_bytes: 0,
+            runs: Vec::new(),
+            marked_ms: 0,
+        })
+    }
+
+    /// Records a monitoring run starting now; returns when the previous one
+    /// ended. `import` and `--once` don't call it, so they leave no run.
+    pub fn start_run(&mut self) -> io::Result<Option<i64>> {
+        self.runs = read_runs(&self.dir)?;
+        let previous = self.runs.iter().map(|r| r.end_ms).max();
+        let now = now_ms();
+        self.runs.push(Run {
+            start_ms: now,
+            end_ms: now,
+        });
+        self.mark(now)?;
+        Ok(previous)
+    }
+
+    pub fn append(&mut self, sample: &Sample) -> io::Result<()> {
+        let line = sample.to_line();
+        writeln!(self.writer, "{line}")?;
+        self.active_bytes += line.len() as u64 + 1;
+        if self.active_bytes >= SEGMENT_MAX_BYTES {
+            self.roll(sample.ts_ms + 1)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        let now = now_ms();
+        if !self.runs.is_empty() && now - self.marked_ms >= RUN_MARK_EVERY_MS {
+            self.mark(now)?;
+        }
+        Ok(())
+    }
+
+    /// Moves the end of this process's run to `now_ms`.
+    fn mark(&mut self, now_ms: i64) -> io::Result<()> {
+        if let Some(run) = self.runs.last_mut() {
+            run.end_ms = now_ms;
+        }
+        self.marked_ms = now_ms;
+        let path = self.dir.join(RUNS_FILE);
+        let tmp = path.with_extension("tmp");
+        {
+            let mut writer = BufWriter::new(File::create(&tmp)?);
+            for run in &self.runs {
+                writeln!(writer, "{}\t{}", run.start_ms, run.end_ms)?;
+            }
+            writer.flush()?;
+        }
+        fs::rename(&tmp, path)
+    }
+
+    /// Starts a background thread that periodically drops samples older than
+    /// `retention` from all closed segments.
+    pub fn spawn_pruner(&self, retention: Duration) -> thread::JoinHandle<()> {
+        let dir = self.dir.clone();
+        let active_path = Arc::clone(&self.active_path);
+        thread::spawn(move || {
+            loop {
+                let cutoff = now_ms() - retention.as_millis() as i64;
+                if let Ok(active) = active_path.lock()
+                    && let Err(err) = prune_except(&dir, cutoff, Some(&active))
+                {
+                    tracing::error!("Failed to prune sample store {}: {err}", dir.display());
+                }
+                thread::sleep(PRUNE_EVERY);
+            }
+        })
+    }
+
+    fn roll(&mut self, next_ts_ms: i64) -> io::Result<()> {
+        self.writer.flush()?;
+        let mut active = self
+            .active_path
+            .lock()
+            .map_err(|_| io::Error::other("store lock poisoned"))?;
+        let (path, writer) = new_segment(&self.dir, next_ts_ms)?;
+        self.writer = writer;
+        *active = path;
+        self.active_bytes = 0;
+        compact_except(&self.dir, Some(&active))
+    }
+}
+
+impl Drop for Store {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+        if !self.runs.is_empty() {
+            let _ = self.mark(now_ms());
+        }
+    }
+}
+
+/// The recorded runs; none for stores written before runs were recorded.
+fn read_runs(dir: &Path) -> io::Result<Vec<Run>> {
+    let text = match fs::read_to_string(dir.join(RUNS_FILE)) {
+        Ok(text) => text,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(text
+        .lines()
+        .filter_map(|line| {
+            let (start, end) = line.split_once('\t')?;
+            Some(Run {
+                start_ms: start.parse().ok()?,
+                end_ms: end.parse().ok()?,
+            })
+        })
+        .collect())
+}
+
+/// The gaps between `from_ms` (or the first run) and `to_ms`: pauses
+/// between runs and the time since the last one ended. Before the first
+/// recorded run nothing is known, so that is no gap.
+pub fn read_gaps(dir: &Path, from_ms: Option<i64>, to_ms: i64) -> io::Result<Vec<Gap>> {
+    Ok(gaps(read_runs(dir)?, from_ms, to_ms))
+}
+
+fn gaps(mut runs: Vec<Run>, from_ms: Option<i64>, to_ms: i64) -> Vec<Gap> {
+    runs.sort_by_key(|r| r.start_ms);
+    let mut gaps = Vec::new();
+    let mut covered: Option<i64> = None;
+    for run in runs {
+        if let Some(end) = covered
+            && run.start_ms - end >= GAP_MIN_MS
+        {
+            gaps.push(Gap {
+                start_ms: end,
+                end_ms: run.start_ms,
+            });
+        }
+        covered = Some(covered.map_or(run.end_ms, |end| end.max(run.end_ms)));
+    }
+    if let Some(end) = covered
+        && to_ms - end >= GAP_MIN_MS
+    {
+        gaps.push(Gap {
+            start_ms: end,
+            end_ms: to_ms,
+        });
+    }
+    gaps.into_iter()
+        .filter_map(|gap| {
+            let start_ms = gap.start_ms.max(from_ms.unwrap_or(i64::MIN));
+            let end_ms = gap.end_ms.min(to_ms);
+            (end_ms > start_ms).then_some(Gap { start_ms, end_ms })
+        })
+        .collect()
+}
+
+/// Reads all samples with `ts_ms >= since_ms` (or everything) in timestamp order.
+pub fn read_samples(dir: &Path, since_ms: Option<i64>) -> io::Result<Vec<Sample>> {
+    let mut samples = Vec::new();
+    for (_, path) in list_segments(dir)? {
+        read_segment(&path, &mut samples)?;
+    }
+    if let Some(since) = since_ms {
+        samples.retain(|s| s.ts_ms >= since);
+    }
+    samples.sort_by_key(|s| s.ts_ms);
+    Ok(samples)
+}
+
+/// Removes samples older than `cutoff_ms`, deleting segments that become empty.
+fn prune_except(dir: &Path, cutoff_ms: i64, active: Option<&Path>) -> io::Result<()> {
+    for (_, path) in list_segments(dir)? {
+        if Some(path.as_path()) == active {
+            continue;
+        }
+        let mut samples = Vec::new();
+        read_segment(&path, &mut samples)?;
+        let before = samples.len();
+        samples.retain(|s| s.ts_ms >= cutoff_ms);
+        if samples.is_empty() {
+            fs::remove_file(&path)?;
+        } else if samples.len() != before {
+            rewrite_segment(&path, &samples)?;
+        }
+    }
+    Ok(())
+}
+
+/// Merges runs of small closed segments into larger ones.
+pub fn compact(dir: &Path) -> io::Result<()> {
+    compact_except(dir, None)
+}
+
+fn compact_except(dir: &Path, active: Option<&Path>) -> io::Result<()> {
+    let segments: Vec<(u64, PathBuf)> = list_segments(dir)?
+        .into_iter()
+        .filter(|(_, path)| Some(path.as_path()) != active)
+        .map(|(_, path)| {
+            let size = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+            (size, path)
+        })
+        .collect();
+
+    let mut group: Vec<PathBuf> = Vec::new();
+    let mut group_bytes = 0;
+    for (size, path) in segments {
+        if size == 0 {
+            fs::remove_file(&path)?;
+            continue;
+        }
+        if group_bytes + size > COMPACT_TARGET_BYTES {
+            merge_segments(&group)?;
+            group.clear();
+            group_bytes = 0;
+        }
+        group.push(path);
+        group_bytes += size;
+    }
+    merge_segments(&group)
+}
+
+fn merge_segments(group: &[PathBuf]) -> io::Result<()> {
+    if group.len() < 2 {
+        return Ok(());
+    }
+    let mut samples = Vec::new();
+    for path in group {
+        read_segment(path, &mut samples)?;
+    }
+    samples.sort_by_key(|s| s.ts_ms);
+
+    rewrite_segment(&group[0], &samples)?;
+    for path in &group[1..] {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
+
+// Writes to a temp file and renames it over `path` so a crash mid-way leaves
+// either the old contents or the new ones, never a partial segment.
+fn rewrite_segment(path: &Path, samples: &[Sample]) -> io::Result<()> {
+    let tmp = path.with_extension("tmp");
+    {
+        let mut writer = BufWriter::new(File::create(&tmp)?);
+        for sample in samples {
+            writeln!(writer, "{}", sample.to_line())?;
+        }
+        writer.flush()?;
+    }
+    fs::rename(&tmp, path)
+}
+
+fn read_segment(path: &Path, out: &mut Vec<Sample>) -> io::Result<()> {
+    let reader = BufReader::new(File::open(path)?);
+    for line in reader.lines() {
+        if let Some(sample) = Sample::from_line(&line?) {
+            out.push(sample);
+        }
+    }
+    Ok(())
+}
+
+fn list_segments(dir: &Path) -> io::Result<Vec<(i64, PathBuf)>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+            continue;
+        };
+        if let Some(ts) = name
+            .strip_prefix(SEGMENT_PREFIX)
+            .and_then(|rest| rest.strip_suffix(SEGMENT_SUFFIX))
+            .and_then(|ts| ts.parse::<i64>().ok())
+        {
+            segments.push((ts, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+fn new_segment(dir: &Path, start_ms: i64) -> io::Result<(PathBuf, BufWriter<File>)> {
+    let mut ts = start_ms;
+    loop {
+        let path = dir.join(format!("{SEGMENT_PREFIX}{ts:013}{SEGMENT_SUFFIX}"));
+        match OpenOptions::new().write(true).create_new(true).open(&path) {
+            Ok(file) => return Ok((path, BufWriter::new(file))),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => ts += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+pub fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    fn temp_store_dir() -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or(0);
+        std::env::temp_dir().join(format!("store_test_{}_{}", std::process::id(), nanos))
+    }
+
+    fn sample(ts_ms: i64, success: bool, latency_ms: Option<f64>) -> Sample {
+        Sample {
+            ts_ms,
+            target: "10.0.0.1".to_string(),
+            success,
+            latency_ms,
+        }
+    }
+
+    #[test]
+    fn sample_line_round_trip() {
+        for s in [sample(1, true, Some(12.5)), sample(2, false, None)] {
+            assert_eq!(Sample::from_line(&s.to_line()), Some(s));
+        }
+        assert_eq!(Sample::from_line("garbage"), None);
+    }
+
+    #[test]
+    fn reopening_compacts_previous_runs() {
+        let dir = temp_store_dir();
+        for run in 0..3 {
+            let mut store = Store::open(&dir).unwrap();
+            store.append(&sample(run * 10, true, Some(1.0))).unwrap();
+            store.append(&sample(run * 10 + 1, false, None)).unwrap();
+        }
+        // Three runs plus the fresh (empty) active segment of the last open.
+        let store = Store::open(&dir).unwrap();
+        drop(store);
+        assert_eq!(list_segments(&dir).unwrap().len(), 2);
+
+        let all = read_samples(&dir, None).unwrap();
+        assert_eq!(all.len(), 6);
+        assert!(all.windows(2).all(|w| w[0].ts_ms <= w[1].ts_ms));
+        assert_eq!(read_samples(&dir, Some(20)).unwrap().len(), 2);
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn pruning_drops_old_samples_and_empty_segments() {
+        let dir = temp_store_dir();
+        {
+            let mut store = Store::open(&dir).unwrap();
+            for ts in [5, 15, 25] {
+                store.append(&sample(ts, true, Some(1.0))).unwrap();
+            }
+        }
+        prune_except(&dir, 10, None).unwrap();
+        let left: Vec<i64> = read_samples(&dir, None)
+            .unwrap()
+            .iter()
+            .map(|s| s.ts_ms)
+            .collect();
+        assert_eq!(left, vec![15, 25]);
+
+        prune_except(&dir, 100, None).unwrap();
+        assert!(list_segments(&dir).unwrap().is_empty());
+        let _ = fs::remove_dir_all(dir);
+    }
+
+    #[test]
+    fn gaps_lie_between_runs_and_after_the_last() {
+        let dir = temp_store_dir();
+        {
+            let mut store = Store::open(&dir).unwrap();
+            assert_eq!(store.start_run().unwrap(), None);
+        }
+        let mut store = Store::open(&dir).unwrap();
+        assert!(store.start_run().unwrap().is_some());
+        assert_eq!(read_runs(&dir).unwrap().len(), 2);
+        drop(store);
+        let _ = fs::remove_dir_all(dir);
+
+        let run = |start_ms, end_ms| Run { start_ms, end_ms };
+        let min = GAP_MIN_MS;
+        let runs = vec![run(0, 10), run(10 + min, 20 + min), run(30 + min, 40 + min)];
+        assert_eq!(
+            gaps(runs.clone(), None, 40 + 3 * min),
+            [
+                Gap {
+                    start_ms: 10,
+                    end_ms: 10 + min
+                },
+                Gap {
+                    start_ms: 40 + min,
+                    end_ms: 40 + 3 * min
+                }
+            ]
+        );
+        assert_eq!(
+            gaps(runs, Some(min), 2 * min),
+            [Gap {
+                start_ms: min,
+                end_ms: 10 + min
+            }]
+        );
+    }
+}
@@ -0,0 +1,977 @@
+use std::{
+    collections::HashMap,
+    fs,
+    io::{self, Read},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use tracing::warn;
+
+use crate::{Target, error::RunError};
+
+/// Where the list of targets comes from. Sources with a refresh interval are
+/// asked again periodically; the monitor then starts workers for new targets
+/// and stops those for removed ones.
+pub trait TargetSource {
+    /// Origin shown in messages, e.g. the file path.
+    fn describe(&self) -> String;
+
+    /// The targets in list order, with their labels.
+    fn load(&mut self) -> Result<Vec<Target>, String>;
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Groups for display, keyed by target.
+    fn groups(&self) -> HashMap<String, Group> {
+        HashMap::new()
+    }
+
+    /// Writes the current targets back, for lists edited in the TUI.
+    fn save(&mut self, _targets: &[Target]) -> Result<(), String> {
+        Err(format!("{} cannot be written", self.describe()))
+    }
+}
+
+/// The source's first list, which must not be empty.
+pub fn load(source: &mut dyn TargetSource) -> Result<Vec<Target>, RunError> {
+    let ips = source
+        .load()
+        .map_err(|err| RunError::Targets(format!("Failed to read IP list: {err}")))?;
+    if ips.is_empty() {
+        return Err(RunError::Targets(format!(
+            "No IPs found in {}",
+            source.describe()
+        )));
+    }
+    Ok(ips)
+}
+
+/// A validated entry of the target list.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Entry {
+    V4(Ipv4Addr),
+    /// With an optional zone for link-local addresses (`fe80::1%eth0`).
+    V6(Ipv6Addr, Option<String>),
+    Host(String),
+    /// `ping` only sees the host part of a URL.
+    Url(Box<Entry>),
+    /// A host name probed over one address family only (`dns.google@v6`).
+    Pinned(String, Family),
+}
+
+/// IPv4 or IPv6, for `-4`/`-6` and host names pinned to one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Family {
+    V4,
+    V6,
+}
+
+impl Family {
+    pub fn of(addr: IpAddr) -> Family {
+        match addr {
+            IpAddr::V4(_) => Family::V4,
+            IpAddr::V6(_) => Family::V6,
+        }
+    }
+
+    /// Appended to a host name to pin it, e.g. `dns.google@v6`.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            Family::V4 => "@v4",
+            Family::V6 => "@v6",
+        }
+    }
+}
+
+/// Splits a pinned host name into the host and its family.
+pub fn split_family(target: &str) -> (&str, Option<Family>) {
+    [Family::V4, Family::V6]
+        .into_iter()
+        .find_map(|family| Some((target.strip_suffix(family.suffix())?, Some(family))))
+        .unwrap_or((target, None))
+}
+
+/// The family `target` is probed over: that of an IP literal, else the one
+/// it is pinned to, else `prefer` (`-4`/`-6`); `None` leaves it to `ping`.
+pub fn family_of(target: &str, prefer: Option<Family>) -> Option<Family> {
+    let (host, pinned) = split_family(target);
+    let addr = host.split('%').next().unwrap_or(host);
+    match addr.parse() {
+        Ok(addr) => Some(Family::of(addr)),
+        Err(_) => pinned.or(prefer),
+    }
+}
+
+/// `--dual-stack`: every host name becomes two rows, pinned to IPv4 and to
+/// IPv6, so both paths to a dual-stack server are measured on their own;
+/// their labels get the same suffix. IP literals and already pinned names
+/// stay as they are.
+pub fn dual_stack(targets: Vec<Target>) -> Vec<Target> {
+    let mut rows: Vec<Target> = Vec::new();
+    for target in targets {
+        let pinned = match Entry::parse(&target.name) {
+            Ok(Entry::Host(host)) => [Family::V4, Family::V6]
+                .map(|family| Target {
+                    label: target
+                        .label
+                        .as_ref()
+                        .map(|l| format!("{l}{}", family.suffix())),
+                    ..Target::new(host.clone() + family.suffix())
+                })
+                .to_vec(),
+            _ => vec![target],
+        };
+        for row in pinned {
+            if !rows.iter().any(|r| r.name == row.name) {
+                rows.push(row);
+            }
+        }
+    }
+    rows
+}
+
+/// Copies the entries of host names in `map` (groups) to their
+/// `dual_stack` rows, each derived with `copy`.
+pub fn dual_stack_keys<V>(
+    map: HashMap<String, V>,
+    copy: impl Fn(&V, Family) -> V,
+) -> HashMap<String, V> {
+    let mut rows = HashMap::new();
+    for (target, value) in map {
+        if let Ok(Entry::Host(host)) = Entry::parse(&target) {
+            for family in [Family::V4, Family::V6] {
+                rows.insert(host.clone() + family.suffix(), copy(&value, family));
+            }
+        }
+        rows.insert(target, value);
+    }
+    rows
+}
+
+impl Entry {
+    /// Parses and normalizes one entry: IPv4 without zero padding, IPv6 in
+    /// its canonical form, host names lowercased without a trailing dot.
+    pub fn parse(input: &str) -> Result<Entry, String> {
+        if let Some((scheme, rest)) = input.split_once("://") {
+            if scheme.is_empty() || !scheme.chars().all(|c| c.is_ascii_alphanumeric()) {
+                return Err("invalid URL scheme".to_string());
+            }
+            let authority = rest.split(['/', '?', '#']).next().unwrap_or_default();
+            let host = authority.rsplit('@').next().unwrap_or_default();
+            let host = match host.strip_prefix('[') {
+                Some(v6) => v6.split(']').next().unwrap_or_default(),
+                None => host.split(':').next().unwrap_or_default(),
+            };
+            return match Entry::parse(host)? {
+                Entry::Url(_) => Err("nested URL".to_string()),
+                target => Ok(Entry::Url(Box::new(target))),
+            };
+        }
+        if let (host, Some(family)) = split_family(input) {
+            return match Entry::parse(host)? {
+                Entry::Host(host) => Ok(Entry::Pinned(host, family)),
+                _ => Err("@v4/@v6 only applies to host names".to_string()),
+            };
+        }
+        let input = input
+            .strip_prefix('[')
+            .and_then(|s| s.strip_suffix(']'))
+            .unwrap_or(input);
+        if input.contains(':') {
+            let (addr, zone) = match input.split_once('%') {
+                Some((addr, zone)) if !zone.is_empty() => (addr, Some(zone.to_string())),
+                Some(_) => return Err("empty IPv6 zone".to_string()),
+                None => (input, None),
+            };
+            return addr
+                .parse()
+                .map(|addr| Entry::V6(addr, zone))
+                .map_err(|_| "invalid IPv6 address".to_string());
+        }
+        let host = input
+            .strip_suffix('.')
+            .unwrap_or(input)
+            .to_ascii_lowercase();
+        let labels: Vec<&str> = host.split('.').collect();
+        // All-numeric names are never host names, so they have to be IPv4.
+        if labels.iter().all(|l| l.chars().all(|c| c.is_ascii_digit())) {
+            let octets: Vec<u8> = labels.iter().filter_map(|l| l.parse().ok()).collect();
+            return match octets[..] {
+                [a, b, c, d] if labels.iter().all(|l| !l.is_empty() && l.len() <= 3) => {
+                    Ok(Entry::V4(Ipv4Addr::new(a, b, c, d)))
+                }
+                _ => Err("invalid IPv4 address".to_string()),
+            };
+        }
+        let valid_label = |l: &&str| {
+            (1..=63).contains(&l.len())
+                && !l.starts_with('-')
+                && !l.ends_with('-')
+                && l.chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        };
+        if host.len() > 253 || !labels.iter().all(valid_label) {
+            return Err("not an IP address, host name or URL".to_string());
+        }
+        Ok(Entry::Host(host))
+    }
+
+    /// What gets pinged and shown.
+    pub fn name(&self) -> String {
+        match self {
+            Entry::V4(addr) => addr.to_string(),
+            Entry::V6(addr, None) => addr.to_string(),
+            Entry::V6(addr, Some(zone)) => format!("{addr}%{zone}"),
+            Entry::Host(host) => host.clone(),
+            Entry::Url(host) => host.name(),
+            Entry::Pinned(host, family) => format!("{host}{}", family.suffix()),
+        }
+    }
+}
+
+/// Targets of a list in file order, with the group each belongs to.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TargetList {
+    /// Labelled by `label="..."` or an inline comment.
+    pub targets: Vec<Target>,
+    pub groups: HashMap<String, Group>,
+    /// Files pulled in with `include`, watched for changes like the list.
+    pub includes: Vec<PathBuf>,
+    /// CIDR lines, expanded into their hosts in `targets`.
+    pub ranges: Vec<String>,
+}
+
+/// Blank lines start a new group; a comment at its start names it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Group {
+    pub index: usize,
+    pub name: Option<String>,
+}
+
+// Nesting limit for `include`, in case of a cycle through symlinks.
+const MAX_INCLUDE_DEPTH: usize = 8;
+
+/// Default `--max-targets`: a /22 worth of hosts, far more than a list
+/// written by hand, so only a mistyped prefix hits it.
+pub const DEFAULT_MAX_TARGETS: usize = 1024;
+
+/// Hosts of a CIDR range like `192.168.1.0/28`, refused if more than
+/// `limit`: every address except the network and broadcast addresses (IPv4
+/// up to /30) or the subnet-router anycast address (IPv6 up to /126).
+pub fn expand_range(range: &str, limit: usize) -> Result<Vec<String>, String> {
+    let (addr, prefix) = range.split_once('/').ok_or("not a CIDR range")?;
+    let addr: IpAddr = addr
+        .parse()
+        .map_err(|_| "invalid network address".to_string())?;
+    let (start, bits) = match addr {
+        IpAddr::V4(a) => (u128::from(u32::from(a)), 32),
+        IpAddr::V6(a) => (u128::from(a), 128),
+    };
+    let prefix: u32 = prefix
+        .parse()
+        .ok()
+        .filter(|p| *p <= bits)
+        .ok_or_else(|| format!("invalid prefix length (0 to {bits})"))?;
+    let host_bits = bits - prefix;
+    let host_mask = u128::MAX.checked_shr(128 - host_bits).unwrap_or(0);
+    if start & host_mask != 0 {
+        let network = match addr {
+            IpAddr::V4(_) => Ipv4Addr::from((start & !host_mask) as u32).to_string(),
+            IpAddr::V6(_) => Ipv6Addr::from(start & !host_mask).to_string(),
+        };
+        return Err(format!("host bits set; the network is {network}/{prefix}"));
+    }
+    let last = start + host_mask;
+    let (first, last) = match addr {
+        _ if host_bits < 2 => (start, last),
+        IpAddr::V4(_) => (start + 1, last - 1),
+        IpAddr::V6(_) => (start + 1, last),
+    };
+    let count = last - first + 1;
+    if count > limit as u128 {
+        return Err(format!(
+            "expands to {count} targets, more than the {limit} --max-targets leaves"
+        ));
+    }
+    Ok((first..=last)
+        .map(|n| match addr {
+            IpAddr::V4(_) => Ipv4Addr::from(n as u32).to_string(),
+            IpAddr::V6(_) => Ipv6Addr::from(n).to_string(),
+        })
+        .collect())
+}
+
+/// Splits off an inline comment. It needs whitespace before the `#`, so URL
+/// fragments stay intact, and may not start inside a quoted label.
+fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let mut quoted = false;
+    let mut prev = ' ';
+    for (pos, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            '#' if !quoted && prev.is_whitespace() && pos > 0 => {
+                let comment = line[pos + 1..].trim();
+                return (
+                    line[..pos].trim_end(),
+                    Some(comment).filter(|c| !c.is_empty()),
+                );
+            }
+            _ => {}
+        }
+        prev = c;
+    }
+    (line, None)
+}
+
+fn unquote(value: &str) -> &str {
+    value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+}
+
+struct ListParser {
+    list: TargetList,
+    invalid: Vec<String>,
+    max_targets: usize,
+    group: Group,
+    // Whether the current group has targets yet.
+    filled: bool,
+    stack: Vec<PathBuf>,
+}
+
+impl ListParser {
+    fn parse(&mut self, content: &str, origin: Option<&str>, dir: &Path) -> Result<(), String> {
+        for (idx, raw) in content.lines().enumerate() {
+            let at = match origin {
+                Some(file) => format!("{file} line {}", idx + 1),
+                None => format!("line {}", idx + 1),
+            };
+            let trimmed = raw.trim();
+            if let Some(comment) = trimmed.strip_prefix('#') {
+                if !self.filled && self.group.name.is_none() {
+                    self.group.name = Some(comment.trim().to_string()).filter(|c| !c.is_empty());
+                }
+                continue;
+            }
+            let (line, comment) = split_comment(trimmed);
+            let (line, label) = match line
+                .split_once(" label=")
+                .or_else(|| line.split_once("\tlabel="))
+            {
+                Some((target, label)) => (target.trim_end(), Some(unquote(label.trim()))),
+                None => (line, comment),
+            };
+            if line.is_empty() {
+                if self.filled {
+                    self.group = Group {
+                        index: self.group.index + 1,
+                        name: None,
+                    };
+                    self.filled = false;
+                }
+                continue;
+            }
+            if let Some(file) = line.strip_prefix("include ") {
+                self.include(&dir.join(file.trim()), &at)?;
+                continue;
+            }
+            let names = if line.contains('/') && !line.contains("://") {
+                let left = self.max_targets.saturating_sub(self.list.targets.len());
+                let hosts = expand_range(line, left);
+                if hosts.is_ok() {
+                    self.list.ranges.push(line.to_string());
+                }
+                hosts
+            } else {
+                Entry::parse(line).map(|target| vec![target.name()])
+            };
+            match names {
+                Ok(names) => {
+                    // A label names one target, not every host of a range.
+                    let label = label
+                        .filter(|l| names.len() == 1 && !l.is_empty())
+                        .map(str::to_string);
+                    for name in names {
+                        self.list
+                            .groups
+                            .entry(name.clone())
+                            .or_insert(self.group.clone());
+                        self.list.targets.push(Target {
+                            label: label.clone(),
+                            ..Target::new(name)
+                        });
+                    }
+                    self.filled = true;
+                }
+                Err(err) => self.invalid.push(format!("{at}: '{line}': {err}")),
+            }
+        }
+        Ok(())
+    }
+
+    fn include(&mut self, path: &Path, at: &str) -> Result<(), String> {
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| format!("{at}: failed to include {}: {e}", path.display()))?;
+        if self.stack.contains(&canonical) || self.stack.len() >= MAX_INCLUDE_DEPTH {
+            return Err(format!("{at}: {} includes itself", path.display()));
+        }
+        let content = fs::read_to_string(path)
+            .map_err(|e| format!("{at}: failed to include {}: {e}", path.display()))?;
+        self.list.includes.push(path.to_path_buf());
+        self.stack.push(canonical);
+        let dir = path.parent().unwrap_or(Path::new("."));
+        let result = self.parse(&content, Some(&path.display().to_string()), dir);
+        self.stack.pop();
+        result
+    }
+}
+
+/// One target or CIDR range per line; `#` starts a comment, `include
+/// <file>` pulls in another list (relative to `dir`). Invalid lines are an
+/// error listing all of them with line numbers, or are skipped with a
+/// warning if `skip_invalid`; so is a range beyond `max_targets`, while more
+/// single targets than that are always an error.
+pub fn parse_list(
+    content: &str,
+    dir: &Path,
+    skip_invalid: bool,
+    max_targets: usize,
+) -> Result<TargetList, String> {
+    let mut parser = ListParser {
+        list: TargetList::default(),
+        invalid: Vec::new(),
+        max_targets,
+        group: Group::default(),
+        filled: false,
+        stack: Vec::new(),
+    };
+    parser.parse(content, None, dir)?;
+    if parser.list.targets.len() > max_targets {
+        return Err(format!(
+            "{} targets, more than --max-targets {max_targets}",
+            parser.list.targets.len()
+        ));
+    }
+    if parser.invalid.is_empty() {
+        return Ok(parser.list);
+    }
+    if skip_invalid {
+        for line in parser.invalid {
+            warn!("skipping invalid target on {line}");
+        }
+        return Ok(parser.list);
+    }
+    Err(format!(
+        "invalid targets (use --skip-invalid to ignore them):\n  {}",
+        parser.invalid.join("\n  ")
+    ))
+}
+
+/// Drops repeated targets (keeping the first spelling) and reports them, as
+/// well as different names that resolve to the same address. Rows and stats
+/// are keyed by target, so a repeated entry would race for the same row.
+pub fn dedupe(
+    targets: Vec<Target>,
+    resolve: impl Fn(&str) -> Vec<IpAddr>,
+) -> (Vec<Target>, Vec<String>) {
+    let mut unique: Vec<Target> = Vec::new();
+    let mut warnings = Vec::new();
+    let mut by_addr: HashMap<IpAddr, String> = HashMap::new();
+    for target in targets {
+        let name = &target.name;
+        if let Some(first) = unique.iter().find(|t| t.name.eq_ignore_ascii_case(name)) {
+            warnings.push(format!(
+                "duplicate target {name} merged with {}",
+                first.name
+            ));
+            continue;
+        }
+        for addr in resolve(name) {
+            match by_addr.get(&addr) {
+                Some(other) if other != name => warnings.push(format!(
+                    "{name} and {other} both resolve to {addr}; both are probed"
+                )),
+                Some(_) => {}
+                None => {
+                    by_addr.insert(addr, name.clone());
+                }
+            }
+        }
+        unique.push(target);
+    }
+    (unique, warnings)
+}
+
+/// Addresses of an IP literal or host name, only those of its family if
+/// pinned; empty if it does not resolve.
+pub fn resolve(target: &str) -> Vec<IpAddr> {
+    if let Ok(ip) = target.parse() {
+        return vec![ip];
+    }
+    let (host, family) = split_family(target);
+    let mut addrs: Vec<IpAddr> = (host, 0)
+        .to_socket_addrs()
+        .map(|addrs| {
+            addrs
+                .map(|a| a.ip())
+                .filter(|ip| family.is_none_or(|f| Family::of(*ip) == f))
+                .collect()
+        })
+        .unwrap_or_default();
+    addrs.dedup();
+    addrs
+}
+
+// Parses a list read from a file or stdin and warns about duplicates.
+fn checked_list(
+    content: &str,
+    dir: &Path,
+    skip_invalid: bool,
+    max_targets: usize,
+) -> Result<TargetList, String> {
+    let mut list = parse_list(content, dir, skip_invalid, max_targets)?;
+    let (targets, warnings) = dedupe(list.targets, resolve);
+    for warning in warnings {
+        warn!("{warning}");
+    }
+    list.targets = targets;
+    Ok(list)
+}
+
+/// Targets that appear in `new` but not `current`, and the other way round,
+/// by name.
+pub fn diff(current: &[Target], new: &[Target]) -> (Vec<Target>, Vec<Target>) {
+    let listed = |list: &[Target], target: &Target| list.iter().any(|t| t.name == target.name);
+    let added = new
+        .iter()
+        .filter(|t| !listed(current, t))
+        .cloned()
+        .collect();
+    let removed = current
+        .iter()
+        .filter(|t| !listed(new, t))
+        .cloned()
+        .collect();
+    (added, removed)
+}
+
+pub struct FileSource {
+    path: PathBuf,
+    refresh: Option<Duration>,
+    skip_invalid: bool,
+    max_targets: usize,
+    // Modification times of the list and its includes at the last read.
+    modified: Vec<Option<SystemTime>>,
+    cached: TargetList,
+}
+
+impl FileSource {
+    pub fn new(
+        path: PathBuf,
+        refresh: Option<Duration>,
+        skip_invalid: bool,
+        max_targets: usize,
+    ) -> FileSource {
+        FileSource {
+            path,
+            refresh,
+            skip_invalid,
+            max_targets,
+            modified: Vec::new(),
+            cached: TargetList::default(),
+        }
+    }
+
+    fn modification_times(&self) -> Vec<Option<SystemTime>> {
+        std::iter::once(&self.path)
+            .chain(&self.cached.includes)
+            .map(|p| fs::metadata(p).and_then(|m| m.modified()).ok())
+            .collect()
+    }
+}
+
+impl TargetSource for FileSource {
+    fn describe(&self) -> String {
+        self.path.display().to_string()
+    }
+
+    fn load(&mut self) -> Result<Vec<Target>, String> {
+        let err = |e: io::Error| format!("failed to read {}: {e}", self.path.display());
+        fs::metadata(&self.path).map_err(err)?;
+        // Only re-read when the file or one of its includes changed since the
+        // last load.
+        if self.modified.is_empty() || self.modified != self.modification_times() {
+            let content = fs::read_to_string(&self.path).map_err(err)?;
+            let dir = self.path.parent().unwrap_or(Path::new("."));
+            self.cached = checked_list(&content, dir, self.skip_invalid, self.max_targets)
+                .map_err(|e| format!("{}: {e}", self.path.display()))?;
+            self.modified = self.modification_times();
+        }
+        Ok(self.cached.targets.clone())
+    }
+
+    fn refresh_interval(&self) -> Option<Duration> {
+        self.refresh
+    }
+
+    fn groups(&self) -> HashMap<String, Group> {
+        self.cached.groups.clone()
+    }
+
+    /// Writes one target per line, keeping the groups, their names and the
+    /// labels (as `label="..."`).
+    /// Lists with includes are left alone; the shared files are not ours.
+    /// So are those with ranges, which would be replaced by their hosts.
+    fn save(&mut self, targets: &[Target]) -> Result<(), String> {
+        if !self.cached.includes.is_empty() {
+            return Err(format!(
+                "{} uses include; edit it by hand",
+                self.path.display()
+            ));
+        }
+        if let Some(range) = self.cached.ranges.first() {
+            return Err(format!(
+                "{} lists the range {range}; edit it by hand",
+                self.path.display()
+            ));
+        }
+        let mut content = String::new();
+        let mut prev: Option<Option<&Group>> = None;
+        for target in targets {
+            let group = self.cached.groups.get(&target.name);
+            if prev != Some(group) {
+                if prev.is_some() {
+                    content.push('\n');
+                }
+                if let Some(name) = group.and_then(|g| g.name.as_ref()) {
+                    content.push_str(&format!("# {name}\n"));
+                }
+            }
+            content.push_str(&target.name);
+            if let Some(label) = &target.label {
+                content.push_str(&format!(" label=\"{label}\""));
+            }
+            content.push('\n');
+            prev = Some(group);
+        }
+        fs::write(&self.path, content)
+            .map_err(|e| format!("failed to write {}: {e}", self.path.display()))?;
+        // Read it back right away so the next refresh sees no change.
+        self.modified.clear();
+        self.load().map(|_| ())
+    }
+}
+
+/// Reads the list from stdin once (until EOF), e.g. `generate-ips | ping-plotter -i -`.
+/// Includes are relative to the working directory.
+pub struct StdinSource {
+    skip_invalid: bool,
+    max_targets: usize,
+    cached: Option<TargetList>,
+}
+
+impl StdinSource {
+    pub fn new(skip_invalid: bool, max_targets: usize) -> StdinSource {
+        StdinSource {
+            skip_invalid,
+            max_targets,
+            cached: None,
+        }
+    }
+}
+
+impl TargetSource for StdinSource {
+    fn describe(&self) -> String {
+        "stdin".to_string()
+    }
+
+    fn load(&mut self) -> Result<Vec<Target>, String> {
+        if self.cached.is_none() {
+            let mut content = String::new();
+            io::stdin()
+                .read_to_string(&mut content)
+                .map_err(|e| format!("failed to read targets from stdin: {e}"))?;
+            let dir = Path::new(".");
+            self.cached = Some(checked_list(
+                &content,
+                dir,
+                self.skip_invalid,
+                self.max_targets,
+            )?);
+        }
+        Ok(self
+            .cached
+            .as_ref()
+            .map(|l| l.targets.clone())
+            .unwrap_or_default())
+    }
+
+    fn groups(&self) -> HashMap<String, Group> {
+        self.cached
+            .as_ref()
+            .map(|l| l.groups.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// A fixed list, e.g. the virtual targets of a simulation profile.
+pub struct StaticSource {
+    pub origin: String,
+    pub targets: Vec<Target>,
+}
+
+impl TargetSource for StaticSource {
+    fn describe(&self) -> String {
+        self.origin.clone()
+    }
+
+    fn load(&mut self) -> Result<Vec<Target>, String> {
+        Ok(self.targets.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn list(items: &[&str]) -> Vec<String> {
+        items.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn rows(items: &[&str]) -> Vec<Target> {
+        items.iter().map(|s| Target::new(*s)).collect()
+    }
+
+    fn names(targets: &[Target]) -> Vec<String> {
+        targets.iter().map(|t| t.name.clone()).collect()
+    }
+
+    #[test]
+    fn diffs_target_lists() {
+        let (added, removed) = diff(&rows(&["a", "b", "c"]), &rows(&["c", "a", "d"]));
+        assert_eq!(added, rows(&["d"]));
+        assert_eq!(removed, rows(&["b"]));
+        let parsed =
+            parse_list(" a \n\n b\r\n", Path::new("."), false, DEFAULT_MAX_TARGETS).unwrap();
+        assert_eq!(names(&parsed.targets), list(&["a", "b"]));
+    }
+
+    #[test]
+    fn normalizes_targets() {
+        let samples = [
+            ("010.001.000.009", Some("10.1.0.9")),
+            ("FE80:0:0::0001%eth0", Some("fe80::1%eth0")),
+            ("[2001:DB8::1]", Some("2001:db8::1")),
+            ("Router.LAN.", Some("router.lan")),
+            ("https://user@Example.com:8443/health", Some("example.com")),
+            ("http://[::1]:80/", Some("::1")),
+            ("1.2.3", None),
+            ("256.1.1.1", None),
+            ("not a host", None),
+            ("-bad.example", None),
+            ("://example.com", None),
+            ("DNS.Google.@v6", Some("dns.google@v6")),
+            ("example.com@v5", None),
+            ("8.8.8.8@v4", None),
+        ];
+        for (input, expected) in samples {
+            let got = Entry::parse(input).ok().map(|t| t.name());
+            assert_eq!(got.as_deref(), expected, "failed on input {input}");
+        }
+        assert_eq!(
+            Entry::parse("8.8.8.8"),
+            Ok(Entry::V4(Ipv4Addr::new(8, 8, 8, 8)))
+        );
+    }
+
+    #[test]
+    fn pins_host_names_to_an_address_family() {
+        let mut targets = rows(&["1.1.1.1", "dns.google", "one.one.one.one@v6", "::1"]);
+        targets[1].label = Some("Google".to_string());
+        let pinned = dual_stack(targets);
+        assert_eq!(
+            names(&pinned),
+            list(&[
+                "1.1.1.1",
+                "dns.google@v4",
+                "dns.google@v6",
+                "one.one.one.one@v6",
+                "::1"
+            ])
+        );
+        assert_eq!(
+            family_of("fe80::1%eth0", Some(Family::V4)),
+            Some(Family::V6)
+        );
+        assert_eq!(
+            family_of("dns.google@v4", Some(Family::V6)),
+            Some(Family::V4)
+        );
+        assert_eq!(family_of("dns.google", Some(Family::V6)), Some(Family::V6));
+        assert_eq!(family_of("dns.google", None), None);
+        assert_eq!(pinned[2].label.as_deref(), Some("Google@v6"));
+        let groups = HashMap::from([("dns.google".to_string(), 3)]);
+        let groups = dual_stack_keys(groups, |group, _| *group);
+        assert_eq!(groups["dns.google@v6"], 3);
+        assert_eq!(groups["dns.google"], 3);
+    }
+
+    #[test]
+    fn reports_invalid_lines_with_numbers() {
+        let content = "1.1.1.1\n\nfoo bar\n999.0.0.1\n";
+        let err = parse_list(content, Path::new("."), false, DEFAULT_MAX_TARGETS).unwrap_err();
+        assert!(err.contains("line 3: 'foo bar'"), "{err}");
+        assert!(err.contains("line 4: '999.0.0.1'"), "{err}");
+        let parsed = parse_list(content, Path::new("."), true, DEFAULT_MAX_TARGETS).unwrap();
+        assert_eq!(names(&parsed.targets), list(&["1.1.1.1"]));
+    }
+
+    #[test]
+    fn expands_cidr_ranges_within_the_limit() {
+        let content = "# Lab\n10.0.0.0/30\n10.0.0.8/31\n2001:db8::/127\n192.0.2.7/32\n";
+        let parsed = parse_list(content, Path::new("."), false, DEFAULT_MAX_TARGETS).unwrap();
+        assert_eq!(
+            names(&parsed.targets),
+            list(&[
+                "10.0.0.1",
+                "10.0.0.2",
+                "10.0.0.8",
+                "10.0.0.9",
+                "2001:db8::",
+                "2001:db8::1",
+                "192.0.2.7"
+            ])
+        );
+        assert_eq!(parsed.groups["10.0.0.9"].name.as_deref(), Some("Lab"));
+        assert_eq!(parsed.ranges.len(), 4);
+        assert_eq!(expand_range("192.168.1.0/28", 14).unwrap().len(), 14);
+        assert_eq!(
+            expand_range("192.168.1.5/28", 100).unwrap_err(),
+            "host bits set; the network is 192.168.1.0/28"
+        );
+
+        let err = parse_list("10.0.0.1\n10.0.0.0/24\n", Path::new("."), false, 254).unwrap_err();
+        assert!(
+            err.contains("line 2: '10.0.0.0/24': expands to 254 targets, more than the 253"),
+            "{err}"
+        );
+        assert!(parse_list("::/0\n", Path::new("."), false, 10).is_err());
+        let err = parse_list("10.0.0.1\n10.0.0.2\n", Path::new("."), true, 1).unwrap_err();
+        assert_eq!(err, "2 targets, more than --max-targets 1");
+    }
+
+    #[test]
+    fn merges_duplicates_and_reports_shared_addresses() {
+        let resolve = |t: &str| match t {
+            "dns.example" | "1.1.1.1" => vec!["1.1.1.1".parse().unwrap()],
+            _ => Vec::new(),
+        };
+        let input = rows(&["1.1.1.1", "Router", "dns.example", "router", "1.1.1.1"]);
+        let (targets, warnings) = dedupe(input, resolve);
+        assert_eq!(names(&targets), list(&["1.1.1.1", "Router", "dns.example"]));
+        assert_eq!(
+            warnings,
+            list(&[
+                "dns.example and 1.1.1.1 both resolve to 1.1.1.1; both are probed",
+                "duplicate target router merged with Router",
+                "duplicate target 1.1.1.1 merged with 1.1.1.1",
+            ])
+        );
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        let nanos = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_nanos();
+        std::env::temp_dir().join(format!(
+            "ping_plotter_{name}_{}_{nanos}",
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn groups_comments_and_includes() {
+        let dir = temp_path("include");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("site-b.txt"), "# Site B\n10.0.2.1\n").unwrap();
+        fs::write(dir.join("loop.txt"), "include loop.txt\n").unwrap();
+        let content = "# Core\n1.1.1.1  # cloudflare\n8.8.8.8 label=\"DNS #1\" # Google\n\n\n\
+                       # not a name\nhttp://example.com/#top\n\ninclude site-b.txt\n";
+        let parsed = parse_list(content, &dir, false, DEFAULT_MAX_TARGETS).unwrap();
+        assert_eq!(
+            names(&parsed.targets),
+            list(&["1.1.1.1", "8.8.8.8", "example.com", "10.0.2.1"])
+        );
+        let group = |t: &str| parsed.groups[t].clone();
+        assert_eq!(group("1.1.1.1"), group("8.8.8.8"));
+        assert_eq!(group("1.1.1.1").name.as_deref(), Some("Core"));
+        assert_eq!(group("example.com").name.as_deref(), Some("not a name"));
+        assert_eq!(group("10.0.2.1").name.as_deref(), Some("Site B"));
+        assert_eq!(group("10.0.2.1").index, 2);
+        assert_eq!(parsed.includes, vec![dir.join("site-b.txt")]);
+        let labels: Vec<Option<&str>> = parsed.targets.iter().map(|t| t.label.as_deref()).collect();
+        assert_eq!(labels, [Some("cloudflare"), Some("DNS #1"), None, None]);
+
+        let err = parse_list("include loop.txt\n", &dir, false, DEFAULT_MAX_TARGETS).unwrap_err();
+        assert!(err.contains("includes itself"), "{err}");
+        let err = parse_list("include missing.txt\n", &dir, true, DEFAULT_MAX_TARGETS).unwrap_err();
+        assert!(err.starts_with("line 1: failed to include"), "{err}");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn save_keeps_groups() {
+        let path = temp_path("save");
+        fs::write(&path, "# Core\n1.1.1.1 # Cloudflare\n\n# Edge\n10.0.0.1\n").unwrap();
+        let mut source = FileSource::new(path.clone(), None, false, DEFAULT_MAX_TARGETS);
+        let mut targets = source.load().unwrap();
+        targets.push(Target::new("9.9.9.9"));
+        source.save(&targets).unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "# Core\n1.1.1.1 label=\"Cloudflare\"\n\n# Edge\n10.0.0.1\n\n9.9.9.9\n"
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn file_source_picks_up_changes() {
+        let path = temp_path("targets");
+        fs::write(&path, "1.1.1.1\n").unwrap();
+        let mut source = FileSource::new(
+            path.clone(),
+            Some(Duration::from_secs(10)),
+            false,
+            DEFAULT_MAX_TARGETS,
+        );
+        assert_eq!(names(&source.load().unwrap()), list(&["1.1.1.1"]));
+
+        fs::write(&path, "1.1.1.1\n8.8.8.8\n").unwrap();
+        // Make sure the modification time differs even on coarse filesystems.
+        let later = SystemTime::now() + Duration::from_secs(5);
+        fs::File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_modified(later)
+            .unwrap();
+        assert_eq!(
+            names(&source.load().unwrap()),
+            list(&["1.1.1.1", "8.8.8.8"])
+        );
+
+        source.save(&rows(&["9.9.9.9"])).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "9.9.9.9\n");
+        assert_eq!(names(&source.load().unwrap()), list(&["9.9.9.9"]));
+
+        fs::remove_file(&path).unwrap();
+        assert!(source.load().is_err());
+    }
+}
@@ -0,0 +1,92 @@
+/// Minimal `{{variable}}` template used for user-defined log messages.
+#[derive(Debug, Clone)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone)]
+enum Part {
+    Text(String),
+    Var(String),
+}
+
+impl Template {
+    pub fn parse(input: &str) -> Result<Template, String> {
+        let mut parts = Vec::new();
+        let mut rest = input;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let after = &rest[start + 2..];
+            let end = after
+                .find("}}")
+                .ok_or_else(|| format!("unclosed '{{{{' in template: {input}"))?;
+            let name = after[..end].trim();
+            if name.is_empty() {
+                return Err(format!("empty variable in template: {input}"));
+            }
+            parts.push(Part::Var(name.to_string()));
+            rest = &after[end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Template { parts })
+    }
+
+    /// Fails on the first variable not in `allowed`, so typos surface at startup.
+    pub fn check(&self, allowed: &[&str]) -> Result<(), String> {
+        for part in &self.parts {
+            if let Part::Var(name) = part
+                && !allowed.contains(&name.as_str())
+            {
+                return Err(format!(
+                    "unknown template variable '{name}' (available: {})",
+                    allowed.join(", ")
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, vars: &[(&str, String)]) -> String {
+        let mut out = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Var(name) => {
+                    if let Some((_, value)) = vars.iter().find(|(k, _)| k == name) {
+                        out.push_str(value);
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_variables() {
+        let t = Template::parse("{{time}} DOWN: {{ targets }} ({{count}})").unwrap();
+        let out = t.render(&[
+            ("time", "12:00".to_string()),
+            ("targets", "a, b".to_string()),
+            ("count", "2".to_string()),
+        ]);
+        assert_eq!(out, "12:00 DOWN: a, b (2)");
+    }
+
+    #[test]
+    fn reports_syntax_and_unknown_variables() {
+        assert!(Template::parse("oops {{time").is_err());
+        assert!(Template::parse("{{}}").is_err());
+        let t = Template::parse("{{tiem}}").unwrap();
+        assert!(t.check(&["time"]).is_err());
+        assert!(t.check(&["tiem"]).is_ok());
+    }
+}
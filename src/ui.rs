@@ -0,0 +1,1112 @@
+use std::{
+    cell::{Cell, RefCell},
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, IsTerminal, Stdout, Write},
+    panic,
+    path::PathBuf,
+    thread,
+    time::Duration,
+};
+
+use chrono::Local;
+use clap::ValueEnum;
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{
+        self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+        KeyModifiers, MouseButton, MouseEventKind,
+    },
+    execute, queue,
+    style::{StyledContent, Stylize},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::{Backend, CrosstermBackend, TestBackend},
+    layout::{Constraint, Direction, Layout},
+    style::{self as tui_style, Color, Modifier},
+    text::{Line as TuiLine, Text},
+    widgets::Paragraph,
+};
+
+use crate::{Session, Target, graph, locale, severity, targets::Group, windows};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Style {
+    Plain,
+    Warning,
+    Critical,
+    Acked,
+}
+
+pub struct Line {
+    pub text: String,
+    pub style: Style,
+    pub selected: bool,
+}
+
+impl Line {
+    pub fn plain(text: String) -> Line {
+        Line {
+            text,
+            style: Style::Plain,
+            selected: false,
+        }
+    }
+}
+
+impl Style {
+    /// The color of a row or graph of this severity.
+    pub fn of(severity: severity::Severity) -> Style {
+        match severity {
+            severity::Severity::Info => Style::Plain,
+            severity::Severity::Warning => Style::Warning,
+            severity::Severity::Critical => Style::Critical,
+        }
+    }
+
+    fn apply(self, text: &str) -> StyledContent<&str> {
+        match self {
+            Style::Plain => text.stylize(),
+            Style::Warning => text.yellow(),
+            Style::Critical => text.red(),
+            Style::Acked => text.dark_grey(),
+        }
+    }
+
+    fn tui(self) -> tui_style::Style {
+        let style = tui_style::Style::default();
+        match self {
+            Style::Plain => style,
+            Style::Warning => style.fg(Color::Yellow),
+            Style::Critical => style.fg(Color::Red),
+            Style::Acked => style.fg(Color::DarkGray),
+        }
+    }
+
+    // CSS color for HTML snapshots, matching the terminal colors.
+    fn css(self) -> Option<&'static str> {
+        match self {
+            Style::Plain => None,
+            Style::Warning => Some("#b58900"),
+            Style::Critical => Some("#dc322f"),
+            Style::Acked => Some("#808080"),
+        }
+    }
+}
+
+fn styled(line: &Line) -> StyledContent<&str> {
+    let styled = line.style.apply(&line.text);
+    if line.selected {
+        styled.reverse()
+    } else {
+        styled
+    }
+}
+
+/// File format of a snapshot of the live view (`s` key).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SnapshotFormat {
+    Html,
+    Ansi,
+}
+
+impl SnapshotFormat {
+    /// Writes the lines to `ping-plotter-<time>.<ext>` in the working directory.
+    pub fn write(self, lines: &[Line]) -> io::Result<PathBuf> {
+        let now = Local::now();
+        let path = PathBuf::from(format!(
+            "ping-plotter-{}.{}",
+            now.format("%Y%m%d-%H%M%S"),
+            self.extension()
+        ));
+        let title = format!("ping-plotter {}", now.format("%Y-%m-%d %H:%M:%S"));
+        fs::write(&path, self.render(lines, &title))?;
+        Ok(path)
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            SnapshotFormat::Html => "html",
+            SnapshotFormat::Ansi => "ans",
+        }
+    }
+
+    /// Renders the lines exactly as `draw` lays them out, colors included.
+    pub fn render(self, lines: &[Line], title: &str) -> String {
+        match self {
+            SnapshotFormat::Ansi => lines.iter().map(|l| format!("{}\n", styled(l))).collect(),
+            SnapshotFormat::Html => {
+                let mut html = format!(
+                    "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\">\
+                     <title>{}</title></head>\n<body><pre style=\"font-family: monospace\">\n",
+                    escape_html(title)
+                );
+                for line in lines {
+                    let text = escape_html(&line.text);
+                    let mut css = line
+                        .style
+                        .css()
+                        .map(|c| format!("color: {c};"))
+                        .unwrap_or_default();
+                    if line.selected {
+                        css.push_str(" background: #ddd;");
+                    }
+                    if css.is_empty() {
+                        html.push_str(&format!("{text}\n"));
+                    } else {
+                        html.push_str(&format!("<span style=\"{}\">{text}</span>\n", css.trim()));
+                    }
+                }
+                html.push_str("</pre></body></html>\n");
+                html
+            }
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+/// Input of the live view. The mouse wheel arrives as `↑`/`↓` key presses.
+pub enum Input {
+    Key(KeyEvent),
+    /// Left click at a zero-based screen position.
+    Click {
+        column: u16,
+        row: u16,
+    },
+}
+
+/// Where the ratatui screen is drawn.
+enum Screen {
+    Tty(ratatui::Terminal<CrosstermBackend<Stdout>>),
+    Offscreen(ratatui::Terminal<TestBackend>),
+}
+
+/// Owns the terminal for the live view. Keyboard input (raw mode) and the
+/// ratatui screen are only used when both stdin and stdout are terminals;
+/// otherwise the view degrades to plain redraws.
+pub struct Terminal {
+    interactive: bool,
+    color: bool,
+    tui: Option<RefCell<Screen>>,
+    /// First line shown; lines beyond the screen are scrolled to with the
+    /// selection or Page Up/Down.
+    scroll: Cell<usize>,
+    /// Where the selection was at the last draw, to follow it only when it moves.
+    last_selected: Cell<Option<usize>>,
+    /// Rows of the content area at the last draw, the page size.
+    height: Cell<usize>,
+    status: RefCell<String>,
+}
+
+impl Terminal {
+    pub fn start() -> Terminal {
+        let tty = io::stdin().is_terminal() && io::stdout().is_terminal();
+        let interactive = tty && terminal::enable_raw_mode().is_ok();
+        let mut tui = None;
+        if interactive {
+            let _ = execute!(io::stdout(), EnterAlternateScreen, Hide, EnableMouseCapture);
+            // The panic message would be garbled in raw mode, so restore the
+            // terminal before printing it.
+            let previous = panic::take_hook();
+            panic::set_hook(Box::new(move |info| {
+                let _ = execute!(
+                    io::stdout(),
+                    LeaveAlternateScreen,
+                    Show,
+                    DisableMouseCapture
+                );
+                let _ = terminal::disable_raw_mode();
+                previous(info);
+            }));
+            tui = ratatui::Terminal::new(CrosstermBackend::new(io::stdout()))
+                .ok()
+                .map(|t| RefCell::new(Screen::Tty(t)));
+        }
+        Terminal {
+            interactive,
+            color: io::stdout().is_terminal(),
+            tui,
+            scroll: Cell::new(0),
+            last_selected: Cell::new(None),
+            height: Cell::new(0),
+            status: RefCell::new(String::new()),
+        }
+    }
+
+    /// A view drawn into a `width`x`height` ratatui `TestBackend` instead of
+    /// stdout, without input; for running the live view in tests.
+    pub fn offscreen(width: u16, height: u16) -> Terminal {
+        let tui = ratatui::Terminal::new(TestBackend::new(width, height)).ok();
+        Terminal {
+            interactive: false,
+            color: false,
+            tui: tui.map(|t| RefCell::new(Screen::Offscreen(t))),
+            scroll: Cell::new(0),
+            last_selected: Cell::new(None),
+            height: Cell::new(0),
+            status: RefCell::new(String::new()),
+        }
+    }
+
+    /// The text of the last offscreen draw, one string per screen row.
+    pub fn offscreen_rows(&self) -> Vec<String> {
+        let Some(tui) = &self.tui else {
+            return Vec::new();
+        };
+        match &*tui.borrow() {
+            Screen::Offscreen(t) => {
+                let buffer = t.backend().buffer();
+                let width = usize::from(buffer.area.width).max(1);
+                let symbols: Vec<&str> = buffer.content.iter().map(|c| c.symbol()).collect();
+                symbols
+                    .chunks(width)
+                    .map(|row| row.concat().trim_end().to_string())
+                    .collect()
+            }
+            Screen::Tty(_) => Vec::new(),
+        }
+    }
+
+    /// Text of the status bar below the view, e.g. elapsed and remaining time.
+    pub fn set_status(&self, text: String) {
+        *self.status.borrow_mut() = text;
+    }
+
+    /// Scrolls the view by one page.
+    pub fn scroll_page(&self, down: bool) {
+        let page = self.height.get().max(1);
+        let scroll = self.scroll.get();
+        self.scroll.set(if down {
+            scroll + page
+        } else {
+            scroll.saturating_sub(page)
+        });
+    }
+
+    /// The index in the drawn lines of screen row `row`, for mouse clicks.
+    pub fn line_at(&self, row: u16) -> usize {
+        usize::from(row) + self.scroll.get()
+    }
+
+    pub fn interactive(&self) -> bool {
+        self.interactive
+    }
+
+    /// Waits up to `timeout` for a key press or click; just sleeps when not
+    /// interactive.
+    pub fn next_input(&self, timeout: Duration) -> Option<Input> {
+        if !self.interactive {
+            thread::sleep(timeout);
+            return None;
+        }
+        let wheel = |code| Some(Input::Key(KeyEvent::new(code, KeyModifiers::NONE)));
+        match event::poll(timeout) {
+            Ok(true) => match event::read() {
+                Ok(Event::Key(key)) if key.kind != KeyEventKind::Release => Some(Input::Key(key)),
+                Ok(Event::Mouse(mouse)) => match mouse.kind {
+                    MouseEventKind::Down(MouseButton::Left) => Some(Input::Click {
+                        column: mouse.column,
+                        row: mouse.row,
+                    }),
+                    MouseEventKind::ScrollUp => wheel(KeyCode::Up),
+                    MouseEventKind::ScrollDown => wheel(KeyCode::Down),
+                    _ => None,
+                },
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// Mouse capture keeps the terminal from selecting text, so it is
+    /// turned off while the display is frozen for copying.
+    pub fn capture_mouse(&self, on: bool) {
+        if self.interactive {
+            let _ = if on {
+                execute!(io::stdout(), EnableMouseCapture)
+            } else {
+                execute!(io::stdout(), DisableMouseCapture)
+            };
+        }
+    }
+
+    fn render<B: Backend>(&self, tui: &mut ratatui::Terminal<B>, lines: &[Line]) {
+        let status = self.status.borrow();
+        let _ = tui.draw(|frame| {
+            let areas = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(1)])
+                .split(frame.size());
+            let height = usize::from(areas[0].height);
+            let selected = lines.iter().position(|l| l.selected);
+            let scroll = follow(self.scroll.get(), height, lines.len(), selected)
+                .filter(|_| selected != self.last_selected.get())
+                .unwrap_or(self.scroll.get())
+                .min(lines.len().saturating_sub(height));
+            self.scroll.set(scroll);
+            self.last_selected.set(selected);
+            self.height.set(height);
+            let text: Text = lines
+                .iter()
+                .map(|l| {
+                    let style = l.style.tui();
+                    let style = if l.selected {
+                        style.add_modifier(Modifier::REVERSED)
+                    } else {
+                        style
+                    };
+                    TuiLine::styled(l.text.as_str(), style)
+                })
+                .collect::<Vec<_>>()
+                .into();
+            let view =
+                Paragraph::new(text).scroll((scroll.min(usize::from(u16::MAX)) as u16, 0));
+            frame.render_widget(view, areas[0]);
+            let bar = tui_style::Style::default().add_modifier(Modifier::REVERSED);
+            frame.render_widget(Paragraph::new(status.as_str()).style(bar), areas[1]);
+        });
+    }
+
+    pub fn draw(&self, lines: &[Line]) {
+        if let Some(tui) = &self.tui {
+            match &mut *tui.borrow_mut() {
+                Screen::Tty(t) => self.render(t, lines),
+                Screen::Offscreen(t) => self.render(t, lines),
+            }
+            return;
+        }
+        let mut out = io::stdout().lock();
+        let _ = queue!(out, Clear(ClearType::All), MoveTo(0, 0));
+        // Raw mode disables output post-processing, so lines need an explicit \r.
+        let eol = if self.interactive { "\r\n" } else { "\n" };
+        for line in lines {
+            if self.color {
+                let _ = write!(out, "{}{eol}", styled(line));
+            } else {
+                let _ = write!(out, "{}{eol}", line.text);
+            }
+        }
+        let status = self.status.borrow();
+        if !status.is_empty() {
+            let _ = write!(out, "{status}{eol}");
+        }
+        let _ = out.flush();
+    }
+}
+
+/// The first line to show so that `selected` is on screen, or `None` when it
+/// already is (or nothing is selected).
+fn follow(scroll: usize, height: usize, len: usize, selected: Option<usize>) -> Option<usize> {
+    let selected = selected?.min(len.saturating_sub(1));
+    if selected < scroll {
+        Some(selected)
+    } else if height > 0 && selected >= scroll + height {
+        Some(selected + 1 - height)
+    } else {
+        None
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        if self.interactive {
+            let _ = execute!(
+                io::stdout(),
+                LeaveAlternateScreen,
+                Show,
+                DisableMouseCapture
+            );
+            let _ = terminal::disable_raw_mode();
+        }
+    }
+}
+
+/// The views of the live display, switched with `1`–`4` and Tab.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum View {
+    Table,
+    Graphs,
+    Events,
+    Config,
+}
+
+impl View {
+    pub const ALL: [View; 4] = [View::Table, View::Graphs, View::Events, View::Config];
+
+    pub fn title(self) -> &'static str {
+        match self {
+            View::Table => "Tabelle",
+            View::Graphs => "Graphen",
+            View::Events => "Ereignisse",
+            View::Config => "Status",
+        }
+    }
+
+    pub fn next(self) -> View {
+        let idx = View::ALL.iter().position(|v| *v == self).unwrap_or(0);
+        View::ALL[(idx + 1) % View::ALL.len()]
+    }
+}
+
+/// Tab bar with the number key of each view, the current one in brackets.
+pub fn tab_bar(current: View) -> String {
+    View::ALL
+        .iter()
+        .enumerate()
+        .map(|(idx, view)| {
+            let label = format!("{} {}", idx + 1, view.title());
+            if *view == current {
+                format!("[{label}]")
+            } else {
+                format!(" {label} ")
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// `Laufzeit 0:01:05 · noch 0:03:55` below the live view.
+pub fn status_bar(elapsed: Duration, remaining: Option<Duration>) -> String {
+    let hms = |d: Duration| {
+        let secs = d.as_secs();
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    };
+    let remaining =
+        remaining.map_or_else(|| "ohne Ende".to_string(), |d| format!("noch {}", hms(d)));
+    format!("Laufzeit {} · {remaining}", hms(elapsed))
+}
+
+/// Table column a click on the header sorts by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortColumn {
+    Target,
+    Success,
+    Min,
+    Avg,
+    Max,
+    P50,
+    P95,
+    P99,
+    Jitter,
+    Stddev,
+    Missed,
+    NoRtt,
+    Losses,
+    Status,
+    Ewma,
+    Window(usize),
+    Recent(RecentValue, Duration),
+}
+
+/// Value of a `--window` column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecentValue {
+    Loss,
+    Avg,
+    Max,
+}
+
+impl SortColumn {
+    pub fn title(self) -> String {
+        match self {
+            SortColumn::Target => "IP".to_string(),
+            SortColumn::Success => "Erfolg/Gesamt".to_string(),
+            SortColumn::Min => "min (ms)".to_string(),
+            SortColumn::Avg => "avg (ms)".to_string(),
+            SortColumn::Max => "max (ms)".to_string(),
+            SortColumn::P50 => "p50".to_string(),
+            SortColumn::P95 => "p95".to_string(),
+            SortColumn::P99 => "p99".to_string(),
+            SortColumn::Jitter => "jitter".to_string(),
+            SortColumn::Stddev => "stddev".to_string(),
+            SortColumn::Missed => "missed".to_string(),
+            SortColumn::NoRtt => "ohne RTT".to_string(),
+            SortColumn::Losses => "Verlusten".to_string(),
+            SortColumn::Status => "Status".to_string(),
+            SortColumn::Ewma => "EWMA (ms)".to_string(),
+            SortColumn::Window(idx) => format!("Verl. {}", windows::WINDOWS[idx].0),
+            SortColumn::Recent(value, span) => {
+                let name = match value {
+                    RecentValue::Loss => "Verl.",
+                    RecentValue::Avg => "avg",
+                    RecentValue::Max => "max",
+                };
+                format!("{name} {}", windows::label(span))
+            }
+        }
+    }
+
+    /// The value `ip`'s row is sorted by; `None` sorts last.
+    fn value(self, session: &Session, ip: &str, now_ms: i64) -> Option<f64> {
+        let stat = session.shown().get(ip);
+        match self {
+            SortColumn::Target => None,
+            SortColumn::Success => stat.and_then(|s| s.loss_pct()).map(|l| -l),
+            SortColumn::Min => stat.and_then(|s| s.min_ms),
+            SortColumn::Avg => stat.and_then(|s| s.avg_ms()),
+            SortColumn::Max => stat.and_then(|s| s.max_ms),
+            SortColumn::P50 => stat.and_then(|s| s.p50_ms()),
+            SortColumn::P95 => stat.and_then(|s| s.p95_ms()),
+            SortColumn::P99 => stat.and_then(|s| s.p99_ms()),
+            SortColumn::Jitter => stat.and_then(|s| s.jitter_ms()),
+            SortColumn::Stddev => stat.and_then(|s| s.stddev_ms()),
+            SortColumn::Missed => stat.map(|s| s.missed as f64),
+            SortColumn::NoRtt => stat.map(|s| s.no_rtt() as f64),
+            SortColumn::Losses => stat.map(|s| (s.total - s.success) as f64),
+            SortColumn::Status => (session.health.get(ip))
+                .map(|h| f64::from(h.severity(&session.settings.rules, now_ms) as u8)),
+            SortColumn::Ewma => session.smoothed.get(ip).copied(),
+            SortColumn::Window(idx) => (session.loss_windows.get(ip))
+                .and_then(|w| w.loss_pct(windows::WINDOWS[idx].1, now_ms)),
+            SortColumn::Recent(value, _) => {
+                let summary = session.recent.get(ip).and_then(|w| w.summary(now_ms));
+                summary.and_then(|s| match value {
+                    RecentValue::Loss => Some(s.loss_pct),
+                    RecentValue::Avg => s.avg_ms,
+                    RecentValue::Max => s.max_ms,
+                })
+            }
+        }
+    }
+}
+
+/// The optional table columns: `--smooth`, `--loss-windows` and `--window`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Columns {
+    pub smooth: bool,
+    pub loss_windows: bool,
+    pub window: Option<Duration>,
+}
+
+impl Columns {
+    /// Every column with the width the header and rows are formatted with.
+    fn widths(self) -> Vec<(SortColumn, usize)> {
+        let mut columns = vec![
+            (SortColumn::Target, 20),
+            (SortColumn::Success, 16),
+            (SortColumn::Min, 10),
+            (SortColumn::Avg, 10),
+            (SortColumn::Max, 10),
+            (SortColumn::P50, 8),
+            (SortColumn::P95, 8),
+            (SortColumn::P99, 8),
+            (SortColumn::Jitter, 8),
+            (SortColumn::Stddev, 8),
+            (SortColumn::Missed, 8),
+            (SortColumn::NoRtt, 8),
+            (SortColumn::Losses, 15),
+            (SortColumn::Status, 9),
+        ];
+        if self.smooth {
+            columns.push((SortColumn::Ewma, 10));
+        }
+        if self.loss_windows {
+            columns.extend((0..windows::WINDOWS.len()).map(|idx| (SortColumn::Window(idx), 9)));
+        }
+        if let Some(span) = self.window {
+            for value in [RecentValue::Loss, RecentValue::Avg, RecentValue::Max] {
+                columns.push((SortColumn::Recent(value, span), 9));
+            }
+        }
+        columns
+    }
+
+    /// Column under screen column `x`; the gap after a column counts
+    /// towards it.
+    pub fn at(self, x: usize) -> Option<SortColumn> {
+        let mut start = 0;
+        for (column, width) in self.widths() {
+            start += width + 1;
+            if x < start {
+                return Some(column);
+            }
+        }
+        None
+    }
+
+    pub fn header(self) -> String {
+        self.widths()
+            .into_iter()
+            .map(|(column, width)| match column {
+                SortColumn::Target => format!("{:<width$}", column.title()),
+                SortColumn::Losses => format!("{:>width$}", "Verl. T/U/X/P/E"),
+                _ => format!("{:>width$}", column.title()),
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// `target`'s row; `state` is its Status cell.
+    fn row(
+        self,
+        session: &Session,
+        target: &Target,
+        state: &str,
+        numbers: locale::Numbers,
+        now_ms: i64,
+    ) -> String {
+        let ip = &target.name;
+        let stat = session.shown().get(ip).copied().unwrap_or_default();
+        let fmt = |v: Option<f64>| -> String {
+            v.map(|n| numbers.fixed(n, 2))
+                .unwrap_or_else(|| "-".to_string())
+        };
+        let mut line = format!(
+            "{:<20} {:>16} {:>10} {:>10} {:>10} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} {:>8} \
+             {:>15} {:>9}",
+            target.title(),
+            format!("{}/{}", stat.success, stat.total),
+            fmt(stat.min_ms),
+            fmt(stat.avg_ms()),
+            fmt(stat.max_ms),
+            fmt(stat.p50_ms()),
+            fmt(stat.p95_ms()),
+            fmt(stat.p99_ms()),
+            fmt(stat.jitter_ms()),
+            fmt(stat.stddev_ms()),
+            stat.missed,
+            stat.no_rtt(),
+            stat.losses.column(),
+            state,
+        );
+        if self.smooth {
+            line.push_str(&format!(" {:>10}", fmt(session.smoothed.get(ip).copied())));
+        }
+        if self.loss_windows {
+            let recent = session.loss_windows.get(ip);
+            for (_, window) in windows::WINDOWS {
+                let loss = recent
+                    .and_then(|w| w.loss_pct(window, now_ms))
+                    .map_or_else(|| "-".to_string(), |l| format!("{}%", numbers.fixed(l, 1)));
+                line.push_str(&format!(" {loss:>9}"));
+            }
+        }
+        if self.window.is_some() {
+            let summary = session.recent.get(ip).and_then(|w| w.summary(now_ms));
+            let loss = summary.map_or_else(
+                || "-".to_string(),
+                |s| format!("{}%", numbers.fixed(s.loss_pct, 1)),
+            );
+            line.push_str(&format!(" {loss:>9}"));
+            line.push_str(&format!(" {:>9}", fmt(summary.and_then(|s| s.avg_ms))));
+            line.push_str(&format!(" {:>9}", fmt(summary.and_then(|s| s.max_ms))));
+        }
+        line
+    }
+}
+
+/// Clicking a header sorts ascending, then descending, then back to list order.
+pub fn next_sort(
+    current: Option<(SortColumn, bool)>,
+    column: SortColumn,
+) -> Option<(SortColumn, bool)> {
+    match current {
+        Some((c, false)) if c == column => Some((column, true)),
+        Some((c, true)) if c == column => None,
+        _ => Some((column, false)),
+    }
+}
+
+/// Sorts rows by `value`, rows without a value last in both directions.
+pub fn sort_rows(
+    rows: &mut [&Target],
+    (column, descending): (SortColumn, bool),
+    value: impl Fn(&str) -> Option<f64>,
+) {
+    rows.sort_by(|a, b| {
+        let ord = match column {
+            SortColumn::Target => a.name.cmp(&b.name),
+            _ => match (value(&a.name), value(&b.name)) {
+                (Some(x), Some(y)) => x.total_cmp(&y),
+                (Some(_), None) => return Ordering::Less,
+                (None, Some(_)) => return Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            },
+        };
+        if descending { ord.reverse() } else { ord }
+    });
+}
+
+/// The table of the live view: the header, then a row per target with
+/// group headings, the disabled targets in their own section at the end.
+pub struct Table<'a> {
+    pub lines: Vec<Line>,
+    /// The targets in row order: the active ones (sorted when asked), then
+    /// the disabled ones.
+    pub order: Vec<&'a Target>,
+    /// The index in `lines` of each row of `order`, for clicks and the
+    /// selection.
+    pub rows: Vec<usize>,
+    /// The `Verlauf` sparkline of each row line, for the screen only.
+    pub sparklines: Vec<(usize, String)>,
+}
+
+impl<'a> Table<'a> {
+    /// The session's targets by `groups`, or by `sort` without the group
+    /// headings.
+    pub fn new(
+        session: &'a Session,
+        groups: &HashMap<String, Group>,
+        disabled: &HashSet<String>,
+        (sort, columns): (Option<(SortColumn, bool)>, Columns),
+        numbers: locale::Numbers,
+        now_ms: i64,
+    ) -> Table<'a> {
+        let ips = &session.targets;
+        let mut order: Vec<&Target> = ips.iter().filter(|t| !disabled.contains(&t.name)).collect();
+        if let Some(sort) = sort {
+            sort_rows(&mut order, sort, |ip| sort.0.value(session, ip, now_ms));
+        }
+        order.extend(ips.iter().filter(|t| disabled.contains(&t.name)));
+        let mut lines = vec![Line::plain(columns.header())];
+        let mut rows = Vec::new();
+        let mut sparklines = Vec::new();
+        for (row, target) in order.iter().copied().enumerate() {
+            let ip = &target.name;
+            let is_disabled = disabled.contains(ip);
+            let group = groups.get(ip);
+            // Sorting mixes the groups, so their headings are left out.
+            if !is_disabled
+                && sort.is_none()
+                && (row == 0 || groups.get(&order[row - 1].name) != group)
+            {
+                if row > 0 {
+                    lines.push(Line::plain(String::new()));
+                }
+                if let Some(name) = group.and_then(|g| g.name.as_ref()) {
+                    lines.push(Line::plain(format!("{name}:")));
+                }
+            }
+            if is_disabled && (row == 0 || !disabled.contains(&order[row - 1].name)) {
+                lines.push(Line::plain(String::new()));
+                lines.push(Line::plain(
+                    "Deaktiviert (werden nicht mehr gepingt):".to_string(),
+                ));
+            }
+            let sev = session.severities.get(ip).copied().unwrap_or_default();
+            let is_acked = session.acked.contains(ip);
+            let style = if is_acked || is_disabled {
+                Style::Acked
+            } else {
+                Style::of(sev)
+            };
+            let state = match (is_disabled, is_acked) {
+                (true, _) => "disabled",
+                (false, true) => "ACK",
+                (false, false) => sev.label(),
+            };
+            if let Some(history) = session.histories.get(ip) {
+                sparklines.push((lines.len(), history.recent_sparkline(graph::TABLE_WIDTH)));
+            }
+            rows.push(lines.len());
+            lines.push(Line {
+                text: columns.row(session, target, state, numbers, now_ms),
+                style,
+                selected: false,
+            });
+        }
+        Table {
+            lines,
+            order,
+            rows,
+            sparklines,
+        }
+    }
+}
+
+// Events shown at once in the events view.
+pub const EVENTS_PAGE: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PromptKind {
+    Target,
+    Note,
+}
+
+pub enum PromptAction {
+    Edit,
+    Submit,
+    Cancel,
+}
+
+pub enum KeyAction {
+    Redraw,
+    ToggleAck,
+    Enable,
+    Add,
+    Delete,
+    Save,
+    Snapshot,
+    Note,
+    NextView,
+    ShowView(View),
+    Detail,
+    Freeze,
+    PageUp,
+    PageDown,
+    Quit,
+}
+
+pub fn handle_key(key: KeyEvent, selected: &mut usize, rows: usize) -> KeyAction {
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => KeyAction::Quit,
+        KeyCode::Char('q') | KeyCode::Esc => KeyAction::Quit,
+        KeyCode::Up | KeyCode::Char('k') => {
+            *selected = selected.saturating_sub(1);
+            KeyAction::Redraw
+        }
+        KeyCode::Down | KeyCode::Char('j') => {
+            *selected = (*selected + 1).min(rows.saturating_sub(1));
+            KeyAction::Redraw
+        }
+        KeyCode::Char(' ') => KeyAction::ToggleAck,
+        KeyCode::Char('e') => KeyAction::Enable,
+        KeyCode::Char('a') => KeyAction::Add,
+        KeyCode::Char('d') => KeyAction::Delete,
+        KeyCode::Char('w') => KeyAction::Save,
+        KeyCode::Char('s') => KeyAction::Snapshot,
+        KeyCode::Char('n') => KeyAction::Note,
+        KeyCode::Tab => KeyAction::NextView,
+        KeyCode::Enter => KeyAction::Detail,
+        KeyCode::Char('f') => KeyAction::Freeze,
+        KeyCode::PageUp => KeyAction::PageUp,
+        KeyCode::PageDown => KeyAction::PageDown,
+        KeyCode::Char(c @ '1'..='4') => KeyAction::ShowView(View::ALL[c as usize - '1' as usize]),
+        _ => KeyAction::Redraw,
+    }
+}
+
+/// Line editing for the add-target and note prompts.
+pub fn edit_prompt(key: KeyEvent, input: &mut String) -> PromptAction {
+    match key.code {
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => PromptAction::Cancel,
+        KeyCode::Esc => PromptAction::Cancel,
+        KeyCode::Enter => PromptAction::Submit,
+        KeyCode::Backspace => {
+            input.pop();
+            PromptAction::Edit
+        }
+        KeyCode::Char(c) => {
+            input.push(c);
+            PromptAction::Edit
+        }
+        _ => PromptAction::Edit,
+    }
+}
+
+/// The last line of the interactive view: the open prompt, or the keys of
+/// the current view.
+pub fn key_hint(prompt: Option<&(PromptKind, String)>, view: View, frozen: bool) -> String {
+    match (prompt, view) {
+        _ if frozen => {
+            "Anzeige eingefroren, Messung läuft weiter · f fortsetzen · q beenden".to_string()
+        }
+        (Some((PromptKind::Target, input)), _) => {
+            format!("Neues Ziel: {input}_ (Enter hinzufügen, Esc abbrechen)")
+        }
+        (Some((PromptKind::Note, input)), _) => {
+            format!("Notiz: {input}_ (Enter speichern, Esc abbrechen)")
+        }
+        (None, View::Table) => "↑/↓ auswählen · Enter Details · \
+             Leertaste quittieren (ACK) · e reaktivieren · a hinzufügen · \
+             d entfernen · w speichern · n Notiz · s Schnappschuss · \
+             f einfrieren · 1–4/Tab Ansicht · q beenden"
+            .to_string(),
+        (None, View::Events) => "↑/↓ blättern · n Notiz · s Schnappschuss · f einfrieren · \
+             1–4/Tab Ansicht · q beenden"
+            .to_string(),
+        (None, _) => {
+            "n Notiz · s Schnappschuss · f einfrieren · 1–4/Tab Ansicht · q beenden".to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshots_keep_layout_and_colors() {
+        let lines = vec![
+            Line::plain("IP        Status".to_string()),
+            Line {
+                text: "10.0.0.1  <critical>".to_string(),
+                style: Style::Critical,
+                selected: true,
+            },
+        ];
+        let html = SnapshotFormat::Html.render(&lines, "Runde 3");
+        assert!(html.contains("<title>Runde 3</title>"), "{html}");
+        assert!(html.contains("IP        Status\n"), "{html}");
+        assert!(html.contains(
+            "<span style=\"color: #dc322f; background: #ddd;\">10.0.0.1  &lt;critical&gt;</span>"
+        ));
+        let ansi = SnapshotFormat::Ansi.render(&lines, "");
+        assert!(ansi.starts_with("IP        Status\n"), "{ansi}");
+        assert!(ansi.contains("\u{1b}["), "{ansi}");
+    }
+
+    #[test]
+    fn scrolls_only_as_far_as_the_selection_needs() {
+        // 10 rows on screen, 30 lines.
+        assert_eq!(follow(0, 10, 30, Some(4)), None);
+        assert_eq!(follow(0, 10, 30, Some(12)), Some(3));
+        assert_eq!(follow(15, 10, 30, Some(4)), Some(4));
+        assert_eq!(follow(15, 10, 30, None), None);
+    }
+
+    #[test]
+    fn prompt_edits_typed_target() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let mut input = String::new();
+        for c in "10.0.0.19".chars() {
+            edit_prompt(key(KeyCode::Char(c)), &mut input);
+        }
+        edit_prompt(key(KeyCode::Backspace), &mut input);
+        assert_eq!(input, "10.0.0.1");
+        assert!(matches!(
+            edit_prompt(key(KeyCode::Enter), &mut input),
+            PromptAction::Submit
+        ));
+        assert!(matches!(
+            edit_prompt(key(KeyCode::Esc), &mut input),
+            PromptAction::Cancel
+        ));
+    }
+
+    #[test]
+    fn keys_move_selection_within_rows() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let mut selected = 0;
+        handle_key(key(KeyCode::Up), &mut selected, 3);
+        assert_eq!(selected, 0);
+        for _ in 0..5 {
+            handle_key(key(KeyCode::Char('j')), &mut selected, 3);
+        }
+        assert_eq!(selected, 2);
+        assert!(matches!(
+            handle_key(key(KeyCode::Char(' ')), &mut selected, 3),
+            KeyAction::ToggleAck
+        ));
+        assert!(matches!(
+            handle_key(key(KeyCode::Char('e')), &mut selected, 3),
+            KeyAction::Enable
+        ));
+        assert!(matches!(
+            handle_key(key(KeyCode::Char('d')), &mut selected, 3),
+            KeyAction::Delete
+        ));
+        assert!(matches!(
+            handle_key(key(KeyCode::Char('f')), &mut selected, 3),
+            KeyAction::Freeze
+        ));
+        assert!(matches!(
+            handle_key(key(KeyCode::Char('3')), &mut selected, 3),
+            KeyAction::ShowView(View::Events)
+        ));
+        assert_eq!(View::Config.next(), View::Table);
+        assert_eq!(
+            tab_bar(View::Graphs),
+            " 1 Tabelle  [2 Graphen]  3 Ereignisse   4 Status "
+        );
+        assert!(matches!(
+            handle_key(
+                KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL),
+                &mut selected,
+                3
+            ),
+            KeyAction::Quit
+        ));
+    }
+
+    #[test]
+    fn header_clicks_pick_columns_and_cycle_sorting() {
+        let columns = |smooth, loss_windows, window| Columns {
+            smooth,
+            loss_windows,
+            window,
+        };
+        assert_eq!(columns(false, false, None).at(0), Some(SortColumn::Target));
+        assert_eq!(columns(false, false, None).at(20), Some(SortColumn::Target));
+        assert_eq!(
+            columns(false, false, None).at(21),
+            Some(SortColumn::Success)
+        );
+        assert_eq!(columns(false, false, None).at(55), Some(SortColumn::Avg));
+        assert_eq!(columns(false, false, None).at(75), Some(SortColumn::P50));
+        assert_eq!(
+            columns(false, false, None).at(100),
+            Some(SortColumn::Jitter)
+        );
+        assert_eq!(columns(false, false, None).at(160), None);
+        assert_eq!(columns(true, true, None).at(160), Some(SortColumn::Ewma));
+        assert_eq!(
+            columns(true, true, None).at(185),
+            Some(SortColumn::Window(1))
+        );
+        let span = Duration::from_secs(300);
+        let avg = SortColumn::Recent(RecentValue::Avg, span);
+        assert_eq!(columns(false, false, Some(span)).at(175), Some(avg));
+        assert_eq!(avg.title(), "avg 5m");
+
+        let sort = next_sort(None, SortColumn::Avg);
+        assert_eq!(sort, Some((SortColumn::Avg, false)));
+        assert_eq!(
+            next_sort(sort, SortColumn::Avg),
+            Some((SortColumn::Avg, true))
+        );
+        assert_eq!(
+            next_sort(Some((SortColumn::Avg, true)), SortColumn::Avg),
+            None
+        );
+        assert_eq!(
+            next_sort(sort, SortColumn::Max),
+            Some((SortColumn::Max, false))
+        );
+
+        let targets = ["b", "a", "c"].map(Target::new);
+        let value = |ip: &str| match ip {
+            "a" => Some(5.0),
+            "b" => Some(1.0),
+            _ => None,
+        };
+        let mut rows: Vec<&Target> = targets.iter().collect();
+        let names = |rows: &[&Target]| rows.iter().map(|t| t.name.clone()).collect::<Vec<_>>();
+        sort_rows(&mut rows, (SortColumn::Avg, true), value);
+        assert_eq!(names(&rows), ["a", "b", "c"]);
+        sort_rows(&mut rows, (SortColumn::Avg, false), value);
+        assert_eq!(names(&rows), ["b", "a", "c"]);
+        sort_rows(&mut rows, (SortColumn::Target, true), value);
+        assert_eq!(names(&rows), ["c", "b", "a"]);
+    }
+
+    #[test]
+    fn status_bar_shows_elapsed_and_remaining_time() {
+        let remaining = Some(Duration::from_secs(235));
+        assert_eq!(
+            status_bar(Duration::from_secs(3665), remaining),
+            "Laufzeit 1:01:05 · noch 0:03:55"
+        );
+        assert_eq!(
+            status_bar(Duration::ZERO, None),
+            "Laufzeit 0:00:00 · ohne Ende"
+        );
+    }
+    #[test]
+    fn offscreen_view_draws_lines_and_status_bar() {
+        let terminal = Terminal::offscreen(40, 5);
+        terminal.set_status("Runde 1".to_string());
+        terminal.draw(&[Line::plain("IP        Status".to_string())]);
+        let rows = terminal.offscreen_rows();
+        assert_eq!(rows.len(), 5);
+        assert_eq!(rows[0], "IP        Status");
+        assert_eq!(rows[4], "Runde 1");
+    }
+}
@@ -0,0 +1,183 @@
+use chrono::{Local, TimeZone};
+
+/// `--down-after` default: consecutive failures before a target is down.
+pub const DEFAULT_DOWN_AFTER: u32 = 3;
+/// `--up-after` default: consecutive successes before it is up again.
+pub const DEFAULT_UP_AFTER: u32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum State {
+    #[default]
+    Up,
+    /// Failing, but not yet for `down_after` probes; or answering again,
+    /// but not yet for `up_after`.
+    Degraded,
+    Down,
+}
+
+impl State {
+    pub fn label(self) -> &'static str {
+        match self {
+            State::Up => "up",
+            State::Degraded => "degraded",
+            State::Down => "down",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Thresholds {
+    pub down_after: u32,
+    pub up_after: u32,
+}
+
+/// An outage starting or ending. Entering and leaving `Degraded` is only
+/// kept as the state, or every single lost probe would be logged twice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    /// `failures` in a row, the first at `since_ms`.
+    Down { since_ms: i64, failures: u32 },
+    /// Up again at `ts_ms` after the outage that started at `since_ms`.
+    Up { since_ms: i64, ts_ms: i64 },
+}
+
+impl Transition {
+    /// The log event, e.g. `DOWN 8.8.8.8: 3 failures in a row since
+    /// 12:00:01` or `UP 8.8.8.8: recovered after 42s (down since 12:00:01)`.
+    pub fn text(&self, name: &str) -> String {
+        match *self {
+            Transition::Down { since_ms, failures } => format!(
+                "DOWN {name}: {failures} failures in a row since {}",
+                clock_time(since_ms)
+            ),
+            Transition::Up { since_ms, ts_ms } => format!(
+                "UP {name}: recovered after {}s (down since {})",
+                (ts_ms - since_ms) / 1000,
+                clock_time(since_ms)
+            ),
+        }
+    }
+}
+
+fn clock_time(ts_ms: i64) -> String {
+    Local
+        .timestamp_millis_opt(ts_ms)
+        .single()
+        .map(|t| t.format("%H:%M:%S").to_string())
+        .unwrap_or_default()
+}
+
+/// Up/degraded/down of one target with hysteresis (`--down-after`,
+/// `--up-after`), so a single lost probe doesn't flip it to down and a
+/// single reply doesn't end an outage.
+#[derive(Debug, Clone, Default)]
+pub struct Tracker {
+    state: State,
+    failures: u32,
+    successes: u32,
+    failing_since: Option<i64>,
+    down_since: Option<i64>,
+}
+
+impl Tracker {
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Start of the current outage, recovering ones included.
+    pub fn down_since(&self) -> Option<i64> {
+        self.down_since
+    }
+
+    pub fn update(
+        &mut self,
+        thresholds: &Thresholds,
+        ts_ms: i64,
+        success: bool,
+    ) -> Option<Transition> {
+        if success {
+            self.successes += 1;
+            self.failures = 0;
+            self.failing_since = None;
+        } else {
+            self.failures += 1;
+            self.successes = 0;
+            self.failing_since.get_or_insert(ts_ms);
+        }
+        let to = match self.state {
+            _ if self.failures >= thresholds.down_after => State::Down,
+            _ if self.successes >= thresholds.up_after => State::Up,
+            State::Up if !success => State::Degraded,
+            State::Down if success => State::Degraded,
+            state => state,
+        };
+        self.state = to;
+        // Failing again while recovering continues the same outage.
+        match (to, self.down_since) {
+            (State::Down, None) => {
+                let since_ms = self.failing_since.unwrap_or(ts_ms);
+                self.down_since = Some(since_ms);
+                Some(Transition::Down {
+                    since_ms,
+                    failures: self.failures,
+                })
+            }
+            (State::Up, Some(since_ms)) => {
+                self.down_since = None;
+                Some(Transition::Up { since_ms, ts_ms })
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THRESHOLDS: Thresholds = Thresholds {
+        down_after: 3,
+        up_after: 2,
+    };
+
+    #[test]
+    fn goes_down_after_consecutive_failures_and_up_after_successes() {
+        let mut t = Tracker::default();
+        let mut seen = Vec::new();
+        let results = [
+            true, false, false, true, true, false, false, false, true, false,
+        ];
+        for (i, success) in results.into_iter().enumerate() {
+            let change = t.update(&THRESHOLDS, i as i64 * 2_000, success);
+            seen.push((t.state(), change.is_some()));
+        }
+        use State::*;
+        assert_eq!(
+            seen.iter().map(|(state, _)| *state).collect::<Vec<_>>(),
+            [
+                Up, Degraded, Degraded, Degraded, Up, Degraded, Degraded, Down, Degraded, Degraded
+            ]
+        );
+        // Only the start of the outage is an event.
+        let events: Vec<usize> = (0..seen.len()).filter(|i| seen[*i].1).collect();
+        assert_eq!(events, [7]);
+
+        // Failing again while recovering keeps the outage open.
+        t.update(&THRESHOLDS, 20_000, false);
+        assert_eq!(t.update(&THRESHOLDS, 22_000, false), None);
+        assert_eq!(t.state(), Down);
+        t.update(&THRESHOLDS, 24_000, true);
+        let up = t.update(&THRESHOLDS, 26_000, true).unwrap();
+        assert_eq!(
+            up,
+            Transition::Up {
+                since_ms: 10_000,
+                ts_ms: 26_000
+            }
+        );
+        assert!(
+            up.text("nas")
+                .starts_with("UP nas: recovered after 16s (down since ")
+        );
+    }
+}
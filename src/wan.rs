@@ -0,0 +1,182 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use crate::{Stats, locale, targets::Group};
+
+/// `--group "wan1 via eth0"`: probes of a named group leave through the
+/// given interface.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Binding {
+    pub group: String,
+    pub interface: String,
+}
+
+pub fn parse_binding(input: &str) -> Result<Binding, String> {
+    let (group, interface) = input
+        .split_once(" via ")
+        .map(|(g, i)| (g.trim(), i.trim()))
+        .filter(|(g, i)| !g.is_empty() && !i.is_empty() && !i.contains(char::is_whitespace))
+        .ok_or_else(|| format!("expected '<group> via <interface>', got '{input}'"))?;
+    Ok(Binding {
+        group: group.to_string(),
+        interface: interface.to_string(),
+    })
+}
+
+/// `ping` arguments binding a probe to `interface`. Windows `ping` can only
+/// pick a source address, so the "interface" is passed as one there.
+pub fn ping_args(interface: &str) -> [&str; 2] {
+    if cfg!(target_os = "windows") {
+        ["-S", interface]
+    } else if cfg!(target_os = "macos") {
+        ["-b", interface]
+    } else {
+        ["-I", interface]
+    }
+}
+
+/// Target → (group, interface), shared with the probe workers and rebuilt
+/// whenever the list's groups change.
+#[derive(Debug, Clone, Default)]
+pub struct Routes(Arc<Mutex<HashMap<String, (String, String)>>>);
+
+impl Routes {
+    pub fn interface(&self, target: &str) -> Option<String> {
+        let routes = self.0.lock().ok()?;
+        routes.get(target).map(|(_, interface)| interface.clone())
+    }
+
+    /// Binds each target of a bound group; returns the bound groups that
+    /// don't exist (by name) in `groups`.
+    pub fn rebind<'a>(
+        &self,
+        bindings: &'a [Binding],
+        groups: &HashMap<String, Group>,
+    ) -> Vec<&'a str> {
+        let mut routes = HashMap::new();
+        let mut missing = Vec::new();
+        for binding in bindings {
+            let mut found = false;
+            for (target, group) in groups {
+                if group.name.as_deref() == Some(binding.group.as_str()) {
+                    let route = (binding.group.clone(), binding.interface.clone());
+                    routes.insert(target.clone(), route);
+                    found = true;
+                }
+            }
+            if !found {
+                missing.push(binding.group.as_str());
+            }
+        }
+        if let Ok(mut current) = self.0.lock() {
+            *current = routes;
+        }
+        missing
+    }
+
+    /// Side-by-side totals per bound group, in the order of `bindings`.
+    pub fn comparison(
+        &self,
+        bindings: &[Binding],
+        stats: &HashMap<String, Stats>,
+        numbers: locale::Numbers,
+    ) -> Vec<String> {
+        let routes = self.0.lock().map(|r| r.clone()).unwrap_or_default();
+        let fmt = |v: Option<f64>, decimals| {
+            v.map_or_else(|| "-".to_string(), |n| numbers.fixed(n, decimals))
+        };
+        let mut lines = vec![
+            "Vergleich der Leitungen:".to_string(),
+            format!(
+                "{:<16} {:<10} {:>5} {:>16} {:>9} {:>10} {:>10}",
+                "Gruppe", "Interface", "Ziele", "Erfolg/Gesamt", "Verlust", "avg (ms)", "max (ms)"
+            ),
+        ];
+        for binding in bindings {
+            let members: Vec<&String> = routes
+                .iter()
+                .filter(|(_, (group, _))| *group == binding.group)
+                .map(|(target, _)| target)
+                .collect();
+            let total = merge(members.iter().filter_map(|t| stats.get(*t)));
+            lines.push(format!(
+                "{:<16} {:<10} {:>5} {:>16} {:>9} {:>10} {:>10}",
+                binding.group,
+                binding.interface,
+                members.len(),
+                format!("{}/{}", total.success, total.total),
+                format!("{} %", fmt(total.loss_pct(), 1)),
+                fmt(total.avg_ms(), 2),
+                fmt(total.max_ms, 2),
+            ));
+        }
+        lines
+    }
+}
+
+/// One target's worth of counters for a whole group.
+fn merge<'a>(stats: impl Iterator<Item = &'a Stats>) -> Stats {
+    stats.fold(Stats::default(), |mut acc, s| {
+        acc.success += s.success;
+        acc.total += s.total;
+        acc.sum_ms += s.sum_ms;
+        acc.samples += s.samples;
+        let pick = |a: Option<f64>, b: Option<f64>, f: fn(f64, f64) -> f64| match (a, b) {
+            (Some(a), Some(b)) => Some(f(a, b)),
+            (a, b) => a.or(b),
+        };
+        acc.min_ms = pick(acc.min_ms, s.min_ms, f64::min);
+        acc.max_ms = pick(acc.max_ms, s.max_ms, f64::max);
+        acc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse::ProbeOutcome;
+
+    #[test]
+    fn binds_groups_and_compares_their_totals() {
+        assert_eq!(
+            parse_binding("wan 2 via eth1"),
+            Ok(Binding {
+                group: "wan 2".to_string(),
+                interface: "eth1".to_string(),
+            })
+        );
+        assert!(parse_binding("wan1 eth0").is_err());
+
+        let group = |index, name: &str| Group {
+            index,
+            name: Some(name.to_string()),
+        };
+        let groups = HashMap::from([
+            ("1.1.1.1".to_string(), group(0, "wan1")),
+            ("8.8.8.8".to_string(), group(0, "wan1")),
+            ("9.9.9.9".to_string(), group(1, "wan2")),
+        ]);
+        let bindings = [
+            parse_binding("wan1 via eth0").unwrap(),
+            parse_binding("wan2 via eth1").unwrap(),
+            parse_binding("lte via wwan0").unwrap(),
+        ];
+        let routes = Routes::default();
+        assert_eq!(routes.rebind(&bindings, &groups), ["lte"]);
+        assert_eq!(routes.interface("8.8.8.8").as_deref(), Some("eth0"));
+        assert_eq!(routes.interface("10.0.0.1"), None);
+
+        let mut stats: HashMap<String, Stats> = HashMap::new();
+        for (target, ms) in [("1.1.1.1", 10.0), ("8.8.8.8", 30.0), ("9.9.9.9", 5.0)] {
+            stats.entry(target.to_string()).or_default().record(0, ProbeOutcome::Reply(ms));
+        }
+        stats.get_mut("9.9.9.9").unwrap().record(1, ProbeOutcome::Unparsed);
+        let lines = routes.comparison(&bindings[..2], &stats, locale::Numbers::Point);
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].starts_with("wan1             eth0"), "{}", lines[2]);
+        assert!(lines[2].ends_with("2/2     0.0 %      20.00      30.00"), "{}", lines[2]);
+        assert!(lines[3].ends_with("2/2     0.0 %       5.00       5.00"), "{}", lines[3]);
+    }
+}
@@ -0,0 +1,154 @@
+use std::{collections::VecDeque, time::Duration};
+
+use crate::parse::ProbeOutcome;
+
+/// Loss windows shown side by side, like load averages.
+pub const WINDOWS: [(&str, Duration); 3] = [
+    ("1m", Duration::from_secs(60)),
+    ("15m", Duration::from_secs(15 * 60)),
+    ("1h", Duration::from_secs(60 * 60)),
+];
+
+/// Recent probe results of one target, kept for the longest window.
+#[derive(Debug, Clone, Default)]
+pub struct LossWindows {
+    samples: VecDeque<(i64, bool)>,
+}
+
+impl LossWindows {
+    pub fn record(&mut self, ts_ms: i64, success: bool) {
+        self.samples.push_back((ts_ms, success));
+        let longest = WINDOWS[WINDOWS.len() - 1].1;
+        let cutoff = ts_ms - longest.as_millis() as i64;
+        while self.samples.front().is_some_and(|(ts, _)| *ts <= cutoff) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// Loss percentage over the last `window` as of `now_ms`; `None` without
+    /// samples in that window. Shorter runs report what they have.
+    pub fn loss_pct(&self, window: Duration, now_ms: i64) -> Option<f64> {
+        let cutoff = now_ms - window.as_millis() as i64;
+        let (mut total, mut lost) = (0u64, 0u64);
+        for (_, success) in self.samples.iter().rev().take_while(|(ts, _)| *ts > cutoff) {
+            total += 1;
+            lost += u64::from(!success);
+        }
+        (total > 0).then(|| lost as f64 * 100.0 / total as f64)
+    }
+}
+
+/// `--window`: one target's probes of the last `span`, so loss, avg and
+/// max over that span can be shown next to the all-time values, which
+/// barely move after a few days.
+#[derive(Debug, Clone)]
+pub struct Recent {
+    span: Duration,
+    samples: VecDeque<(i64, ProbeOutcome)>,
+}
+
+/// Values over a `Recent` window; `avg_ms` and `max_ms` are `None`
+/// without a reply time in it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    pub loss_pct: f64,
+    pub avg_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+}
+
+impl Recent {
+    pub fn new(span: Duration) -> Recent {
+        Recent {
+            span,
+            samples: VecDeque::new(),
+        }
+    }
+
+    pub fn record(&mut self, ts_ms: i64, outcome: ProbeOutcome) {
+        self.samples.push_back((ts_ms, outcome));
+        let cutoff = ts_ms - self.span.as_millis() as i64;
+        while self.samples.front().is_some_and(|(ts, _)| *ts <= cutoff) {
+            self.samples.pop_front();
+        }
+    }
+
+    /// The window ending at `now_ms`; `None` without samples in it.
+    pub fn summary(&self, now_ms: i64) -> Option<Summary> {
+        let cutoff = now_ms - self.span.as_millis() as i64;
+        let (mut total, mut lost, mut rtt_sum, mut rtts) = (0u64, 0u64, 0.0, 0u64);
+        let mut max_ms: Option<f64> = None;
+        for (_, outcome) in self.samples.iter().rev().take_while(|(ts, _)| *ts > cutoff) {
+            total += 1;
+            lost += u64::from(!outcome.success());
+            if let Some(ms) = outcome.rtt_ms() {
+                rtt_sum += ms;
+                rtts += 1;
+                max_ms = Some(max_ms.map_or(ms, |m| m.max(ms)));
+            }
+        }
+        (total > 0).then(|| Summary {
+            loss_pct: lost as f64 * 100.0 / total as f64,
+            avg_ms: (rtts > 0).then(|| rtt_sum / rtts as f64),
+            max_ms,
+        })
+    }
+}
+
+/// Column label of a window length: `90s`, `5m`, `2h`.
+pub fn label(span: Duration) -> String {
+    let secs = span.as_secs();
+    match secs {
+        s if s >= 3600 && s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s >= 60 && s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{s}s"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_blip_fades_from_shorter_windows_first() {
+        let mut w = LossWindows::default();
+        let mut ts = 0;
+        // Ten minutes of probes every 2s with a 20s outage at the start.
+        while ts < 600_000 {
+            w.record(ts, ts >= 20_000);
+            ts += 2_000;
+        }
+        let now = ts - 2_000;
+        assert_eq!(w.loss_pct(WINDOWS[0].1, now), Some(0.0));
+        let long = w.loss_pct(WINDOWS[1].1, now).unwrap();
+        assert!((long - 10.0 * 100.0 / 300.0).abs() < 1e-9, "{long}");
+        assert_eq!(w.loss_pct(WINDOWS[2].1, now), Some(long));
+        assert_eq!(LossWindows::default().loss_pct(WINDOWS[0].1, now), None);
+    }
+
+    #[test]
+    fn drops_samples_older_than_the_longest_window() {
+        let mut w = LossWindows::default();
+        w.record(0, false);
+        w.record(3_600_000, true);
+        assert_eq!(w.samples.len(), 1);
+    }
+
+    #[test]
+    fn recent_window_forgets_an_old_spike() {
+        let mut w = Recent::new(Duration::from_secs(300));
+        w.record(0, ProbeOutcome::Reply(900.0));
+        w.record(1_000, ProbeOutcome::Lost(crate::parse::Loss::Timeout));
+        for i in 0..10 {
+            w.record(400_000 + i * 2_000, ProbeOutcome::Reply(10.0 + i as f64));
+        }
+        let now = 418_000;
+        let summary = w.summary(now).unwrap();
+        assert_eq!(summary.loss_pct, 0.0);
+        assert_eq!(summary.avg_ms, Some(14.5));
+        assert_eq!(summary.max_ms, Some(19.0));
+        assert_eq!(w.samples.len(), 10);
+        assert_eq!(w.summary(now + 300_000), None);
+        assert_eq!(label(Duration::from_secs(300)), "5m");
+        assert_eq!(label(Duration::from_secs(90)), "90s");
+    }
+}
@@ -0,0 +1,76 @@
+use std::{
+    fmt, io,
+    net::{Ipv4Addr, UdpSocket},
+    str::FromStr,
+};
+
+/// Wake-on-LAN listens on the discard port; 7 works for most cards too.
+const PORT: u16 = 9;
+
+/// A MAC address, written `aa:bb:cc:dd:ee:ff` or `aa-bb-cc-dd-ee-ff`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mac([u8; 6]);
+
+impl FromStr for Mac {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Mac, String> {
+        let invalid = || format!("'{input}' is no MAC address like aa:bb:cc:dd:ee:ff");
+        let parts: Vec<&str> = input.split([':', '-']).collect();
+        if parts.len() != 6 {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 6];
+        for (byte, part) in bytes.iter_mut().zip(parts) {
+            if part.len() != 2 || !part.bytes().all(|b| b.is_ascii_hexdigit()) {
+                return Err(invalid());
+            }
+            *byte = u8::from_str_radix(part, 16).map_err(|_| invalid())?;
+        }
+        Ok(Mac(bytes))
+    }
+}
+
+impl fmt::Display for Mac {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex: Vec<String> = self.0.iter().map(|b| format!("{b:02x}")).collect();
+        f.write_str(&hex.join(":"))
+    }
+}
+
+/// Six `0xff` bytes, then the MAC 16 times.
+pub fn magic_packet(mac: &Mac) -> Vec<u8> {
+    let mut packet = vec![0xff; 6];
+    for _ in 0..16 {
+        packet.extend_from_slice(&mac.0);
+    }
+    packet
+}
+
+/// Broadcasts the magic packet for `mac` on the local network. Whether the
+/// machine wakes up shows in its next replies; nothing answers the packet.
+pub fn wake(mac: &Mac) -> io::Result<()> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0))?;
+    socket.set_broadcast(true)?;
+    socket.send_to(&magic_packet(mac), (Ipv4Addr::BROADCAST, PORT))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_macs_and_builds_the_magic_packet() {
+        let mac: Mac = "00-1A-2b-3c-4d-5e".parse().unwrap();
+        assert_eq!(mac.to_string(), "00:1a:2b:3c:4d:5e");
+        assert!("00:1a:2b:3c:4d".parse::<Mac>().is_err());
+        assert!("00:1a:2b:3c:4d:5".parse::<Mac>().is_err());
+        assert!("00:1a:2b:3c:4d:zz".parse::<Mac>().is_err());
+
+        let packet = magic_packet(&mac);
+        assert_eq!(packet.len(), 102);
+        assert_eq!(packet[..6], [0xff; 6]);
+        assert_eq!(packet[96..], [0x00, 0x1a, 0x2b, 0x3c, 0x4d, 0x5e]);
+    }
+}